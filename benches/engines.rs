@@ -0,0 +1,90 @@
+//! Timings for each way of matching the same grammars.
+//!
+//! Run with `cargo bench`. Every engine is measured on the same
+//! grammars and inputs, so adding a backend means adding one more
+//! entry to `anchored` or `unanchored`.
+
+extern crate weighted_regexp;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use weighted_regexp::*;
+use weighted_regexp::bitparallel::BitParallel;
+use weighted_regexp::core::{Describe, Regex};
+use weighted_regexp::dfa::LazyDfa;
+use weighted_regexp::search::find_spans;
+use weighted_regexp::weights::leftmost::Leftmost;
+
+const SIZES: &[usize] = &[1 << 10, 1 << 16, 1 << 20];
+
+/// Run `f` repeatedly for about a tenth of a second and report its
+/// throughput on `len` bytes.
+fn bench<F: FnMut() -> R, R>(group: &str, engine: &str, len: usize, mut f: F) {
+    let budget = Duration::from_millis(100);
+    let start = Instant::now();
+    let mut iterations = 0u32;
+    while iterations == 0 || start.elapsed() < budget {
+        black_box(f());
+        iterations += 1;
+    }
+    let per_iter = start.elapsed() / iterations;
+    let rate = len as f64 / per_iter.as_secs_f64() / (1 << 20) as f64;
+    println!("{:<12} {:<14} {:>8} bytes {:>12?}/iter {:>10.1} MiB/s", group, engine, len, per_iter, rate);
+}
+
+/// Lowercase words separated by spaces, ending in a digit, so the
+/// anchored grammar has to read everything before deciding.
+fn input(len: usize) -> Vec<u8> {
+    let mut input: Vec<u8> = b"lorem ipsum dolor sit amet ".iter().cycle().take(len - 1).cloned().collect();
+    input.push(b'7');
+    input
+}
+
+fn words() -> AnyRegex<u8, Match, impl Regex<u8, Match> + Describe<u8, Match>> {
+    let lower = || is(|b: &u8| b.is_ascii_lowercase()).label("[a-z]");
+    let space = is(|&b: &u8| b == b' ').label(" ");
+    let digit = is(|b: &u8| b.is_ascii_digit()).label("[0-9]");
+    many(lower() + many(lower()) + space) + digit
+}
+
+fn anchored() {
+    for &len in SIZES {
+        let input = input(len);
+
+        let mut re = words();
+        bench("anchored", "recursive", len, || has_match(&mut re, input.iter().cloned()));
+
+        let grammar = words();
+        let mut flat = AnyRegex::new(grammar.to_flat().unwrap());
+        bench("anchored", "flat", len, || has_match(&mut flat, input.iter().cloned()));
+
+        let mut dfa = LazyDfa::new(grammar.to_nfa().unwrap(), 1000);
+        bench("anchored", "lazy-dfa", len, || dfa.is_match(input.iter().cloned()));
+
+        let bits = BitParallel::new(&grammar.to_nfa().unwrap());
+        bench("anchored", "bit-parallel", len, || bits.is_match(input.iter().cloned()));
+    }
+}
+
+/// A word starting with "do", weighted with where each match starts.
+fn word_starting_do() -> AnyRegex<u8, Leftmost, impl Regex<u8, Leftmost> + Describe<u8, Leftmost>> {
+    let byte = |c: u8| is(move |&b: &u8| b == c);
+    byte(b'd') + byte(b'o') + many(is(|b: &u8| b.is_ascii_lowercase()))
+}
+
+fn unanchored() {
+    for &len in SIZES {
+        let input = input(len);
+        let mut re = word_starting_do();
+        bench("unanchored", "recursive", len, || find_spans(&mut re, &input).len());
+
+        let grammar = word_starting_do();
+        let mut flat = AnyRegex::new(grammar.to_flat().unwrap());
+        bench("unanchored", "flat", len, || find_spans(&mut flat, &input).len());
+    }
+}
+
+fn main() {
+    anchored();
+    unanchored();
+}
@@ -0,0 +1,125 @@
+//! Checking that matching doesn't allocate.
+//!
+//! Once a grammar has been built and has matched at least one input,
+//! shifting further input through the built-in combinators doesn't
+//! touch the heap, as long as the weights don't either: `Match` and
+//! other `Copy` weights are fine. The exceptions are `delay`, which
+//! allocates the grammar it wraps the first time it's needed after
+//! each reset, and weights which own heap data.
+//!
+//! To check that in your own program, install `CountingAllocator` as
+//! the global allocator and wrap matching in `assert_no_allocations`:
+//!
+//! ```
+//! extern crate weighted_regexp;
+//! use weighted_regexp::{has_match_str, is, many, AnyRegex, Match};
+//! use weighted_regexp::alloc_counter::{assert_no_allocations, CountingAllocator};
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator;
+//!
+//! fn main() {
+//!     let mut re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a'));
+//!     has_match_str(&mut re, "aaa");
+//!     assert!(assert_no_allocations(|| has_match_str(&mut re, "aaaa")));
+//! }
+//! ```
+//!
+//! This module is only available with the `count-allocations` feature.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A global allocator which forwards to `System`, counting how many
+/// allocations each thread makes.
+pub struct CountingAllocator;
+
+fn count() {
+    // During thread teardown the counter may already be gone, and
+    // there's nothing useful to count then anyway.
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// How many allocations the current thread has made through
+/// `CountingAllocator`. Always zero if it isn't the global allocator.
+pub fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// Run `f`, panicking if it allocated any memory on this thread.
+pub fn assert_no_allocations<F, R>(f: F) -> R where
+    F: FnOnce() -> R,
+{
+    let before = allocations();
+    let result = f();
+    let allocated = allocations() - before;
+    assert!(allocated == 0, "expected no allocations, but found {}", allocated);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn counts() {
+        let before = allocations();
+        drop(vec![1u8]);
+        assert_eq!(allocations(), before + 1);
+    }
+
+    #[test]
+    fn built_in_combinators() {
+        let input: Vec<u8> = b"the quick brown fox".to_vec();
+        let word = || many(is(|&c: &u8| c.is_ascii_lowercase()));
+        let mut re = !(word() & empty()) + many(is(|&c: &u8| c == b' ') + (word() | is(|&c| c == b'!')));
+        let mut keywords = many(is(|_: &u8| true)) + literals(vec![&b"fox"[..], b"dog"].into_iter().map(|w| w.iter().cloned()));
+        // Let any scratch buffers, and the copies a complement keeps
+        // for each match attempt, reach their working size first.
+        has_match(&mut re, input.iter().cloned());
+        has_match(&mut keywords, input.iter().cloned());
+
+        let flat_source: AnyRegex<u8, Match, _> = many(is(|&c: &u8| c != b'?'));
+        let mut flat = AnyRegex::new(flat_source.to_flat().ok().unwrap());
+        has_match(&mut flat, input.iter().cloned());
+
+        assert_no_allocations(|| {
+            assert!(has_match(&mut re, input.iter().cloned()));
+            assert!(has_match(&mut keywords, input.iter().cloned()));
+            assert!(has_match(&mut flat, input.iter().cloned()));
+        });
+    }
+    #[test]
+    fn byte_class_scanning() {
+        let class = byteclass::ByteClass::from_bytes(b"xyz");
+        let mut haystack = vec![b'a'; 100];
+        haystack.push(b'y');
+        assert_no_allocations(|| assert_eq!(class.find(&haystack), Some(100)));
+    }
+}
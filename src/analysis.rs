@@ -0,0 +1,252 @@
+//! Static analysis of grammars, without matching anything.
+//!
+//! `is_empty_language` answers whether a whole grammar can match at
+//! all, and `diagnose` reports on each of its sub-expressions, for
+//! pointing pattern authors at parts that don't do what they expect.
+//!
+//! Leaves are opaque predicates, so these analyses can't know which
+//! items a leaf accepts, only that it consumes exactly one. They are
+//! conservative in that direction: a grammar reported as never
+//! matching really can't match any input, but some grammars which
+//! never match aren't detected.
+
+use core::{AnyRegex, Describe, Node};
+use std::cmp::{max, min};
+use syntax::to_extended_string;
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    R: Describe<T, M>,
+{
+    /// Whether this grammar provably can't match any input, like
+    /// `is(f) & empty()`, which would need an input that is both one
+    /// item long and empty. Patterns which can never match are usually
+    /// mistakes, and rejecting them up front is better than scanning
+    /// everything for nothing.
+    pub fn is_empty_language(&self) -> bool { is_empty_language(self) }
+
+    /// Report which parts of this grammar match the empty input, and
+    /// which can never contribute to a match.
+    pub fn diagnose(&self) -> Vec<Diagnostic> { diagnose(self) }
+}
+
+/// Something worth telling a pattern's author about one of its
+/// sub-expressions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The sub-expression's position in a preorder walk of the grammar,
+    /// where the whole grammar is 0 and labels count as nodes, as in
+    /// `state_size`.
+    pub node: usize,
+    /// The sub-expression, in the extended syntax `Display` uses.
+    pub text: String,
+    pub finding: Finding,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Finding {
+    /// The sub-expression matches the empty input.
+    Nullable,
+    /// The sub-expression can never match, and not just because one of
+    /// its parts can't.
+    NeverMatches,
+    /// The sub-expression is in sequence with, or intersected with,
+    /// something that never matches, so it can never contribute to a
+    /// match and simplification could remove it. Nothing inside it is
+    /// reported.
+    Unreachable,
+}
+
+/// Like `AnyRegex::is_empty_language`, but for any grammar node.
+pub fn is_empty_language<T, M>(re: &dyn Describe<T, M>) -> bool {
+    lengths(re).is_none()
+}
+
+/// Whether `re` might match the empty input. Unlike the nullability
+/// `diagnose` reports, this gives an answer for `delay`ed grammars too:
+/// they're assumed to match it, so that what follows them is treated as
+/// reachable.
+pub(crate) fn nullable<T, M>(re: &dyn Describe<T, M>) -> bool {
+    match re.describe() {
+        Node::Empty | Node::Many(_, _) | Node::Delay => true,
+        Node::Is(_) => false,
+        Node::Label(_, inner) => nullable(inner),
+        Node::Not(inner) => !nullable(inner),
+        Node::Or(left, right) => nullable(left) || nullable(right),
+        Node::And(left, right) | Node::Sequence(left, right, _) => nullable(left) && nullable(right),
+    }
+}
+
+/// Like `AnyRegex::diagnose`, but for any grammar node. Diagnostics
+/// are in preorder. Whether a `delay`ed grammar is nullable isn't
+/// known, so it isn't reported.
+pub fn diagnose<T, M>(re: &dyn Describe<T, M>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    walk(re, &mut 0, &mut diagnostics);
+    diagnostics.sort_by_key(|d| d.node);
+    diagnostics
+}
+
+/// Diagnose `re` and its descendants, numbering them from `next`, and
+/// return its length bounds and whether it's nullable, if known.
+fn walk<T, M>(re: &dyn Describe<T, M>, next: &mut usize, out: &mut Vec<Diagnostic>) -> (Lengths, Option<bool>) {
+    let node = *next;
+    *next += 1;
+    let report = |out: &mut Vec<Diagnostic>, finding| {
+        out.push(Diagnostic { node, text: to_extended_string(re), finding });
+    };
+    let (lengths, nullable) = match re.describe() {
+        Node::Empty => (Some((0, Some(0))), Some(true)),
+        Node::Is(_) => (Some((1, Some(1))), Some(false)),
+        Node::Delay => (Some((0, None)), None),
+        Node::Label(_, inner) => walk(inner, next, out),
+        Node::Not(inner) => (Some((0, None)), walk(inner, next, out).1.map(|n| !n)),
+        Node::Many(inner, _) => (repeat(walk(inner, next, out).0), Some(true)),
+        Node::Or(left, right) => {
+            let (llen, lnull) = walk(left, next, out);
+            let (rlen, rnull) = walk(right, next, out);
+            let nullable = match (lnull, rnull) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            };
+            (either(llen, rlen), nullable)
+        }
+        Node::And(left, right) | Node::Sequence(left, right, _) => {
+            let left_start = *next;
+            let (llen, lnull) = walk(left, next, out);
+            let right_start = *next;
+            let (rlen, rnull) = walk(right, next, out);
+            let nullable = match (lnull, rnull) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+            let lengths = if let Node::And(_, _) = re.describe() {
+                let lengths = both(llen, rlen);
+                if lengths.is_none() && llen.is_some() && rlen.is_some() {
+                    report(out, Finding::NeverMatches);
+                }
+                lengths
+            } else {
+                then(llen, rlen)
+            };
+            // An unreachable child's range of node numbers, for
+            // retracting what was said about it.
+            let unreachable = match (llen, rlen) {
+                (None, Some(_)) => Some((right_start, *next)),
+                (Some(_), None) => Some((left_start, right_start)),
+                _ => None,
+            };
+            if let Some((start, end)) = unreachable {
+                out.retain(|d| d.node < start || d.node >= end);
+                let child = if start == left_start { left } else { right };
+                out.push(Diagnostic { node: start, text: to_extended_string(child), finding: Finding::Unreachable });
+            }
+            (lengths, nullable)
+        }
+    };
+    if nullable == Some(true) {
+        report(out, Finding::Nullable);
+    }
+    (lengths, nullable)
+}
+
+/// Bounds on the lengths of inputs a grammar might match, as a minimum
+/// and an optional maximum, or `None` if it can't match anything.
+type Lengths = Option<(usize, Option<usize>)>;
+
+fn lengths<T, M>(re: &dyn Describe<T, M>) -> Lengths {
+    match re.describe() {
+        Node::Empty => Some((0, Some(0))),
+        Node::Is(_) => Some((1, Some(1))),
+        Node::Label(_, inner) => lengths(inner),
+        // The complement of a grammar can match anything the grammar
+        // doesn't, which could be inputs of any length.
+        Node::Not(_) | Node::Delay => Some((0, None)),
+        Node::Or(left, right) => either(lengths(left), lengths(right)),
+        Node::And(left, right) => both(lengths(left), lengths(right)),
+        Node::Sequence(left, right, _) => then(lengths(left), lengths(right)),
+        Node::Many(inner, _) => repeat(lengths(inner)),
+    }
+}
+
+fn either(left: Lengths, right: Lengths) -> Lengths {
+    match (left, right) {
+        (None, other) | (other, None) => other,
+        (Some((lmin, lmax)), Some((rmin, rmax))) =>
+            Some((min(lmin, rmin), lmax.and_then(|l| rmax.map(|r| max(l, r))))),
+    }
+}
+
+fn both(left: Lengths, right: Lengths) -> Lengths {
+    let (lmin, lmax) = left?;
+    let (rmin, rmax) = right?;
+    let low = max(lmin, rmin);
+    let high = match (lmax, rmax) {
+        (Some(l), Some(r)) => Some(min(l, r)),
+        (bound, None) | (None, bound) => bound,
+    };
+    if high.is_some_and(|high| high < low) {
+        return None;
+    }
+    Some((low, high))
+}
+
+fn then(left: Lengths, right: Lengths) -> Lengths {
+    let (lmin, lmax) = left?;
+    let (rmin, rmax) = right?;
+    Some((lmin.saturating_add(rmin), lmax.and_then(|l| rmax.and_then(|r| l.checked_add(r)))))
+}
+
+fn repeat(inner: Lengths) -> Lengths {
+    match inner {
+        None | Some((_, Some(0))) => Some((0, Some(0))),
+        Some(_) => Some((0, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn impossible() {
+        let re: AnyRegex<char, Match, _> = is(|_| true) & empty();
+        assert!(re.is_empty_language());
+        let re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a')) + (is(|_| true) & empty()).label("x");
+        assert!(re.is_empty_language());
+        let re: AnyRegex<char, Match, _> = (is(|_| true) + is(|_| true)) & (is(|_| true) | empty());
+        assert!(re.is_empty_language());
+        // Only the empty input is left.
+        let re: AnyRegex<char, Match, _> = many(is(|_| true) & empty());
+        assert!(!re.is_empty_language());
+        assert!(has_match(&mut many(is(|_| true) & empty()), "".chars()));
+    }
+
+    #[test]
+    fn possible() {
+        let re: AnyRegex<char, Match, _> = many(is(|_| true)) & (is(|_| true) + is(|_| true));
+        assert!(!re.is_empty_language());
+        let re: AnyRegex<char, Match, _> = (is(|_| true) & empty()) | empty();
+        assert!(!re.is_empty_language());
+        let re: AnyRegex<char, Match, _> = !empty() & empty();
+        assert!(!re.is_empty_language(), "complements aren't analyzed");
+    }
+
+    #[test]
+    fn diagnostics() {
+        let a = || is(|&c| c == 'a').label("a");
+        let re: AnyRegex<char, Match, _> = (a() + (many(a()) & a()) + ((a() & empty()) + many(a()))) | empty();
+        let found: Vec<_> = re.diagnose().into_iter().map(|d| (d.node, d.text, d.finding)).collect();
+        assert_eq!(found, vec![
+            (0, "a(?:a*&a)(?:a&)a*|".to_string(), Finding::Nullable),
+            // Everything before the impossible part is wasted work.
+            (2, "a(?:a*&a)".to_string(), Finding::Unreachable),
+            (12, "a&".to_string(), Finding::NeverMatches),
+            (15, "".to_string(), Finding::Nullable),
+            (16, "a*".to_string(), Finding::Unreachable),
+            (19, "".to_string(), Finding::Nullable),
+        ]);
+    }
+}
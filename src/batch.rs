@@ -0,0 +1,55 @@
+//! Matching many independent inputs in parallel.
+//!
+//! A `Compiled` grammar can be shared between threads, and each thread
+//! only needs its own `State`. That makes batch workloads, like scoring
+//! every record in a large data set, embarrassingly parallel.
+
+use core::Describe;
+use flat::Compiled;
+use num_traits::{Zero, One};
+use std::cmp;
+use std::ops;
+use std::thread;
+
+/// Compute the weight of every input, spreading the work across one
+/// thread per available CPU. Results are in the same order as
+/// `inputs`.
+///
+/// Each thread handles a contiguous run of inputs with a single
+/// `State`, so the per-thread setup cost is paid once rather than per
+/// input.
+pub fn match_all_par<T, M, R, I>(compiled: &Compiled<T, M, R>, inputs: &[I]) -> Vec<M> where
+    T: Clone + Sync,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + Send + Sync,
+    R: Describe<T, M> + Sync,
+    I: AsRef<[T]> + Sync,
+{
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let per_thread = cmp::max(1, inputs.len().div_ceil(threads));
+    thread::scope(|scope| {
+        let handles: Vec<_> = inputs.chunks(per_thread).map(|chunk| {
+            scope.spawn(move || {
+                let mut state = compiled.state();
+                chunk.iter().map(|input| state.over(input.as_ref().iter().cloned())).collect::<Vec<M>>()
+            })
+        }).collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use num_traits::{zero, one};
+
+    #[test]
+    fn in_order() {
+        let re: AnyRegex<u8, Match, _> = many(is(|&c: &u8| c == b'a') + is(|&c: &u8| c == b'b'));
+        let compiled = Compiled::new(re).ok().unwrap();
+        let inputs: Vec<Vec<u8>> = (0..100).map(|n| b"ab".iter().cycle().take(n).cloned().collect()).collect();
+        let expected: Vec<Match> = (0..100).map(|n| if n % 2 == 0 { one() } else { zero() }).collect();
+        assert_eq!(match_all_par(&compiled, &inputs), expected);
+        assert_eq!(match_all_par(&compiled, &[] as &[Vec<u8>]), vec![]);
+    }
+}
@@ -0,0 +1,82 @@
+//! Search files for a pattern in the extended syntax, printing each
+//! match's byte offsets and text.
+//!
+//! Usage: `wgrep PATTERN [FILE]...`, reading standard input if no files
+//! are given. Files are mapped into memory where the platform allows.
+//! Like grep, it exits with status 0 if anything matched, 1 if nothing
+//! did, and 2 on errors.
+
+extern crate weighted_regexp;
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::process;
+use weighted_regexp::AnyRegex;
+use weighted_regexp::core::Regex;
+use weighted_regexp::parse::parse;
+use weighted_regexp::search::{find_spans, Haystack};
+use weighted_regexp::weights::leftmost::Leftmost;
+
+/// Print every match in `haystack`, prefixed by `name` if given, and
+/// report whether there were any.
+fn search<R, W>(re: &mut AnyRegex<u8, Leftmost, R>, name: Option<&str>, haystack: &[u8], out: &mut W) -> io::Result<bool> where
+    R: Regex<u8, Leftmost>,
+    W: Write,
+{
+    let spans = find_spans(re, haystack);
+    for &(start, end) in &spans {
+        if let Some(name) = name {
+            write!(out, "{}:", name)?;
+        }
+        let text = String::from_utf8_lossy(&haystack[start..end]);
+        writeln!(out, "{}-{}:{}", start, end, text.escape_debug())?;
+    }
+    Ok(!spans.is_empty())
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let pattern = match args.next() {
+        Some(pattern) => pattern,
+        None => {
+            eprintln!("usage: wgrep PATTERN [FILE]...");
+            process::exit(2);
+        }
+    };
+    let mut re = match parse::<Leftmost>(&pattern) {
+        Ok(re) => re,
+        Err(e) => {
+            eprintln!("wgrep: {}", e);
+            process::exit(2);
+        }
+    };
+
+    let paths: Vec<String> = args.collect();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut matched = false;
+    let mut failed = false;
+
+    if paths.is_empty() {
+        let mut haystack = Vec::new();
+        match io::stdin().read_to_end(&mut haystack).and_then(|_| search(&mut re, None, &haystack, &mut out)) {
+            Ok(found) => matched = found,
+            Err(e) => {
+                eprintln!("wgrep: {}", e);
+                failed = true;
+            }
+        }
+    }
+    for path in &paths {
+        let name = if paths.len() > 1 { Some(&path[..]) } else { None };
+        match Haystack::open(path).and_then(|haystack| search(&mut re, name, &haystack, &mut out)) {
+            Ok(found) => matched |= found,
+            Err(e) => {
+                eprintln!("wgrep: {}: {}", path, e);
+                failed = true;
+            }
+        }
+    }
+
+    process::exit(if failed { 2 } else if matched { 0 } else { 1 });
+}
@@ -0,0 +1,145 @@
+//! Bit-parallel recognition for byte grammars.
+//!
+//! This is the classic "shift-and" family of techniques, generalized to
+//! arbitrary Glushkov automata: the set of live `Nfa` states is a bit
+//! vector, one bit per state, and each input byte is handled with a few
+//! table lookups and bitwise operations instead of a walk over the
+//! grammar.
+//!
+//! Two tables drive the simulation. `classes` holds, for each byte
+//! value, the states that byte may enter. `follow` holds, for every
+//! group of eight states and every combination of them being live, the
+//! union of the states they lead to. Stepping is then the OR of one
+//! `follow` entry per group, masked by the `classes` entry for the byte.
+
+use core::Leaf;
+use nfa::Nfa;
+use num_traits::Zero;
+use weights::recognize::Match;
+
+/// Tables for simulating an `Nfa` over bytes with bit vectors.
+pub struct BitParallel {
+    words: usize,
+    classes: Vec<u64>,
+    follow: Vec<u64>,
+    finals: Vec<u64>,
+}
+
+impl BitParallel {
+    /// Precompute the tables for `nfa`. This evaluates every state's
+    /// test on all 256 byte values, so afterward the grammar's
+    /// predicates are never called again.
+    pub fn new(nfa: &Nfa<u8, Match>) -> Self {
+        let states = nfa.states();
+        let words = states.len().div_ceil(64);
+        let groups = states.len().div_ceil(8);
+
+        let mut classes = vec![0; 256 * words];
+        for (p, state) in states.iter().enumerate().skip(1) {
+            let test = state.test().unwrap();
+            for b in 0..256 {
+                if !test.weigh(&(b as u8)).is_zero() {
+                    classes[b * words + p / 64] |= 1 << (p % 64);
+                }
+            }
+        }
+
+        let mut follow = vec![0; groups * 256 * words];
+        for group in 0..groups {
+            for live in 1..256 {
+                let row = (group * 256 + live) * words;
+                for bit in 0..8 {
+                    let q = group * 8 + bit;
+                    if live & (1 << bit) == 0 || q >= states.len() {
+                        continue;
+                    }
+                    for &p in states[q].next() {
+                        follow[row + p / 64] |= 1 << (p % 64);
+                    }
+                }
+            }
+        }
+
+        let mut finals = vec![0; words];
+        for (q, state) in states.iter().enumerate() {
+            if state.is_final() {
+                finals[q / 64] |= 1 << (q % 64);
+            }
+        }
+
+        BitParallel { words, classes, follow, finals }
+    }
+
+    /// Check whether the entire input matches the grammar.
+    pub fn is_match<I>(&self, over: I) -> bool where
+        I: IntoIterator<Item=u8>,
+    {
+        let words = self.words;
+        let mut live = vec![0u64; words];
+        let mut next = vec![0u64; words];
+        // Only the start state is live before any input.
+        live[0] = 1;
+
+        for b in over {
+            for word in next.iter_mut() {
+                *word = 0;
+            }
+            for (w, &bits) in live.iter().enumerate() {
+                for byte in 0..8 {
+                    let group = w * 8 + byte;
+                    let index = ((bits >> (byte * 8)) & 0xff) as usize;
+                    if index == 0 {
+                        continue;
+                    }
+                    let row = &self.follow[(group * 256 + index) * words..][..words];
+                    for (n, &r) in next.iter_mut().zip(row) {
+                        *n |= r;
+                    }
+                }
+            }
+
+            let class = &self.classes[b as usize * words..][..words];
+            let mut any = false;
+            for ((l, &n), &c) in live.iter_mut().zip(&next).zip(class) {
+                *l = n & c;
+                any |= *l != 0;
+            }
+            if !any {
+                return false;
+            }
+        }
+
+        live.iter().zip(&self.finals).any(|(&l, &f)| l & f != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    quickcheck! {
+        fn agrees_with_grammar(to_match : Vec<u8>) -> bool {
+            let mut re = many(is(|&c: &u8| c % 3 == 0) + many(is(|&c: &u8| c % 2 == 0)))
+                + (is(|&c: &u8| c > 200) | empty());
+            let expected = has_match(&mut re, to_match.iter().cloned());
+            let bits = BitParallel::new(&re.to_nfa().ok().unwrap());
+            expected == bits.is_match(to_match.iter().cloned())
+        }
+
+        fn many_states(to_match : Vec<u8>) -> bool {
+            // Enough leaves to need more than one word of state.
+            fn small() -> AnyRegex<u8, Match, impl ::core::Regex<u8, Match> + ::core::Describe<u8, Match>> {
+                let small = || is(|&c: &u8| c < 100);
+                small() + small() + small() + small() + small() + small() + small() + small()
+            }
+            let mut re = many(is(|&c: &u8| c < 100))
+                + small() + small() + small() + small() + small()
+                + small() + small() + small() + small();
+            let to_match: Vec<u8> = to_match.iter().cycle().take(80).cloned().collect();
+            let expected = has_match(&mut re, to_match.iter().cloned());
+            let bits = BitParallel::new(&re.to_nfa().ok().unwrap());
+            expected == bits.is_match(to_match.iter().cloned())
+        }
+    }
+}
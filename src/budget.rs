@@ -0,0 +1,111 @@
+//! Limiting how much work matching may do.
+//!
+//! Matching takes time linear in the input, but with expensive weights
+//! or large grammars, linear can still be too long for a service that
+//! matches untrusted patterns against untrusted input. `over_within`
+//! matches like `over`, but gives up once a `Budget` runs out.
+
+use core::{AnyRegex, Regex};
+use num_traits::{Zero, zero, One, one};
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How much input matching may consume, and until when. The default
+/// budget is unlimited.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    items: Option<usize>,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    /// Give up before shifting more than `items` items.
+    pub fn max_items(self, items: usize) -> Self {
+        Budget { items: Some(items), ..self }
+    }
+
+    /// Give up once `deadline` has passed. The clock is only checked
+    /// every so often, so matching may run slightly past it.
+    pub fn deadline(self, deadline: Instant) -> Self {
+        Budget { deadline: Some(deadline), ..self }
+    }
+
+    /// Give up once `timeout` has passed, starting now.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+}
+
+/// How many items are shifted between checks of the clock.
+const CLOCK_INTERVAL: usize = 256;
+
+/// Matching gave up because its `Budget` ran out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    /// How many items had been shifted when matching gave up.
+    pub consumed: usize,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "matching budget exceeded after {} items", self.consumed)
+    }
+}
+
+impl Error for BudgetExceeded {}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero + One,
+    R: Regex<T, M>,
+{
+    /// Like `over`, but give up with `BudgetExceeded` once `budget`
+    /// runs out. Either way, the grammar is reset afterward.
+    pub fn over_within<I>(&mut self, over: I, budget: Budget) -> Result<M, BudgetExceeded> where
+        I: IntoIterator<Item=T>,
+    {
+        let mut result = None;
+        for (consumed, c) in over.into_iter().enumerate() {
+            let out_of_items = budget.items.is_some_and(|items| consumed >= items);
+            let out_of_time = consumed % CLOCK_INTERVAL == 0
+                && budget.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            if out_of_items || out_of_time {
+                self.reset();
+                return Err(BudgetExceeded { consumed });
+            }
+            let mark = if result.is_none() { one() } else { zero() };
+            result = Some(self.shift(&c, mark));
+        }
+        let result = match result {
+            Some(result) => result,
+            None => return Ok(if self.empty() { one() } else { zero() }),
+        };
+        self.reset();
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn items() {
+        let mut re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a'));
+        let budget = Budget::default().max_items(3);
+        assert_eq!(re.over_within("aaa".chars(), budget), Ok(Match::one()));
+        assert_eq!(re.over_within("aaaa".chars(), budget), Err(BudgetExceeded { consumed: 3 }));
+        assert!(!re.active());
+        assert_eq!(re.over_within("".chars(), Budget::default().max_items(0)), Ok(Match::one()));
+    }
+
+    #[test]
+    fn deadline() {
+        let mut re: AnyRegex<char, Match, _> = many(is(|_| true));
+        let passed = Budget::default().deadline(Instant::now());
+        assert_eq!(re.over_within("abc".chars(), passed), Err(BudgetExceeded { consumed: 0 }));
+        let later = Budget::default().timeout(Duration::from_secs(60));
+        assert_eq!(re.over_within("abc".chars(), later), Ok(Match::one()));
+    }
+}
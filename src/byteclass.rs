@@ -0,0 +1,178 @@
+//! Sets of bytes, and fast scanning for them.
+//!
+//! A leaf of a byte grammar is an arbitrary predicate, but there are
+//! only 256 bytes, so it can be evaluated once for each of them and
+//! stored as a bitmap. Scanning a haystack for the next byte in the set
+//! is then a table lookup per byte instead of a call through the leaf.
+//!
+//! With the `simd` feature enabled on x86-64 processors that support
+//! SSSE3, scanning checks sixteen bytes at a time using the nibble
+//! lookup technique: the low four bits of each byte select a row of a
+//! table using `pshufb`, and the high four bits select a bit from that
+//! row.
+
+use core::Leaf;
+use num_traits::Zero;
+use std::ops;
+use weights::recognize::Match;
+
+/// A set of byte values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct ByteClass {
+    bits: [u64; 4],
+    /// The same set as nibble lookup tables, kept up to date so that
+    /// scanning doesn't have to build them. Row `lo` of `low` has bit
+    /// `hi` set if the byte with those nibbles is in the set, for `hi`
+    /// below 8; `high` covers the rest.
+    low: [u8; 16],
+    high: [u8; 16],
+}
+
+impl ByteClass {
+    /// The bytes for which `leaf` has a non-zero weight.
+    pub fn new(leaf: &dyn Leaf<u8, Match>) -> Self {
+        let mut class = ByteClass::default();
+        for b in 0..=255u8 {
+            if !leaf.weigh(&b).is_zero() {
+                class.insert(b);
+            }
+        }
+        class
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut class = ByteClass::default();
+        for &b in bytes {
+            class.insert(b);
+        }
+        class
+    }
+
+    pub fn insert(&mut self, b: u8) {
+        self.bits[b as usize / 64] |= 1 << (b % 64);
+        let (hi, lo) = (b >> 4, (b & 15) as usize);
+        if hi < 8 {
+            self.low[lo] |= 1 << hi;
+        } else {
+            self.high[lo] |= 1 << (hi - 8);
+        }
+    }
+
+    pub fn contains(&self, b: u8) -> bool {
+        self.bits[b as usize / 64] & (1 << (b % 64)) != 0
+    }
+
+    /// How many bytes are in the set.
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool { self.bits == [0; 4] }
+
+    /// The bytes in the set, in increasing order.
+    pub fn bytes(&self) -> Vec<u8> {
+        (0..=255u8).filter(|&b| self.contains(b)).collect()
+    }
+
+    /// Find the first position in `haystack` holding a byte in the set.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("ssse3") {
+                return unsafe { x86::find(self, haystack) };
+            }
+        }
+        haystack.iter().position(|&b| self.contains(b))
+    }
+}
+
+impl ops::BitOr for ByteClass {
+    type Output = ByteClass;
+    fn bitor(mut self, other: ByteClass) -> ByteClass {
+        for (word, other) in self.bits.iter_mut().zip(&other.bits) {
+            *word |= other;
+        }
+        for (row, other) in self.low.iter_mut().chain(&mut self.high).zip(other.low.iter().chain(&other.high)) {
+            *row |= other;
+        }
+        self
+    }
+}
+
+impl ops::Not for ByteClass {
+    type Output = ByteClass;
+    fn not(mut self) -> ByteClass {
+        for word in &mut self.bits {
+            *word = !*word;
+        }
+        // Each bit of the tables stands for exactly one byte.
+        for row in self.low.iter_mut().chain(&mut self.high) {
+            *row = !*row;
+        }
+        self
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod x86 {
+    use super::ByteClass;
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn find(class: &ByteClass, haystack: &[u8]) -> Option<usize> {
+        let low_table = _mm_loadu_si128(class.low.as_ptr() as *const __m128i);
+        let high_table = _mm_loadu_si128(class.high.as_ptr() as *const __m128i);
+        let bit_for_nibble = _mm_setr_epi8(1, 2, 4, 8, 16, 32, 64, -128, 1, 2, 4, 8, 16, 32, 64, -128);
+        let nibble = _mm_set1_epi8(15);
+        let seven = _mm_set1_epi8(7);
+        let zero = _mm_setzero_si128();
+
+        let mut chunks = haystack.chunks_exact(16);
+        let mut at = 0;
+        for chunk in &mut chunks {
+            let bytes = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            let lo = _mm_and_si128(bytes, nibble);
+            let hi = _mm_and_si128(_mm_srli_epi16(bytes, 4), nibble);
+            let is_high = _mm_cmpgt_epi8(hi, seven);
+            let row = _mm_or_si128(
+                _mm_and_si128(is_high, _mm_shuffle_epi8(high_table, lo)),
+                _mm_andnot_si128(is_high, _mm_shuffle_epi8(low_table, lo)));
+            let hits = _mm_and_si128(row, _mm_shuffle_epi8(bit_for_nibble, hi));
+            let misses = _mm_movemask_epi8(_mm_cmpeq_epi8(hits, zero));
+            let found = !misses & 0xffff;
+            if found != 0 {
+                return Some(at + found.trailing_zeros() as usize);
+            }
+            at += 16;
+        }
+        chunks.remainder().iter().position(|&b| class.contains(b)).map(|p| at + p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn from_leaf() {
+        let digit: AnyRegex<u8, Match, _> = is(|&c: &u8| c.is_ascii_digit());
+        let nfa = digit.to_nfa().ok().unwrap();
+        let class = ByteClass::new(nfa.states()[1].test().unwrap());
+        assert_eq!(class.bytes(), b"0123456789");
+        assert_eq!(class.len(), 10);
+        assert_eq!((!class).len(), 246);
+        assert_eq!(class.find(b"abc123"), Some(3));
+        assert_eq!((!class).find(b"123abc"), Some(3));
+    }
+
+    quickcheck! {
+        fn find_matches_scalar(members : Vec<u8>, haystack : Vec<u8>) -> bool {
+            let class = ByteClass::from_bytes(&members);
+            // Repeat the haystack so the vectorized loop gets exercised.
+            let haystack: Vec<u8> = haystack.iter().cycle().take(haystack.len() * 5).cloned().collect();
+            class.find(&haystack) == haystack.iter().position(|b| members.contains(b)) &&
+                (!class).find(&haystack) == haystack.iter().position(|b| !members.contains(b))
+        }
+    }
+}
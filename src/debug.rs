@@ -0,0 +1,118 @@
+//! Showing a grammar's state while it matches.
+//!
+//! When a grammar doesn't match what you expected, it helps to see
+//! where the marks are after each item. `debug_state` lists the
+//! combinator tree one node per line, indented by depth, along with
+//! whether each node is active and the marks held by `Sequence` and
+//! `Many` nodes, and the match attempts complements and intersections
+//! are following. `AnyRegex` uses the same format for `Debug`.
+
+use core::{AnyRegex, Describe, Node};
+use std::fmt::{self, Debug, Write};
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Debug,
+    R: Describe<T, M>,
+{
+    /// Describe this grammar's structure and current state.
+    pub fn debug_state(&self) -> String {
+        debug_state(self)
+    }
+}
+
+/// Like `AnyRegex::debug_state`, but for any grammar node.
+pub fn debug_state<T, M: Debug>(re: &dyn Describe<T, M>) -> String {
+    let mut out = String::new();
+    line(re, 0, &mut out);
+    out
+}
+
+fn line<T, M: Debug>(re: &dyn Describe<T, M>, depth: usize, out: &mut String) {
+    let (name, mark, children) = match re.describe() {
+        Node::Empty => ("empty".to_string(), None, vec![]),
+        Node::Is(_) => ("is".to_string(), None, vec![]),
+        Node::Label(text, inner) => (format!("label {:?}", text), None, vec![inner]),
+        Node::Not(inner) => ("not".to_string(), None, vec![inner]),
+        Node::Or(left, right) => ("or".to_string(), None, vec![left, right]),
+        Node::And(left, right) => ("and".to_string(), None, vec![left, right]),
+        Node::Sequence(left, right, mark) => ("sequence".to_string(), Some(mark), vec![left, right]),
+        Node::Many(inner, mark) => ("many".to_string(), Some(mark), vec![inner]),
+        Node::Delay => ("delay".to_string(), None, vec![]),
+    };
+
+    write!(out, "{:1$}{2}", "", depth * 2, name).unwrap();
+    if re.activity() == Some(true) {
+        out.push_str(" (active)");
+    }
+    if let Some(mark) = mark {
+        write!(out, " {:?}", mark).unwrap();
+    }
+    out.push('\n');
+
+    for child in children {
+        line(child, depth + 1, out);
+    }
+    // Complements and intersections follow each match attempt with a
+    // copy of their children, which are shown after the originals.
+    if let Some(state) = re.attempts() {
+        for (weight, attempt) in state.attempts {
+            writeln!(out, "{:1$}attempt {2:?}", "", depth * 2 + 2, weight).unwrap();
+            line(attempt, depth + 2, out);
+        }
+    }
+}
+
+impl<T, M, R> Debug for AnyRegex<T, M, R> where
+    M: Debug,
+    R: Describe<T, M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&debug_state(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use num_traits::{zero, one};
+
+    #[test]
+    fn marks_and_activity() {
+        let mut re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a')).label("a*") + is(|&c| c == 'b');
+        assert_eq!(re.debug_state(), "\
+sequence Match(false)
+  label \"a*\"
+    many Match(false)
+      is
+  is
+");
+        re.shift(&'a', one());
+        re.shift(&'a', zero());
+        assert_eq!(format!("{:?}", re), "\
+sequence (active) Match(true)
+  label \"a*\" (active)
+    many (active) Match(true)
+      is
+  is
+");
+    }
+
+    #[test]
+    fn complement_attempts() {
+        let mut re: AnyRegex<char, Match, _> = !(is(|&c| c == 'a') + many(is(|&c| c == 'b')));
+        re.shift(&'a', one());
+        assert_eq!(re.debug_state(), "\
+not (active)
+  sequence Match(false)
+    is
+    many Match(false)
+      is
+  attempt Match(true)
+    not
+      sequence (active) Match(true)
+        is
+        many Match(false)
+          is
+");
+    }
+}
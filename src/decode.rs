@@ -0,0 +1,370 @@
+//! Decoding text into `char`s while a grammar consumes it.
+//!
+//! Grammars over `char`s can run directly on encoded buffers by passing
+//! one of these decoders to `over`, instead of transcoding the whole
+//! input first. Each decoder takes an `Invalid` policy for input that
+//! doesn't encode any character.
+//!
+//! Input that arrives in pieces can be decoded with `Utf8Decoder`,
+//! which holds back a character split across two chunks until the rest
+//! of it arrives, so the grammar can be shifted chunk by chunk.
+
+use std::iter::Peekable;
+
+/// What a decoder does with input that isn't a valid encoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Invalid {
+    /// Produce U+FFFD REPLACEMENT CHARACTER in its place.
+    Replace,
+    /// Leave it out.
+    Skip,
+    /// End the decoded input there, and record where it was.
+    Stop,
+}
+
+/// The characters encoded in UTF-16 code units; see `utf16`.
+pub struct Utf16<I: Iterator<Item=u16>> {
+    units: Peekable<I>,
+    invalid: Invalid,
+    position: usize,
+    stopped: Option<usize>,
+}
+
+/// Decode the UTF-16 `units`, combining surrogate pairs, and handling
+/// unpaired surrogates according to `invalid`.
+pub fn utf16<I: IntoIterator<Item=u16>>(units: I, invalid: Invalid) -> Utf16<I::IntoIter> {
+    Utf16 { units: units.into_iter().peekable(), invalid, position: 0, stopped: None }
+}
+
+impl<I: Iterator<Item=u16>> Utf16<I> {
+    /// If decoding stopped at an unpaired surrogate under
+    /// `Invalid::Stop`, the offset of that code unit.
+    pub fn stopped_at(&self) -> Option<usize> { self.stopped }
+}
+
+impl<I: Iterator<Item=u16>> Iterator for Utf16<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if self.stopped.is_some() {
+                return None;
+            }
+            let start = self.position;
+            let unit = self.units.next()?;
+            self.position += 1;
+            let decoded = match unit {
+                0xD800..=0xDBFF => match self.units.peek() {
+                    Some(&low) if (0xDC00..=0xDFFF).contains(&low) => {
+                        self.units.next();
+                        self.position += 1;
+                        let code = 0x10000 + ((u32::from(unit) - 0xD800) << 10) + (u32::from(low) - 0xDC00);
+                        ::std::char::from_u32(code)
+                    }
+                    _ => None,
+                },
+                0xDC00..=0xDFFF => None,
+                _ => ::std::char::from_u32(u32::from(unit)),
+            };
+            match (decoded, self.invalid) {
+                (Some(c), _) => return Some(c),
+                (None, Invalid::Replace) => return Some('\u{FFFD}'),
+                (None, Invalid::Skip) => {}
+                (None, Invalid::Stop) => self.stopped = Some(start),
+            }
+        }
+    }
+}
+
+/// Decodes UTF-8 one chunk at a time, for input that arrives in
+/// pieces. Like most decoders, it treats each maximal prefix of a valid
+/// sequence as one invalid sequence, and rejects overlong encodings and
+/// surrogates.
+#[derive(Clone, Debug)]
+pub struct Utf8Decoder {
+    invalid: Invalid,
+    /// Bytes consumed so far.
+    position: usize,
+    /// Where the sequence in progress started.
+    start: usize,
+    code: u32,
+    needed: u8,
+    seen: u8,
+    /// The range the next continuation byte must fall in.
+    lower: u8,
+    upper: u8,
+    stopped: Option<usize>,
+}
+
+enum Step {
+    Char(char),
+    Pending,
+    Invalid,
+}
+
+impl Utf8Decoder {
+    pub fn new(invalid: Invalid) -> Self {
+        Utf8Decoder { invalid, position: 0, start: 0, code: 0, needed: 0, seen: 0, lower: 0x80, upper: 0xBF, stopped: None }
+    }
+
+    /// The characters completed by `chunk`, following any bytes held
+    /// back from earlier chunks. A character which isn't complete by
+    /// the end of the chunk is held back in turn.
+    pub fn feed<'a>(&'a mut self, chunk: &'a [u8]) -> Chunk<'a> {
+        Chunk { decoder: self, chunk, next: 0 }
+    }
+
+    /// End the input, returning U+FFFD if a character was left
+    /// incomplete and invalid input is being replaced.
+    pub fn finish(&mut self) -> Option<char> {
+        if self.needed == 0 {
+            return None;
+        }
+        // Forget the whole sequence, including the range its next byte
+        // had to fall in, so the decoder can start on another input.
+        self.reset();
+        self.resolve(Step::Invalid)
+    }
+
+    /// If decoding stopped at invalid input under `Invalid::Stop`, the
+    /// offset of its first byte.
+    pub fn stopped_at(&self) -> Option<usize> { self.stopped }
+
+    /// Decode one byte, reporting whether it was used up. A byte which
+    /// interrupts a sequence ends it, and then must be decoded again.
+    fn step(&mut self, b: u8) -> (Step, bool) {
+        if self.needed == 0 {
+            self.start = self.position;
+            self.position += 1;
+            let (needed, code) = match b {
+                0x00..=0x7F => return (Step::Char(b as char), true),
+                0xC2..=0xDF => (1, b & 0x1F),
+                0xE0..=0xEF => {
+                    match b {
+                        0xE0 => self.lower = 0xA0,
+                        0xED => self.upper = 0x9F,
+                        _ => {}
+                    }
+                    (2, b & 0x0F)
+                }
+                0xF0..=0xF4 => {
+                    match b {
+                        0xF0 => self.lower = 0x90,
+                        0xF4 => self.upper = 0x8F,
+                        _ => {}
+                    }
+                    (3, b & 0x07)
+                }
+                _ => return (Step::Invalid, true),
+            };
+            self.needed = needed;
+            self.code = u32::from(code);
+            return (Step::Pending, true);
+        }
+        if b < self.lower || b > self.upper {
+            self.reset();
+            return (Step::Invalid, false);
+        }
+        self.position += 1;
+        self.lower = 0x80;
+        self.upper = 0xBF;
+        self.code = (self.code << 6) | u32::from(b & 0x3F);
+        self.seen += 1;
+        if self.seen < self.needed {
+            return (Step::Pending, true);
+        }
+        let code = self.code;
+        self.reset();
+        (Step::Char(::std::char::from_u32(code).unwrap()), true)
+    }
+
+    fn reset(&mut self) {
+        self.needed = 0;
+        self.seen = 0;
+        self.lower = 0x80;
+        self.upper = 0xBF;
+    }
+
+    /// Apply the policy for invalid input.
+    fn resolve(&mut self, step: Step) -> Option<char> {
+        match (step, self.invalid) {
+            (Step::Char(c), _) => Some(c),
+            (Step::Pending, _) | (Step::Invalid, Invalid::Skip) => None,
+            (Step::Invalid, Invalid::Replace) => Some('\u{FFFD}'),
+            (Step::Invalid, Invalid::Stop) => {
+                self.stopped = Some(self.start);
+                None
+            }
+        }
+    }
+}
+
+/// The characters completed by one chunk; see `Utf8Decoder::feed`.
+pub struct Chunk<'a> {
+    decoder: &'a mut Utf8Decoder,
+    chunk: &'a [u8],
+    next: usize,
+}
+
+impl<'a> Iterator for Chunk<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        while self.decoder.stopped.is_none() {
+            let &b = self.chunk.get(self.next)?;
+            let (step, consumed) = self.decoder.step(b);
+            if consumed {
+                self.next += 1;
+            }
+            if let Some(c) = self.decoder.resolve(step) {
+                return Some(c);
+            }
+        }
+        None
+    }
+}
+
+/// The characters encoded in UTF-8 bytes; see `utf8`.
+pub struct Utf8<I: Iterator<Item=u8>> {
+    bytes: I,
+    decoder: Utf8Decoder,
+    /// A byte which interrupted a sequence, to be decoded again.
+    retry: Option<u8>,
+    finished: bool,
+}
+
+/// Decode the UTF-8 `bytes`, handling invalid sequences according to
+/// `invalid`.
+pub fn utf8<I: IntoIterator<Item=u8>>(bytes: I, invalid: Invalid) -> Utf8<I::IntoIter> {
+    Utf8 { bytes: bytes.into_iter(), decoder: Utf8Decoder::new(invalid), retry: None, finished: false }
+}
+
+impl<I: Iterator<Item=u8>> Utf8<I> {
+    /// If decoding stopped at invalid input under `Invalid::Stop`, the
+    /// offset of its first byte.
+    pub fn stopped_at(&self) -> Option<usize> { self.decoder.stopped_at() }
+}
+
+impl<I: Iterator<Item=u8>> Iterator for Utf8<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        while self.decoder.stopped.is_none() && !self.finished {
+            let b = match self.retry.take().or_else(|| self.bytes.next()) {
+                Some(b) => b,
+                None => {
+                    self.finished = true;
+                    return self.decoder.finish();
+                }
+            };
+            let (step, consumed) = self.decoder.step(b);
+            if !consumed {
+                self.retry = Some(b);
+            }
+            if let Some(c) = self.decoder.resolve(step) {
+                return Some(c);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use num_traits::{Zero, zero, one};
+
+    fn units(text: &str) -> Vec<u16> { text.encode_utf16().collect() }
+
+    quickcheck! {
+        fn utf16_round_trip(text : String) -> bool {
+            utf16(units(&text), Invalid::Stop).collect::<String>() == text
+        }
+    }
+
+    #[test]
+    fn utf16_invalid() {
+        let broken = [0x61, 0xD83D, 0x62, 0xDE00, 0xD83D, 0xDE00];
+        assert_eq!(utf16(broken.iter().cloned(), Invalid::Replace).collect::<String>(), "a\u{FFFD}b\u{FFFD}😀");
+        assert_eq!(utf16(broken.iter().cloned(), Invalid::Skip).collect::<String>(), "ab😀");
+        let mut stopped = utf16(broken.iter().cloned(), Invalid::Stop);
+        assert_eq!(stopped.by_ref().collect::<String>(), "a");
+        assert_eq!(stopped.stopped_at(), Some(1));
+    }
+
+    quickcheck! {
+        fn utf8_round_trip(text : String) -> bool {
+            utf8(text.bytes(), Invalid::Stop).collect::<String>() == text
+        }
+
+        fn utf8_like_std(bytes : Vec<u8>) -> bool {
+            utf8(bytes.iter().cloned(), Invalid::Replace).collect::<String>() == String::from_utf8_lossy(&bytes)
+        }
+
+        fn utf8_chunks(text : String, split : usize) -> bool {
+            let bytes = text.as_bytes();
+            let split = split % (bytes.len() + 1);
+            let mut decoder = Utf8Decoder::new(Invalid::Stop);
+            let mut decoded: String = decoder.feed(&bytes[..split]).collect();
+            decoded.extend(decoder.feed(&bytes[split..]));
+            decoded.extend(decoder.finish());
+            decoded == text
+        }
+    }
+
+    #[test]
+    fn utf8_invalid() {
+        let broken = b"a\xF0\x9Fb\xED\xA0\x80\xE2\x82";
+        assert_eq!(utf8(broken.iter().cloned(), Invalid::Replace).collect::<String>(), "a\u{FFFD}b\u{FFFD}\u{FFFD}\u{FFFD}\u{FFFD}");
+        assert_eq!(utf8(broken.iter().cloned(), Invalid::Skip).collect::<String>(), "ab");
+        let mut stopped = utf8(broken.iter().cloned(), Invalid::Stop);
+        assert_eq!(stopped.by_ref().collect::<String>(), "a");
+        assert_eq!(stopped.stopped_at(), Some(1));
+
+        // An incomplete character at the end of a stream is invalid.
+        let mut decoder = Utf8Decoder::new(Invalid::Stop);
+        assert_eq!(decoder.feed(b"ab\xE2\x82").collect::<String>(), "ab");
+        assert_eq!(decoder.finish(), None);
+        assert_eq!(decoder.stopped_at(), Some(2));
+    }
+
+    #[test]
+    fn utf8_reused() {
+        let mut decoder = Utf8Decoder::new(Invalid::Replace);
+        assert_eq!(decoder.feed(b"\xE1\x80").collect::<String>(), "");
+        assert_eq!(decoder.finish(), Some('\u{FFFD}'));
+        assert_eq!(decoder.feed(b"\xE1\x80\x80").collect::<String>(), "\u{1000}");
+        assert_eq!(decoder.finish(), None);
+
+        // E0 limits the byte after it to A0..BF, which mustn't carry over.
+        assert_eq!(decoder.feed(b"\xE0").collect::<String>(), "");
+        assert_eq!(decoder.finish(), Some('\u{FFFD}'));
+        assert_eq!(decoder.feed(b"\xE1\x80\x80").collect::<String>(), "\u{1000}");
+    }
+
+    #[test]
+    fn utf8_streaming() {
+        let mut re = many(is(|&c: &char| c != 'x')) + is(|&c| c == '😀');
+        let bytes = "ab😀".as_bytes();
+        let mut decoder = Utf8Decoder::new(Invalid::Stop);
+        let mut result: Match = zero();
+        let mut mark = one();
+        for chunk in bytes.chunks(3) {
+            for c in decoder.feed(chunk) {
+                result = re.shift(&c, mark);
+                mark = zero();
+            }
+        }
+        assert!(decoder.finish().is_none());
+        assert!(!result.is_zero());
+    }
+
+    #[test]
+    fn utf16_matching() {
+        let mut re = many(is(|&c: &char| c != 'x')) + is(|&c| c == '😀');
+        assert!(has_match(&mut re, utf16(units("ab😀"), Invalid::Stop)));
+        assert!(!has_match(&mut re, utf16(units("ax😀"), Invalid::Stop)));
+        assert!(has_match(&mut re, utf8("ab😀".bytes(), Invalid::Stop)));
+    }
+}
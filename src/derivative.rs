@@ -0,0 +1,198 @@
+//! An independent recognizer based on Brzozowski derivatives.
+//!
+//! The main engine pushes marks through the grammar, which is fast but
+//! subtle, especially around complement, intersection, and the
+//! `Sequence` bookkeeping. Derivatives are slow but easy to check by
+//! hand: after each item, the grammar is rewritten into the grammar for
+//! whatever is left to match. Running both and comparing the answers is
+//! a cheap way to catch bugs in either.
+//!
+//! Only `Match` weights are supported, and `delay`ed grammars can't be
+//! inspected, so recursive grammars are rejected.
+
+use core::{AnyRegex, Describe, Leaf, Node, Regex};
+use num_traits::Zero;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+use syntax::Inexpressible;
+use weights::recognize::{has_match, Match};
+
+enum Expr<'a, T: 'a> {
+    /// Matches nothing at all, not even the empty string.
+    Void,
+    Empty,
+    Is(&'a dyn Leaf<T, Match>),
+    Not(Rc<Expr<'a, T>>),
+    Or(Rc<Expr<'a, T>>, Rc<Expr<'a, T>>),
+    And(Rc<Expr<'a, T>>, Rc<Expr<'a, T>>),
+    Sequence(Rc<Expr<'a, T>>, Rc<Expr<'a, T>>),
+    Many(Rc<Expr<'a, T>>),
+}
+
+/// A grammar in the form the derivative engine works with.
+pub struct Derivative<'a, T: 'a> {
+    expr: Rc<Expr<'a, T>>,
+}
+
+impl<'a, T> Derivative<'a, T> {
+    /// Convert a grammar, borrowing its leaves.
+    pub fn new(re: &'a dyn Describe<T, Match>) -> Result<Self, Inexpressible> {
+        Ok(Derivative { expr: convert(re)? })
+    }
+
+    /// Check whether the entire input matches, by taking one derivative
+    /// per item.
+    pub fn is_match<I>(&self, over: I) -> bool where
+        I: IntoIterator<Item=T>,
+    {
+        let mut expr = self.expr.clone();
+        for c in over {
+            expr = derive(&expr, &c);
+            if let Expr::Void = *expr {
+                return false;
+            }
+        }
+        nullable(&expr)
+    }
+}
+
+/// Why `check` couldn't confirm an answer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CheckError {
+    /// The derivative engine can't handle this grammar.
+    Unsupported(Inexpressible),
+    /// The two engines disagreed about whether the input matches.
+    Divergence { shift: bool, derivative: bool },
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CheckError::Unsupported(ref reason) => reason.fmt(f),
+            CheckError::Divergence { shift, derivative } =>
+                write!(f, "shift engine says {} but derivatives say {}", shift, derivative),
+        }
+    }
+}
+
+impl Error for CheckError {}
+
+/// Match `input` with both the usual engine and derivatives, returning
+/// the answer if they agree.
+pub fn check<T, R>(re: &mut AnyRegex<T, Match, R>, input: &[T]) -> Result<bool, CheckError> where
+    T: Clone,
+    R: Regex<T, Match> + Describe<T, Match>,
+{
+    let shift = has_match(re, input.iter().cloned());
+    let derivative = Derivative::new(re)
+        .map_err(CheckError::Unsupported)?
+        .is_match(input.iter().cloned());
+    if shift == derivative {
+        Ok(shift)
+    } else {
+        Err(CheckError::Divergence { shift, derivative })
+    }
+}
+
+fn convert<'a, T>(re: &'a dyn Describe<T, Match>) -> Result<Rc<Expr<'a, T>>, Inexpressible> {
+    Ok(Rc::new(match re.describe() {
+        Node::Empty => Expr::Empty,
+        Node::Is(leaf) => Expr::Is(leaf),
+        Node::Label(_, inner) => return convert(inner),
+        Node::Not(inner) => Expr::Not(convert(inner)?),
+        Node::Or(left, right) => Expr::Or(convert(left)?, convert(right)?),
+        Node::And(left, right) => Expr::And(convert(left)?, convert(right)?),
+        Node::Sequence(left, right, _) => Expr::Sequence(convert(left)?, convert(right)?),
+        Node::Many(inner, _) => Expr::Many(convert(inner)?),
+        Node::Delay => return Err(Inexpressible::Recursion),
+    }))
+}
+
+fn nullable<T>(expr: &Expr<T>) -> bool {
+    match *expr {
+        Expr::Void | Expr::Is(_) => false,
+        Expr::Empty | Expr::Many(_) => true,
+        Expr::Not(ref inner) => !nullable(inner),
+        Expr::Or(ref left, ref right) => nullable(left) || nullable(right),
+        Expr::And(ref left, ref right) | Expr::Sequence(ref left, ref right) =>
+            nullable(left) && nullable(right),
+    }
+}
+
+fn derive<'a, T>(expr: &Rc<Expr<'a, T>>, c: &T) -> Rc<Expr<'a, T>> {
+    match **expr {
+        Expr::Void | Expr::Empty => Rc::new(Expr::Void),
+        Expr::Is(leaf) =>
+            Rc::new(if leaf.weigh(c).is_zero() { Expr::Void } else { Expr::Empty }),
+        Expr::Not(ref inner) => Rc::new(Expr::Not(derive(inner, c))),
+        Expr::Or(ref left, ref right) => or(derive(left, c), derive(right, c)),
+        Expr::And(ref left, ref right) => and(derive(left, c), derive(right, c)),
+        Expr::Sequence(ref left, ref right) => {
+            let through_left = sequence(derive(left, c), right.clone());
+            if nullable(left) {
+                or(through_left, derive(right, c))
+            } else {
+                through_left
+            }
+        }
+        Expr::Many(ref inner) => sequence(derive(inner, c), expr.clone()),
+    }
+}
+
+// Smart constructors which keep derivatives from growing without bound
+// in the common cases.
+
+fn or<'a, T>(left: Rc<Expr<'a, T>>, right: Rc<Expr<'a, T>>) -> Rc<Expr<'a, T>> {
+    match (&*left, &*right) {
+        (&Expr::Void, _) => right,
+        (_, &Expr::Void) => left,
+        _ => Rc::new(Expr::Or(left, right)),
+    }
+}
+
+fn and<'a, T>(left: Rc<Expr<'a, T>>, right: Rc<Expr<'a, T>>) -> Rc<Expr<'a, T>> {
+    match (&*left, &*right) {
+        (&Expr::Void, _) => left,
+        (_, &Expr::Void) => right,
+        _ => Rc::new(Expr::And(left, right)),
+    }
+}
+
+fn sequence<'a, T>(left: Rc<Expr<'a, T>>, right: Rc<Expr<'a, T>>) -> Rc<Expr<'a, T>> {
+    match (&*left, &*right) {
+        (&Expr::Void, _) => left,
+        (_, &Expr::Void) => right,
+        (&Expr::Empty, _) => right,
+        (_, &Expr::Empty) => left,
+        _ => Rc::new(Expr::Sequence(left, right)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    quickcheck! {
+        fn boolean_operators(to_match : Vec<u8>) -> bool {
+            let mut re = !(is(|&c: &u8| c % 3 == 0) + many(is(|&c: &u8| c % 2 == 0)))
+                & ((is(|&c: &u8| c > 100) + many(is(|_: &u8| true))) | empty());
+            check(&mut re, &to_match).is_ok()
+        }
+
+        fn sequences(to_match : String) -> bool {
+            let upper = is(|&c| char::is_uppercase(c));
+            let lower = is(|&c| char::is_lowercase(c));
+            let space = is(|&c| c == ' ');
+            let mut re = many(many(upper) + (lower | empty())) + many(space);
+            check(&mut re, &to_match.chars().collect::<Vec<_>>()).is_ok()
+        }
+    }
+
+    #[test]
+    fn recursion() {
+        let mut re = delay(|| is(|&b| b).boxed());
+        assert_eq!(check(&mut re, &[true]), Err(CheckError::Unsupported(Inexpressible::Recursion)));
+    }
+}
@@ -0,0 +1,636 @@
+//! Lazily-built deterministic automata for fast recognition.
+//!
+//! When all you need to know is whether the input matches, each step of
+//! the `Nfa` only depends on which of its states are marked. `LazyDfa`
+//! remembers those sets of states as it discovers them, along with the
+//! transitions between them, so that after warming up, each input item
+//! costs a single table lookup.
+//!
+//! The number of sets can grow exponentially with the size of the
+//! grammar, so the cache has a cap. Once it's full, sets that aren't
+//! already cached are stepped through the `Nfa` directly, which is
+//! slower but still correct.
+//!
+//! For other weights, `Dfa` determinizes the whole automaton up front
+//! over a fixed alphabet. That only terminates for semirings where a
+//! finite number of distinct weights can arise, such as `Match` or
+//! counting with saturation.
+
+use core::Leaf;
+use nfa::Nfa;
+use num_traits::{Zero, zero, One, one};
+#[cfg(feature = "rand")]
+use rand::Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::mem::{replace, take};
+use std::ops;
+use weights::recognize::Match;
+
+/// A recognizer which determinizes an `Nfa` as it runs.
+pub struct LazyDfa<'a, T: 'a> {
+    nfa: Nfa<'a, T, Match>,
+    sets: Vec<Vec<usize>>,
+    accepting: Vec<bool>,
+    ids: HashMap<Vec<usize>, usize>,
+    transitions: HashMap<(usize, T), usize>,
+    max_states: usize,
+}
+
+enum Current {
+    Cached(usize),
+    Uncached(Vec<usize>),
+}
+
+impl<'a, T> LazyDfa<'a, T> where
+    T: Hash + Eq + Clone,
+{
+    /// Wrap `nfa`, caching at most `max_states` deterministic states.
+    pub fn new(nfa: Nfa<'a, T, Match>, max_states: usize) -> Self {
+        let mut dfa = LazyDfa {
+            nfa,
+            sets: Vec::new(),
+            accepting: Vec::new(),
+            ids: HashMap::new(),
+            transitions: HashMap::new(),
+            max_states,
+        };
+        // The start state is always cached, even if the cap is zero, so
+        // that every match begins from a known state.
+        dfa.insert(vec![0]);
+        dfa
+    }
+
+    /// Check whether the entire input matches the grammar.
+    pub fn is_match<I>(&mut self, over: I) -> bool where
+        I: IntoIterator<Item=T>,
+    {
+        let mut current = Current::Cached(0);
+        for c in over {
+            current = match current {
+                Current::Cached(id) => {
+                    if let Some(&next) = self.transitions.get(&(id, c.clone())) {
+                        Current::Cached(next)
+                    } else {
+                        let next = self.step(&self.sets[id], &c);
+                        match self.intern(next) {
+                            Ok(next) => {
+                                self.transitions.insert((id, c), next);
+                                Current::Cached(next)
+                            }
+                            Err(next) => Current::Uncached(next),
+                        }
+                    }
+                }
+                Current::Uncached(set) => {
+                    let next = self.step(&set, &c);
+                    match self.ids.get(&next) {
+                        Some(&next) => Current::Cached(next),
+                        None => Current::Uncached(next),
+                    }
+                }
+            };
+
+            // Nothing can match once every state is dead.
+            let dead = match current {
+                Current::Cached(id) => self.sets[id].is_empty(),
+                Current::Uncached(ref set) => set.is_empty(),
+            };
+            if dead {
+                return false;
+            }
+        }
+
+        match current {
+            Current::Cached(id) => self.accepting[id],
+            Current::Uncached(set) => self.is_accepting(&set),
+        }
+    }
+
+    /// How many deterministic states are currently cached.
+    pub fn cached_states(&self) -> usize { self.sets.len() }
+
+    fn step(&self, set: &[usize], c: &T) -> Vec<usize> {
+        let states = self.nfa.states();
+        let mut checked = vec![false; states.len()];
+        let mut next = Vec::new();
+        for &q in set {
+            for &p in states[q].next() {
+                if checked[p] {
+                    continue;
+                }
+                checked[p] = true;
+                if !states[p].test().unwrap().weigh(c).is_zero() {
+                    next.push(p);
+                }
+            }
+        }
+        next.sort();
+        next
+    }
+
+    fn is_accepting(&self, set: &[usize]) -> bool {
+        let states = self.nfa.states();
+        set.iter().any(|&q| states[q].is_final())
+    }
+
+    /// Look up the id for this set, adding it if there's room. If the
+    /// cache is full, hand the set back instead.
+    fn intern(&mut self, set: Vec<usize>) -> Result<usize, Vec<usize>> {
+        if let Some(&id) = self.ids.get(&set) {
+            return Ok(id);
+        }
+        if self.sets.len() >= self.max_states {
+            return Err(set);
+        }
+        Ok(self.insert(set))
+    }
+
+    fn insert(&mut self, set: Vec<usize>) -> usize {
+        let id = self.sets.len();
+        self.accepting.push(self.is_accepting(&set));
+        self.ids.insert(set.clone(), id);
+        self.sets.push(set);
+        id
+    }
+}
+
+/// A fully determinized weighted automaton over a fixed alphabet. Each
+/// input item costs one table lookup, and the weight of the whole input
+/// is read off the final state.
+pub struct Dfa<T, M> {
+    alphabet: HashMap<T, usize>,
+    /// The alphabet in its original order.
+    items: Vec<T>,
+    transitions: Vec<Vec<usize>>,
+    weights: Vec<M>,
+}
+
+/// Determinization gave up because the automaton needed more states
+/// than allowed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StateLimitExceeded;
+
+impl fmt::Display for StateLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("determinization exceeded its state limit")
+    }
+}
+
+impl Error for StateLimitExceeded {}
+
+impl<T, M> Dfa<T, M> where
+    T: Hash + Eq + Clone,
+    M: Zero + One + ops::Mul<Output=M> + Clone + Hash + Eq,
+{
+    /// Determinize `nfa` for inputs drawn from `alphabet`, building at
+    /// most `max_states` states.
+    ///
+    /// Each state is the vector of marks the `Nfa` would hold, so this
+    /// fails with `StateLimitExceeded` if the semiring can produce
+    /// unboundedly many distinct weights on this grammar.
+    pub fn new(nfa: &Nfa<T, M>, alphabet: &[T], max_states: usize) -> Result<Self, StateLimitExceeded> {
+        let states = nfa.states();
+        let mut start = vec![zero::<M>(); states.len()];
+        start[0] = one();
+
+        let mut ids = HashMap::new();
+        let mut pending = vec![start.clone()];
+        ids.insert(start, 0);
+        let mut dfa = Dfa {
+            alphabet: alphabet.iter().cloned().enumerate().map(|(i, c)| (c, i)).collect(),
+            items: alphabet.to_vec(),
+            transitions: Vec::new(),
+            weights: Vec::new(),
+        };
+
+        // States are numbered in the order they're discovered, which is
+        // also the order they're popped from the front of `pending`.
+        let mut next_pending = 0;
+        while next_pending < pending.len() {
+            let marks = take(&mut pending[next_pending]);
+            next_pending += 1;
+
+            let mut weight = zero();
+            for (mark, state) in marks.iter().zip(states) {
+                if state.is_final() {
+                    weight = weight + mark.clone();
+                }
+            }
+            dfa.weights.push(weight);
+
+            let mut row = Vec::with_capacity(alphabet.len());
+            for c in alphabet {
+                let next = step(nfa, &marks, c);
+                let id = match ids.get(&next) {
+                    Some(&id) => id,
+                    None => {
+                        if pending.len() >= max_states {
+                            return Err(StateLimitExceeded);
+                        }
+                        let id = pending.len();
+                        ids.insert(next.clone(), id);
+                        pending.push(next);
+                        id
+                    }
+                };
+                row.push(id);
+            }
+            dfa.transitions.push(row);
+        }
+        Ok(dfa)
+    }
+}
+
+impl<T, M> Dfa<T, M> where
+    T: Hash + Eq,
+    M: Clone,
+{
+    /// The weight of the entire input, or `None` if it contains an item
+    /// that isn't in the alphabet.
+    pub fn weigh<I>(&self, over: I) -> Option<M> where
+        I: IntoIterator<Item=T>,
+    {
+        let mut state = 0;
+        for c in over {
+            state = self.transitions[state][*self.alphabet.get(&c)?];
+        }
+        Some(self.weights[state].clone())
+    }
+
+    /// How many states the determinized automaton has.
+    pub fn state_count(&self) -> usize { self.weights.len() }
+}
+
+impl<T, M> Dfa<T, M> where
+    M: Zero,
+{
+    /// Whether every input this automaton gives a nonzero weight, `other`
+    /// does too. Both must have been built over the same alphabet, in
+    /// the same order. This walks the states of the product automaton
+    /// reachable from the start, looking for one where only `self`
+    /// accepts.
+    pub fn is_subset_of<N: Zero>(&self, other: &Dfa<T, N>) -> bool {
+        assert_eq!(self.alphabet.len(), other.alphabet.len(), "automata over different alphabets");
+        let mut seen = HashSet::new();
+        let mut pending = vec![(0, 0)];
+        seen.insert((0, 0));
+        while let Some((a, b)) = pending.pop() {
+            if !self.weights[a].is_zero() && other.weights[b].is_zero() {
+                return false;
+            }
+            for (&next_a, &next_b) in self.transitions[a].iter().zip(&other.transitions[b]) {
+                if seen.insert((next_a, next_b)) {
+                    pending.push((next_a, next_b));
+                }
+            }
+        }
+        true
+    }
+}
+
+impl<T, M> Dfa<T, M> where
+    T: Clone,
+    M: Zero,
+{
+    /// The shortest input given a nonzero weight, choosing the earliest
+    /// items in the alphabet among inputs of that length, or `None` if
+    /// no input is.
+    pub fn shortest_accepted(&self) -> Option<Vec<T>> {
+        // Breadth-first search, remembering the state and item each
+        // state was first reached from. The start state is never
+        // reached from anywhere else.
+        let mut seen = vec![false; self.weights.len()];
+        let mut reached_by = vec![(0, 0); self.weights.len()];
+        let mut pending = VecDeque::new();
+        seen[0] = true;
+        pending.push_back(0);
+        while let Some(state) = pending.pop_front() {
+            if !self.weights[state].is_zero() {
+                let mut path = Vec::new();
+                let mut at = state;
+                while at != 0 {
+                    let (from, item) = reached_by[at];
+                    path.push(self.items[item].clone());
+                    at = from;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for (item, &next) in self.transitions[state].iter().enumerate() {
+                if !seen[next] {
+                    seen[next] = true;
+                    reached_by[next] = (state, item);
+                    pending.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<T, M> Dfa<T, M> where
+    M: Zero,
+{
+    /// Every input given a nonzero weight, shortest first, and in
+    /// alphabet order within each length. Inputs which can't be extended
+    /// to an accepted one are never explored, so this ends if the
+    /// language is finite.
+    pub fn into_accepted(self) -> Accepted<T, M> {
+        // A state is live if an accepting state is reachable from it.
+        // Walk the transitions backward from the accepting states.
+        let mut incoming = vec![Vec::new(); self.weights.len()];
+        for (from, row) in self.transitions.iter().enumerate() {
+            for &to in row {
+                incoming[to].push(from);
+            }
+        }
+        let mut live = vec![false; self.weights.len()];
+        let mut pending = Vec::new();
+        for (state, weight) in self.weights.iter().enumerate() {
+            if !weight.is_zero() {
+                live[state] = true;
+                pending.push(state);
+            }
+        }
+        while let Some(state) = pending.pop() {
+            for &from in &incoming[state] {
+                if !live[from] {
+                    live[from] = true;
+                    pending.push(from);
+                }
+            }
+        }
+
+        let mut pending = VecDeque::new();
+        if live[0] {
+            pending.push_back((Vec::new(), 0));
+        }
+        Accepted { dfa: self, live, pending }
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<T, M> Dfa<T, M> where
+    T: Clone,
+    M: Zero,
+{
+    /// A random input of at most `max_len` items which is given a
+    /// nonzero weight, or `None` if there isn't one. Every such input is
+    /// equally likely, as long as there are fewer than `u64::MAX` of
+    /// them.
+    pub fn sample_accepted<G: Rng + ?Sized>(&self, max_len: usize, rng: &mut G) -> Option<Vec<T>> {
+        // How many accepted inputs of each length lead from each state,
+        // so that every choice can be weighted by the inputs it leaves
+        // open, and nothing needs to be retried.
+        let mut counts = vec![self.weights.iter().map(|w| if w.is_zero() { 0 } else { 1 }).collect::<Vec<u64>>()];
+        for len in 1..=max_len {
+            let shorter = &counts[len - 1];
+            let row = self.transitions.iter().map(|row| {
+                row.iter().fold(0u64, |total, &next| total.saturating_add(shorter[next]))
+            }).collect();
+            counts.push(row);
+        }
+
+        // Saturated counts are never more than the sum of the choices
+        // they stand for, so a choice is always found below.
+        let total = counts.iter().fold(0u64, |total, row| total.saturating_add(row[0]));
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rng.gen_range(0, total);
+        let mut len = 0;
+        while pick >= counts[len][0] {
+            pick -= counts[len][0];
+            len += 1;
+        }
+
+        let mut input = Vec::with_capacity(len);
+        let mut state = 0;
+        while len > 0 {
+            len -= 1;
+            let mut pick = rng.gen_range(0, counts[len + 1][state]);
+            for (item, &next) in self.transitions[state].iter().enumerate() {
+                if pick < counts[len][next] {
+                    input.push(self.items[item].clone());
+                    state = next;
+                    break;
+                }
+                pick -= counts[len][next];
+            }
+        }
+        Some(input)
+    }
+}
+
+/// The inputs a `Dfa` accepts, from `Dfa::into_accepted`.
+pub struct Accepted<T, M> {
+    dfa: Dfa<T, M>,
+    live: Vec<bool>,
+    /// Inputs to visit, as indexes into the alphabet, with the states
+    /// they lead to. Visiting in first-in, first-out order keeps them
+    /// sorted by length and then alphabetically.
+    pending: VecDeque<(Vec<usize>, usize)>,
+}
+
+impl<T, M> Iterator for Accepted<T, M> where
+    T: Clone,
+    M: Zero,
+{
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        while let Some((path, state)) = self.pending.pop_front() {
+            for (item, &next) in self.dfa.transitions[state].iter().enumerate() {
+                if self.live[next] {
+                    let mut longer = path.clone();
+                    longer.push(item);
+                    self.pending.push_back((longer, next));
+                }
+            }
+            if !self.dfa.weights[state].is_zero() {
+                return Some(path.into_iter().map(|i| self.dfa.items[i].clone()).collect());
+            }
+        }
+        None
+    }
+}
+
+impl<T, M> Dfa<T, M> where
+    M: Hash + Eq,
+{
+    /// Merge states which give the same weight to every possible
+    /// suffix, producing the smallest equivalent automaton.
+    pub fn minimize(self) -> Self {
+        // Moore's algorithm: start by splitting states by their own
+        // weight, then keep splitting until each state's transitions
+        // agree with the rest of its class. Classes are numbered in
+        // order of their first state, so the start state stays 0.
+        let mut classes = {
+            let mut ids = HashMap::new();
+            self.weights.iter().map(|w| {
+                let next = ids.len();
+                *ids.entry(w).or_insert(next)
+            }).collect::<Vec<_>>()
+        };
+        let mut count = classes.iter().max().map_or(0, |&c| c + 1);
+        loop {
+            let mut ids = HashMap::new();
+            let refined = self.transitions.iter().zip(&classes).map(|(row, &class)| {
+                let signature = (class, row.iter().map(|&t| classes[t]).collect::<Vec<_>>());
+                let next = ids.len();
+                *ids.entry(signature).or_insert(next)
+            }).collect::<Vec<_>>();
+            classes = refined;
+            if ids.len() == count {
+                break;
+            }
+            count = ids.len();
+        }
+
+        let mut transitions = vec![Vec::new(); count];
+        let mut weights = Vec::with_capacity(count);
+        for (state, (row, weight)) in self.transitions.into_iter().zip(self.weights).enumerate() {
+            // The first state of each class is its representative.
+            if classes[state] == weights.len() {
+                transitions[classes[state]] = row.into_iter().map(|t| classes[t]).collect();
+                weights.push(weight);
+            }
+        }
+        Dfa { alphabet: self.alphabet, items: self.items, transitions, weights }
+    }
+}
+
+fn step<T, M>(nfa: &Nfa<T, M>, marks: &[M], c: &T) -> Vec<M> where
+    M: Zero + ops::Mul<Output=M> + Clone,
+{
+    let states = nfa.states();
+    let mut incoming = vec![zero::<M>(); states.len()];
+    for (mark, state) in marks.iter().zip(states) {
+        if mark.is_zero() {
+            continue;
+        }
+        for &p in state.next() {
+            let old = replace(&mut incoming[p], zero());
+            incoming[p] = old + mark.clone();
+        }
+    }
+    for (mark, state) in incoming.iter_mut().zip(states) {
+        if !mark.is_zero() {
+            let old = replace(mark, zero());
+            *mark = old * state.test().unwrap().weigh(c);
+        }
+    }
+    incoming
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    quickcheck! {
+        fn agrees_with_grammar(to_match : Vec<u8>) -> bool {
+            let mut re = many(is(|&c: &u8| c % 3 == 0) + many(is(|&c: &u8| c % 2 == 0)))
+                + (is(|&c: &u8| c > 200) | empty());
+            let expected = has_match(&mut re, to_match.iter().cloned());
+            let mut dfa = LazyDfa::new(re.to_nfa().ok().unwrap(), 1000);
+            let first = dfa.is_match(to_match.iter().cloned());
+            let second = dfa.is_match(to_match.iter().cloned());
+            expected == first && expected == second
+        }
+
+        fn capped(to_match : Vec<u8>) -> bool {
+            let mut re = many(is(|&c: &u8| c % 3 == 0) | is(|&c: &u8| c % 2 == 0))
+                + is(|&c: &u8| c % 5 == 0);
+            let expected = has_match(&mut re, to_match.iter().cloned());
+            let mut dfa = LazyDfa::new(re.to_nfa().ok().unwrap(), 2);
+            let actual = dfa.is_match(to_match.iter().cloned());
+            dfa.cached_states() <= 2 && expected == actual
+        }
+    }
+
+    /// Counts derivations, but can't tell apart counts above three.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    struct Saturating(u8);
+
+    impl ::std::ops::Add for Saturating {
+        type Output = Saturating;
+        fn add(self, rhs: Saturating) -> Saturating { Saturating((self.0 + rhs.0).min(3)) }
+    }
+
+    impl ::std::ops::Mul for Saturating {
+        type Output = Saturating;
+        fn mul(self, rhs: Saturating) -> Saturating { Saturating((self.0 * rhs.0).min(3)) }
+    }
+
+    impl Zero for Saturating {
+        fn zero() -> Saturating { Saturating(0) }
+        fn is_zero(&self) -> bool { self.0 == 0 }
+    }
+
+    impl One for Saturating {
+        fn one() -> Saturating { Saturating(1) }
+    }
+
+    impl<T> ::core::IntoWithInput<T, Saturating> for Saturating {
+        fn into_with_input(self, _input: &T) -> Saturating { self }
+    }
+
+    fn saturating(b: bool) -> Saturating {
+        if b { one() } else { zero() }
+    }
+
+    quickcheck! {
+        fn determinized_counts(to_match : Vec<bool>) -> bool {
+            // Ambiguous, so the number of derivations grows with the
+            // number of `true`s, until it saturates.
+            let mut re = many(is(|&c| saturating(c)) | is(|&c| saturating(c)))
+                + many(is(|_: &bool| saturating(true)));
+            let expected = re.over(to_match.iter().cloned());
+            let dfa = Dfa::new(&re.to_nfa().ok().unwrap(), &[false, true], 100).unwrap();
+            Some(expected) == dfa.weigh(to_match.iter().cloned())
+        }
+    }
+
+    #[test]
+    fn determinized_limit() {
+        let re: AnyRegex<u8, Match, _> = many(is(|&c| c < 128)) + is(|&c| c == 0);
+        let nfa = re.to_nfa().ok().unwrap();
+        let alphabet: Vec<u8> = (0..=255).collect();
+        assert_eq!(Dfa::new(&nfa, &alphabet, 1).err(), Some(StateLimitExceeded));
+        let dfa = Dfa::new(&nfa, &alphabet, 10).ok().unwrap();
+        assert_eq!(dfa.weigh(vec![5, 0]).map(|m| m.is_zero()), Some(false));
+        assert_eq!(dfa.weigh(vec![200, 0]).map(|m| m.is_zero()), Some(true));
+    }
+
+    quickcheck! {
+        fn minimized(to_match : Vec<u8>) -> bool {
+            // Both alternatives accept the same language, so determinizing
+            // produces redundant states that minimization should merge.
+            let digit = |c: &u8| c.is_ascii_digit();
+            let mut re: AnyRegex<u8, Match, _> = many(is(digit) + is(digit))
+                | many(is(digit) + is(digit) + is(digit) + is(digit));
+            let alphabet = b"0123456789x".to_vec();
+            let dfa = Dfa::new(&re.to_nfa().ok().unwrap(), &alphabet, 100).unwrap();
+            let before = dfa.state_count();
+            let dfa = dfa.minimize();
+            let to_match: Vec<u8> = to_match.into_iter()
+                .map(|c| alphabet[c as usize % alphabet.len()])
+                .collect();
+            let expected = re.over(to_match.iter().cloned());
+            dfa.state_count() < before && Some(expected) == dfa.weigh(to_match)
+        }
+    }
+
+    #[test]
+    fn empty_input() {
+        let re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a'));
+        let mut dfa = LazyDfa::new(re.to_nfa().ok().unwrap(), 10);
+        assert!(dfa.is_match(None));
+        assert!(dfa.is_match("aaa".chars()));
+        assert!(!dfa.is_match("ab".chars()));
+    }
+}
@@ -0,0 +1,100 @@
+//! Cross-checking matches against the `regex` crate.
+//!
+//! Grammars which `to_regex_string` can render, with labels written in
+//! the `regex` crate's syntax, mean the same thing to both engines.
+//! `first_disagreement` runs both on a set of inputs and reports any
+//! input where one matches and the other doesn't, which is evidence
+//! about bugs in either engine, or in the rendering.
+
+use core::{AnyRegex, Describe, Regex};
+use regex;
+use std::error::Error;
+use std::fmt;
+use syntax::{to_regex_string, Inexpressible};
+use weights::recognize::{has_match, Match};
+
+/// Why a grammar couldn't be handed to the `regex` crate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Unsupported {
+    /// The grammar has no regular expression syntax.
+    Inexpressible(Inexpressible),
+    /// The rendered syntax, usually from a label, was rejected by the
+    /// `regex` crate, with its message.
+    Syntax(String),
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Unsupported::Inexpressible(reason) => write!(f, "grammar has no regular expression syntax: {}", reason),
+            Unsupported::Syntax(ref message) => write!(f, "regex crate rejected the pattern: {}", message),
+        }
+    }
+}
+
+impl Error for Unsupported {}
+
+/// Compile a `regex::Regex` matching the same inputs as the whole of
+/// `re`, by anchoring its `to_regex_string` rendering at both ends.
+pub fn to_std_regex<T, M>(re: &dyn Describe<T, M>) -> Result<regex::Regex, Unsupported> {
+    let text = to_regex_string(re).map_err(Unsupported::Inexpressible)?;
+    regex::Regex::new(&format!("^(?:{})$", text)).map_err(|e| Unsupported::Syntax(e.to_string()))
+}
+
+/// The first of `inputs` which exactly one of `re` and its `regex`
+/// crate equivalent matches, if any.
+pub fn first_disagreement<R, I>(re: &mut AnyRegex<char, Match, R>, inputs: I) -> Result<Option<String>, Unsupported> where
+    R: Regex<char, Match> + Describe<char, Match>,
+    I: IntoIterator<Item=String>,
+{
+    let other = to_std_regex(re)?;
+    Ok(inputs.into_iter().find(|input| has_match(re, input.chars()) != other.is_match(input)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use core::Grammar;
+    use testing::Strings;
+
+    fn class(text: &'static str) -> AnyRegex<char, Match, impl Grammar<char, Match>> {
+        let set = text.trim_start_matches('[').trim_end_matches(']');
+        is(move |&c: &char| set.contains(c)).label(text)
+    }
+
+    fn inputs() -> impl Iterator<Item=String> {
+        Strings::new(&['a', 'b', 'c'], Some(6)).map(|s| s.into_iter().collect())
+    }
+
+    fn agrees<R: Regex<char, Match> + Describe<char, Match>>(mut re: AnyRegex<char, Match, R>) {
+        assert_eq!(first_disagreement(&mut re, inputs()), Ok(None), "{}", re);
+    }
+
+    #[test]
+    fn agreement() {
+        agrees(many(class("a") | class("b")) + class("c"));
+        agrees(many(many(class("a")) + class("[bc]")));
+        agrees((class("a") | empty()) + many(class("b") + class("a")));
+        agrees(many(empty()) + class("[abc]") + many(class("c")));
+    }
+
+    #[test]
+    fn disagreement() {
+        // A label that doesn't describe its leaf is caught.
+        let mut re = many(is(|&c| c == 'a').label("b"));
+        assert_eq!(first_disagreement(&mut re, inputs()), Ok(Some("a".to_string())));
+
+        let mut re = class("a") & class("b");
+        assert_eq!(first_disagreement(&mut re, inputs()), Err(Unsupported::Inexpressible(Inexpressible::And)));
+        let mut re = class("[a");
+        assert!(first_disagreement(&mut re, inputs()).is_err());
+    }
+
+    quickcheck! {
+        fn arbitrary_inputs(to_match : String) -> bool {
+            let mut re = many(class("[ab]") + class("c")) + many(class("a"));
+            first_disagreement(&mut re, Some(to_match)) == Ok(None)
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Drawing grammars with [Graphviz].
+//!
+//! The output is a `digraph` with one vertex per combinator, so you can
+//! see how a grammar was built and, optionally, where marks currently
+//! are. Pipe it through `dot -Tsvg` or similar to view it.
+//!
+//! Grammars which only use regular operators can also be drawn as the
+//! automaton they induce, by rendering their `Nfa` instead: one vertex
+//! per state, with each transition labeled by the test on the item it
+//! consumes.
+//!
+//! [Graphviz]: https://graphviz.org/
+
+use core::{AnyRegex, Describe, Node};
+use nfa::Nfa;
+use num_traits::Zero;
+use std::fmt::{Debug, Write};
+use std::ops;
+use structural::leaf_text;
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    R: Describe<T, M>,
+{
+    /// Render this grammar's combinator tree in DOT syntax.
+    pub fn to_dot(&self) -> String {
+        to_dot(self)
+    }
+
+    /// Like `to_dot`, but also show the marks held by the stateful
+    /// combinators, which is handy when stepping through a match.
+    pub fn to_dot_with_marks(&self) -> String where
+        M: Debug,
+    {
+        to_dot_with_marks(self)
+    }
+}
+
+impl<'a, T, M> Nfa<'a, T, M> where
+    M: Zero + ops::Mul<Output=M>,
+{
+    /// Render this automaton's states and transitions in DOT syntax.
+    /// Final states are drawn with a double circle, and transitions
+    /// are labeled with the `label` of the state they enter, or else
+    /// the text of its test.
+    pub fn to_dot(&self) -> String {
+        render_nfa(self, &|_| None)
+    }
+
+    /// Like `to_dot`, but also show the mark each state holds, for an
+    /// automaton which is being used as a grammar.
+    pub fn to_dot_with_marks(&self) -> String where
+        M: Debug,
+    {
+        render_nfa(self, &|mark| Some(format!("{:?}", mark)))
+    }
+}
+
+/// Like `AnyRegex::to_dot`, but for any grammar node.
+pub fn to_dot<T, M>(re: &dyn Describe<T, M>) -> String {
+    render(re, &|_| None)
+}
+
+/// Like `AnyRegex::to_dot_with_marks`, but for any grammar node.
+pub fn to_dot_with_marks<T, M: Debug>(re: &dyn Describe<T, M>) -> String {
+    render(re, &|mark| Some(format!("{:?}", mark)))
+}
+
+fn render<T, M>(re: &dyn Describe<T, M>, marks: &dyn Fn(&M) -> Option<String>) -> String {
+    let mut out = String::from("digraph grammar {\n");
+    let mut next = 0;
+    vertex(re, marks, &mut next, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+/// Write out `re` and everything under it, returning the name of the
+/// vertex for `re` itself.
+fn vertex<T, M>(
+    re: &dyn Describe<T, M>,
+    marks: &dyn Fn(&M) -> Option<String>,
+    next: &mut usize,
+    out: &mut String,
+) -> usize {
+    let id = *next;
+    *next += 1;
+
+    let (label, mark, children) = match re.describe() {
+        Node::Empty => ("empty".to_string(), None, vec![]),
+        Node::Is(_) => ("is".to_string(), None, vec![]),
+        Node::Label(text, inner) => (format!("label {}", text), None, vec![inner]),
+        Node::Not(inner) => ("not".to_string(), None, vec![inner]),
+        Node::Or(left, right) => ("or".to_string(), None, vec![left, right]),
+        Node::And(left, right) => ("and".to_string(), None, vec![left, right]),
+        Node::Sequence(left, right, mark) =>
+            ("sequence".to_string(), marks(mark), vec![left, right]),
+        Node::Many(inner, mark) => ("many".to_string(), marks(mark), vec![inner]),
+        Node::Delay => ("delay".to_string(), None, vec![]),
+    };
+
+    let mut label = escape(&label);
+    if let Some(mark) = mark {
+        label.push_str("\\n");
+        label.push_str(&escape(&mark));
+    }
+    writeln!(out, "    n{} [label=\"{}\"];", id, label).unwrap();
+
+    for child in children {
+        let child = vertex(child, marks, next, out);
+        writeln!(out, "    n{} -> n{};", id, child).unwrap();
+    }
+    id
+}
+
+fn render_nfa<T, M>(nfa: &Nfa<'_, T, M>, marks: &dyn Fn(&M) -> Option<String>) -> String where
+    M: Zero + ops::Mul<Output=M>,
+{
+    let mut out = String::from("digraph automaton {\n    rankdir=LR;\n");
+    for (q, state) in nfa.states().iter().enumerate() {
+        let shape = if state.is_final() { "doublecircle" } else { "circle" };
+        let mut label = q.to_string();
+        if let Some(mark) = marks(&nfa.marks()[q]) {
+            label.push_str("\\n");
+            label.push_str(&escape(&mark));
+        }
+        writeln!(out, "    s{} [shape={}, label=\"{}\"];", q, shape, label).unwrap();
+    }
+    for (q, state) in nfa.states().iter().enumerate() {
+        for &p in state.next() {
+            let target = &nfa.states()[p];
+            let label = match target.label() {
+                Some(text) => text.to_string(),
+                None => target.test().and_then(|test| leaf_text(test)).unwrap_or_else(|| "is".to_string()),
+            };
+            writeln!(out, "    s{} -> s{} [label=\"{}\"];", q, p, escape(&label)).unwrap();
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => { escaped.push('\\'); escaped.push(c); }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use core::Regex;
+    use num_traits::one;
+
+    #[test]
+    fn tree() {
+        let re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a').label("\"a\"")) + empty();
+        assert_eq!(re.to_dot(), "digraph grammar {
+    n0 [label=\"sequence\"];
+    n1 [label=\"many\"];
+    n2 [label=\"label \\\"a\\\"\"];
+    n3 [label=\"is\"];
+    n2 -> n3;
+    n1 -> n2;
+    n0 -> n1;
+    n4 [label=\"empty\"];
+    n0 -> n4;
+}
+");
+    }
+
+    #[test]
+    fn automaton() {
+        let re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a').label("a")) + is(|&c| c == 'b');
+        let nfa = re.to_nfa().ok().unwrap();
+        assert_eq!(nfa.to_dot(), "digraph automaton {
+    rankdir=LR;
+    s0 [shape=circle, label=\"0\"];
+    s1 [shape=circle, label=\"1\"];
+    s2 [shape=doublecircle, label=\"2\"];
+    s0 -> s1 [label=\"a\"];
+    s0 -> s2 [label=\"is\"];
+    s1 -> s1 [label=\"a\"];
+    s1 -> s2 [label=\"is\"];
+}
+");
+    }
+
+    #[test]
+    fn automaton_marks() {
+        let re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a').label("a"));
+        let mut nfa = re.to_nfa().ok().unwrap();
+        Regex::shift(&mut nfa, &'a', one::<Match>());
+        assert_eq!(nfa.to_dot_with_marks(), "digraph automaton {
+    rankdir=LR;
+    s0 [shape=doublecircle, label=\"0\\nMatch(false)\"];
+    s1 [shape=doublecircle, label=\"1\\nMatch(true)\"];
+    s0 -> s1 [label=\"a\"];
+    s1 -> s1 [label=\"a\"];
+}
+");
+    }
+
+    #[test]
+    fn marks() {
+        let mut re = many(is(|&c| c == 'a'));
+        re.shift(&'a', one::<Match>());
+        assert_eq!(re.to_dot_with_marks(), "digraph grammar {
+    n0 [label=\"many\\nMatch(true)\"];
+    n1 [label=\"is\"];
+    n0 -> n1;
+}
+");
+    }
+}
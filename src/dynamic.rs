@@ -0,0 +1,473 @@
+//! Grammars whose shape is only known at run time.
+//!
+//! The combinators in `grammars` encode a grammar's structure in its
+//! type, which is great when the grammar is written in the source code
+//! but impossible when it's assembled from user input. The usual
+//! workaround is to `boxed()` every node, paying for an allocation and
+//! dynamic dispatch per node, and losing `Describe` along the way.
+//!
+//! `Dynamic` is a single type covering all the built-in node kinds, so
+//! grammars of any shape can be built from it. Each node holds its
+//! children in one allocation, with no dynamic dispatch, and the usual
+//! combinator structs do the real work, so the weights are exactly the
+//! same. Only leaves and user-defined grammars, wrapped with
+//! `Dynamic::other`, are behind trait objects.
+
+use core::{AnyRegex, Attempts, CloneRegex, Describe, IntoWithInput, Leaf, Node, Regex};
+use grammars::{self, Label, Many, Not, Or, And, Sequence};
+use num_traits::{Zero, One};
+use std::borrow::Cow;
+use std::mem::size_of_val;
+use std::ops::{self, Bound, RangeBounds};
+use std::rc::Rc;
+
+/// A node of a dynamically-constructed grammar. Build these with the
+/// associated functions, which all return `AnyRegex`s.
+pub enum Dynamic<T, M> {
+    Empty,
+    Is(Rc<dyn Leaf<T, M>>),
+    Label(Boxed<T, M, Label<T, M, Self>>),
+    Not(Boxed<T, M, Not<T, M, Self>>),
+    Or(Boxed<T, M, Or<T, M, Self, Self>>),
+    And(Boxed<T, M, And<T, M, Self, Self>>),
+    Sequence(Boxed<T, M, Sequence<T, M, Self, Self>>),
+    Many(Boxed<T, M, Many<T, M, Self>>),
+    /// A possibly-recursive grammar, constructed the first time it's
+    /// needed after each reset, like `grammars::delay`.
+    Delay(Rc<dyn Fn() -> Dyn<T, M>>, Option<Boxed<T, M, Self>>),
+    Other(Box<dyn Custom<T, M>>),
+}
+
+type Boxed<T, M, R> = Box<AnyRegex<T, M, R>>;
+type Dyn<T, M> = AnyRegex<T, M, Dynamic<T, M>>;
+
+/// User-defined grammars that can be embedded in a `Dynamic` grammar.
+/// This is implemented for every `AnyRegex` whose node type supports
+/// cloning and description.
+pub trait Custom<T, M> {
+    fn empty(&mut self) -> bool;
+    fn active(&self) -> bool;
+    fn shift(&mut self, c : &T, mark : M) -> M;
+    fn reset(&mut self);
+    fn describe(&self) -> Node<'_, T, M>;
+    fn heap_size(&self) -> usize;
+    fn attempts(&self) -> Option<Attempts<'_, T, M>>;
+    fn clone_reset(&self) -> Box<dyn Custom<T, M>>;
+}
+
+impl<T, M, R> Custom<T, M> for AnyRegex<T, M, R> where
+    T: 'static,
+    M: Zero + 'static,
+    R: CloneRegex<T, M> + Describe<T, M> + 'static,
+{
+    fn empty(&mut self) -> bool { AnyRegex::empty(self) }
+    fn active(&self) -> bool { AnyRegex::active(self) }
+    fn shift(&mut self, c : &T, mark : M) -> M { AnyRegex::shift(self, c, mark) }
+    fn reset(&mut self) { AnyRegex::reset(self) }
+    fn describe(&self) -> Node<'_, T, M> { Describe::describe(self) }
+    fn heap_size(&self) -> usize { Describe::heap_size(self) }
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> { Describe::attempts(self) }
+    fn clone_reset(&self) -> Box<dyn Custom<T, M>> { Box::new(AnyRegex::clone_reset(self)) }
+}
+
+impl<T, M> Dynamic<T, M> where
+    T: 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+{
+    /// Like `grammars::empty`.
+    pub fn empty() -> Dyn<T, M> { AnyRegex::new(Dynamic::Empty) }
+
+    /// Like `grammars::is`.
+    pub fn is<N, F>(f: F) -> Dyn<T, M> where
+        F: Fn(&T) -> N + 'static,
+        N: IntoWithInput<T, M> + 'static,
+    {
+        Dynamic::leaf(Rc::new(grammars::is::<T, T, M, N, F>(f)))
+    }
+
+    /// A leaf that weighs items with an arbitrary `Leaf`.
+    pub fn leaf(leaf: Rc<dyn Leaf<T, M>>) -> Dyn<T, M> {
+        AnyRegex::new(Dynamic::Is(leaf))
+    }
+
+    /// Like `AnyRegex::label`.
+    pub fn label<S>(re: Dyn<T, M>, text: S) -> Dyn<T, M> where
+        S: Into<Cow<'static, str>>,
+    {
+        AnyRegex::new(Dynamic::Label(Box::new(re.label(text))))
+    }
+
+    /// Like `!re`.
+    pub fn not(re: Dyn<T, M>) -> Dyn<T, M> {
+        AnyRegex::new(Dynamic::Not(Box::new(!re)))
+    }
+
+    /// Like `left | right`.
+    pub fn or(left: Dyn<T, M>, right: Dyn<T, M>) -> Dyn<T, M> {
+        AnyRegex::new(Dynamic::Or(Box::new(left | right)))
+    }
+
+    /// Like `left & right`.
+    pub fn and(left: Dyn<T, M>, right: Dyn<T, M>) -> Dyn<T, M> {
+        AnyRegex::new(Dynamic::And(Box::new(left & right)))
+    }
+
+    /// Like `left + right`.
+    pub fn sequence(left: Dyn<T, M>, right: Dyn<T, M>) -> Dyn<T, M> {
+        AnyRegex::new(Dynamic::Sequence(Box::new(left + right)))
+    }
+
+    /// Like `grammars::many`.
+    pub fn many(re: Dyn<T, M>) -> Dyn<T, M> {
+        AnyRegex::new(Dynamic::Many(Box::new(grammars::many(re))))
+    }
+
+    /// Like `grammars::delay`, but the constructor returns another
+    /// `Dynamic` grammar instead of a boxed one.
+    pub fn delay<F>(constructor: F) -> Dyn<T, M> where
+        F: Fn() -> Dyn<T, M> + 'static,
+    {
+        AnyRegex::new(Dynamic::Delay(Rc::new(constructor), None))
+    }
+
+    /// Match `re` exactly `n` times in a row, using reset copies of it.
+    pub fn exactly(re: Dyn<T, M>, n: usize) -> Dyn<T, M> {
+        if n == 0 {
+            return Dynamic::empty();
+        }
+        let copies: Vec<Dyn<T, M>> = (1..n).map(|_| re.clone_reset()).collect();
+        copies.into_iter().fold(re, Dynamic::sequence)
+    }
+
+    /// Match `re` at least `min` times, and at most `max` times if
+    /// given, using reset copies of it. Each number of repetitions is
+    /// matched only one way, so weights aren't counted twice.
+    ///
+    /// Each copy can hold marks from a different repetition, so there's
+    /// one copy per repetition up to `max`, or `min + 1` without one:
+    /// memory, and time per item while they're all active, grow with
+    /// the bound. To only limit how long a match is, `len_between` is
+    /// cheaper.
+    pub fn repeat(re: Dyn<T, M>, min: usize, max: Option<usize>) -> Dyn<T, M> {
+        let more = match max {
+            None => Dynamic::many(re.clone_reset()),
+            Some(max) => {
+                assert!(min <= max, "repetition range is empty");
+                // Nest the optional copies, as in `(x(x)?)?`.
+                (min..max).fold(Dynamic::empty(), |rest, _| {
+                    Dynamic::or(Dynamic::empty(), Dynamic::sequence(re.clone_reset(), rest))
+                })
+            }
+        };
+        Dynamic::sequence(Dynamic::exactly(re, min), more)
+    }
+
+    /// Match any one of `res`, like `a | b | c`. With no grammars, this
+    /// matches nothing.
+    pub fn any_of<R, I>(res: I) -> Dyn<T, M> where
+        bool: IntoWithInput<T, M>,
+        R: CloneRegex<T, M> + Describe<T, M> + 'static,
+        I: IntoIterator<Item=AnyRegex<T, M, R>>,
+    {
+        let mut res = res.into_iter().map(Dynamic::other);
+        match res.next() {
+            Some(first) => res.fold(first, Dynamic::or),
+            None => Dynamic::not(Dynamic::anything()),
+        }
+    }
+
+    /// Match all of `res` at once, like `a & b & c`. With no grammars,
+    /// this matches everything.
+    pub fn all_of<R, I>(res: I) -> Dyn<T, M> where
+        bool: IntoWithInput<T, M>,
+        R: CloneRegex<T, M> + Describe<T, M> + 'static,
+        I: IntoIterator<Item=AnyRegex<T, M, R>>,
+    {
+        let mut res = res.into_iter().map(Dynamic::other);
+        match res.next() {
+            Some(first) => res.fold(first, Dynamic::and),
+            None => Dynamic::anything(),
+        }
+    }
+
+    /// Match each of `res` in turn, like `a + b + c`. With no grammars,
+    /// this matches only the empty sequence.
+    pub fn chain<R, I>(res: I) -> Dyn<T, M> where
+        R: CloneRegex<T, M> + Describe<T, M> + 'static,
+        I: IntoIterator<Item=AnyRegex<T, M, R>>,
+    {
+        let mut res = res.into_iter().map(Dynamic::other);
+        match res.next() {
+            Some(first) => res.fold(first, Dynamic::sequence),
+            None => Dynamic::empty(),
+        }
+    }
+
+    /// Every sequence.
+    fn anything() -> Dyn<T, M> where
+        bool: IntoWithInput<T, M>,
+    {
+        Dynamic::many(Dynamic::is(|_: &T| true))
+    }
+
+    /// Embed any other grammar.
+    pub fn other<R>(re: AnyRegex<T, M, R>) -> Dyn<T, M> where
+        R: CloneRegex<T, M> + Describe<T, M> + 'static,
+    {
+        AnyRegex::new(Dynamic::Other(Box::new(re)))
+    }
+}
+
+/// `re * n` matches `re` exactly `n` times in a row, like `re{n}` in
+/// other regex syntaxes. The result is a `Dynamic` grammar, since its
+/// shape depends on `n`, and it holds `n` copies of `re`.
+impl<T, M, R> ops::Mul<usize> for AnyRegex<T, M, R> where
+    T: 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+    R: CloneRegex<T, M> + Describe<T, M> + 'static,
+{
+    type Output = Dyn<T, M>;
+    fn mul(self, n: usize) -> Self::Output {
+        Dynamic::exactly(Dynamic::other(self), n)
+    }
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    T: 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+    R: CloneRegex<T, M> + Describe<T, M> + 'static,
+{
+    /// Match this grammar any number of times within `bounds`, such as
+    /// `2..=5` or `3..`, like `re{2,5}` or `re{3,}` in other regex
+    /// syntaxes. Panics if `bounds` is empty.
+    ///
+    /// The result holds a reset copy of this grammar for every
+    /// repetition up to the upper bound, as `Dynamic::repeat` explains,
+    /// so `re.repeat(..=100_000)` is a hundred thousand copies.
+    pub fn repeat<B: RangeBounds<usize>>(self, bounds: B) -> Dyn<T, M> {
+        let min = match bounds.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let max = match bounds.end_bound() {
+            Bound::Included(&n) => Some(n),
+            Bound::Excluded(&n) => Some(n.checked_sub(1).expect("repetition range is empty")),
+            Bound::Unbounded => None,
+        };
+        Dynamic::repeat(Dynamic::other(self), min, max)
+    }
+
+}
+
+impl<T, M> Regex<T, M> for Dynamic<T, M> where
+    T: 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+{
+    fn empty(&mut self) -> bool {
+        match *self {
+            Dynamic::Empty => true,
+            Dynamic::Is(_) => false,
+            Dynamic::Label(ref mut re) => re.empty(),
+            Dynamic::Not(ref mut re) => re.empty(),
+            Dynamic::Or(ref mut re) => re.empty(),
+            Dynamic::And(ref mut re) => re.empty(),
+            Dynamic::Sequence(ref mut re) => re.empty(),
+            Dynamic::Many(ref mut re) => re.empty(),
+            Dynamic::Delay(ref constructor, ref mut value) =>
+                value.get_or_insert_with(|| Box::new(constructor())).empty(),
+            Dynamic::Other(ref mut re) => re.empty(),
+        }
+    }
+    fn active(&self) -> bool {
+        match *self {
+            Dynamic::Empty | Dynamic::Is(_) => false,
+            Dynamic::Label(ref re) => re.active(),
+            Dynamic::Not(ref re) => re.active(),
+            Dynamic::Or(ref re) => re.active(),
+            Dynamic::And(ref re) => re.active(),
+            Dynamic::Sequence(ref re) => re.active(),
+            Dynamic::Many(ref re) => re.active(),
+            Dynamic::Delay(_, ref value) => value.as_ref().is_some_and(|re| re.active()),
+            Dynamic::Other(ref re) => re.active(),
+        }
+    }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        match *self {
+            Dynamic::Empty => M::zero(),
+            Dynamic::Is(ref leaf) => mark * leaf.weigh(c),
+            Dynamic::Label(ref mut re) => re.shift(c, mark),
+            Dynamic::Not(ref mut re) => re.shift(c, mark),
+            Dynamic::Or(ref mut re) => re.shift(c, mark),
+            Dynamic::And(ref mut re) => re.shift(c, mark),
+            Dynamic::Sequence(ref mut re) => re.shift(c, mark),
+            Dynamic::Many(ref mut re) => re.shift(c, mark),
+            Dynamic::Delay(ref constructor, ref mut value) =>
+                value.get_or_insert_with(|| Box::new(constructor())).shift(c, mark),
+            Dynamic::Other(ref mut re) => re.shift(c, mark),
+        }
+    }
+    fn reset(&mut self) {
+        match *self {
+            Dynamic::Empty | Dynamic::Is(_) => {}
+            Dynamic::Label(ref mut re) => re.reset(),
+            Dynamic::Not(ref mut re) => re.reset(),
+            Dynamic::Or(ref mut re) => re.reset(),
+            Dynamic::And(ref mut re) => re.reset(),
+            Dynamic::Sequence(ref mut re) => re.reset(),
+            Dynamic::Many(ref mut re) => re.reset(),
+            Dynamic::Delay(_, ref mut value) => *value = None,
+            Dynamic::Other(ref mut re) => re.reset(),
+        }
+    }
+}
+
+impl<T, M> CloneRegex<T, M> for Dynamic<T, M> where
+    T: 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+{
+    fn clone_reset(&self) -> Dyn<T, M> {
+        AnyRegex::new(match *self {
+            Dynamic::Empty => Dynamic::Empty,
+            Dynamic::Is(ref leaf) => Dynamic::Is(leaf.clone()),
+            Dynamic::Label(ref re) => Dynamic::Label(Box::new(re.clone_reset())),
+            Dynamic::Not(ref re) => Dynamic::Not(Box::new(re.clone_reset())),
+            Dynamic::Or(ref re) => Dynamic::Or(Box::new(re.clone_reset())),
+            Dynamic::And(ref re) => Dynamic::And(Box::new(re.clone_reset())),
+            Dynamic::Sequence(ref re) => Dynamic::Sequence(Box::new(re.clone_reset())),
+            Dynamic::Many(ref re) => Dynamic::Many(Box::new(re.clone_reset())),
+            Dynamic::Delay(ref constructor, _) => Dynamic::Delay(constructor.clone(), None),
+            Dynamic::Other(ref re) => Dynamic::Other(re.clone_reset()),
+        })
+    }
+}
+
+impl<T, M> Describe<T, M> for Dynamic<T, M> {
+    fn describe(&self) -> Node<'_, T, M> {
+        match *self {
+            Dynamic::Empty => Node::Empty,
+            Dynamic::Is(ref leaf) => Node::Is(&**leaf),
+            Dynamic::Label(ref re) => re.describe(),
+            Dynamic::Not(ref re) => re.describe(),
+            Dynamic::Or(ref re) => re.describe(),
+            Dynamic::And(ref re) => re.describe(),
+            Dynamic::Sequence(ref re) => re.describe(),
+            Dynamic::Many(ref re) => re.describe(),
+            Dynamic::Delay(_, _) => Node::Delay,
+            Dynamic::Other(ref re) => re.describe(),
+        }
+    }
+
+    // Each boxed node's own heap data is reported here, because it
+    // describes itself in this node's place.
+    fn heap_size(&self) -> usize {
+        match *self {
+            Dynamic::Empty => 0,
+            Dynamic::Is(ref leaf) => size_of_val(&**leaf),
+            Dynamic::Label(ref re) => size_of_val(&**re) + re.heap_size(),
+            Dynamic::Not(ref re) => size_of_val(&**re) + re.heap_size(),
+            Dynamic::Or(ref re) => size_of_val(&**re) + re.heap_size(),
+            Dynamic::And(ref re) => size_of_val(&**re) + re.heap_size(),
+            Dynamic::Sequence(ref re) => size_of_val(&**re) + re.heap_size(),
+            Dynamic::Many(ref re) => size_of_val(&**re) + re.heap_size(),
+            Dynamic::Delay(_, ref value) => value.as_ref().map_or(0, |re| size_of_val(&**re)),
+            Dynamic::Other(ref re) => size_of_val(&**re) + re.heap_size(),
+        }
+    }
+
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> {
+        match *self {
+            Dynamic::Not(ref re) => re.attempts(),
+            Dynamic::And(ref re) => re.attempts(),
+            Dynamic::Other(ref re) => re.attempts(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    type D = Dynamic<char, Match>;
+
+    fn parens() -> AnyRegex<char, Match, Dynamic<char, Match>> {
+        let open = D::is(|&c| c == '(');
+        let close = D::is(|&c| c == ')');
+        D::many(D::sequence(D::sequence(open, D::delay(parens)), close))
+    }
+
+    #[test]
+    fn recursive() {
+        let mut re = parens();
+        assert!(has_match(&mut re, "(()())()".chars()));
+        assert!(!has_match(&mut re, "(()".chars()));
+        assert!(has_match(&mut re.clone_reset(), "".chars()));
+    }
+
+    #[test]
+    fn describable() {
+        let re = D::sequence(D::many(D::label(D::is(|&c| c == 'a'), "a")), D::other(is(|&c| c == 'b').label("b")));
+        assert_eq!(re.to_regex_string().unwrap(), "a*b");
+    }
+
+    #[test]
+    fn exact_repetition() {
+        let digit = || is(|c: &char| c.is_ascii_digit()).label("[0-9]");
+        let mut year = digit() * 4;
+        assert!(has_match(&mut year, "2019".chars()));
+        assert!(!has_match(&mut year, "201".chars()));
+        assert!(!has_match(&mut year, "20190".chars()));
+        assert_eq!(year.to_regex_string().unwrap(), "[0-9][0-9][0-9][0-9]");
+        assert!(has_match(&mut (digit() * 0), "".chars()));
+    }
+
+    #[test]
+    fn bounded_repetition() {
+        let a = || is(|&c: &char| c == 'a');
+        let matches = |re: &mut AnyRegex<char, Match, Dynamic<char, Match>>| -> Vec<usize> {
+            (0..6).filter(|&n| has_match(re, "a".repeat(n).chars())).collect()
+        };
+        assert_eq!(matches(&mut a().repeat(2..=4)), vec![2, 3, 4]);
+        assert_eq!(matches(&mut a().repeat(2..4)), vec![2, 3]);
+        assert_eq!(matches(&mut a().repeat(3..)), vec![3, 4, 5]);
+        assert_eq!(matches(&mut a().repeat(..2)), vec![0, 1]);
+        assert_eq!(matches(&mut a().repeat(..)), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn collections() {
+        let word = |w: &'static str| D::chain(w.chars().map(|c| is(move |&x: &char| x == c)));
+        let mut keywords = D::any_of(vec![word("if"), word("in"), word("for")]);
+        for &(w, expected) in &[("if", true), ("for", true), ("fo", false), ("", false)] {
+            assert_eq!(has_match(&mut keywords, w.chars()), expected, "{}", w);
+        }
+
+        // Grammars of different types can be collected as `Dynamic`s.
+        let mut both = D::all_of(vec![
+            D::other(many(is(|c: &char| c.is_lowercase()))),
+            D::other(is(|_: &char| true) + is(|_: &char| true)),
+        ]);
+        assert!(has_match(&mut both, "ab".chars()));
+        assert!(!has_match(&mut both, "aB".chars()));
+        assert!(!has_match(&mut both, "abc".chars()));
+
+        let none = || Vec::<AnyRegex<char, Match, Dynamic<char, Match>>>::new();
+        assert!(!has_match(&mut D::any_of(none()), "".chars()));
+        assert!(has_match(&mut D::all_of(none()), "xyz".chars()));
+        assert!(has_match(&mut D::chain(none()), "".chars()));
+    }
+
+    quickcheck! {
+        fn same_as_static(to_match : String) -> bool {
+            let upper = |c: &char| c.is_uppercase();
+            let lower = |c: &char| c.is_lowercase();
+            let mut expected = many(is(upper) + (!many(is(lower)) & is(|_: &char| true))) | empty();
+            let mut dynamic = D::or(
+                D::many(D::sequence(D::is(upper), D::and(D::not(D::many(D::is(lower))), D::is(|_| true)))),
+                D::empty());
+            has_match(&mut expected, to_match.chars()) == has_match(&mut dynamic, to_match.chars())
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! Building blocks for defining language grammars. You need this module
+//! if you want to write down the exact type of a language you've
+//! defined, or if you want to create your own combinators over language
+//! grammars.
+//!
+//! Everything in this module is parameterized over:
+//!
+//! - `T`, the type of individual elements of the input; and
+//! - `M`, an arbitrary semiring used for tracking state during parsing.
+//!
+//! Semiring implementations that are widely useful can be found in the
+//! `weights` module, but you can write your own to do all sorts of
+//! exotic things.
+
+use num_traits::{Zero, zero, One, one};
+use std::fmt;
+use std::boxed::Box;
+use std::marker::PhantomData;
+use std::vec::Vec;
+use std::ops::ControlFlow;
+#[cfg(feature = "stats")]
+use stats;
+
+pub struct AnyRegex<T, M, R> {
+    re: R,
+    active: bool,
+    input_type: PhantomData<T>,
+    mark_type: PhantomData<M>,
+}
+
+impl<T, M, R> AnyRegex<T, M, R>
+    where M: Zero + One, R: Regex<T, M>
+{
+    pub fn over<I>(&mut self, over : I) -> M
+        where I: IntoIterator<Item=T>
+    {
+        let mut iter = over.into_iter();
+        let mut result;
+        if let Some(c) = iter.next() {
+            result = self.shift(&c, one());
+        } else {
+            return if self.empty() { one() } else { zero() };
+        }
+        while let Some(c) = iter.next() {
+            result = self.shift(&c, zero());
+        }
+        self.reset();
+        return result;
+    }
+
+    /// Like `over`, but call `on_match` as soon as the items read so
+    /// far match, with how many items that is and their weight, and
+    /// again each time they match after that. Stream monitors can act
+    /// at the earliest match instead of after the stream ends. Reading
+    /// stops if `on_match` returns `Break`, and the result is the
+    /// weight of what was read.
+    pub fn over_notify<I, F>(&mut self, over : I, mut on_match : F) -> M
+        where I: IntoIterator<Item=T>, F: FnMut(usize, &M) -> ControlFlow<()>
+    {
+        let mut result: M = if self.empty() { one() } else { zero() };
+        if !result.is_zero() && on_match(0, &result).is_break() {
+            return result;
+        }
+        for (read, c) in over.into_iter().enumerate() {
+            result = self.shift(&c, if read == 0 { one() } else { zero() });
+            if !result.is_zero() && on_match(read + 1, &result).is_break() {
+                break;
+            }
+        }
+        self.reset();
+        result
+    }
+
+    /// Like `over`, but convert the weight with `f`, such as into a
+    /// type from the caller's own domain.
+    pub fn over_map<I, S, F>(&mut self, over : I, f : F) -> S
+        where I: IntoIterator<Item=T>, F: FnOnce(M) -> S
+    {
+        f(self.over(over))
+    }
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    R: Regex<T, M>,
+{
+    pub fn new(re: R) -> Self
+    {
+        #[cfg(feature = "stats")]
+        stats::activity(false, re.active());
+        AnyRegex {
+            active: re.active(),
+            re: re,
+            input_type: PhantomData,
+            mark_type: PhantomData,
+        }
+    }
+
+    pub fn boxed(self) -> Box<Regex<T, M>> where
+        R: 'static,
+    {
+        Box::new(self.into_inner())
+    }
+}
+
+impl<T, M, R> AnyRegex<T, M, R> {
+    /// Take the node out, for rebuilding the grammar. With the `stats`
+    /// feature, this wrapper no longer counts as active, though
+    /// whatever wraps the node next will if it's still active.
+    pub(crate) fn into_inner(self) -> R {
+        #[cfg(feature = "stats")]
+        stats::activity(self.active, false);
+        self.re
+    }
+
+    /// Look at the node, without the activity bookkeeping.
+    pub(crate) fn node(&self) -> &R { &self.re }
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero,
+    R: Regex<T, M>,
+{
+    pub fn empty(&mut self) -> bool { self.re.empty() }
+    pub fn active(&self) -> bool { self.active }
+    pub fn shift(&mut self, c : &T, mark : M) -> M {
+        if !self.active && mark.is_zero() {
+            #[cfg(feature = "stats")]
+            stats::skipped();
+            return mark;
+        }
+        let mark = self.re.shift(c, mark);
+        #[cfg(feature = "stats")]
+        {
+            stats::shifted();
+            stats::activity(self.active, self.re.active());
+        }
+        self.active = self.re.active();
+        mark
+    }
+    pub fn reset(&mut self) {
+        if self.active {
+            self.re.reset();
+            #[cfg(feature = "stats")]
+            stats::activity(true, self.re.active());
+            self.active = self.re.active();
+        }
+    }
+}
+
+/// Grammar types must implement `Regex`.
+pub trait Regex<T, M> {
+    fn empty(&mut self) -> bool;
+    fn active(&self) -> bool;
+    fn shift(&mut self, c : &T, mark : M) -> M;
+    fn reset(&mut self);
+}
+
+impl<T, M, R: CloneRegex<T, M>> AnyRegex<T, M, R> {
+    pub fn clone_reset(&self) -> Self { self.re.clone_reset() }
+}
+
+/// Grammar types _should_ implement `CloneRegex`.
+pub trait CloneRegex<T, M>: Regex<T, M> + Sized {
+    fn clone_reset(&self) -> AnyRegex<T, M, Self>;
+}
+
+/// Implement `Regex` for a wrapper around a single `AnyRegex` field by
+/// forwarding every method to that field, like:
+///
+/// ```
+/// # #[macro_use] extern crate weighted_regexp;
+/// # use weighted_regexp::engine::{AnyRegex, Regex};
+/// # use weighted_regexp::Match;
+/// struct Word<R>(AnyRegex<char, Match, R>);
+///
+/// delegate_regex! {
+///     impl[R: Regex<char, Match>] Regex<char, Match> for Word<R> { self.0 }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// Writing `Regex + CloneRegex` instead of `Regex` implements
+/// `CloneRegex` too, which needs the field to be the wrapper's only
+/// one.
+#[macro_export]
+macro_rules! delegate_regex {
+    (impl[$($gen:tt)*] Regex<$t:ty, $m:ty> for $ty:ty { self.$field:tt }) => {
+        impl<$($gen)*> $crate::engine::Regex<$t, $m> for $ty {
+            fn empty(&mut self) -> bool { self.$field.empty() }
+            fn active(&self) -> bool { self.$field.active() }
+            fn shift(&mut self, c : &$t, mark : $m) -> $m { self.$field.shift(c, mark) }
+            fn reset(&mut self) { self.$field.reset() }
+        }
+    };
+    (impl[$($gen:tt)*] Regex<$t:ty, $m:ty> + CloneRegex for $ty:ty { self.$field:tt }) => {
+        delegate_regex! { impl[$($gen)*] Regex<$t, $m> for $ty { self.$field } }
+
+        impl<$($gen)*> $crate::engine::CloneRegex<$t, $m> for $ty {
+            fn clone_reset(&self) -> $crate::engine::AnyRegex<$t, $m, Self> {
+                $crate::engine::AnyRegex::new(Self { $field: self.$field.clone_reset() })
+            }
+        }
+    };
+}
+
+/// Like std::convert::Into, except that the conversion may optionally
+/// use the current item of parse input in addition to `self`.
+///
+/// Weight types should at least provide a reflexive implementation
+/// which ignores the input and performs an identity conversion, so that
+/// grammars can explicitly choose which type of weight to produce.
+///
+/// Weight types should also provide an implementation for converting
+/// `bool` to a weight. This may be as simple as ignoring the input and
+/// just returning `one()` if `self` is `true`, or `zero()` otherwise.
+/// But weight implementations can choose to record the matched input
+/// within the weights, and this trait allows generic bool-returning
+/// grammars to work with those weights.
+pub trait IntoWithInput<T, M> {
+    fn into_with_input(self, input: &T) -> M;
+}
+
+/// Grammar types _may_ implement `Describe`, which lets generic code
+/// inspect how a grammar was put together without knowing its concrete
+/// type.
+pub trait Describe<T, M> {
+    fn describe(&self) -> Node<'_, T, M>;
+
+    /// Bytes this node owns on the heap, not counting any children
+    /// reported by `describe`, which report their own.
+    fn heap_size(&self) -> usize { 0 }
+
+    /// Whether this grammar had marks in progress after the last
+    /// shift, if it keeps track. `AnyRegex` does, so every child of a
+    /// built-in combinator can answer.
+    fn activity(&self) -> Option<bool> { None }
+
+    /// For complements, intersections, and other nodes which follow
+    /// each match attempt separately, the attempts in progress. The
+    /// children reported by `describe` never have any marks in them.
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> { None }
+}
+
+/// The state of a node which follows each match attempt, from
+/// `Describe::attempts`.
+pub struct Attempts<'a, T: 'a, M: 'a> {
+    /// The total weight of attempts which will match after any item
+    /// from now on.
+    pub settled: &'a M,
+    /// Each attempt in progress, with the total weight of the marks
+    /// that started it, and a copy of the node which follows only that
+    /// attempt.
+    pub attempts: Vec<(&'a M, &'a dyn Describe<T, M>)>,
+}
+
+/// Grammars that can be both cloned and described, which is what most
+/// code that stores or inspects grammars needs. Functions returning
+/// `AnyRegex<T, M, impl Grammar<T, M>>` can hide their node types
+/// without giving up either.
+pub trait Grammar<T, M>: CloneRegex<T, M> + Describe<T, M> {}
+
+impl<T, M, R: CloneRegex<T, M> + Describe<T, M>> Grammar<T, M> for R {}
+
+impl<T, M, R: Describe<T, M>> Describe<T, M> for AnyRegex<T, M, R> {
+    fn describe(&self) -> Node<'_, T, M> { self.re.describe() }
+    fn heap_size(&self) -> usize { self.re.heap_size() }
+    fn activity(&self) -> Option<bool> { Some(self.active) }
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> { self.re.attempts() }
+}
+
+impl<T, M, R: Leaf<T, M>> Leaf<T, M> for AnyRegex<T, M, R> {
+    fn weigh(&self, c : &T) -> M { self.re.weigh(c) }
+    fn fmt_leaf(&self, f: &mut fmt::Formatter) -> fmt::Result { self.re.fmt_leaf(f) }
+}
+
+/// Grammars which match exactly one item, like those built by `is`,
+/// can expose the weight they'd give each item through `Leaf`.
+pub trait Leaf<T, M> {
+    fn weigh(&self, c : &T) -> M;
+
+    /// Describe the items this leaf accepts, for displaying grammars
+    /// which contain it. Leaves which can't describe themselves are
+    /// shown as `<is>`; giving them a `label` is usually easier than
+    /// overriding this.
+    fn fmt_leaf(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("<is>") }
+}
+
+/// One level of a grammar's structure, as reported by `Describe`.
+pub enum Node<'a, T: 'a, M: 'a> {
+    /// Matches only the empty string.
+    Empty,
+    /// Matches one item using an arbitrary function.
+    Is(&'a dyn Leaf<T, M>),
+    /// Matches the same language as the inner grammar, which has been
+    /// given a human-readable description.
+    Label(&'a str, &'a dyn Describe<T, M>),
+    Not(&'a dyn Describe<T, M>),
+    Or(&'a dyn Describe<T, M>, &'a dyn Describe<T, M>),
+    And(&'a dyn Describe<T, M>, &'a dyn Describe<T, M>),
+    /// Concatenation, along with the mark the left side has produced
+    /// but the right side hasn't consumed yet.
+    Sequence(&'a dyn Describe<T, M>, &'a dyn Describe<T, M>, &'a M),
+    /// Repetition, along with the mark that will be fed back into the
+    /// inner grammar on the next shift.
+    Many(&'a dyn Describe<T, M>, &'a M),
+    /// A lazily-constructed, possibly recursive, grammar.
+    Delay,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    struct Named<R> {
+        re: AnyRegex<char, Match, R>,
+    }
+
+    delegate_regex! {
+        impl[R: CloneRegex<char, Match>] Regex<char, Match> + CloneRegex for Named<R> { self.re }
+    }
+
+    struct Tuple<R>(AnyRegex<char, Match, R>);
+
+    delegate_regex! {
+        impl[R: CloneRegex<char, Match>] Regex<char, Match> + CloneRegex for Tuple<R> { self.0 }
+    }
+
+    #[test]
+    fn delegation() {
+        let a = || is(|&c| c == 'a');
+        let mut re = AnyRegex::new(Named { re: many(a()) });
+        assert!(has_match(&mut re, "aaa".chars()));
+        assert!(!has_match(&mut re.clone_reset(), "ab".chars()));
+        let mut re = AnyRegex::new(Tuple(a() + a()));
+        assert!(has_match(&mut re, "aa".chars()));
+        assert!(!has_match(&mut re.clone_reset(), "a".chars()));
+    }
+
+    #[test]
+    fn notified() {
+        let mut re: AnyRegex<char, Match, _> = is(|&c: &char| c == 'a') + many(is(|&c: &char| c != '!'));
+        let mut seen = Vec::new();
+        let weight = re.over_notify("xyz".chars(), |at, _| { seen.push(at); ControlFlow::Continue(()) });
+        assert!(weight.is_zero() && seen.is_empty());
+
+        let weight = re.over_notify("abc!d".chars(), |at, _| {
+            seen.push(at);
+            if at < 2 { ControlFlow::Continue(()) } else { ControlFlow::Break(()) }
+        });
+        assert!(!weight.is_zero());
+        assert_eq!(seen, vec![1, 2]);
+
+        // An empty match is reported before reading anything.
+        seen.clear();
+        many(is(|&c: &char| c == 'a')).over_notify("ab".chars(), |at, _: &Match| { seen.push(at); ControlFlow::Continue(()) });
+        assert_eq!(seen, vec![0, 1]);
+    }
+
+    #[test]
+    fn mapped_weight() {
+        use weights::leftmost::Leftmost;
+        let mut re = many(is(|&c: &char| c == 'a'));
+        let describe = |m: Leftmost| if m.is_zero() { "no" } else { "yes" };
+        assert_eq!(re.over_map("aa".chars(), describe), "yes");
+        assert_eq!(re.over_map("ab".chars(), describe), "no");
+    }
+}
@@ -0,0 +1,225 @@
+//! Explaining why an input didn't match.
+//!
+//! `has_match` only says no. `explain` matches the same way, but when
+//! the match fails it reports how far into the input the grammar still
+//! had marks in progress, and describes the leaves that could have
+//! accepted the next item there, like a parser's "expected one of"
+//! error message.
+//!
+//! Leaves are described by their label, when the label is directly on
+//! the leaf, or else by their own description, falling back to `<is>`.
+//! What a `delay`ed grammar expects can't be inspected, so it's shown
+//! as `<delay>`.
+//!
+//! The same information is available while feeding a grammar one item
+//! at a time, through `AnyRegex::next_allowed` and
+//! `AnyRegex::next_items`, for things like autocompletion.
+
+use analysis::nullable;
+use core::{AnyRegex, Describe, Leaf, Node, Regex};
+use num_traits::{Zero, zero, One, one};
+use std::error::Error;
+use std::fmt;
+use structural::leaf_text;
+
+/// Why an input didn't match, from `explain`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchFailure {
+    /// How many items were consumed at the furthest point where the
+    /// grammar still had marks in progress.
+    pub position: usize,
+    /// Whether the input ended at `position`. Otherwise, the item there
+    /// wasn't accepted.
+    pub at_end: bool,
+    /// Descriptions of the leaves which could have accepted an item at
+    /// `position`, without duplicates. If this is empty, nothing more
+    /// was allowed there.
+    pub expected: Vec<String>,
+}
+
+impl fmt::Display for MatchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.at_end {
+            write!(f, "input ended after {} items", self.position)?;
+        } else {
+            write!(f, "unexpected item at position {}", self.position)?;
+        }
+        if self.expected.is_empty() {
+            f.write_str(", expected end of input")
+        } else {
+            write!(f, ", expected one of: {}", self.expected.join(", "))
+        }
+    }
+}
+
+impl Error for MatchFailure {}
+
+/// Match `over` like `AnyRegex::over`, returning the weight if it's
+/// nonzero, or an explanation of where matching failed. This inspects
+/// the whole grammar before every item, so it's much slower than
+/// matching alone.
+pub fn explain<T, M, R, I>(re: &mut AnyRegex<T, M, R>, over: I) -> Result<M, MatchFailure> where
+    M: Zero + One,
+    R: Regex<T, M> + Describe<T, M>,
+    I: IntoIterator<Item=T>,
+{
+    // The furthest point reached so far, assuming the input ends there.
+    let mut failure = MatchFailure { position: 0, at_end: true, expected: expected(re, true) };
+    let mut result = None;
+    for (position, c) in over.into_iter().enumerate() {
+        if failure.position == position {
+            failure.at_end = false;
+        }
+        let mark = if result.is_none() { one() } else { zero() };
+        let out = re.shift(&c, mark);
+        if re.active() || !out.is_zero() {
+            failure = MatchFailure { position: position + 1, at_end: true, expected: expected(re, false) };
+        }
+        result = Some(out);
+    }
+    let result = match result {
+        Some(result) => result,
+        None => if re.empty() { one() } else { zero() },
+    };
+    re.reset();
+    if result.is_zero() { Err(failure) } else { Ok(result) }
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero,
+    R: Describe<T, M>,
+{
+    /// Describe the leaves which could accept the next item, in the
+    /// same way as `MatchFailure::expected`. Pass `at_start` if the
+    /// next shift will start a match, with a mark of `one()`, as the
+    /// first shift usually does; otherwise only matches already in
+    /// progress are considered.
+    pub fn next_allowed(&self, at_start: bool) -> Vec<String> {
+        expected(self, at_start)
+    }
+
+    /// Pick out the `candidates` which some leaf could accept next,
+    /// under the same conditions as `next_allowed`. What a `delay`ed
+    /// grammar would accept can't be inspected, so it doesn't allow
+    /// any candidates.
+    pub fn next_items<I>(&self, at_start: bool, candidates: I) -> Vec<T> where
+        I: IntoIterator<Item=T>,
+    {
+        let mut leaves = Vec::new();
+        frontier(self, at_start, &mut leaves);
+        candidates.into_iter().filter(|c| {
+            leaves.iter().any(|next| match *next {
+                Next::Leaf(leaf, _) => !leaf.weigh(c).is_zero(),
+                Next::Delay => false,
+            })
+        }).collect()
+    }
+}
+
+/// Something which could accept the next item.
+enum Next<'a, T: 'a, M: 'a> {
+    /// A leaf, with the label directly on it, if any.
+    Leaf(&'a dyn Leaf<T, M>, Option<&'a str>),
+    Delay,
+}
+
+/// Describe the leaves of `re` which would receive a mark on the next
+/// shift, given whether the shift brings a new one.
+fn expected<T, M: Zero>(re: &dyn Describe<T, M>, incoming: bool) -> Vec<String> {
+    let mut next = Vec::new();
+    frontier(re, incoming, &mut next);
+    let mut found = Vec::new();
+    for next in next {
+        let text = match next {
+            Next::Leaf(_, Some(label)) => label.to_string(),
+            Next::Leaf(leaf, None) => leaf_text(leaf).unwrap_or_else(|| "<is>".to_string()),
+            Next::Delay => "<delay>".to_string(),
+        };
+        if !found.contains(&text) {
+            found.push(text);
+        }
+    }
+    found
+}
+
+fn frontier<'a, T, M: Zero>(re: &'a dyn Describe<T, M>, incoming: bool, found: &mut Vec<Next<'a, T, M>>) {
+    // Complements and intersections keep a copy of their children for
+    // each match attempt, and only a new attempt reaches the children
+    // they describe.
+    if let Some(state) = re.attempts() {
+        for (_, attempt) in state.attempts {
+            frontier(attempt, false, found);
+        }
+    }
+    match re.describe() {
+        Node::Empty => {}
+        Node::Is(leaf) => if incoming { found.push(Next::Leaf(leaf, None)) },
+        Node::Label(text, inner) => match inner.describe() {
+            Node::Is(leaf) => if incoming { found.push(Next::Leaf(leaf, Some(text))) },
+            _ => frontier(inner, incoming, found),
+        },
+        Node::Delay => if incoming { found.push(Next::Delay) },
+        Node::Not(inner) => frontier(inner, incoming, found),
+        Node::Or(left, right) | Node::And(left, right) => {
+            frontier(left, incoming, found);
+            frontier(right, incoming, found);
+        }
+        Node::Sequence(left, right, from_left) => {
+            frontier(left, incoming, found);
+            frontier(right, (incoming && nullable(left)) || !from_left.is_zero(), found);
+        }
+        Node::Many(inner, marked) => frontier(inner, incoming || !marked.is_zero(), found),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    fn keyword() -> AnyRegex<char, Match, impl Regex<char, Match> + Describe<char, Match>> {
+        let letter = |c: char| is(move |&x| x == c).label(c.to_string());
+        letter('i') + letter('f') + (letter(' ') | letter('('))
+    }
+
+    #[test]
+    fn furthest_position() {
+        let mut re = keyword();
+        let failure = explain(&mut re, "ix".chars()).err().unwrap();
+        assert_eq!(failure, MatchFailure { position: 1, at_end: false, expected: vec!["f".to_string()] });
+        assert_eq!(failure.to_string(), "unexpected item at position 1, expected one of: f");
+
+        let failure = explain(&mut re, "if".chars()).err().unwrap();
+        assert_eq!(failure.to_string(), "input ended after 2 items, expected one of:  , (");
+
+        let failure = explain(&mut re, "if(x".chars()).err().unwrap();
+        assert_eq!(failure.to_string(), "unexpected item at position 3, expected end of input");
+
+        let failure = explain(&mut re, "".chars()).err().unwrap();
+        assert_eq!(failure.to_string(), "input ended after 0 items, expected one of: i");
+
+        assert!(explain(&mut re, "if(".chars()).is_ok());
+    }
+
+    #[test]
+    fn repetition() {
+        let digit = || is(|c: &char| c.is_ascii_digit()).label("[0-9]");
+        let mut re: AnyRegex<char, Match, _> = digit() + many(digit()) + is(|&c| c == ';').label(";");
+        let failure = explain(&mut re, "12x".chars()).err().unwrap();
+        assert_eq!(failure.expected, vec!["[0-9]".to_string(), ";".to_string()]);
+        assert_eq!(failure.position, 2);
+    }
+
+    #[test]
+    fn completion() {
+        let mut re = keyword();
+        assert_eq!(re.next_allowed(true), vec!["i".to_string()]);
+        re.shift(&'i', one());
+        assert_eq!(re.next_allowed(false), vec!["f".to_string()]);
+        re.shift(&'f', zero());
+        assert_eq!(re.next_allowed(false), vec![" ".to_string(), "(".to_string()]);
+        assert_eq!(re.next_items(false, "abc (".chars()), vec![' ', '(']);
+        re.shift(&'(', zero());
+        assert!(re.next_allowed(false).is_empty());
+    }
+}
@@ -0,0 +1,961 @@
+//! Grammars flattened into arrays.
+//!
+//! A grammar built from the combinators in `grammars` is a deeply
+//! nested tree of generic structs, and shifting an item walks the
+//! whole tree recursively. `Flat` holds the same grammar as a
+//! contiguous array of node descriptors, numbered in preorder, with the
+//! state of every node in one `Vec<M>`. Shifting is two loops over
+//! those arrays.
+//!
+//! That works because marks flow down the tree without depending on
+//! anything computed during the same shift. A `Sequence` passes its
+//! right child the mark its left child produced on the *previous*
+//! shift, and `Many` does the same with its own output. So the first
+//! loop pushes the incoming mark from each node down to its children,
+//! in preorder. The second loop walks back up, combining the
+//! children's results in reverse preorder.
+//!
+//! Subtrees with no marks in them and none coming in are skipped, just
+//! as `AnyRegex` skips inactive grammars.
+//!
+//! Complements and intersections are the exception to all of that,
+//! because whether they match depends on where each match attempt
+//! started. As in `grammars`, they keep a separate copy of their
+//! subtree's state for each attempt, and shift each copy by swapping it
+//! into the arrays and running the two loops over just that subtree.
+//!
+//! Resetting doesn't touch the marks at all. Each node's state is
+//! stamped with the generation it was last written in, and `reset` just
+//! starts a new generation, so anything stamped with an older one reads
+//! as if it had been reset. That keeps `reset` constant-time, which
+//! matters when a large grammar is matched against many short inputs.
+//!
+//! The node array never changes after it's built, so it can also be
+//! shared: a `Compiled` grammar owns the original grammar and its node
+//! array, and hands out any number of independent `State`s for
+//! matching, across threads if the grammar's leaves allow it.
+//!
+//! Machine-generated grammars often repeat the same leaf in many
+//! places. Flattening with `interned` finds leaves which are the same
+//! object, or which describe themselves identically, and evaluates
+//! each of them at most once per item, however many nodes use it. The
+//! nodes themselves are never merged, because the same leaf at two
+//! positions in a grammar carries different marks.
+//!
+//! A `Flat` grammar can also count how often each of its nodes was
+//! shifted, and how often the shift skipped it for having no marks, to
+//! show which parts of a grammar the time goes to. With the `debug`
+//! feature, it can also report the marks going into and out of every
+//! node it shifts, through `Flat::observe`.
+
+use core::{AnyRegex, Describe, Leaf, Node, Regex};
+use num_traits::{Zero, zero, One, one};
+use std::collections::HashMap;
+use std::mem::{replace, swap};
+use std::ops;
+use structural::leaf_text;
+use syntax::{Inexpressible, to_extended_string};
+
+enum Op {
+    Empty,
+    /// A leaf, by its index in preorder among all the leaves.
+    Is(usize),
+    Not(usize),
+    Or(usize, usize),
+    And(usize, usize),
+    Sequence(usize, usize),
+    Many(usize),
+}
+
+/// The immutable part of a flattened grammar.
+struct Program {
+    ops: Vec<Op>,
+    /// One past the last node in each node's subtree.
+    end: Vec<usize>,
+    nullable: Vec<bool>,
+    /// For each leaf, whether more than one node refers to it, so its
+    /// weight is worth remembering for the rest of the shift.
+    shared: Vec<bool>,
+}
+
+/// The state of the nodes under a complement or intersection, for one
+/// match attempt. Each vector is indexed from the node's first child.
+#[derive(Clone, PartialEq)]
+struct Attempt<M> {
+    active: Vec<bool>,
+    marks: Vec<M>,
+    splits: Vec<Split<M>>,
+}
+
+/// The match attempts a complement or intersection is following.
+#[derive(Clone, PartialEq)]
+struct Split<M> {
+    /// Attempts in progress, each with the total weight of the marks
+    /// that started it.
+    attempts: Vec<(M, Attempt<M>)>,
+    /// The total weight of attempts which will match after any item
+    /// from now on.
+    settled: M,
+}
+
+/// The mutable part of a flattened grammar.
+struct Marks<M> {
+    generation: u32,
+    /// The generation each node's `active` and `marks` were written in.
+    stamps: Vec<u32>,
+    active: Vec<bool>,
+    /// The mark `Sequence` and `Many` nodes carry between shifts.
+    marks: Vec<M>,
+    /// The attempts each complement or intersection is following.
+    splits: Vec<Split<M>>,
+    /// Attempts which are no longer in use, so they can be reused
+    /// without allocating.
+    spare: Vec<Attempt<M>>,
+    /// Scratch space for marks flowing down, then up, during a shift.
+    flow: Vec<M>,
+    visited: Vec<usize>,
+    /// Weights of shared leaves already evaluated during this shift.
+    weights: Vec<Option<M>>,
+    /// Shifted and skipped counts for each node, when profiling.
+    profile: Option<Vec<(u64, u64)>>,
+}
+
+/// A callback given a node's index, the item being shifted, and the
+/// node's incoming and outgoing marks.
+type Observer<'o, T, M> = dyn FnMut(usize, &T, &M, &M) + 'o;
+
+/// What makes two leaves interchangeable.
+#[derive(PartialEq, Eq, Hash)]
+enum LeafKey {
+    Text(String),
+    Address(usize),
+}
+
+impl Program {
+    fn new<'a, T, M>(re: &'a dyn Describe<T, M>, leaves: &mut Vec<&'a dyn Leaf<T, M>>, intern: bool) -> Result<Self, Inexpressible> {
+        let mut program = Program {
+            ops: Vec::new(),
+            end: Vec::new(),
+            nullable: Vec::new(),
+            shared: Vec::new(),
+        };
+        program.push(re, leaves)?;
+        program.shared = leaves.iter().map(|_| false).collect();
+        if intern {
+            program.intern(leaves);
+        }
+        Ok(program)
+    }
+
+    /// Point every leaf node at the first leaf interchangeable with its
+    /// own.
+    fn intern<T, M>(&mut self, leaves: &[&dyn Leaf<T, M>]) {
+        let mut first = HashMap::new();
+        let canonical: Vec<usize> = leaves.iter().enumerate().map(|(i, &leaf)| {
+            let key = match leaf_text(leaf) {
+                Some(text) => LeafKey::Text(text),
+                None => LeafKey::Address(leaf as *const dyn Leaf<T, M> as *const () as usize),
+            };
+            *first.entry(key).or_insert(i)
+        }).collect();
+        for op in self.ops.iter_mut() {
+            if let Op::Is(ref mut leaf) = *op {
+                if canonical[*leaf] != *leaf {
+                    *leaf = canonical[*leaf];
+                    self.shared[*leaf] = true;
+                }
+            }
+        }
+    }
+
+    /// Add `re` and its descendants in preorder, returning its index.
+    fn push<'a, T, M>(&mut self, re: &'a dyn Describe<T, M>, leaves: &mut Vec<&'a dyn Leaf<T, M>>) -> Result<usize, Inexpressible> {
+        let node = re.describe();
+        if let Node::Label(_, inner) = node {
+            return self.push(inner, leaves);
+        }
+
+        let i = self.ops.len();
+        self.ops.push(Op::Empty);
+        self.end.push(0);
+        self.nullable.push(false);
+        let (op, nullable) = match node {
+            Node::Empty => (Op::Empty, true),
+            Node::Is(leaf) => {
+                leaves.push(leaf);
+                (Op::Is(leaves.len() - 1), false)
+            }
+            Node::Label(_, _) => unreachable!(),
+            Node::Not(inner) => {
+                let inner = self.push(inner, leaves)?;
+                (Op::Not(inner), !self.nullable[inner])
+            }
+            Node::Or(left, right) => {
+                let (left, right) = (self.push(left, leaves)?, self.push(right, leaves)?);
+                (Op::Or(left, right), self.nullable[left] || self.nullable[right])
+            }
+            Node::And(left, right) => {
+                let (left, right) = (self.push(left, leaves)?, self.push(right, leaves)?);
+                (Op::And(left, right), self.nullable[left] && self.nullable[right])
+            }
+            Node::Sequence(left, right, _) => {
+                let (left, right) = (self.push(left, leaves)?, self.push(right, leaves)?);
+                (Op::Sequence(left, right), self.nullable[left] && self.nullable[right])
+            }
+            Node::Many(inner, _) => (Op::Many(self.push(inner, leaves)?), true),
+            Node::Delay => return Err(Inexpressible::Recursion),
+        };
+        self.ops[i] = op;
+        self.nullable[i] = nullable;
+        self.end[i] = self.ops.len();
+        Ok(i)
+    }
+}
+
+/// Describe each node of `re` in the same order `Program::push` adds
+/// them, by its innermost label if it has one.
+fn node_texts<T, M>(re: &dyn Describe<T, M>, label: Option<&str>, texts: &mut Vec<String>) {
+    let node = re.describe();
+    if let Node::Label(text, inner) = node {
+        return node_texts(inner, Some(label.unwrap_or(text)), texts);
+    }
+    texts.push(label.map_or_else(|| to_extended_string(re), str::to_string));
+    match node {
+        Node::Empty | Node::Is(_) | Node::Delay | Node::Label(_, _) => {}
+        Node::Not(inner) | Node::Many(inner, _) => node_texts(inner, None, texts),
+        Node::Or(left, right) | Node::And(left, right) | Node::Sequence(left, right, _) => {
+            node_texts(left, None, texts);
+            node_texts(right, None, texts);
+        }
+    }
+}
+
+/// Collect the leaves of `re` in the same order `Program::push` does.
+fn leaves<'a, T, M>(re: &'a dyn Describe<T, M>, leaves_so_far: &mut Vec<&'a dyn Leaf<T, M>>) {
+    match re.describe() {
+        Node::Empty | Node::Delay => {}
+        Node::Is(leaf) => leaves_so_far.push(leaf),
+        Node::Label(_, inner) | Node::Not(inner) | Node::Many(inner, _) => leaves(inner, leaves_so_far),
+        Node::Or(left, right) | Node::And(left, right) | Node::Sequence(left, right, _) => {
+            leaves(left, leaves_so_far);
+            leaves(right, leaves_so_far);
+        }
+    }
+}
+
+impl<M> Split<M> where
+    M: Zero,
+{
+    fn new() -> Self {
+        Split { attempts: Vec::new(), settled: zero() }
+    }
+
+    fn is_empty(&self) -> bool { self.attempts.is_empty() && self.settled.is_zero() }
+}
+
+impl<M> Marks<M> where
+    M: Zero,
+{
+    fn new(program: &Program) -> Self {
+        Marks {
+            generation: 0,
+            stamps: program.ops.iter().map(|_| 0).collect(),
+            active: program.ops.iter().map(|_| false).collect(),
+            marks: program.ops.iter().map(|_| zero()).collect(),
+            splits: program.ops.iter().map(|_| Split::new()).collect(),
+            spare: Vec::new(),
+            flow: program.ops.iter().map(|_| zero()).collect(),
+            visited: Vec::new(),
+            weights: if program.shared.contains(&true) {
+                program.shared.iter().map(|_| None).collect()
+            } else {
+                Vec::new()
+            },
+            profile: None,
+        }
+    }
+
+    fn is_active(&self, i: usize) -> bool {
+        self.stamps[i] == self.generation && self.active[i]
+    }
+
+    /// Bring node `i` into the current generation, clearing its state if
+    /// it was left over from an earlier one.
+    fn freshen(&mut self, i: usize) {
+        if self.stamps[i] != self.generation {
+            self.stamps[i] = self.generation;
+            self.active[i] = false;
+            self.marks[i] = zero();
+            self.splits[i] = Split::new();
+        }
+    }
+
+    fn update_active(&mut self, program: &Program, i: usize) {
+        let active = match program.ops[i] {
+            Op::Empty | Op::Is(_) => false,
+            Op::Not(_) | Op::And(_, _) => !self.splits[i].is_empty(),
+            Op::Or(left, right) =>
+                self.is_active(left) || self.is_active(right),
+            Op::Sequence(left, right) => !self.marks[i].is_zero()
+                || self.is_active(left) || self.is_active(right),
+            Op::Many(inner) => !self.marks[i].is_zero() || self.is_active(inner),
+        };
+        self.active[i] = active;
+    }
+
+    fn reset(&mut self) {
+        if self.generation == u32::MAX {
+            // Old stamps are about to become ambiguous, so clear
+            // everything for real this once.
+            for i in 0..self.stamps.len() {
+                self.stamps[i] = 0;
+                self.active[i] = false;
+                self.marks[i] = zero();
+                self.splits[i] = Split::new();
+            }
+            self.generation = 0;
+        } else {
+            self.generation += 1;
+        }
+    }
+}
+
+impl<M> Marks<M> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+{
+    fn shift<T>(&mut self, program: &Program, leaves: &[&dyn Leaf<T, M>], c : &T, mark : M, mut observer: Option<&mut Observer<T, M>>) -> M {
+        // The observer needs each node's incoming mark after the node
+        // has passed it on.
+        let mut incoming: Vec<Option<M>> = match observer {
+            Some(_) => program.ops.iter().map(|_| None).collect(),
+            None => Vec::new(),
+        };
+        let result = self.run(program, leaves, c, 0, mark, &mut incoming, &mut observer);
+        for weight in self.weights.iter_mut() {
+            *weight = None;
+        }
+        result
+    }
+
+    /// Shift the subtree rooted at `start`.
+    #[allow(clippy::too_many_arguments)]
+    fn run<T>(&mut self, program: &Program, leaves: &[&dyn Leaf<T, M>], c : &T, start: usize, mark : M,
+              incoming: &mut Vec<Option<M>>, observer: &mut Option<&mut Observer<T, M>>) -> M {
+        // Nested runs for complements and intersections push their
+        // nodes after ours, and take them off again before returning.
+        let base = self.visited.len();
+
+        // Push marks down. Every node's incoming mark is in `flow`
+        // before the loop reaches it.
+        self.flow[start] = mark;
+        let mut i = start;
+        while i < program.end[start] {
+            if !self.is_active(i) && self.flow[i].is_zero() {
+                if let Some(profile) = self.profile.as_mut() {
+                    profile[i].1 += 1;
+                }
+                i = program.end[i];
+                continue;
+            }
+            if let Some(profile) = self.profile.as_mut() {
+                profile[i].0 += 1;
+            }
+            self.freshen(i);
+            self.visited.push(i);
+            if let Some(slot) = incoming.get_mut(i) {
+                *slot = Some(self.flow[i].clone());
+            }
+            match program.ops[i] {
+                // Leaves keep their mark until the upward pass.
+                Op::Empty | Op::Is(_) => {}
+                // So do complements and intersections, which shift their
+                // subtree once for each attempt on the way back up.
+                Op::Not(_) | Op::And(_, _) => {
+                    i = program.end[i];
+                    continue;
+                }
+                Op::Or(left, right) => {
+                    let mark = replace(&mut self.flow[i], zero());
+                    self.flow[left] = mark.clone();
+                    self.flow[right] = mark;
+                }
+                Op::Sequence(left, right) => {
+                    let mark = replace(&mut self.flow[i], zero());
+                    let skip_empty_left = if !mark.is_zero() && program.nullable[left] {
+                        mark.clone()
+                    } else {
+                        zero()
+                    };
+                    let old_from_left = replace(&mut self.marks[i], zero());
+                    self.flow[left] = mark;
+                    self.flow[right] = skip_empty_left + old_from_left;
+                }
+                Op::Many(inner) => {
+                    let mark = replace(&mut self.flow[i], zero());
+                    self.flow[inner] = mark + replace(&mut self.marks[i], zero());
+                }
+            }
+            i += 1;
+        }
+
+        // Combine results on the way back up. Skipped subtrees
+        // produce zero, which is what `flow` already holds for them.
+        for k in (base..self.visited.len()).rev() {
+            let i = self.visited[k];
+            let result = match program.ops[i] {
+                Op::Empty => {
+                    self.flow[i] = zero();
+                    zero()
+                }
+                Op::Is(leaf) => {
+                    let mark = replace(&mut self.flow[i], zero());
+                    if mark.is_zero() {
+                        mark
+                    } else if program.shared[leaf] {
+                        let weight = self.weights[leaf].get_or_insert_with(|| leaves[leaf].weigh(c));
+                        mark * weight.clone()
+                    } else {
+                        mark * leaves[leaf].weigh(c)
+                    }
+                }
+                Op::Not(_) | Op::And(_, _) => {
+                    let mark = replace(&mut self.flow[i], zero());
+                    self.split(program, leaves, c, i, mark, incoming, observer)
+                }
+                Op::Or(left, right) =>
+                    replace(&mut self.flow[left], zero()) + replace(&mut self.flow[right], zero()),
+                Op::Sequence(left, right) => {
+                    let from_left = replace(&mut self.flow[left], zero());
+                    let skip_empty_right = if !from_left.is_zero() && program.nullable[right] {
+                        from_left.clone()
+                    } else {
+                        zero()
+                    };
+                    self.marks[i] = from_left;
+                    skip_empty_right + replace(&mut self.flow[right], zero())
+                }
+                Op::Many(inner) => {
+                    let result = replace(&mut self.flow[inner], zero());
+                    self.marks[i] = result.clone();
+                    result
+                }
+            };
+            if let Some(observer) = observer.as_mut() {
+                observer(i, c, incoming[i].as_ref().unwrap(), &result);
+            }
+            self.flow[i] = result;
+            self.update_active(program, i);
+        }
+
+        self.visited.truncate(base);
+        replace(&mut self.flow[start], zero())
+    }
+
+    /// Shift every attempt the complement or intersection at `i` is
+    /// following, and start a new one if `mark` is nonzero. Attempts
+    /// whose result can't change any more are retired, and attempts
+    /// whose subtrees have equal marks in all the same places are
+    /// merged, since they'll agree from now on.
+    #[allow(clippy::too_many_arguments)]
+    fn split<T>(&mut self, program: &Program, leaves: &[&dyn Leaf<T, M>], c : &T, i: usize, mark : M,
+                incoming: &mut Vec<Option<M>>, observer: &mut Option<&mut Observer<T, M>>) -> M {
+        let mut split = replace(&mut self.splits[i], Split::new());
+        let mut result = split.settled.clone();
+        for &mut (ref weight, ref mut attempt) in &mut split.attempts {
+            let out = self.resume(program, leaves, c, i, attempt, zero(), incoming, observer);
+            if !out.is_zero() {
+                result = result + weight.clone() * out;
+            }
+        }
+        if !mark.is_zero() {
+            let mut attempt = self.fresh_attempt(program.end[i] - i - 1);
+            let out = self.resume(program, leaves, c, i, &mut attempt, one(), incoming, observer);
+            if !out.is_zero() {
+                result = result + mark.clone() * out;
+            }
+            split.attempts.push((mark, attempt));
+        }
+
+        let attempts = &mut split.attempts;
+        let mut k = 0;
+        while k < attempts.len() {
+            let active = &attempts[k].1.active;
+            let merge = match program.ops[i] {
+                // A complement matches everything once its subtree
+                // can't, and an intersection nothing once either side
+                // can't.
+                Op::Not(_) if !active[0] => Some(None),
+                Op::And(left, right) if !active[left - i - 1] || !active[right - i - 1] => None,
+                _ => match (0..k).find(|&j| attempts[j].1.same_marks(&attempts[k].1)) {
+                    Some(j) => Some(Some(j)),
+                    None => {
+                        k += 1;
+                        continue;
+                    }
+                },
+            };
+            let (weight, attempt) = attempts.swap_remove(k);
+            self.spare.push(attempt);
+            let total = match merge {
+                Some(Some(j)) => &mut attempts[j].0,
+                Some(None) => &mut split.settled,
+                None => continue,
+            };
+            *total = replace(total, zero()) + weight;
+        }
+        self.splits[i] = split;
+        result
+    }
+
+    /// Swap `attempt` into the subtree under the node at `i`, shift it
+    /// as if it were the node's only attempt, and swap it back out.
+    #[allow(clippy::too_many_arguments)]
+    fn resume<T>(&mut self, program: &Program, leaves: &[&dyn Leaf<T, M>], c : &T, i: usize, attempt: &mut Attempt<M>, mark : M,
+                 incoming: &mut Vec<Option<M>>, observer: &mut Option<&mut Observer<T, M>>) -> M {
+        self.swap_attempt(i + 1, attempt);
+        let result = match program.ops[i] {
+            Op::Not(inner) => {
+                if self.run(program, leaves, c, inner, mark, incoming, observer).is_zero() { one() } else { zero() }
+            }
+            Op::And(left, right) => {
+                let left = self.run(program, leaves, c, left, mark.clone(), incoming, observer);
+                left * self.run(program, leaves, c, right, mark, incoming, observer)
+            }
+            _ => unreachable!(),
+        };
+        self.swap_attempt(i + 1, attempt);
+        result
+    }
+
+    /// Exchange the state of the nodes from `start` on with `attempt`.
+    /// Outside of `resume`, the nodes under a complement or intersection
+    /// always hold the state of a fresh attempt.
+    fn swap_attempt(&mut self, start: usize, attempt: &mut Attempt<M>) {
+        for k in 0..attempt.active.len() {
+            self.stamps[start + k] = self.generation;
+            swap(&mut self.active[start + k], &mut attempt.active[k]);
+            swap(&mut self.marks[start + k], &mut attempt.marks[k]);
+            swap(&mut self.splits[start + k], &mut attempt.splits[k]);
+        }
+    }
+
+    /// An attempt for a subtree of `len` nodes, reusing a spare one if
+    /// there is one.
+    fn fresh_attempt(&mut self, len: usize) -> Attempt<M> {
+        let mut attempt = self.spare.pop().unwrap_or_else(|| Attempt {
+            active: Vec::new(),
+            marks: Vec::new(),
+            splits: Vec::new(),
+        });
+        attempt.active.clear();
+        attempt.active.resize(len, false);
+        attempt.marks.clear();
+        attempt.marks.extend((0..len).map(|_| zero()));
+        attempt.splits.clear();
+        attempt.splits.extend((0..len).map(|_| Split::new()));
+        attempt
+    }
+}
+
+impl<M> Attempt<M> where
+    M: Zero + PartialEq,
+{
+    /// Whether the two attempts have equal marks in all the same
+    /// places. Attempts with attempts of their own in progress are never
+    /// considered the same.
+    fn same_marks(&self, other: &Attempt<M>) -> bool {
+        self.active == other.active
+            && self.marks == other.marks
+            && self.splits.iter().chain(&other.splits).all(Split::is_empty)
+    }
+}
+
+/// A grammar compiled into flat arrays. It implements `Regex` with
+/// the same weights as the grammar it came from.
+pub struct Flat<'a, T: 'a, M: 'a> {
+    re: &'a dyn Describe<T, M>,
+    program: Program,
+    leaves: Vec<&'a dyn Leaf<T, M>>,
+    marks: Marks<M>,
+    #[cfg(feature = "debug")]
+    observer: Option<Box<Observer<'a, T, M>>>,
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero,
+    R: Describe<T, M>,
+{
+    /// Compile this grammar into flat arrays, borrowing its leaves.
+    /// Recursive grammars built with `delay` can't be flattened.
+    pub fn to_flat(&self) -> Result<Flat<'_, T, M>, Inexpressible> {
+        Flat::new(self)
+    }
+}
+
+impl<'a, T, M> Flat<'a, T, M> where
+    M: Zero,
+{
+    /// Like `AnyRegex::to_flat`, but for any grammar node.
+    pub fn new(re: &'a dyn Describe<T, M>) -> Result<Self, Inexpressible> {
+        Flat::build(re, false)
+    }
+
+    /// Like `new`, but evaluate repeated leaves only once per item.
+    pub fn interned(re: &'a dyn Describe<T, M>) -> Result<Self, Inexpressible> {
+        Flat::build(re, true)
+    }
+
+    fn build(re: &'a dyn Describe<T, M>, intern: bool) -> Result<Self, Inexpressible> {
+        let mut leaves = Vec::new();
+        let program = Program::new(re, &mut leaves, intern)?;
+        let marks = Marks::new(&program);
+        Ok(Flat {
+            re,
+            program,
+            leaves,
+            marks,
+            #[cfg(feature = "debug")]
+            observer: None,
+        })
+    }
+
+    /// How many nodes the grammar was flattened into. Labels don't get
+    /// nodes of their own.
+    pub fn node_count(&self) -> usize { self.program.ops.len() }
+
+    /// Start counting shifts for each node, from zero, for `profile`.
+    pub fn profile_shifts(&mut self) {
+        self.marks.profile = Some(self.program.ops.iter().map(|_| (0, 0)).collect());
+    }
+
+    /// How often each node has been shifted since `profile_shifts`, in
+    /// preorder. This is empty if profiling was never started.
+    pub fn profile(&self) -> Vec<NodeProfile> {
+        let counts = match self.marks.profile {
+            Some(ref counts) => counts,
+            None => return Vec::new(),
+        };
+        let mut texts = Vec::new();
+        node_texts(self.re, None, &mut texts);
+        texts.into_iter().zip(counts).enumerate().map(|(node, (text, &(shifted, skipped)))| {
+            NodeProfile { node, text, shifted, skipped }
+        }).collect()
+    }
+}
+
+#[cfg(feature = "debug")]
+impl<'a, T, M> Flat<'a, T, M> {
+    /// Call `observer` for every node that does work during a shift,
+    /// with the node's index in preorder as in `profile`, the item, and
+    /// the marks that went into and came out of the node. Children are
+    /// reported before their parents.
+    pub fn observe<F>(&mut self, observer: F) where
+        F: FnMut(usize, &T, &M, &M) + 'a,
+    {
+        self.observer = Some(Box::new(observer));
+    }
+}
+
+/// How often one node of a profiled `Flat` grammar was shifted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeProfile {
+    /// The node's position in preorder, not counting labels.
+    pub node: usize,
+    /// The node's label, or else the node in the extended syntax
+    /// `Display` uses.
+    pub text: String,
+    /// Shifts which did work in this node.
+    pub shifted: u64,
+    /// Shifts which reached this node but skipped it, and everything
+    /// in it, because it had no marks.
+    pub skipped: u64,
+}
+
+impl<'a, T, M> Regex<T, M> for Flat<'a, T, M> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+{
+    fn empty(&mut self) -> bool { self.program.nullable[0] }
+    fn active(&self) -> bool { self.marks.is_active(0) }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        #[cfg(feature = "debug")]
+        let observer = self.observer.as_mut().map(|f| &mut **f as &mut Observer<T, M>);
+        #[cfg(not(feature = "debug"))]
+        let observer = None;
+        self.marks.shift(&self.program, &self.leaves, c, mark, observer)
+    }
+    fn reset(&mut self) { self.marks.reset() }
+}
+
+/// A flattened grammar which owns the original and never changes, so
+/// one copy can serve any number of matches at once. If the grammar's
+/// leaves are `Sync`, so is `Compiled`, and it can be shared between
+/// threads, for example behind an `Arc`.
+pub struct Compiled<T, M, R> {
+    re: AnyRegex<T, M, R>,
+    program: Program,
+}
+
+impl<T, M, R> Compiled<T, M, R> where
+    M: Zero,
+    R: Describe<T, M>,
+{
+    /// Flatten `re` and take ownership of it. Recursive grammars built
+    /// with `delay` can't be compiled.
+    pub fn new(re: AnyRegex<T, M, R>) -> Result<Self, Inexpressible> {
+        let program = Program::new(&re, &mut Vec::new(), false)?;
+        Ok(Compiled { re, program })
+    }
+
+    /// Like `new`, but evaluate repeated leaves only once per item.
+    pub fn interned(re: AnyRegex<T, M, R>) -> Result<Self, Inexpressible> {
+        let program = Program::new(&re, &mut Vec::new(), true)?;
+        Ok(Compiled { re, program })
+    }
+
+    /// The grammar this was compiled from.
+    pub fn grammar(&self) -> &AnyRegex<T, M, R> { &self.re }
+}
+
+impl<T, M, R> Compiled<T, M, R> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    R: Describe<T, M>,
+{
+    /// Start a new, independent match. This collects references to the
+    /// grammar's leaves and allocates the marks, but doesn't copy the
+    /// grammar. A `State` can be `reset` and reused for further matches.
+    pub fn state(&self) -> AnyRegex<T, M, State<'_, T, M>> {
+        let mut leaves_so_far = Vec::new();
+        leaves(&self.re, &mut leaves_so_far);
+        AnyRegex::new(State {
+            program: &self.program,
+            leaves: leaves_so_far,
+            marks: Marks::new(&self.program),
+        })
+    }
+}
+
+/// The marks for one match against a `Compiled` grammar.
+pub struct State<'a, T: 'a, M: 'a> {
+    program: &'a Program,
+    leaves: Vec<&'a dyn Leaf<T, M>>,
+    marks: Marks<M>,
+}
+
+/// The marks of a `State` between two shifts, which can be compared
+/// with another's, or put back later.
+#[derive(Clone, PartialEq)]
+pub(crate) struct Snapshot<M> {
+    active: Vec<bool>,
+    marks: Vec<M>,
+    splits: Vec<Split<M>>,
+}
+
+impl<'a, T, M> State<'a, T, M> where
+    M: Zero + Clone,
+{
+    pub(crate) fn snapshot(&self) -> Snapshot<M> {
+        let marks = &self.marks;
+        let nodes = 0..self.program.ops.len();
+        let fresh = |i: usize| marks.stamps[i] == marks.generation;
+        Snapshot {
+            active: nodes.clone().map(|i| marks.is_active(i)).collect(),
+            marks: nodes.clone().map(|i| if fresh(i) { marks.marks[i].clone() } else { zero() }).collect(),
+            splits: nodes.map(|i| if fresh(i) { marks.splits[i].clone() } else { Split::new() }).collect(),
+        }
+    }
+
+    pub(crate) fn restore(&mut self, snapshot: &Snapshot<M>) {
+        let marks = &mut self.marks;
+        for i in 0..self.program.ops.len() {
+            marks.stamps[i] = marks.generation;
+            marks.active[i] = snapshot.active[i];
+            marks.marks[i] = snapshot.marks[i].clone();
+            marks.splits[i] = snapshot.splits[i].clone();
+        }
+    }
+}
+
+impl<'a, T, M> Regex<T, M> for State<'a, T, M> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+{
+    fn empty(&mut self) -> bool { self.program.nullable[0] }
+    fn active(&self) -> bool { self.marks.is_active(0) }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        self.marks.shift(self.program, &self.leaves, c, mark, None)
+    }
+    fn reset(&mut self) { self.marks.reset() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use num_traits::{zero, one};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn same_as_grammar<R>(re: &mut AnyRegex<u8, Match, R>, to_match: &[u8]) -> bool where
+        R: Regex<u8, Match> + Describe<u8, Match>,
+    {
+        let expected = has_match(re, to_match.iter().cloned());
+        let mut flat = AnyRegex::new(re.to_flat().ok().unwrap());
+        expected == has_match(&mut flat, to_match.iter().cloned())
+    }
+
+    #[test]
+    fn labels_are_transparent() {
+        let re: AnyRegex<u8, Match, _> = many(is(|&c| c > 10).label("big")) + empty();
+        assert_eq!(re.to_flat().ok().unwrap().node_count(), 4);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let re = many(is(|&c: &u8| c == b'a') + is(|&c: &u8| c == b'b'));
+        let compiled = Arc::new(Compiled::new(re).ok().unwrap());
+        let handles: Vec<_> = (0..4).map(|n| {
+            let compiled = compiled.clone();
+            thread::spawn(move || {
+                let input: Vec<u8> = b"ab".iter().cycle().take(n * 2 + n % 2).cloned().collect();
+                has_match(&mut compiled.state(), input)
+            })
+        }).collect();
+        let results: Vec<bool> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn attempts_with_different_weights_stay_apart() {
+        use weights::count::Count;
+        // Both alternatives match each `a`, so attempts which started at
+        // different places have marks in the same places but with
+        // different counts. Intersecting with anything mustn't change
+        // the count.
+        let any = || many(is(|_: &char| true));
+        let a = || is(|&c: &char| c == 'a');
+        let mut plain: AnyRegex<char, Count, _> = any() + many(a() | a());
+        let mut both: AnyRegex<char, Count, _> = any() + (many(a() | a()) & any());
+        assert_eq!(plain.over("aaa".chars()), Count(15));
+        assert_eq!(both.over("aaa".chars()), Count(15));
+        let mut flat = AnyRegex::new(both.to_flat().ok().unwrap());
+        assert_eq!(flat.over("aaa".chars()), Count(15));
+    }
+
+    #[test]
+    fn generation_wraps() {
+        let re: AnyRegex<u8, Match, _> = many(is(|&c| c == b'a') + is(|&c| c == b'b'));
+        let mut flat = re.to_flat().ok().unwrap();
+        flat.marks.generation = u32::MAX - 1;
+        for _ in 0..3 {
+            flat.shift(&b'a', one());
+            assert!(flat.active());
+            flat.reset();
+            assert!(!flat.active());
+            assert!(flat.shift(&b'b', zero()).is_zero());
+        }
+        assert_eq!(flat.marks.generation, 1);
+    }
+
+    #[test]
+    fn interned_leaves_are_weighed_once() {
+        use dynamic::Dynamic;
+        use std::cell::Cell;
+        use std::rc::Rc;
+        type D = Dynamic<u8, Match>;
+
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let a: Rc<dyn Leaf<u8, Match>> = Rc::new(is(move |&c: &u8| {
+            counter.set(counter.get() + 1);
+            c == b'a'
+        }));
+        // (a | a a)* : both alternatives see every item.
+        let re = D::many(D::or(D::leaf(a.clone()), D::sequence(D::leaf(a.clone()), D::leaf(a))));
+
+        let mut flat = AnyRegex::new(Flat::new(&re).ok().unwrap());
+        assert!(has_match(&mut flat, b"aaa".iter().cloned()));
+        let plain = calls.replace(0);
+
+        let mut flat = AnyRegex::new(Flat::interned(&re).ok().unwrap());
+        assert!(has_match(&mut flat, b"aaa".iter().cloned()));
+        assert!(calls.get() < plain);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn profiling() {
+        let re: AnyRegex<u8, Match, _> = many(is(|&c| c == b'a').label("a")) + is(|&c| c == b'b').label("b");
+        let mut flat = re.to_flat().ok().unwrap();
+        assert_eq!(flat.profile(), vec![]);
+        flat.profile_shifts();
+        let mut mark = one();
+        for c in b"aab" {
+            mark = flat.shift(c, replace(&mut mark, zero()));
+        }
+        assert!(!mark.is_zero());
+        flat.reset();
+        assert!(flat.shift(&b'x', zero()).is_zero());
+        let counts: Vec<_> = flat.profile().into_iter().map(|p| (p.text, p.shifted, p.skipped)).collect();
+        assert_eq!(counts, vec![
+            ("a*b".to_string(), 3, 1),
+            ("a*".to_string(), 3, 0),
+            ("a".to_string(), 3, 0),
+            ("b".to_string(), 3, 0),
+        ]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn observer() {
+        use std::cell::RefCell;
+
+        let re: AnyRegex<u8, Match, _> = is(|&c| c == b'a') + is(|&c| c == b'b');
+        let seen = RefCell::new(Vec::new());
+        let mut flat = re.to_flat().ok().unwrap();
+        flat.observe(|node, &c, incoming: &Match, outgoing: &Match| {
+            seen.borrow_mut().push((node, c, !incoming.is_zero(), !outgoing.is_zero()));
+        });
+        flat.shift(&b'a', one());
+        flat.shift(&b'b', zero());
+        drop(flat);
+        // Nodes with no marks in or out are skipped, and not reported.
+        assert_eq!(seen.into_inner(), vec![
+            (1, b'a', true, true),
+            (0, b'a', true, false),
+            (2, b'b', true, true),
+            (0, b'b', false, true),
+        ]);
+    }
+
+    quickcheck! {
+        fn regular(to_match : Vec<u8>) -> bool {
+            let mut re = many(many(is(|&c: &u8| c % 3 == 0)) + (is(|&c: &u8| c % 2 == 0) | empty()));
+            same_as_grammar(&mut re, &to_match)
+        }
+
+        fn boolean_operators(to_match : Vec<u8>) -> bool {
+            let mut re = (!(is(|&c: &u8| c % 3 == 0) + many(is(|&c: &u8| c % 2 == 0)))
+                & many(is(|&c: &u8| c > 100))) + !many(is(|&c: &u8| c < 50));
+            same_as_grammar(&mut re, &to_match)
+        }
+
+        fn compiled_state_is_reusable(inputs : Vec<Vec<u8>>) -> bool {
+            let re = !(is(|&c: &u8| c % 3 == 0) + many(is(|&c: &u8| c % 2 == 0)));
+            let mut reference = re.clone_reset();
+            let compiled = Compiled::new(re).ok().unwrap();
+            let mut state = compiled.state();
+            inputs.iter().all(|input| {
+                has_match(&mut reference, input.iter().cloned()) ==
+                    has_match(&mut state, input.iter().cloned())
+            })
+        }
+
+        fn interned_agrees(to_match : Vec<u8>) -> bool {
+            let mut re = many(is(|&c: &u8| c % 3 == 0) + is(|&c: &u8| c % 3 == 0))
+                + !many(is(|&c: &u8| c < 50));
+            let expected = has_match(&mut re, to_match.iter().cloned());
+            let compiled = Compiled::interned(re).ok().unwrap();
+            let mut state = compiled.state();
+            expected == has_match(&mut state, to_match.iter().cloned())
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Estimating how much memory a grammar uses.
+//!
+//! Patterns from untrusted sources can be arbitrarily large, so code
+//! which accepts them may want to refuse ones that are too big before
+//! matching with them. Every combinator stores its children inline,
+//! so most of a grammar's memory is in its own type; the rest is
+//! whatever nodes report through `Describe::heap_size`.
+//!
+//! `metrics` breaks a grammar's size down further, for comparing
+//! different ways of writing the same language or setting finer limits
+//! on untrusted patterns.
+
+use core::{AnyRegex, Describe, Node};
+use std::cmp::max;
+use std::mem;
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    R: Describe<T, M>,
+{
+    /// How many nodes make up this grammar, including labels. A
+    /// `delay`ed grammar counts as one node, because what it expands to
+    /// can't be inspected.
+    pub fn state_size(&self) -> usize { state_size(self) }
+
+    /// Approximately how many bytes this grammar occupies, including
+    /// its marks and any heap allocations its nodes report.
+    pub fn memory_footprint(&self) -> usize {
+        mem::size_of::<Self>() + heap_size(self)
+    }
+
+    /// Count this grammar's nodes by kind, along with other measures of
+    /// its complexity.
+    pub fn metrics(&self) -> Metrics { metrics(self) }
+}
+
+/// Measurements of a grammar's shape, from `metrics`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub empty: usize,
+    pub is: usize,
+    pub label: usize,
+    pub not: usize,
+    pub or: usize,
+    pub and: usize,
+    pub sequence: usize,
+    pub many: usize,
+    pub delay: usize,
+    /// The most nodes on any path from the root to a leaf, counting
+    /// both ends. A `delay`ed grammar counts as a leaf.
+    pub depth: usize,
+    /// How many marks the grammar carries from one shift to the next,
+    /// one for each `Sequence` and `Many`.
+    pub marks: usize,
+    /// How many nodes own heap allocations, such as `Dynamic` nodes,
+    /// forced `delay`s, and labels with owned text.
+    pub boxed: usize,
+}
+
+impl Metrics {
+    /// The total number of nodes, the same as `state_size`.
+    pub fn nodes(&self) -> usize {
+        self.empty + self.is + self.label + self.not + self.or
+            + self.and + self.sequence + self.many + self.delay
+    }
+}
+
+/// Like `AnyRegex::metrics`, but for any grammar node.
+pub fn metrics<T, M>(re: &dyn Describe<T, M>) -> Metrics {
+    let mut metrics = Metrics::default();
+    metrics.depth = measure(re, &mut metrics);
+    metrics
+}
+
+/// Add `re`'s subtree to `metrics` and return its depth.
+fn measure<T, M>(re: &dyn Describe<T, M>, metrics: &mut Metrics) -> usize {
+    if re.heap_size() > 0 {
+        metrics.boxed += 1;
+    }
+    1 + match re.describe() {
+        Node::Empty => { metrics.empty += 1; 0 }
+        Node::Is(_) => { metrics.is += 1; 0 }
+        Node::Delay => { metrics.delay += 1; 0 }
+        Node::Label(_, inner) => { metrics.label += 1; measure(inner, metrics) }
+        Node::Not(inner) => { metrics.not += 1; measure(inner, metrics) }
+        Node::Many(inner, _) => {
+            metrics.many += 1;
+            metrics.marks += 1;
+            measure(inner, metrics)
+        }
+        Node::Or(left, right) => {
+            metrics.or += 1;
+            max(measure(left, metrics), measure(right, metrics))
+        }
+        Node::And(left, right) => {
+            metrics.and += 1;
+            max(measure(left, metrics), measure(right, metrics))
+        }
+        Node::Sequence(left, right, _) => {
+            metrics.sequence += 1;
+            metrics.marks += 1;
+            max(measure(left, metrics), measure(right, metrics))
+        }
+    }
+}
+
+/// Like `AnyRegex::state_size`, but for any grammar node.
+pub fn state_size<T, M>(re: &dyn Describe<T, M>) -> usize {
+    1 + match re.describe() {
+        Node::Empty | Node::Is(_) | Node::Delay => 0,
+        Node::Label(_, inner) | Node::Not(inner) | Node::Many(inner, _) => state_size(inner),
+        Node::Or(left, right) | Node::And(left, right) | Node::Sequence(left, right, _) =>
+            state_size(left) + state_size(right),
+    }
+}
+
+/// The total of `Describe::heap_size` over a grammar and all its
+/// descendants.
+pub fn heap_size<T, M>(re: &dyn Describe<T, M>) -> usize {
+    re.heap_size() + match re.describe() {
+        Node::Empty | Node::Is(_) | Node::Delay => 0,
+        Node::Label(_, inner) | Node::Not(inner) | Node::Many(inner, _) => heap_size(inner),
+        Node::Or(left, right) | Node::And(left, right) | Node::Sequence(left, right, _) =>
+            heap_size(left) + heap_size(right),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn counts() {
+        let re: AnyRegex<u8, Match, _> = many(is(|&c| c > 10)) + empty();
+        assert_eq!(re.state_size(), 4);
+        assert_eq!(re.memory_footprint(), mem::size_of_val(&re));
+
+        let re: AnyRegex<u8, Match, _> = empty().label(String::with_capacity(100));
+        assert_eq!(re.state_size(), 2);
+        assert_eq!(re.memory_footprint(), mem::size_of_val(&re) + 100);
+    }
+
+    #[test]
+    fn metrics() {
+        use dynamic::Dynamic;
+        type D = Dynamic<u8, Match>;
+
+        let re: AnyRegex<u8, Match, _> = many(is(|&c| c > 10) | !empty()) + empty().label("e");
+        let metrics = re.metrics();
+        assert_eq!(metrics, Metrics {
+            empty: 2, is: 1, label: 1, not: 1, or: 1, sequence: 1, many: 1,
+            depth: 5, marks: 2,
+            ..Metrics::default()
+        });
+        assert_eq!(metrics.nodes(), re.state_size());
+
+        let re = D::sequence(D::many(D::is(|&c| c > 10)), D::empty());
+        assert_eq!(re.metrics().boxed, 3);
+    }
+}
@@ -1,9 +1,18 @@
-use core::{Regex, CloneRegex, AnyRegex, IntoWithInput};
+use engine::{Regex, CloneRegex, Attempts, Describe, Grammar, Leaf, Node, AnyRegex, IntoWithInput};
 use num_traits::{Zero, zero, One, one};
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
+use std::boxed::Box;
+use std::fmt::{self, Write};
+use std::iter;
 use std::marker::PhantomData;
-use std::mem::replace;
+use std::mem::{self, replace, take};
 use std::ops;
+use std::rc::Rc;
+use std::string::String;
+use std::vec::Vec;
+
+#[cfg(feature = "std")]
+pub mod bytes;
 
 pub struct Empty;
 
@@ -22,6 +31,10 @@ impl<T, M> CloneRegex<T, M> for Empty where
     fn clone_reset(&self) -> AnyRegex<T, M, Self> { empty() }
 }
 
+impl<T, M> Describe<T, M> for Empty {
+    fn describe(&self) -> Node<'_, T, M> { Node::Empty }
+}
+
 /// Language which only matches an empty string.
 pub fn empty<T, M>() -> AnyRegex<T, M, Empty> where
     M: Zero,
@@ -39,9 +52,7 @@ impl<T, U, M, N, F> Regex<T, M> for Is<U, N, F> where
 {
     fn empty(&mut self) -> bool { false }
     fn active(&self) -> bool { false }
-    fn shift(&mut self, c : &T, mark : M) -> M {
-        mark * (self.0)(c.borrow()).into_with_input(c)
-    }
+    fn shift(&mut self, c : &T, mark : M) -> M { mark * self.weigh(c) }
     fn reset(&mut self) { }
 }
 
@@ -54,6 +65,22 @@ impl<T, U, M, N, F> CloneRegex<T, M> for Is<U, N, F> where
     fn clone_reset(&self) -> AnyRegex<T, M, Self> { is(self.0.clone()) }
 }
 
+impl<T, U, M, N, F> Leaf<T, M> for Is<U, N, F> where
+    F: Fn(&U) -> N,
+    T: Borrow<U>,
+    N: IntoWithInput<T, M>,
+{
+    fn weigh(&self, c : &T) -> M { (self.0)(c.borrow()).into_with_input(c) }
+}
+
+impl<T, U, M, N, F> Describe<T, M> for Is<U, N, F> where
+    F: Fn(&U) -> N,
+    T: Borrow<U>,
+    N: IntoWithInput<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::Is(self) }
+}
+
 /// Language which only matches inputs containing exactly one item, and
 /// passes that item to an arbitrary function you provide.
 ///
@@ -70,45 +97,236 @@ pub fn is<T, U, M, N, F>(f: F) -> AnyRegex<T, M, Is<U, N, F>> where
     AnyRegex::new(Is(f, PhantomData, PhantomData))
 }
 
-pub struct Not<T, M, R>(AnyRegex<T, M, R>);
+/// Like `is`, but `f` also gets a shared context, such as feature flags
+/// or a dictionary loaded at startup. Keep anything that changes in a
+/// `Cell` or `RefCell` inside the context, and later items see the
+/// change without rebuilding the grammar. Copies made by `clone_reset`
+/// share the same context.
+pub fn is_with_ctx<T, M, C, N, F>(ctx: Rc<C>, f: F) -> AnyRegex<T, M, impl Grammar<T, M>> where
+    M: Zero + ops::Mul<Output=M>,
+    F: Fn(&C, &T) -> N + Clone,
+    N: IntoWithInput<T, M>,
+{
+    is(move |c: &T| f(&ctx, c))
+}
+
+/// The complement of a grammar: everything it doesn't match.
+///
+/// Whether the complement matches depends on where the match attempt
+/// started, and once marks from attempts that started in different
+/// places have been added together, that's lost. So each attempt gets
+/// its own copy of the inner grammar, in a `Split`.
+pub struct Not<T, M, R> {
+    /// A reset copy of the inner grammar, for describing and copying.
+    pub(crate) re: AnyRegex<T, M, R>,
+    split: Split<M, Complement<T, M, R>>,
+}
+
+/// One match attempt's copy of a complement.
+struct Complement<T, M, R>(AnyRegex<T, M, R>);
 
 impl<T, M, R> ops::Not for AnyRegex<T, M, R> where
-    M: Zero + One,
-    R: Regex<T, M>,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    R: CloneRegex<T, M> + Describe<T, M>,
 {
     type Output = AnyRegex<T, M, Not<T, M, R>>;
-    fn not(self) -> Self::Output { AnyRegex::new(Not(self)) }
+    fn not(mut self) -> Self::Output {
+        self.reset();
+        AnyRegex::new(Not { re: self, split: Split::new() })
+    }
 }
 
 impl<T, M, R> Regex<T, M> for Not<T, M, R> where
-    M: Zero + One,
-    R: Regex<T, M>,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    R: CloneRegex<T, M> + Describe<T, M>,
+{
+    fn empty(&mut self) -> bool { !self.re.empty() }
+    fn active(&self) -> bool { self.split.active() }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        let re = &self.re;
+        self.split.shift(c, mark, || Complement(re.clone_reset()))
+    }
+    fn reset(&mut self) { self.split.reset() }
+}
+
+impl<T, M, R> CloneRegex<T, M> for Not<T, M, R> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    R: CloneRegex<T, M> + Describe<T, M>,
 {
-    fn empty(&mut self) -> bool { !self.0.empty() }
+    fn clone_reset(&self) -> AnyRegex<T, M, Self> { !self.re.clone_reset() }
+}
 
-    // Complement grammars are always active, because shifting in a zero
-    // may still result in a non-zero being shifted out.
-    fn active(&self) -> bool { true }
+impl<T, M, R> Describe<T, M> for Not<T, M, R> where
+    R: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::Not(&self.re) }
+    fn heap_size(&self) -> usize { self.split.heap_size() }
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> { Some(self.split.attempts()) }
+}
 
+impl<T, M, R> Attempt<T, M> for Complement<T, M, R> where
+    M: Zero + One + PartialEq,
+    R: Regex<T, M> + Describe<T, M>,
+{
     fn shift(&mut self, c : &T, mark : M) -> M {
-        let new_mark = self.0.shift(c, mark);
-        if new_mark.is_zero() { one() } else { zero() }
-    }
-    fn reset(&mut self) {
-        self.0.reset();
+        if self.0.shift(c, mark).is_zero() { one() } else { zero() }
     }
+    fn reset(&mut self) { self.0.reset() }
+    fn settled(&self) -> Option<bool> { if self.0.active() { None } else { Some(true) } }
+    fn same_marks(&self, other: &Self) -> bool { same_marks(&self.0, &other.0) }
 }
 
-impl<T, M, R> CloneRegex<T, M> for Not<T, M, R> where
-    M: Zero + One,
-    R: CloneRegex<T, M>,
+impl<T, M, R> Describe<T, M> for Complement<T, M, R> where
+    R: Describe<T, M>,
 {
-    fn clone_reset(&self) -> AnyRegex<T, M, Self> { !self.0.clone_reset() }
+    fn describe(&self) -> Node<'_, T, M> { Node::Not(&self.0) }
+}
+
+/// The match attempts a `Not` or `And` is following, each with its own
+/// copy of the node's children. Copies with equal marks in all the same
+/// places will produce the same marks from then on, so they're merged
+/// by adding the weights that started them. With weights like `Match`,
+/// that keeps the number of copies down to the number of distinct
+/// states the children go through rather than the length of the input.
+/// Copies whose marks only agree on where they're nonzero are kept
+/// apart, since adding isn't idempotent for weights like `Count`.
+struct Split<M, S> {
+    /// Attempts in progress, each with the total weight of the marks
+    /// that started it.
+    attempts: Vec<(M, S)>,
+    /// The total weight of attempts which will match after any item
+    /// from now on.
+    settled: M,
+    /// Reset copies which aren't in use, so they can be reused without
+    /// allocating.
+    spare: Vec<S>,
+}
+
+/// One match attempt's copy of a node's children, for `Split`.
+trait Attempt<T, M>: Describe<T, M> {
+    /// Shift the way the node would if this were its only attempt.
+    fn shift(&mut self, c : &T, mark : M) -> M;
+    fn reset(&mut self);
+    /// Whether further items can no longer change this attempt's
+    /// result: `Some(true)` if it will match after any item, and
+    /// `Some(false)` if it will never match again.
+    fn settled(&self) -> Option<bool>;
+    /// Whether the two copies have equal marks in all the same places,
+    /// so they'll produce the same marks from now on.
+    fn same_marks(&self, other: &Self) -> bool;
+}
+
+impl<M, S> Split<M, S> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+{
+    fn new() -> Self {
+        Split { attempts: Vec::new(), settled: zero(), spare: Vec::new() }
+    }
+
+    fn active(&self) -> bool { !self.settled.is_zero() || !self.attempts.is_empty() }
+
+    /// Shift every attempt, and start a new one with a copy from
+    /// `fresh` if `mark` is nonzero.
+    fn shift<T, F>(&mut self, c : &T, mark : M, fresh: F) -> M where
+        S: Attempt<T, M>,
+        F: FnOnce() -> S,
+    {
+        let mut result = self.settled.clone();
+        for &mut (ref weight, ref mut attempt) in &mut self.attempts {
+            let out = attempt.shift(c, zero());
+            if !out.is_zero() {
+                result = result + weight.clone() * out;
+            }
+        }
+        if !mark.is_zero() {
+            let mut attempt = self.spare.pop().unwrap_or_else(fresh);
+            let out = attempt.shift(c, one());
+            if !out.is_zero() {
+                result = result + mark.clone() * out;
+            }
+            self.attempts.push((mark, attempt));
+        }
+
+        let mut i = 0;
+        while i < self.attempts.len() {
+            let merge = match self.attempts[i].1.settled() {
+                Some(true) => Some(None),
+                Some(false) => None,
+                None => match (0..i).find(|&j| self.attempts[j].1.same_marks(&self.attempts[i].1)) {
+                    Some(j) => Some(Some(j)),
+                    None => {
+                        i += 1;
+                        continue;
+                    }
+                },
+            };
+            let (weight, mut attempt) = self.attempts.swap_remove(i);
+            attempt.reset();
+            self.spare.push(attempt);
+            let total = match merge {
+                Some(Some(j)) => &mut self.attempts[j].0,
+                Some(None) => &mut self.settled,
+                None => continue,
+            };
+            *total = replace(total, zero()) + weight;
+        }
+        result
+    }
+
+    fn reset<T>(&mut self) where
+        S: Attempt<T, M>,
+    {
+        for (_, mut attempt) in self.attempts.drain(..) {
+            attempt.reset();
+            self.spare.push(attempt);
+        }
+        self.settled = zero();
+    }
+}
+
+impl<M, S> Split<M, S> {
+    fn attempts<T>(&self) -> Attempts<'_, T, M> where
+        S: Describe<T, M>,
+    {
+        Attempts {
+            settled: &self.settled,
+            attempts: self.attempts.iter().map(|(weight, attempt)| (weight, attempt as &dyn Describe<T, M>)).collect(),
+        }
+    }
+
+    // The copies are opaque, so this only counts their top level.
+    fn heap_size(&self) -> usize {
+        self.attempts.capacity() * mem::size_of::<(M, S)>() + self.spare.capacity() * mem::size_of::<S>()
+    }
+}
+
+/// Whether two copies of the same grammar have equal marks in all the
+/// same places. That's `false` whenever it can't be seen, such as
+/// inside a `delay` or any active node which follows its own match
+/// attempts.
+fn same_marks<T, M: PartialEq>(a: &dyn Describe<T, M>, b: &dyn Describe<T, M>) -> bool {
+    match (a.activity(), b.activity()) {
+        (Some(false), Some(false)) => return true,
+        (x, y) if x != y => return false,
+        _ => {}
+    }
+    if a.attempts().is_some() || b.attempts().is_some() {
+        return false;
+    }
+    match (a.describe(), b.describe()) {
+        (Node::Label(_, a), Node::Label(_, b)) => same_marks(a, b),
+        (Node::Or(al, ar), Node::Or(bl, br)) =>
+            same_marks(al, bl) && same_marks(ar, br),
+        (Node::Sequence(al, ar, am), Node::Sequence(bl, br, bm)) =>
+            am == bm && same_marks(al, bl) && same_marks(ar, br),
+        (Node::Many(a, am), Node::Many(b, bm)) => am == bm && same_marks(a, b),
+        _ => false,
+    }
 }
 
 pub struct Or<T, M, L, R> {
-    left : AnyRegex<T, M, L>,
-    right : AnyRegex<T, M, R>,
+    pub(crate) left : AnyRegex<T, M, L>,
+    pub(crate) right : AnyRegex<T, M, R>,
 }
 
 impl<T, M, L, R> ops::BitOr<AnyRegex<T, M, R>> for AnyRegex<T, M, L> where
@@ -149,30 +367,81 @@ impl<T, M, L, R> CloneRegex<T, M> for Or<T, M, L, R> where
     }
 }
 
+impl<T, M, L, R> Describe<T, M> for Or<T, M, L, R> where
+    L: Describe<T, M>,
+    R: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::Or(&self.left, &self.right) }
+}
+
+/// The intersection of two grammars. Like `Not`, this follows each
+/// match attempt separately, so that both sides have to match from
+/// the same start.
 pub struct And<T, M, L, R> {
+    /// Reset copies of both sides, for describing and copying.
+    pub(crate) left : AnyRegex<T, M, L>,
+    pub(crate) right : AnyRegex<T, M, R>,
+    split: Split<M, Both<T, M, L, R>>,
+}
+
+/// One match attempt's copy of an intersection.
+struct Both<T, M, L, R> {
     left : AnyRegex<T, M, L>,
     right : AnyRegex<T, M, R>,
 }
 
 impl<T, M, L, R> ops::BitAnd<AnyRegex<T, M, R>> for AnyRegex<T, M, L> where
-    M: Zero + ops::Mul<Output=M> + Clone,
-    L: Regex<T, M>,
-    R: Regex<T, M>,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    L: CloneRegex<T, M> + Describe<T, M>,
+    R: CloneRegex<T, M> + Describe<T, M>,
 {
     type Output = AnyRegex<T, M, And<T, M, L, R>>;
-    fn bitand(self, other: AnyRegex<T, M, R>) -> Self::Output
+    fn bitand(mut self, mut other: AnyRegex<T, M, R>) -> Self::Output
     {
-        AnyRegex::new(And { left: self, right: other })
+        self.reset();
+        other.reset();
+        AnyRegex::new(And { left: self, right: other, split: Split::new() })
     }
 }
 
 impl<T, M, L, R> Regex<T, M> for And<T, M, L, R> where
-    M: Zero + ops::Mul<Output=M> + Clone,
-    L: Regex<T, M>,
-    R: Regex<T, M>,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    L: CloneRegex<T, M> + Describe<T, M>,
+    R: CloneRegex<T, M> + Describe<T, M>,
 {
     fn empty(&mut self) -> bool { self.left.empty() && self.right.empty() }
-    fn active(&self) -> bool { self.left.active() || self.right.active() }
+    fn active(&self) -> bool { self.split.active() }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        let (left, right) = (&self.left, &self.right);
+        self.split.shift(c, mark, || Both { left: left.clone_reset(), right: right.clone_reset() })
+    }
+    fn reset(&mut self) { self.split.reset() }
+}
+
+impl<T, M, L, R> CloneRegex<T, M> for And<T, M, L, R> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    L: CloneRegex<T, M> + Describe<T, M>,
+    R: CloneRegex<T, M> + Describe<T, M>,
+{
+    fn clone_reset(&self) -> AnyRegex<T, M, Self> {
+        self.left.clone_reset() & self.right.clone_reset()
+    }
+}
+
+impl<T, M, L, R> Describe<T, M> for And<T, M, L, R> where
+    L: Describe<T, M>,
+    R: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::And(&self.left, &self.right) }
+    fn heap_size(&self) -> usize { self.split.heap_size() }
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> { Some(self.split.attempts()) }
+}
+
+impl<T, M, L, R> Attempt<T, M> for Both<T, M, L, R> where
+    M: Zero + ops::Mul<Output=M> + Clone + PartialEq,
+    L: Regex<T, M> + Describe<T, M>,
+    R: Regex<T, M> + Describe<T, M>,
+{
     fn shift(&mut self, c : &T, mark : M) -> M {
         self.left.shift(c, mark.clone()) * self.right.shift(c, mark)
     }
@@ -180,21 +449,24 @@ impl<T, M, L, R> Regex<T, M> for And<T, M, L, R> where
         self.left.reset();
         self.right.reset();
     }
+    fn settled(&self) -> Option<bool> {
+        if self.left.active() && self.right.active() { None } else { Some(false) }
+    }
+    fn same_marks(&self, other: &Self) -> bool {
+        same_marks(&self.left, &other.left) && same_marks(&self.right, &other.right)
+    }
 }
 
-impl<T, M, L, R> CloneRegex<T, M> for And<T, M, L, R> where
-    M: Zero + ops::Mul<Output=M> + Clone,
-    L: CloneRegex<T, M>,
-    R: CloneRegex<T, M>,
+impl<T, M, L, R> Describe<T, M> for Both<T, M, L, R> where
+    L: Describe<T, M>,
+    R: Describe<T, M>,
 {
-    fn clone_reset(&self) -> AnyRegex<T, M, Self> {
-        self.left.clone_reset() & self.right.clone_reset()
-    }
+    fn describe(&self) -> Node<'_, T, M> { Node::And(&self.left, &self.right) }
 }
 
 pub struct Sequence<T, M, L, R> {
-    left : AnyRegex<T, M, L>,
-    right : AnyRegex<T, M, R>,
+    pub(crate) left : AnyRegex<T, M, L>,
+    pub(crate) right : AnyRegex<T, M, R>,
     from_left : M,
 }
 
@@ -210,6 +482,18 @@ impl<T, M, L, R> ops::Add<AnyRegex<T, M, R>> for AnyRegex<T, M, L> where
     }
 }
 
+/// `a >> b` is the same as `a + b`, for readers who'd sooner not see `+`
+/// mean sequencing, given what it means in regexes and semirings.
+impl<T, M, L, R> ops::Shr<AnyRegex<T, M, R>> for AnyRegex<T, M, L> where
+    M: Zero + Clone,
+    L: Regex<T, M>,
+    R: Regex<T, M>,
+{
+    type Output = AnyRegex<T, M, Sequence<T, M, L, R>>;
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn shr(self, other: AnyRegex<T, M, R>) -> Self::Output { self + other }
+}
+
 impl<T, M, L, R> Regex<T, M> for Sequence<T, M, L, R> where
     M: Zero + Clone,
     L: Regex<T, M>,
@@ -313,8 +597,17 @@ impl<T, M, L, R> CloneRegex<T, M> for Sequence<T, M, L, R> where
     }
 }
 
+impl<T, M, L, R> Describe<T, M> for Sequence<T, M, L, R> where
+    L: Describe<T, M>,
+    R: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> {
+        Node::Sequence(&self.left, &self.right, &self.from_left)
+    }
+}
+
 pub struct Many<T, M, R> {
-    re : AnyRegex<T, M, R>,
+    pub(crate) re : AnyRegex<T, M, R>,
     marked : M,
 }
 
@@ -354,6 +647,12 @@ impl<T, M, R> CloneRegex<T, M> for Many<T, M, R> where
     }
 }
 
+impl<T, M, R> Describe<T, M> for Many<T, M, R> where
+    R: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::Many(&self.re, &self.marked) }
+}
+
 impl<T, M> Regex<T, M> for Box<Regex<T, M>>
 {
     fn empty(&mut self) -> bool { self.as_mut().empty() }
@@ -409,3 +708,881 @@ impl<T, M, F> CloneRegex<T, M> for Thunk<T, M, F> where
         delay(self.constructor.clone())
     }
 }
+
+impl<T, M, F> Describe<T, M> for Thunk<T, M, F> {
+    fn describe(&self) -> Node<'_, T, M> { Node::Delay }
+
+    // The forced grammar is opaque, so this only counts its top level.
+    fn heap_size(&self) -> usize {
+        self.value.as_ref().map_or(0, |re| ::std::mem::size_of_val(&**re))
+    }
+}
+
+pub struct Label<T, M, R> {
+    pub(crate) re : AnyRegex<T, M, R>,
+    pub(crate) text : Cow<'static, str>,
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero,
+    R: Regex<T, M>,
+{
+    /// Attach a description to this grammar without changing what it
+    /// matches. The description should use conventional regular
+    /// expression syntax, like `[0-9]` or `\s`, because tools such as
+    /// `to_regex_string` use it in place of the grammar it labels.
+    pub fn label<S>(self, text: S) -> AnyRegex<T, M, Label<T, M, R>> where
+        S: Into<Cow<'static, str>>,
+    {
+        AnyRegex::new(Label { re: self, text: text.into() })
+    }
+}
+
+impl<T, M, R> Regex<T, M> for Label<T, M, R> where
+    M: Zero,
+    R: Regex<T, M>,
+{
+    fn empty(&mut self) -> bool { self.re.empty() }
+    fn active(&self) -> bool { self.re.active() }
+    fn shift(&mut self, c : &T, mark : M) -> M { self.re.shift(c, mark) }
+    fn reset(&mut self) { self.re.reset() }
+}
+
+impl<T, M, R> CloneRegex<T, M> for Label<T, M, R> where
+    M: Zero,
+    R: CloneRegex<T, M>,
+{
+    fn clone_reset(&self) -> AnyRegex<T, M, Self> {
+        self.re.clone_reset().label(self.text.clone())
+    }
+}
+
+impl<T, M, R> Describe<T, M> for Label<T, M, R> where
+    R: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::Label(&self.text, &self.re) }
+    fn heap_size(&self) -> usize {
+        match self.text {
+            Cow::Borrowed(_) => 0,
+            Cow::Owned(ref text) => text.capacity(),
+        }
+    }
+}
+
+pub struct Filter<T, M, R, F> {
+    re : AnyRegex<T, M, R>,
+    keep : F,
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero + One,
+    R: Regex<T, M>,
+{
+    /// Zero out every mark leaving this grammar for which `keep` returns
+    /// false, such as derivations costing too much. An empty match is
+    /// kept if `keep(&one())` is true.
+    ///
+    /// This changes what the grammar matches, but in a way `Describe`
+    /// can't express, so filtered grammars can't be described.
+    pub fn filter_weight<F>(self, keep: F) -> AnyRegex<T, M, Filter<T, M, R, F>> where
+        F: Fn(&M) -> bool,
+    {
+        AnyRegex::new(Filter { re: self, keep })
+    }
+}
+
+impl<T, M, R, F> Regex<T, M> for Filter<T, M, R, F> where
+    M: Zero + One,
+    R: Regex<T, M>,
+    F: Fn(&M) -> bool,
+{
+    fn empty(&mut self) -> bool { self.re.empty() && (self.keep)(&one()) }
+    fn active(&self) -> bool { self.re.active() }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        let mark = self.re.shift(c, mark);
+        if mark.is_zero() || (self.keep)(&mark) { mark } else { zero() }
+    }
+    fn reset(&mut self) { self.re.reset() }
+}
+
+impl<T, M, R, F> CloneRegex<T, M> for Filter<T, M, R, F> where
+    M: Zero + One,
+    R: CloneRegex<T, M>,
+    F: Fn(&M) -> bool + Clone,
+{
+    fn clone_reset(&self) -> AnyRegex<T, M, Self> {
+        self.re.clone_reset().filter_weight(self.keep.clone())
+    }
+}
+
+/// Matches any sequence between `min` and `max` items long, used by
+/// `len_between` to bound another grammar's matches. The intersection
+/// gives each match attempt its own copy, so this only counts the items
+/// one attempt has matched.
+///
+/// It describes itself as a chain of `max` items, all but the first
+/// `min` of them optional, under a label like `.{2,5}`, and the attempt
+/// in progress as a mark waiting before the items it has left. Copies
+/// share the chain.
+pub struct Length<M> {
+    progress : Progress<M>,
+    none : M,
+}
+
+/// The attempt a `Length` is following.
+struct Progress<M> {
+    /// How many items the attempt has matched.
+    count : usize,
+    /// The weight of the attempt, or zero if there's none.
+    mark : M,
+    shape : Rc<Shape<M>>,
+}
+
+struct Shape<M> {
+    text : String,
+    min : usize,
+    max : usize,
+    rest : Tail<M>,
+}
+
+/// What's left of a `Length` after some number of items: either
+/// nothing, or another item, which may be optional.
+struct Tail<M> {
+    optional : bool,
+    next : Option<Box<Step<M>>>,
+}
+
+/// One item of a `Length`'s description.
+struct Step<M> {
+    none : M,
+    rest : Tail<M>,
+}
+
+/// The item a `Length` counts, which can be anything at all.
+struct AnyItem;
+
+/// Match `re`, but only with between `min` and `max` items, inclusive.
+/// The intersection follows each match attempt separately, so attempts
+/// that started at different places are counted apart. Time per item
+/// grows with the number of attempts in progress, which is at most
+/// `max`. The weights are those of `re`.
+pub fn len_between<T, M, R>(re: AnyRegex<T, M, R>, min: usize, max: usize) -> AnyRegex<T, M, And<T, M, R, Length<M>>> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    R: CloneRegex<T, M> + Describe<T, M>,
+{
+    assert!(min <= max, "length range is empty");
+    re & AnyRegex::new(Length::new(Rc::new(Shape::new(min, max))))
+}
+
+/// Match `re`, but only with at most `max` items, such as to cap a
+/// field at 64 characters.
+pub fn max_len<T, M, R>(re: AnyRegex<T, M, R>, max: usize) -> AnyRegex<T, M, And<T, M, R, Length<M>>> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    R: CloneRegex<T, M> + Describe<T, M>,
+{
+    len_between(re, 0, max)
+}
+
+impl<M: Zero> Shape<M> {
+    fn new(min: usize, max: usize) -> Self {
+        let mut rest = Tail { optional: true, next: None };
+        for i in (0..max).rev() {
+            rest = Tail { optional: i >= min, next: Some(Box::new(Step { none: zero(), rest })) };
+        }
+        let mut text = String::new();
+        write!(text, ".{{{},{}}}", min, max).unwrap();
+        Shape { text, min, max, rest }
+    }
+}
+
+impl<M: Zero> Length<M> {
+    fn new(shape: Rc<Shape<M>>) -> Self {
+        Length { progress: Progress { count: 0, mark: zero(), shape }, none: zero() }
+    }
+}
+
+impl<T, M> Regex<T, M> for Length<M> where
+    M: Zero + Clone,
+{
+    fn empty(&mut self) -> bool { self.progress.shape.min == 0 }
+    fn active(&self) -> bool { !self.progress.mark.is_zero() }
+    fn shift(&mut self, _c : &T, mark : M) -> M {
+        let progress = &mut self.progress;
+        if !mark.is_zero() {
+            // `And` starts every attempt in a copy of its own.
+            debug_assert!(progress.mark.is_zero(), "length bound given two attempts");
+            progress.count = 0;
+            progress.mark = mark;
+        }
+        if progress.mark.is_zero() {
+            return zero();
+        }
+        progress.count += 1;
+        let shape = &*progress.shape;
+        let result = if progress.count >= shape.min && progress.count <= shape.max {
+            progress.mark.clone()
+        } else {
+            zero()
+        };
+        if progress.count >= shape.max {
+            progress.mark = zero();
+        }
+        result
+    }
+    fn reset(&mut self) {
+        self.progress.count = 0;
+        self.progress.mark = zero();
+    }
+}
+
+impl<T, M> CloneRegex<T, M> for Length<M> where
+    M: Zero + Clone,
+{
+    fn clone_reset(&self) -> AnyRegex<T, M, Self> {
+        AnyRegex::new(Length::new(self.progress.shape.clone()))
+    }
+}
+
+impl<T, M: Zero + One> Describe<T, M> for Length<M> {
+    fn describe(&self) -> Node<'_, T, M> {
+        let shape = &*self.progress.shape;
+        Node::Label(&shape.text, &shape.rest)
+    }
+    // Every copy reports the shared chain.
+    fn heap_size(&self) -> usize {
+        let shape = &*self.progress.shape;
+        mem::size_of::<Shape<M>>() + shape.text.capacity() + shape.max * mem::size_of::<Step<M>>()
+    }
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> {
+        let mut attempts = Vec::new();
+        if !self.progress.mark.is_zero() {
+            attempts.push((&self.progress.mark, &self.progress as &dyn Describe<T, M>));
+        }
+        Some(Attempts { settled: &self.none, attempts })
+    }
+}
+
+impl<T, M: One> Describe<T, M> for Progress<M> {
+    fn describe(&self) -> Node<'_, T, M> {
+        let mut rest = &self.shape.rest;
+        for _ in 0..self.count {
+            rest = match rest.next {
+                Some(ref step) => &step.rest,
+                None => break,
+            };
+        }
+        Node::Sequence(&Empty, rest, &self.mark)
+    }
+}
+
+impl<T, M: One> Describe<T, M> for Tail<M> {
+    fn describe(&self) -> Node<'_, T, M> {
+        match self.next {
+            None => Node::Empty,
+            Some(ref step) if self.optional => Node::Or(&Empty, &**step),
+            Some(ref step) => step.describe(),
+        }
+    }
+}
+
+impl<T, M: One> Describe<T, M> for Step<M> {
+    fn describe(&self) -> Node<'_, T, M> { Node::Sequence(&AnyItem, &self.rest, &self.none) }
+}
+
+impl<T, M: One> Leaf<T, M> for AnyItem {
+    fn weigh(&self, _c : &T) -> M { one() }
+    fn fmt_leaf(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(".") }
+}
+
+impl<T, M: One> Describe<T, M> for AnyItem {
+    fn describe(&self) -> Node<'_, T, M> { Node::Is(self) }
+}
+
+// Dropping the chain one step at a time keeps long ones from
+// overflowing the stack.
+impl<M> Drop for Shape<M> {
+    fn drop(&mut self) {
+        let mut next = self.rest.next.take();
+        while let Some(mut step) = next {
+            next = step.rest.next.take();
+        }
+    }
+}
+
+/// Matches any sequence with at most some number of non-overlapping
+/// occurrences of a grammar, used by `limit_occurrences`. Each match
+/// attempt keeps a counter of how many more occurrences it allows, and
+/// one copy of the grammar looking for the next occurrence, which is
+/// reset each time one ends so occurrences can't overlap.
+///
+/// It describes itself as the complement of one occurrence too many,
+/// like `!(.*x.*x.*)` for a limit of one.
+pub struct Occurrences<T, M, X> {
+    /// A reset counter, for describing and copying.
+    proto: Count<T, M, X>,
+    split: Split<M, Count<T, M, X>>,
+}
+
+/// One match attempt's counter.
+struct Count<T, M, X> {
+    limit: usize,
+    /// How many more occurrences this attempt allows.
+    left: usize,
+    /// Whether it has already seen more than `limit`.
+    over: bool,
+    /// What follows the first occurrence, for starting over.
+    head: Option<Rc<Link<T, M, X>>>,
+    /// The rest of the input this attempt must not match, with the
+    /// copy of the grammar looking for the next occurrence.
+    bad: Link<T, M, X>,
+}
+
+/// Anything, then an occurrence, then whatever has to follow it.
+struct Link<T, M, X> {
+    gap: Gap<M>,
+    then: Then<T, M, X>,
+}
+
+/// Any sequence, with the mark which is always there once an attempt
+/// has started.
+struct Gap<M> {
+    mark: M,
+}
+
+/// An occurrence, then either another link or anything at all.
+struct Then<T, M, X> {
+    x: AnyRegex<T, M, X>,
+    rest: Option<Rc<Link<T, M, X>>>,
+    end: Gap<M>,
+    none: M,
+}
+
+/// Match `re`, but only where the match contains at most `limit`
+/// occurrences of `x`, counted as the most non-overlapping occurrences
+/// that fit. Only occurrences of at least one item count. The
+/// intersection follows each match attempt separately, and each
+/// attempt counts with one copy of `x`, however big `limit` is. The
+/// weights are those of `re`.
+pub fn limit_occurrences<T, M, R, X>(re: AnyRegex<T, M, R>, x: AnyRegex<T, M, X>, limit: usize) -> AnyRegex<T, M, impl Grammar<T, M>> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    R: CloneRegex<T, M> + Describe<T, M>,
+    X: CloneRegex<T, M> + Describe<T, M>,
+{
+    re & AnyRegex::new(Occurrences { proto: Count::new(&x, limit), split: Split::new() })
+}
+
+impl<T, M, X> Count<T, M, X> where
+    M: Zero,
+    X: CloneRegex<T, M>,
+{
+    fn new(x: &AnyRegex<T, M, X>, limit: usize) -> Self {
+        let mut head = None;
+        for _ in 0..limit {
+            let then = Then { x: x.clone_reset(), rest: head, end: Gap { mark: zero() }, none: zero() };
+            head = Some(Rc::new(Link { gap: Gap { mark: zero() }, then }));
+        }
+        Count {
+            limit,
+            left: limit,
+            over: false,
+            bad: Link {
+                gap: Gap { mark: zero() },
+                then: Then { x: x.clone_reset(), rest: head.clone(), end: Gap { mark: zero() }, none: zero() },
+            },
+            head,
+        }
+    }
+
+    /// A reset copy, sharing the description of later occurrences.
+    fn fresh(&self) -> Self {
+        Count {
+            limit: self.limit,
+            left: self.limit,
+            over: false,
+            head: self.head.clone(),
+            bad: Link {
+                gap: Gap { mark: zero() },
+                then: Then { x: self.bad.then.x.clone_reset(), rest: self.head.clone(), end: Gap { mark: zero() }, none: zero() },
+            },
+        }
+    }
+}
+
+impl<T, M, X> Regex<T, M> for Occurrences<T, M, X> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    X: CloneRegex<T, M> + Describe<T, M>,
+{
+    fn empty(&mut self) -> bool { true }
+    fn active(&self) -> bool { self.split.active() }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        let proto = &self.proto;
+        self.split.shift(c, mark, || proto.fresh())
+    }
+    fn reset(&mut self) { self.split.reset() }
+}
+
+impl<T, M, X> CloneRegex<T, M> for Occurrences<T, M, X> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+    X: CloneRegex<T, M> + Describe<T, M>,
+{
+    fn clone_reset(&self) -> AnyRegex<T, M, Self> {
+        AnyRegex::new(Occurrences { proto: self.proto.fresh(), split: Split::new() })
+    }
+}
+
+impl<T, M: One, X> Describe<T, M> for Occurrences<T, M, X> where
+    X: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { self.proto.describe() }
+    fn heap_size(&self) -> usize { self.split.heap_size() }
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> { Some(self.split.attempts()) }
+}
+
+impl<T, M, X> Attempt<T, M> for Count<T, M, X> where
+    M: Zero + One + PartialEq,
+    X: Regex<T, M> + Describe<T, M>,
+{
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        if !mark.is_zero() {
+            self.bad.gap.mark = one();
+        }
+        if self.over || self.bad.gap.mark.is_zero() {
+            return zero();
+        }
+        if self.bad.then.x.shift(c, one()).is_zero() {
+            return one();
+        }
+        // An occurrence ends here, so the next one has to start after
+        // this item.
+        self.bad.then.x.reset();
+        if self.left == 0 {
+            self.over = true;
+            return zero();
+        }
+        self.left -= 1;
+        let rest = self.bad.then.rest.take();
+        self.bad.then.rest = rest.and_then(|link| link.then.rest.clone());
+        one()
+    }
+    fn reset(&mut self) {
+        self.bad.gap.mark = zero();
+        self.bad.then.x.reset();
+        self.bad.then.rest = self.head.clone();
+        self.left = self.limit;
+        self.over = false;
+    }
+    fn settled(&self) -> Option<bool> { if self.over { Some(false) } else { None } }
+    fn same_marks(&self, other: &Self) -> bool {
+        self.left == other.left && self.over == other.over &&
+            self.bad.gap.mark == other.bad.gap.mark &&
+            same_marks(&self.bad.then.x, &other.bad.then.x)
+    }
+}
+
+impl<T, M: One, X> Describe<T, M> for Count<T, M, X> where
+    X: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::Not(&self.bad) }
+}
+
+impl<T, M: One, X> Describe<T, M> for Link<T, M, X> where
+    X: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::Sequence(&self.gap, &self.then, &self.gap.mark) }
+}
+
+impl<T, M: One> Describe<T, M> for Gap<M> {
+    fn describe(&self) -> Node<'_, T, M> { Node::Many(&AnyItem, &self.mark) }
+}
+
+impl<T, M: One, X> Describe<T, M> for Then<T, M, X> where
+    X: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> {
+        match self.rest {
+            Some(ref link) => Node::Sequence(&self.x, &**link, &self.none),
+            None => Node::Sequence(&self.x, &self.end, &self.none),
+        }
+    }
+}
+
+pub struct WithParams<T, M, P, F, R> {
+    params : P,
+    factory : F,
+    re : AnyRegex<T, M, R>,
+}
+
+/// Build a grammar from `params` using `factory`, for grammars shaped by
+/// run-time settings like repetition counts, character sets, or
+/// thresholds. The parameters stay with the grammar, so `clone_reset`
+/// builds the copy from the same ones, and `params` can report them.
+pub fn with_params<T, M, P, F, R>(params: P, factory: F) -> AnyRegex<T, M, WithParams<T, M, P, F, R>> where
+    M: Zero,
+    R: Regex<T, M>,
+    F: Fn(&P) -> AnyRegex<T, M, R>,
+{
+    let re = factory(&params);
+    AnyRegex::new(WithParams { params, factory, re })
+}
+
+impl<T, M, P, F, R> AnyRegex<T, M, WithParams<T, M, P, F, R>> {
+    /// The parameters this grammar was built from.
+    pub fn params(&self) -> &P { &self.node().params }
+}
+
+impl<T, M, P, F, R> Regex<T, M> for WithParams<T, M, P, F, R> where
+    M: Zero,
+    R: Regex<T, M>,
+{
+    fn empty(&mut self) -> bool { self.re.empty() }
+    fn active(&self) -> bool { self.re.active() }
+    fn shift(&mut self, c : &T, mark : M) -> M { self.re.shift(c, mark) }
+    fn reset(&mut self) { self.re.reset() }
+}
+
+impl<T, M, P, F, R> CloneRegex<T, M> for WithParams<T, M, P, F, R> where
+    M: Zero,
+    R: Regex<T, M>,
+    P: Clone,
+    F: Fn(&P) -> AnyRegex<T, M, R> + Clone,
+{
+    fn clone_reset(&self) -> AnyRegex<T, M, Self> {
+        with_params(self.params.clone(), self.factory.clone())
+    }
+}
+
+impl<T, M, P, F, R> Describe<T, M> for WithParams<T, M, P, F, R> where
+    R: Describe<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { self.re.describe() }
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> { self.re.attempts() }
+}
+
+pub struct Literals<T, M> {
+    trie : Rc<Trie<T, M>>,
+    /// The mark on each trie node, from attempts which have matched
+    /// the items leading to it.
+    marks : Vec<Waiting<T, M>>,
+    /// Nodes whose mark is currently non-zero.
+    live : Vec<usize>,
+    pending : Vec<(usize, M)>,
+    none : M,
+}
+
+/// The literals of a `Literals`, shared by its copies.
+struct Trie<T, M> {
+    /// Outgoing edges of each trie node; node 0 is the root.
+    edges : Vec<Vec<(T, usize)>>,
+    /// How many of the literals end at each node.
+    accepting : Vec<usize>,
+    /// Each node other than the root's parent, and which of the
+    /// parent's alternatives leads to it.
+    parents : Vec<(usize, usize)>,
+    /// The root's alternatives, or `None` if there are no literals.
+    root : Option<Alternatives<T, M>>,
+}
+
+/// The trie as a grammar, for describing it: each node is a choice
+/// between the empty sequence, once for each literal ending there, and
+/// each edge followed by the node it leads to.
+struct Alternatives<T, M> {
+    first : Alternative<T, M>,
+    rest : Option<Box<Alternatives<T, M>>>,
+}
+
+enum Alternative<T, M> {
+    End,
+    Edge(Equals<T>, Box<Alternatives<T, M>>, M),
+}
+
+/// The leaf for one edge of a `Literals` trie.
+struct Equals<T>(T);
+
+/// One trie node's mark, which describes itself as the mark waiting
+/// before what's left of the literals from there.
+struct Waiting<T, M> {
+    mark : M,
+    node : usize,
+    trie : Rc<Trie<T, M>>,
+}
+
+/// Language which matches any one of a list of literal sequences.
+///
+/// This is equivalent to an alternation of `Sequence`s of `is` tests
+/// for equality, but the literals are stored in a single trie, so
+/// shifting an item only visits the trie nodes which are currently
+/// marked instead of every leaf of every literal. After a `many` of
+/// anything, the marked nodes are the states of an Aho-Corasick
+/// automaton for the literals. Prefer it when scanning for many
+/// keywords at once; `parse` uses it for alternations of plain text.
+///
+/// As with `|`, a literal which appears more than once contributes its
+/// weight once per occurrence.
+pub fn literals<T, M, I, L>(words: I) -> AnyRegex<T, M, Literals<T, M>> where
+    T: PartialEq + Clone,
+    M: Zero + ops::Mul<Output=M> + Clone,
+    bool: IntoWithInput<T, M>,
+    I: IntoIterator<Item=L>,
+    L: IntoIterator<Item=T>,
+{
+    let mut edges = vec![Vec::new()];
+    let mut accepting = vec![0];
+    for word in words {
+        let mut node = 0;
+        for c in word {
+            node = match edges[node].iter().find(|&(d, _)| *d == c) {
+                Some(&(_, next)) => next,
+                None => {
+                    let next = edges.len();
+                    edges[node].push((c, next));
+                    edges.push(Vec::new());
+                    accepting.push(0);
+                    next
+                }
+            };
+        }
+        accepting[node] += 1;
+    }
+    let mut parents = vec![(0, 0); edges.len()];
+    let root = alternatives(&edges, &accepting, &mut parents, 0);
+    Literals::new(Rc::new(Trie { edges, accepting, parents, root }))
+}
+
+/// The alternatives at `node`, or `None` if it has none, which only
+/// the root of an empty trie can have. Records where each child is
+/// among them in `parents`.
+///
+/// A literal which appears more than once ends in an item of its own
+/// for each extra copy, so it's counted the way `|` would count it; an
+/// empty alternative after the shared part would only count once.
+fn alternatives<T: Clone, M: Zero>(edges: &[Vec<(T, usize)>], accepting: &[usize], parents: &mut [(usize, usize)], node: usize) -> Option<Alternatives<T, M>> {
+    let mut all = Vec::new();
+    if accepting[node] > 0 {
+        all.push(Alternative::End);
+    }
+    for (c, next) in &edges[node] {
+        parents[*next] = (node, all.len());
+        let then = alternatives(edges, accepting, parents, *next).expect("trie node leads nowhere");
+        all.push(Alternative::Edge(Equals(c.clone()), Box::new(then), zero()));
+        for _ in 1..accepting[*next] {
+            let end = Alternatives { first: Alternative::End, rest: None };
+            all.push(Alternative::Edge(Equals(c.clone()), Box::new(end), zero()));
+        }
+    }
+    all.into_iter().rev().fold(None, |rest, first| Some(Alternatives { first, rest: rest.map(Box::new) }))
+}
+
+impl<T, M: Zero> Literals<T, M> {
+    fn new(trie: Rc<Trie<T, M>>) -> AnyRegex<T, M, Self> where
+        Self: Regex<T, M>,
+    {
+        let marks = (0..trie.edges.len()).map(|node| Waiting { mark: zero(), node, trie: trie.clone() }).collect();
+        AnyRegex::new(Literals { trie, marks, live: Vec::new(), pending: Vec::new(), none: zero() })
+    }
+}
+
+impl<T, M> Trie<T, M> {
+    /// What's left of the literals from `node`.
+    fn from(&self, node: usize) -> Option<&Alternatives<T, M>> {
+        let mut path = Vec::new();
+        let mut at = node;
+        while at != 0 {
+            let (parent, i) = self.parents[at];
+            path.push(i);
+            at = parent;
+        }
+        let mut alternatives = self.root.as_ref()?;
+        for i in path.into_iter().rev() {
+            for _ in 0..i {
+                alternatives = alternatives.rest.as_ref().expect("trie path leads nowhere");
+            }
+            alternatives = match alternatives.first {
+                Alternative::Edge(_, ref then, _) => then,
+                Alternative::End => unreachable!("trie path leads to an end"),
+            };
+        }
+        Some(alternatives)
+    }
+}
+
+fn step<T: PartialEq>(edges : &[(T, usize)], c : &T) -> Option<usize> {
+    edges.iter().find(|&(d, _)| d == c).map(|&(_, next)| next)
+}
+
+impl<T, M> Regex<T, M> for Literals<T, M> where
+    T: PartialEq,
+    M: Zero + ops::Mul<Output=M> + Clone,
+    bool: IntoWithInput<T, M>,
+{
+    fn empty(&mut self) -> bool { self.trie.accepting[0] > 0 }
+    fn active(&self) -> bool { !self.live.is_empty() }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        // Every trie node has exactly one parent, so each node receives
+        // at most one mark. Collect them all before storing any, since a
+        // node may be both a source and a destination.
+        let mut pending = take(&mut self.pending);
+        let trie = &*self.trie;
+        if !mark.is_zero() {
+            if let Some(next) = step(&trie.edges[0], c) {
+                pending.push((next, mark));
+            }
+        }
+        for &node in &self.live {
+            let mark = replace(&mut self.marks[node].mark, zero());
+            if let Some(next) = step(&trie.edges[node], c) {
+                pending.push((next, mark));
+            }
+        }
+        self.live.clear();
+
+        let mut result = zero();
+        for (node, mark) in pending.drain(..) {
+            let mark = mark * true.into_with_input(c);
+            if mark.is_zero() {
+                continue;
+            }
+            for _ in 0..trie.accepting[node] {
+                result = result + mark.clone();
+            }
+            self.marks[node].mark = mark;
+            self.live.push(node);
+        }
+        self.pending = pending;
+        result
+    }
+    fn reset(&mut self) {
+        for node in self.live.drain(..) {
+            self.marks[node].mark = zero();
+        }
+    }
+}
+
+/// Collecting strings builds a `literals` grammar matching any of them:
+/// `["GET", "PUT"].iter().collect()`.
+impl<'a, M> iter::FromIterator<&'a str> for AnyRegex<char, M, Literals<char, M>> where
+    M: Zero + ops::Mul<Output=M> + Clone,
+    bool: IntoWithInput<char, M>,
+{
+    fn from_iter<I: IntoIterator<Item=&'a str>>(words: I) -> Self {
+        literals(words.into_iter().map(str::chars))
+    }
+}
+
+impl<'a, 'b, M> iter::FromIterator<&'a &'b str> for AnyRegex<char, M, Literals<char, M>> where
+    M: Zero + ops::Mul<Output=M> + Clone,
+    bool: IntoWithInput<char, M>,
+{
+    fn from_iter<I: IntoIterator<Item=&'a &'b str>>(words: I) -> Self {
+        literals(words.into_iter().map(|word| word.chars()))
+    }
+}
+
+impl<T, M> iter::FromIterator<Vec<T>> for AnyRegex<T, M, Literals<T, M>> where
+    T: PartialEq + Clone,
+    M: Zero + ops::Mul<Output=M> + Clone,
+    bool: IntoWithInput<T, M>,
+{
+    fn from_iter<I: IntoIterator<Item=Vec<T>>>(words: I) -> Self {
+        literals(words)
+    }
+}
+
+impl<T, M> CloneRegex<T, M> for Literals<T, M> where
+    T: PartialEq + Clone,
+    M: Zero + ops::Mul<Output=M> + Clone,
+    bool: IntoWithInput<T, M>,
+{
+    fn clone_reset(&self) -> AnyRegex<T, M, Self> {
+        Literals::new(self.trie.clone())
+    }
+}
+
+impl<T, M> Describe<T, M> for Literals<T, M> where
+    T: PartialEq,
+    M: Zero + One,
+    bool: IntoWithInput<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> {
+        match self.trie.root {
+            Some(ref root) => root.describe(),
+            // Nothing at all, as the intersection of the empty input
+            // with one item.
+            None => Node::And(&Empty, &AnyItem),
+        }
+    }
+    // Every copy reports the shared trie's tables.
+    fn heap_size(&self) -> usize {
+        let trie = &*self.trie;
+        let edges: usize = trie.edges.iter().map(|out| out.capacity() * mem::size_of::<(T, usize)>()).sum();
+        edges + trie.edges.capacity() * mem::size_of::<Vec<(T, usize)>>() +
+            trie.accepting.capacity() * mem::size_of::<usize>() +
+            trie.parents.capacity() * mem::size_of::<(usize, usize)>() +
+            self.marks.capacity() * mem::size_of::<Waiting<T, M>>() +
+            self.live.capacity() * mem::size_of::<usize>() +
+            self.pending.capacity() * mem::size_of::<(usize, M)>()
+    }
+    // Each marked node is an attempt of its own, continuing with what's
+    // left of the literals from there.
+    fn attempts(&self) -> Option<Attempts<'_, T, M>> {
+        Some(Attempts {
+            settled: &self.none,
+            attempts: self.live.iter().map(|&node| {
+                let waiting = &self.marks[node];
+                (&waiting.mark, waiting as &dyn Describe<T, M>)
+            }).collect(),
+        })
+    }
+}
+
+impl<T, M> Describe<T, M> for Waiting<T, M> where
+    T: PartialEq,
+    M: Zero + One,
+    bool: IntoWithInput<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> {
+        match self.trie.from(self.node) {
+            Some(rest) => Node::Sequence(&Empty, rest, &self.mark),
+            None => Node::And(&Empty, &AnyItem),
+        }
+    }
+}
+
+impl<T, M> Describe<T, M> for Alternatives<T, M> where
+    T: PartialEq,
+    M: One,
+    bool: IntoWithInput<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> {
+        match self.rest {
+            Some(ref rest) => Node::Or(&self.first, &**rest),
+            None => self.first.describe(),
+        }
+    }
+}
+
+impl<T, M> Describe<T, M> for Alternative<T, M> where
+    T: PartialEq,
+    M: One,
+    bool: IntoWithInput<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> {
+        match *self {
+            Alternative::End => Node::Empty,
+            Alternative::Edge(ref item, ref then, ref none) => Node::Sequence(item, &**then, none),
+        }
+    }
+}
+
+impl<T, M> Leaf<T, M> for Equals<T> where
+    T: PartialEq,
+    bool: IntoWithInput<T, M>,
+{
+    fn weigh(&self, c : &T) -> M { (*c == self.0).into_with_input(c) }
+}
+
+impl<T, M> Describe<T, M> for Equals<T> where
+    T: PartialEq,
+    bool: IntoWithInput<T, M>,
+{
+    fn describe(&self) -> Node<'_, T, M> { Node::Is(self) }
+}
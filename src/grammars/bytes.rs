@@ -0,0 +1,152 @@
+//! Leaves for grammars over bytes.
+//!
+//! These match one byte from a set stored as a 256-bit `ByteClass`, so
+//! shifting a byte is a single table lookup rather than a call through
+//! an arbitrary closure. Unlike `is` leaves, they can describe the bytes
+//! they match, so grammars built from them display readably.
+
+use byteclass::ByteClass;
+use engine::{AnyRegex, CloneRegex, Describe, IntoWithInput, Leaf, Node, Regex};
+use grammars::{literals, Literals};
+use num_traits::Zero;
+use std::fmt;
+use std::iter;
+use std::ops::{self, RangeInclusive};
+
+/// A leaf matching any one byte in its set.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bytes(ByteClass);
+
+/// Match exactly the byte `b`.
+pub fn byte<M>(b: u8) -> AnyRegex<u8, M, Bytes> where
+    M: ops::Mul<Output=M>,
+    bool: IntoWithInput<u8, M>,
+{
+    byte_class(ByteClass::from_bytes(&[b]))
+}
+
+/// Match any byte in `range`, such as `b'a'..=b'z'`.
+pub fn byte_range<M>(range: RangeInclusive<u8>) -> AnyRegex<u8, M, Bytes> where
+    M: ops::Mul<Output=M>,
+    bool: IntoWithInput<u8, M>,
+{
+    let mut class = ByteClass::default();
+    for b in range {
+        class.insert(b);
+    }
+    byte_class(class)
+}
+
+/// Match any of `bytes`.
+pub fn byte_set<M, I>(bytes: I) -> AnyRegex<u8, M, Bytes> where
+    M: ops::Mul<Output=M>,
+    bool: IntoWithInput<u8, M>,
+    I: IntoIterator<Item=u8>,
+{
+    let mut class = ByteClass::default();
+    for b in bytes {
+        class.insert(b);
+    }
+    byte_class(class)
+}
+
+/// Match any byte in `class`.
+pub fn byte_class<M>(class: ByteClass) -> AnyRegex<u8, M, Bytes> where
+    M: ops::Mul<Output=M>,
+    bool: IntoWithInput<u8, M>,
+{
+    AnyRegex::new(Bytes(class))
+}
+
+/// Match exactly the sequence `literal`, such as `b"GET "`.
+pub fn bytes_literal<M>(literal: &[u8]) -> AnyRegex<u8, M, Literals<u8, M>> where
+    M: Zero + ops::Mul<Output=M> + Clone,
+    bool: IntoWithInput<u8, M>,
+{
+    literals(iter::once(literal.iter().cloned()))
+}
+
+impl<M> Regex<u8, M> for Bytes where
+    M: ops::Mul<Output=M>,
+    bool: IntoWithInput<u8, M>,
+{
+    fn empty(&mut self) -> bool { false }
+    fn active(&self) -> bool { false }
+    fn shift(&mut self, c : &u8, mark : M) -> M { mark * self.weigh(c) }
+    fn reset(&mut self) { }
+}
+
+impl<M> CloneRegex<u8, M> for Bytes where
+    M: ops::Mul<Output=M>,
+    bool: IntoWithInput<u8, M>,
+{
+    fn clone_reset(&self) -> AnyRegex<u8, M, Self> { byte_class(self.0) }
+}
+
+impl<M> Leaf<u8, M> for Bytes where
+    bool: IntoWithInput<u8, M>,
+{
+    fn weigh(&self, c : &u8) -> M { self.0.contains(*c).into_with_input(c) }
+
+    fn fmt_leaf(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = self.0.bytes();
+        if bytes.len() == 1 {
+            return fmt_byte(bytes[0], f);
+        }
+        f.write_str("[")?;
+        let mut i = 0;
+        while i < bytes.len() {
+            // Find the end of this run of consecutive bytes.
+            let mut j = i;
+            while j + 1 < bytes.len() && bytes[j + 1] == bytes[j] + 1 {
+                j += 1;
+            }
+            fmt_byte(bytes[i], f)?;
+            if j > i + 1 {
+                f.write_str("-")?;
+            }
+            if j > i {
+                fmt_byte(bytes[j], f)?;
+            }
+            i = j + 1;
+        }
+        f.write_str("]")
+    }
+}
+
+impl<M> Describe<u8, M> for Bytes where
+    bool: IntoWithInput<u8, M>,
+{
+    fn describe(&self) -> Node<'_, u8, M> { Node::Is(self) }
+}
+
+/// Write `b` as itself if it's an ASCII letter or digit, or as a hex
+/// escape otherwise.
+fn fmt_byte(b: u8, f: &mut fmt::Formatter) -> fmt::Result {
+    if b.is_ascii_alphanumeric() {
+        write!(f, "{}", b as char)
+    } else {
+        write!(f, "\\x{:02X}", b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn leaves() {
+        let mut re = bytes_literal::<Match>(b"GET ") + byte_range(b'a'..=b'z') + many(byte_range(b'a'..=b'z') | byte(b'/')) + byte_set(b"\r\n".iter().cloned());
+        assert!(!has_match(&mut re, b"GET /index\n".iter().cloned()));
+        assert!(has_match(&mut re, b"GET a/index\r".iter().cloned()));
+        assert!(!has_match(&mut re, b"GET A\r".iter().cloned()));
+        assert!(!has_match(&mut re, b"PUT a\n".iter().cloned()));
+    }
+
+    #[test]
+    fn display() {
+        let re = byte::<Match>(b'a') + byte_set(vec![b'0', b'1', b'2', b'x', b'y', b' ']) + byte_range(0..=255);
+        assert_eq!(re.to_string(), "a[\\x200-2xy][\\x00-\\xFF]");
+    }
+}
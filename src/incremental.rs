@@ -0,0 +1,146 @@
+//! Keeping matches up to date while a buffer is edited.
+//!
+//! A `Document` holds a sequence of items, such as the text in an
+//! editor, along with the end of every match of a compiled grammar in
+//! it. As in `writer`, matches may start anywhere. Every `interval`
+//! items, the document also keeps a snapshot of the grammar's marks.
+//!
+//! After an edit, the document rescans from the last snapshot before
+//! the edit. Once it's past the edit, it compares its marks with the
+//! snapshots taken before the edit, and as soon as they're the same,
+//! it stops: everything after that point matches just as it did, only
+//! moved by the change in length.
+//!
+//! How soon that happens depends on the grammar. Most grammars forget
+//! about earlier input shortly after a match attempt fails, but a match
+//! attempt that can go on indefinitely, like one for an `a` followed by
+//! any number of `x`s, may force a rescan all the way to the end.
+
+use core::{Describe, Regex};
+use flat::{Compiled, Snapshot, State};
+use num_traits::{Zero, One, one};
+use std::ops::{self, Range};
+
+/// A buffer of items and the matches in it; see the module docs.
+pub struct Document<'c, T: 'c, M: 'c> {
+    state: State<'c, T, M>,
+    items: Vec<T>,
+    interval: usize,
+    /// The marks before the item at each position, by position. There
+    /// is always one for position 0.
+    snapshots: Vec<(usize, Snapshot<M>)>,
+    /// The offset just past the end of each match, and its weight.
+    ends: Vec<(usize, M)>,
+}
+
+impl<'c, T, M> Document<'c, T, M> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq,
+{
+    /// Find the matches of `compiled` in `items`, keeping a snapshot
+    /// every `interval` items. Smaller intervals make edits cheaper to
+    /// rescan, but take more memory.
+    pub fn new<R>(compiled: &'c Compiled<T, M, R>, items: Vec<T>, interval: usize) -> Self where
+        R: Describe<T, M>,
+    {
+        assert!(interval > 0, "snapshot interval must be positive");
+        let state = compiled.state().into_inner();
+        let start = state.snapshot();
+        let mut document = Document {
+            state,
+            items: Vec::new(),
+            interval,
+            snapshots: vec![(0, start)],
+            ends: Vec::new(),
+        };
+        document.edit(0..0, items);
+        document
+    }
+
+    pub fn items(&self) -> &[T] { &self.items }
+
+    /// The offset just past the end of each match, and its weight, in
+    /// order.
+    pub fn ends(&self) -> &[(usize, M)] { &self.ends }
+
+    /// Replace the items in `range` with `replacement`, and update the
+    /// matches. Returns how many items had to be rescanned.
+    pub fn edit<I>(&mut self, range: Range<usize>, replacement: I) -> usize where
+        I: IntoIterator<Item=T>,
+    {
+        let old_len = self.items.len();
+        self.items.splice(range.clone(), replacement);
+        let inserted = self.items.len() + range.len() - old_len;
+        let edit_end = range.start + inserted;
+        // Where the item at new position `at` was before the edit.
+        let old_position = |at: usize| at + range.len() - inserted;
+
+        let keep = self.snapshots.iter().rposition(|&(at, _)| at <= range.start).unwrap();
+        let mut old_snapshots = self.snapshots.split_off(keep + 1);
+        let from = self.snapshots[keep].0;
+        self.state.restore(&self.snapshots[keep].1);
+        let first_old_end = self.ends.iter().position(|&(end, _)| end > from).unwrap_or(self.ends.len());
+        let mut old_ends = self.ends.split_off(first_old_end);
+
+        for at in from..self.items.len() {
+            if at >= edit_end {
+                let old = old_position(at);
+                if let Ok(i) = old_snapshots.binary_search_by_key(&old, |&(at, _)| at) {
+                    if self.state.snapshot() == old_snapshots[i].1 {
+                        // Everything from here on is unchanged.
+                        self.snapshots.extend(old_snapshots.drain(i..).map(|(was, snapshot)| (was + at - old, snapshot)));
+                        let unchanged = old_ends.iter().position(|&(end, _)| end > old).unwrap_or(old_ends.len());
+                        self.ends.extend(old_ends.drain(unchanged..).map(|(was, weight)| (was + at - old, weight)));
+                        return at - from;
+                    }
+                }
+            }
+            if at > from && at % self.interval == 0 {
+                self.snapshots.push((at, self.state.snapshot()));
+            }
+            let weight = self.state.shift(&self.items[at], one());
+            if !weight.is_zero() {
+                self.ends.push((at + 1, weight));
+            }
+        }
+        self.items.len() - from
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    fn fresh_ends(compiled: &Compiled<u8, Match, impl Describe<u8, Match>>, items: &[u8]) -> Vec<usize> {
+        Document::new(compiled, items.to_vec(), 4).ends().iter().map(|&(end, _)| end).collect()
+    }
+
+    #[test]
+    fn edits() {
+        let compiled = Compiled::new(is(|&b: &u8| b == b'a') + many(is(|&b: &u8| b == b'b'))).ok().unwrap();
+        let mut text = b"xxabbxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxab".to_vec();
+        let mut document = Document::new(&compiled, text.clone(), 4);
+        for &(ref range, replacement) in &[(3..4, &b"xa"[..]), (10..10, b"abb"), (0..5, b""), (20..30, b"b"), (0..0, b"a")] {
+            let rescanned = document.edit(range.clone(), replacement.iter().cloned());
+            text.splice(range.clone(), replacement.iter().cloned());
+            assert_eq!(document.items(), &text[..]);
+            let ends: Vec<usize> = document.ends().iter().map(|&(end, _)| end).collect();
+            assert_eq!(ends, fresh_ends(&compiled, &text));
+            assert!(rescanned < 16, "rescanned {} items", rescanned);
+        }
+    }
+
+    #[test]
+    fn long_lived_marks() {
+        // Whether a match is in progress depends on whether there was an
+        // `a` anywhere before the run of `x`s.
+        let compiled = Compiled::new(is(|&b: &u8| b == b'a') + many(is(|&b: &u8| b == b'x')) + is(|&b: &u8| b == b'c')).ok().unwrap();
+        let mut text = b"a".to_vec();
+        text.extend(b"x".iter().cycle().take(40));
+        text.push(b'c');
+        let mut document: Document<u8, Match> = Document::new(&compiled, text, 4);
+        assert_eq!(document.ends().len(), 1);
+        assert_eq!(document.edit(0..1, b"x".iter().cloned()), 42);
+        assert!(document.ends().is_empty());
+    }
+}
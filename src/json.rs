@@ -0,0 +1,405 @@
+//! Matching the structure of JSON documents as streams of events.
+//!
+//! `events` reads JSON text one character at a time and produces an
+//! `Event` for each scalar and for the start and end of each object and
+//! array, without building the document in memory. Grammars over
+//! `Event`s can then check a document's shape: which keys appear, in
+//! what order, and what kinds of values they have. `value` matches any
+//! one complete value, however deeply nested, for skipping the parts
+//! of a document a grammar doesn't care about.
+
+use core::{AnyRegex, Grammar, IntoWithInput, Regex};
+use dynamic::Dynamic;
+use grammars::is;
+use num_traits::{Zero, zero, One, one};
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::ops;
+
+/// One step through a JSON document.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    /// An object key, which is always followed by its value.
+    Key(String),
+    String(String),
+    /// A number, as written in the document.
+    Number(String),
+    Bool(bool),
+    Null,
+}
+
+/// The input wasn't valid JSON, as discovered after reading `position`
+/// characters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    pub position: usize,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid JSON at offset {}", self.position)
+    }
+}
+
+impl Error for JsonError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Expect {
+    Value,
+    /// A value or the end of an array which has no values yet.
+    FirstValue,
+    Key,
+    /// A key or the end of an object which has no keys yet.
+    FirstKey,
+    Colon,
+    /// A comma or the end of the innermost object or array.
+    Next,
+    Done,
+}
+
+/// The events of a JSON document; see `events`.
+pub struct Events<I: Iterator<Item=char>> {
+    input: Peekable<I>,
+    position: usize,
+    /// For each open container, whether it's an object.
+    objects: Vec<bool>,
+    expect: Expect,
+    failed: bool,
+}
+
+/// Read a single JSON value from `input` as a stream of events. After
+/// the first error, the stream ends.
+pub fn events<I: IntoIterator<Item=char>>(input: I) -> Events<I::IntoIter> {
+    Events { input: input.into_iter().peekable(), position: 0, objects: Vec::new(), expect: Expect::Value, failed: false }
+}
+
+impl<I: Iterator<Item=char>> Events<I> {
+    fn peek(&mut self) -> Option<char> { self.input.peek().cloned() }
+
+    fn bump(&mut self) -> Result<char, JsonError> {
+        let c = self.input.next().ok_or(JsonError { position: self.position })?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    fn error(&self) -> JsonError { JsonError { position: self.position } }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+            self.bump().unwrap();
+        }
+    }
+
+    fn expect_char(&mut self, expected: char) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(c) if c == expected => self.bump().map(|_| ()),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn finish_value(&mut self) {
+        self.expect = if self.objects.is_empty() { Expect::Done } else { Expect::Next };
+    }
+
+    fn close(&mut self, object: bool) -> Result<Event, JsonError> {
+        if self.objects.pop() != Some(object) {
+            return Err(self.error());
+        }
+        self.bump()?;
+        self.finish_value();
+        Ok(if object { Event::EndObject } else { Event::EndArray })
+    }
+
+    fn step(&mut self) -> Result<Option<Event>, JsonError> {
+        loop {
+            self.skip_whitespace();
+            let c = match self.peek() {
+                Some(c) => c,
+                None if self.expect == Expect::Done => return Ok(None),
+                None => return Err(self.error()),
+            };
+            match (self.expect, c) {
+                (Expect::Done, _) => return Err(self.error()),
+                (Expect::FirstValue, ']') | (Expect::Next, ']') => return self.close(false).map(Some),
+                (Expect::FirstKey, '}') | (Expect::Next, '}') => return self.close(true).map(Some),
+                (Expect::Next, ',') => {
+                    self.bump()?;
+                    self.expect = if self.objects.last() == Some(&true) { Expect::Key } else { Expect::Value };
+                }
+                (Expect::Key, '"') | (Expect::FirstKey, '"') => {
+                    let key = self.string()?;
+                    self.expect = Expect::Colon;
+                    return Ok(Some(Event::Key(key)));
+                }
+                (Expect::Colon, ':') => {
+                    self.bump()?;
+                    self.expect = Expect::Value;
+                }
+                (Expect::Value, _) | (Expect::FirstValue, _) => return self.value(c).map(Some),
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    fn value(&mut self, c: char) -> Result<Event, JsonError> {
+        let event = match c {
+            '{' | '[' => {
+                self.bump()?;
+                let object = c == '{';
+                self.objects.push(object);
+                self.expect = if object { Expect::FirstKey } else { Expect::FirstValue };
+                return Ok(if object { Event::StartObject } else { Event::StartArray });
+            }
+            '"' => Event::String(self.string()?),
+            '-' | '0'..='9' => Event::Number(self.number()?),
+            't' => self.word("true", Event::Bool(true))?,
+            'f' => self.word("false", Event::Bool(false))?,
+            'n' => self.word("null", Event::Null)?,
+            _ => return Err(self.error()),
+        };
+        self.finish_value();
+        Ok(event)
+    }
+
+    fn word(&mut self, word: &str, event: Event) -> Result<Event, JsonError> {
+        for expected in word.chars() {
+            self.expect_char(expected)?;
+        }
+        Ok(event)
+    }
+
+    fn digits(&mut self, text: &mut String) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {}
+            _ => return Err(self.error()),
+        }
+        while let Some(c) = self.peek().filter(char::is_ascii_digit) {
+            text.push(c);
+            self.bump()?;
+        }
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<String, JsonError> {
+        let mut text = String::new();
+        if self.peek() == Some('-') {
+            text.push(self.bump()?);
+        }
+        if self.peek() == Some('0') {
+            text.push(self.bump()?);
+        } else {
+            self.digits(&mut text)?;
+        }
+        if self.peek() == Some('.') {
+            text.push(self.bump()?);
+            self.digits(&mut text)?;
+        }
+        if let Some('e') | Some('E') = self.peek() {
+            text.push(self.bump()?);
+            if let Some('+') | Some('-') = self.peek() {
+                text.push(self.bump()?);
+            }
+            self.digits(&mut text)?;
+        }
+        Ok(text)
+    }
+
+    fn hex(&mut self) -> Result<u32, JsonError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = self.bump()?.to_digit(16).ok_or_else(|| self.error())?;
+            value = value * 16 + digit;
+        }
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect_char('"')?;
+        let mut text = String::new();
+        loop {
+            match self.bump()? {
+                '"' => return Ok(text),
+                '\\' => text.push(match self.bump()? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let mut code = self.hex()?;
+                        if (0xD800..0xDC00).contains(&code) {
+                            self.expect_char('\\')?;
+                            self.expect_char('u')?;
+                            let low = self.hex()?;
+                            if !(0xDC00..0xE000).contains(&low) {
+                                return Err(self.error());
+                            }
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        }
+                        ::std::char::from_u32(code).ok_or_else(|| self.error())?
+                    }
+                    _ => return Err(self.error()),
+                }),
+                c if c < ' ' => return Err(self.error()),
+                c => text.push(c),
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item=char>> Iterator for Events<I> {
+    type Item = Result<Event, JsonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.step();
+        self.failed = result.is_err();
+        result.transpose()
+    }
+}
+
+impl<M, R> AnyRegex<Event, M, R> where
+    M: Zero + One,
+    R: Regex<Event, M>,
+{
+    /// Match the events of the JSON document in `input`, or report
+    /// where it stopped being valid JSON. Either way, the grammar is
+    /// reset afterward.
+    pub fn over_json<I>(&mut self, input: I) -> Result<M, JsonError> where
+        I: IntoIterator<Item=char>,
+    {
+        let mut result = None;
+        let mut mark = one();
+        for event in events(input) {
+            match event {
+                Ok(event) => result = Some(self.shift(&event, mark)),
+                Err(e) => {
+                    self.reset();
+                    return Err(e);
+                }
+            }
+            mark = zero();
+        }
+        // A valid document always has at least one event.
+        let result = result.unwrap_or_else(zero);
+        self.reset();
+        Ok(result)
+    }
+}
+
+/// A leaf matching exactly `event`.
+pub fn event<M>(event: Event) -> AnyRegex<Event, M, impl Grammar<Event, M>> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<Event, M>,
+{
+    let text = format!("{:?}", event);
+    is(move |e: &Event| *e == event).label(text)
+}
+
+/// A leaf matching the object key `name`.
+pub fn key<M>(name: &str) -> AnyRegex<Event, M, impl Grammar<Event, M>> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<Event, M>,
+{
+    event(Event::Key(name.to_string()))
+}
+
+/// A leaf matching any object key.
+pub fn any_key<M>() -> AnyRegex<Event, M, impl Grammar<Event, M>> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<Event, M>,
+{
+    is(|e: &Event| matches!(*e, Event::Key(_))).label("<key>")
+}
+
+/// A leaf matching any string value.
+pub fn string<M>() -> AnyRegex<Event, M, impl Grammar<Event, M>> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<Event, M>,
+{
+    is(|e: &Event| matches!(*e, Event::String(_))).label("<string>")
+}
+
+/// A leaf matching any number.
+pub fn number<M>() -> AnyRegex<Event, M, impl Grammar<Event, M>> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<Event, M>,
+{
+    is(|e: &Event| matches!(*e, Event::Number(_))).label("<number>")
+}
+
+/// A leaf matching any string, number, boolean, or null.
+pub fn scalar<M>() -> AnyRegex<Event, M, impl Grammar<Event, M>> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<Event, M>,
+{
+    is(|e: &Event| matches!(*e, Event::String(_) | Event::Number(_) | Event::Bool(_) | Event::Null)).label("<scalar>")
+}
+
+/// Any one complete value: a scalar, or an object or array and
+/// everything in it.
+pub fn value<M>() -> AnyRegex<Event, M, Dynamic<Event, M>> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+    bool: IntoWithInput<Event, M>,
+{
+    let array = Dynamic::sequence(
+        Dynamic::sequence(Dynamic::other(event(Event::StartArray)), Dynamic::many(Dynamic::delay(value))),
+        Dynamic::other(event(Event::EndArray)));
+    let member = Dynamic::sequence(Dynamic::other(any_key()), Dynamic::delay(value));
+    let object = Dynamic::sequence(
+        Dynamic::sequence(Dynamic::other(event(Event::StartObject)), Dynamic::many(member)),
+        Dynamic::other(event(Event::EndObject)));
+    Dynamic::or(Dynamic::other(scalar()), Dynamic::or(array, object))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    fn all(text: &str) -> Result<Vec<Event>, JsonError> { events(text.chars()).collect() }
+
+    #[test]
+    fn event_stream() {
+        use self::Event::*;
+        assert_eq!(all(r#" {"a": [1, -2.5e3, true], "b\u00e9": {}, "c": null} "#), Ok(vec![
+            StartObject,
+            Key("a".to_string()), StartArray, Number("1".to_string()), Number("-2.5e3".to_string()), Bool(true), EndArray,
+            Key("bé".to_string()), StartObject, EndObject,
+            Key("c".to_string()), Null,
+            EndObject,
+        ]));
+        assert_eq!(all(r#""\ud83d\ude00\n""#), Ok(vec![String("😀\n".to_string())]));
+
+        assert_eq!(all(""), Err(JsonError { position: 0 }));
+        assert_eq!(all("[1,]"), Err(JsonError { position: 3 }));
+        assert_eq!(all("[1}"), Err(JsonError { position: 2 }));
+        assert_eq!(all(r#"{"a" 1}"#), Err(JsonError { position: 5 }));
+        assert_eq!(all("01"), Err(JsonError { position: 1 }));
+        assert_eq!(all("[] []"), Err(JsonError { position: 3 }));
+        assert_eq!(all("tru"), Err(JsonError { position: 3 }));
+    }
+
+    #[test]
+    fn shapes() {
+        // An array of objects, each with key "id" somewhere before "name".
+        let skip = || many(any_key() + value());
+        let record = event(Event::StartObject) + skip() + key("id") + value() + skip() + key("name") + string() + skip() + event(Event::EndObject);
+        let mut re = event::<Match>(Event::StartArray) + many(record) + event(Event::EndArray);
+
+        assert_eq!(re.over_json(r#"[{"id": 1, "name": "a"}, {"x": [{}], "id": {"n": 2}, "y": 0, "name": "b"}]"#.chars()).map(|m| !m.is_zero()), Ok(true));
+        assert_eq!(re.over_json(r#"[{"name": "a", "id": 1}]"#.chars()).map(|m| !m.is_zero()), Ok(false));
+        assert_eq!(re.over_json(r#"[{"id": 1, "name": 2}]"#.chars()).map(|m| !m.is_zero()), Ok(false));
+        assert_eq!(re.over_json("[{]".chars()).map(|m| !m.is_zero()), Err(JsonError { position: 2 }));
+    }
+}
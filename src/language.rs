@@ -0,0 +1,220 @@
+//! Questions about the whole language a grammar matches.
+//!
+//! Leaves are arbitrary predicates, so these all take an alphabet: the
+//! items the answers are about. Grammars which only use regular
+//! operators are converted to a `Dfa` over the alphabet and answered
+//! exactly. Others, using complement, intersection across more than
+//! one item, or recursion, are answered by trying every input up to a
+//! length limit instead.
+
+use core::{AnyRegex, Describe, Regex};
+use dfa::{Accepted, Dfa};
+use nfa::Nfa;
+#[cfg(feature = "rand")]
+use rand::Rng;
+use std::hash::Hash;
+use testing::Strings;
+use weights::recognize::{has_match, Match};
+
+/// The most states `Dfa` conversion may build before falling back to
+/// trying inputs one at a time.
+const MAX_STATES: usize = 10_000;
+
+/// Whether every input from `alphabet` that `a` matches, `b` matches
+/// too, so that a rewritten pattern `a` only tightens the original `b`.
+///
+/// If both grammars can be converted to automata, the answer is exact.
+/// Otherwise, it only covers inputs of up to `max_len` items.
+pub fn is_subset_of<T, A, B>(a: &mut AnyRegex<T, Match, A>, b: &mut AnyRegex<T, Match, B>, alphabet: &[T], max_len: usize) -> bool where
+    T: Hash + Eq + Clone,
+    A: Regex<T, Match> + Describe<T, Match>,
+    B: Regex<T, Match> + Describe<T, Match>,
+{
+    if let (Some(a), Some(b)) = (to_dfa(a, alphabet), to_dfa(b, alphabet)) {
+        return a.is_subset_of(&b);
+    }
+    Strings::new(alphabet, Some(max_len)).all(|input| {
+        !has_match(a, input.iter().cloned()) || has_match(b, input.iter().cloned())
+    })
+}
+
+/// The shortest input from `alphabet` that `re` matches, choosing the
+/// earliest items in `alphabet` among inputs of that length, or `None`
+/// if there isn't one. A match is a useful example of what a pattern
+/// accepts, and not finding one means the pattern is vacuous.
+///
+/// If `re` can be converted to an automaton, the answer is exact.
+/// Otherwise, only inputs of up to `max_len` items are searched.
+pub fn shortest_member<T, R>(re: &mut AnyRegex<T, Match, R>, alphabet: &[T], max_len: usize) -> Option<Vec<T>> where
+    T: Hash + Eq + Clone,
+    R: Regex<T, Match> + Describe<T, Match>,
+{
+    if let Some(dfa) = to_dfa(re, alphabet) {
+        return dfa.shortest_accepted();
+    }
+    Strings::new(alphabet, Some(max_len)).find(|input| has_match(re, input.iter().cloned()))
+}
+
+/// Every input from `alphabet` that `re` matches, shortest first, and
+/// in alphabet order within each length, for listing what a pattern
+/// accepts.
+///
+/// If `re` can be converted to an automaton, inputs that can't lead to
+/// a match are skipped, and the iterator ends once there are no more
+/// members. Otherwise every input is tried in turn, so asking for more
+/// members than the language has never returns.
+pub fn enumerate_members<'a, T, R>(re: &'a mut AnyRegex<T, Match, R>, alphabet: &'a [T]) -> Members<'a, T, R> where
+    T: Hash + Eq + Clone,
+    R: Regex<T, Match> + Describe<T, Match>,
+{
+    match to_dfa(re, alphabet) {
+        Some(dfa) => Members(Search::Exact(dfa.into_accepted())),
+        None => Members(Search::Bounded(re, Strings::new(alphabet, None))),
+    }
+}
+
+/// The members of a grammar's language, from `enumerate_members`.
+pub struct Members<'a, T: 'a, R: 'a>(Search<'a, T, R>);
+
+enum Search<'a, T: 'a, R: 'a> {
+    Exact(Accepted<T, Match>),
+    Bounded(&'a mut AnyRegex<T, Match, R>, Strings<'a, T>),
+}
+
+impl<'a, T, R> Iterator for Members<'a, T, R> where
+    T: Clone,
+    R: Regex<T, Match>,
+{
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        match self.0 {
+            Search::Exact(ref mut accepted) => accepted.next(),
+            Search::Bounded(ref mut re, ref mut strings) =>
+                strings.find(|input| has_match(re, input.iter().cloned())),
+        }
+    }
+}
+
+/// A random input of at most `max_len` items from `alphabet` that `re`
+/// matches, or `None` if there isn't one, for generating test inputs
+/// for whatever consumes the pattern's matches. Every member of that
+/// length is equally likely.
+///
+/// If `re` can be converted to an automaton, this counts the members
+/// reachable from each state instead of guessing and retrying.
+/// Otherwise it tries every input up to `max_len` first.
+#[cfg(feature = "rand")]
+pub fn sample_member<T, R, G>(re: &mut AnyRegex<T, Match, R>, alphabet: &[T], max_len: usize, rng: &mut G) -> Option<Vec<T>> where
+    T: Hash + Eq + Clone,
+    R: Regex<T, Match> + Describe<T, Match>,
+    G: Rng + ?Sized,
+{
+    if let Some(dfa) = to_dfa(re, alphabet) {
+        return dfa.sample_accepted(max_len, rng);
+    }
+    let mut members: Vec<_> = Strings::new(alphabet, Some(max_len))
+        .filter(|input| has_match(re, input.iter().cloned()))
+        .collect();
+    if members.is_empty() {
+        return None;
+    }
+    let pick = rng.gen_range(0, members.len());
+    Some(members.swap_remove(pick))
+}
+
+fn to_dfa<T>(re: &dyn Describe<T, Match>, alphabet: &[T]) -> Option<Dfa<T, Match>> where
+    T: Hash + Eq + Clone,
+{
+    Dfa::new(&Nfa::new(re).ok()?, alphabet, MAX_STATES).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn containment() {
+        let a = || is(|&c| c == 'a');
+        let b = || is(|&c| c == 'b');
+        let alphabet = ['a', 'b'];
+        let mut narrow: AnyRegex<char, Match, _> = a() + many(a() | b());
+        let mut wide = many(a() | b());
+        assert!(is_subset_of(&mut narrow, &mut wide, &alphabet, 0));
+        assert!(!is_subset_of(&mut wide, &mut narrow, &alphabet, 0));
+
+        // Complements can't become automata, so check short inputs.
+        let mut not_b = !(many(a() | b()) + b());
+        assert!(is_subset_of(&mut not_b, &mut wide, &alphabet, 6));
+        assert!(!is_subset_of(&mut not_b, &mut narrow, &alphabet, 6));
+    }
+
+    #[test]
+    fn shortest() {
+        let a = || is(|&c| c == 'a');
+        let b = || is(|&c| c == 'b');
+        let alphabet = ['a', 'b'];
+        let mut re: AnyRegex<char, Match, _> = (b() + a() + b()) | (many(a()) + b() + b());
+        assert_eq!(shortest_member(&mut re, &alphabet, 0), Some(vec!['b', 'b']));
+        let mut re: AnyRegex<char, Match, _> = (b() + a()) | (a() + b());
+        assert_eq!(shortest_member(&mut re, &alphabet, 0), Some(vec!['a', 'b']));
+        let mut never: AnyRegex<char, Match, _> = a() & b();
+        assert_eq!(shortest_member(&mut never, &alphabet, 0), None);
+
+        let mut not_short = !(many(a() | b()) + b()) & (a() + a() + many(a() | b()));
+        assert_eq!(shortest_member(&mut not_short, &alphabet, 4), Some(vec!['a', 'a']));
+    }
+
+    #[test]
+    fn enumerate() {
+        let a = || is(|&c| c == 'a');
+        let b = || is(|&c| c == 'b');
+        let alphabet = ['a', 'b'];
+        fn words<I: Iterator<Item=Vec<char>>>(members: I) -> Vec<String> {
+            members.map(|m| m.into_iter().collect()).collect()
+        }
+
+        let mut finite: AnyRegex<char, Match, _> = (b() + a() + b()) | (a() + (b() | empty()));
+        assert_eq!(words(enumerate_members(&mut finite, &alphabet)), vec!["a", "ab", "bab"]);
+
+        let mut infinite: AnyRegex<char, Match, _> = many(a()) + b();
+        assert_eq!(words(enumerate_members(&mut infinite, &alphabet).take(3)), vec!["b", "ab", "aab"]);
+
+        let mut not_b = !(many(a() | b()) + b());
+        assert_eq!(words(enumerate_members(&mut not_b, &alphabet).take(4)), vec!["", "a", "aa", "ba"]);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn sampling() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+        use std::collections::HashMap;
+
+        let a = || is(|&c| c == 'a');
+        let b = || is(|&c| c == 'b');
+        let alphabet = ['a', 'b'];
+        let mut rng = StdRng::seed_from_u64(1);
+
+        // Four members: b, ab, aab, aaab.
+        let mut re: AnyRegex<char, Match, _> = many(a()) + b();
+        let mut seen = HashMap::new();
+        for _ in 0..400 {
+            let member = sample_member(&mut re, &alphabet, 4, &mut rng).unwrap();
+            assert!(has_match(&mut re, member.iter().cloned()));
+            *seen.entry(member).or_insert(0) += 1;
+        }
+        assert_eq!(seen.len(), 4);
+        assert!(seen.values().all(|&n| n > 50), "{:?}", seen);
+
+        let mut not_b = !(many(a() | b()) + b());
+        for _ in 0..20 {
+            let member = sample_member(&mut not_b, &alphabet, 3, &mut rng).unwrap();
+            assert!(member.len() <= 3 && member.last() != Some(&'b'));
+        }
+
+        let mut never: AnyRegex<char, Match, _> = a() & b();
+        assert_eq!(sample_member(&mut never, &alphabet, 4, &mut rng), None);
+    }
+}
@@ -0,0 +1,677 @@
+//! Splitting input into tokens.
+//!
+//! A `Lexer` holds a list of rules, each pairing a token kind with a
+//! grammar. At each position in the input, every rule is run forward
+//! in parallel for as long as any of them has a match in progress. The
+//! longest match wins, and among rules matching the same length, the
+//! one added first wins, so keywords should be added before the
+//! identifier rule they would otherwise lose to.
+//!
+//! Rules never produce empty tokens: a rule which matches the empty
+//! input only counts where it matches at least one item.
+//!
+//! The `lexer!` macro declares an enum of token kinds along with the
+//! rules for each, in priority order.
+//!
+//! Rules can be grouped into modes, like one for the inside of string
+//! literals, where only that mode's rules apply. A rule can push a new
+//! mode onto a stack when it produces a token, or pop back to the mode
+//! before.
+//!
+//! Where no rule matches, the lexer stops with a `LexError` by default.
+//! With a `Recovery` policy, it reports the error along with the items
+//! it skipped, and carries on.
+//!
+//! Input which arrives in pieces can be fed to `Lexer::stream` as it
+//! comes, which holds back any token that the next piece could extend.
+//!
+//! Grammars can match the lexer's output in turn, using `kind` and
+//! `kind_where` for leaves, and `find_tokens` to search a token list
+//! and report matches in terms of the original input.
+//!
+//! Tokens locate themselves by item index. For text, as `char`s or
+//! bytes, `Tokens::located` also gives byte offsets and line and column
+//! numbers, for error messages.
+
+use core::{AnyRegex, Grammar, IntoWithInput, Regex};
+use grammars::is;
+use num_traits::{Zero, zero, one};
+use search::find_spans;
+use std::error::Error;
+use std::fmt;
+use std::ops::{self, Range};
+use weights::leftmost::Leftmost;
+use weights::recognize::Match;
+
+type Rule<T> = AnyRegex<T, Match, Box<dyn Regex<T, Match>>>;
+
+/// Token rules, in priority order.
+pub struct Lexer<K, T> {
+    rules: Vec<Entry<K, T>>,
+    recovery: Recovery,
+    /// The mode for rules added next.
+    mode: usize,
+}
+
+struct Entry<K, T> {
+    kind: K,
+    mode: usize,
+    then: Option<Transition>,
+    re: Rule<T>,
+}
+
+/// How a rule changes the lexer's mode when it produces a token.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Transition {
+    /// Switch to this mode, remembering the current one.
+    Push(usize),
+    /// Switch back to the mode before the last `Push`. In the starting
+    /// mode, this does nothing.
+    Pop,
+}
+
+/// What to do where no rule matches.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Recovery {
+    /// Report the error and produce no more tokens.
+    Stop,
+    /// Skip one item, report it as an error, and continue.
+    SkipItem,
+    /// Skip to the next position where some rule matches, report the
+    /// skipped items as one error, and continue.
+    SkipToToken,
+}
+
+/// One token: the rule that matched, and the range of items it covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<K> {
+    pub kind: K,
+    pub span: Range<usize>,
+}
+
+/// No rule matched the input at `position`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LexError {
+    pub position: usize,
+    /// How many items were skipped to recover, or 0 if the lexer
+    /// stopped here.
+    pub skipped: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no token matches at position {}", self.position)
+    }
+}
+
+impl Error for LexError {}
+
+impl<K, T> Lexer<K, T> {
+    /// A lexer with no rules, which can't match anything yet.
+    pub fn new() -> Self {
+        Lexer { rules: Vec::new(), recovery: Recovery::Stop, mode: 0 }
+    }
+
+    /// Put the rules added after this in mode `mode`. Lexing starts in
+    /// mode 0, which is also where rules go until this is called.
+    pub fn mode(self, mode: usize) -> Self {
+        Lexer { mode, ..self }
+    }
+
+    /// Recover from errors according to `recovery`, instead of stopping
+    /// at the first one.
+    pub fn recover(self, recovery: Recovery) -> Self {
+        Lexer { recovery, ..self }
+    }
+
+    /// Add a rule producing `kind` tokens, with lower priority than
+    /// every rule added before it.
+    pub fn rule<R>(mut self, kind: K, re: AnyRegex<T, Match, R>) -> Self where
+        R: Regex<T, Match> + 'static,
+    {
+        self.rules.push(Entry { kind, mode: self.mode, then: None, re: AnyRegex::new(re.boxed()) });
+        self
+    }
+
+    /// Like `rule`, but change modes according to `then` after each
+    /// token this rule produces.
+    pub fn rule_then<R>(mut self, kind: K, re: AnyRegex<T, Match, R>, then: Transition) -> Self where
+        R: Regex<T, Match> + 'static,
+    {
+        self.rules.push(Entry { kind, mode: self.mode, then: Some(then), re: AnyRegex::new(re.boxed()) });
+        self
+    }
+
+    /// The tokens in `input`, in order. If no rule matches somewhere,
+    /// the iterator yields a `LexError` for that position, and then
+    /// either stops or recovers, according to the lexer's `Recovery`.
+    pub fn tokens<'a>(&'a mut self, input: &'a [T]) -> Tokens<'a, K, T> {
+        Tokens { lexer: self, input, position: 0, modes: vec![0], failed: false }
+    }
+
+    /// Lex input which arrives in chunks, as from a socket or reader.
+    pub fn stream(&mut self) -> Stream<'_, K, T> {
+        Stream { lexer: self, buffer: Vec::new(), offset: 0, modes: vec![0], failed: false }
+    }
+
+    /// Find the next token or error starting at `start` in `mode`.
+    /// Unless the input is `complete`, give up if it might depend on
+    /// input that hasn't arrived yet.
+    fn step(&mut self, input: &[T], start: usize, mode: usize, complete: bool) -> Step {
+        let (best, exhausted) = self.longest(input, start, mode);
+        if exhausted && !complete {
+            return Step::NeedMore;
+        }
+        if let Some((index, end)) = best {
+            return Step::Token(index, end);
+        }
+        Step::Error(match self.recovery {
+            Recovery::Stop => 0,
+            Recovery::SkipItem => 1,
+            Recovery::SkipToToken => {
+                let mut end = start + 1;
+                loop {
+                    if end == input.len() {
+                        // Whatever comes next might not match either.
+                        if !complete {
+                            return Step::NeedMore;
+                        }
+                        break;
+                    }
+                    match self.longest(input, end, mode) {
+                        (_, true) if !complete => return Step::NeedMore,
+                        (None, _) => end += 1,
+                        (Some(_), _) => break,
+                    }
+                }
+                end - start
+            }
+        })
+    }
+
+    /// The longest token starting at `start` from the rules in `mode`,
+    /// as its rule's index and its end, and whether some rule was still
+    /// matching when the input ran out.
+    fn longest(&mut self, input: &[T], start: usize, mode: usize) -> (Option<(usize, usize)>, bool) {
+        let mut best = None;
+        let mut exhausted = true;
+        for (at, c) in input[start..].iter().enumerate() {
+            let mark = if at == 0 { one() } else { zero() };
+            let mut active = false;
+            for (index, rule) in self.rules.iter_mut().enumerate() {
+                let re = &mut rule.re;
+                if rule.mode != mode || (at > 0 && !re.active()) {
+                    continue;
+                }
+                let matched = !re.shift(c, mark).is_zero();
+                // Earlier rules already had their chance at this end.
+                if matched && best.is_none_or(|(_, end)| end < start + at + 1) {
+                    best = Some((index, start + at + 1));
+                }
+                active |= re.active();
+            }
+            if !active {
+                exhausted = false;
+                break;
+            }
+        }
+        for rule in &mut self.rules {
+            rule.re.reset();
+        }
+        (best, exhausted)
+    }
+}
+
+impl<K, T> Default for Lexer<K, T> {
+    fn default() -> Self { Lexer::new() }
+}
+
+/// A place in text being lexed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// Bytes before this position.
+    pub offset: usize,
+    /// Line number, counting from 1.
+    pub line: usize,
+    /// Items since the start of the line, counting from 1, so for
+    /// `char`s this counts characters rather than bytes.
+    pub column: usize,
+}
+
+impl Position {
+    /// The start of the text.
+    pub fn start() -> Self {
+        Position { offset: 0, line: 1, column: 1 }
+    }
+
+    /// The position just after `c`.
+    fn after<T: TextItem>(self, c: &T) -> Self {
+        if c.is_newline() {
+            Position { offset: self.offset + c.len_bytes(), line: self.line + 1, column: 1 }
+        } else {
+            Position { offset: self.offset + c.len_bytes(), column: self.column + 1, ..self }
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Items of text, which `Position` can count.
+pub trait TextItem {
+    fn len_bytes(&self) -> usize;
+    fn is_newline(&self) -> bool;
+}
+
+impl TextItem for char {
+    fn len_bytes(&self) -> usize { self.len_utf8() }
+    fn is_newline(&self) -> bool { *self == '\n' }
+}
+
+impl TextItem for u8 {
+    fn len_bytes(&self) -> usize { 1 }
+    fn is_newline(&self) -> bool { *self == b'\n' }
+}
+
+/// A token or error, with where it starts and ends in the text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Located<V> {
+    pub value: V,
+    pub start: Position,
+    pub end: Position,
+}
+
+/// The tokens of some input, from `Lexer::tokens`.
+pub struct Tokens<'a, K: 'a, T: 'a> {
+    lexer: &'a mut Lexer<K, T>,
+    input: &'a [T],
+    position: usize,
+    modes: Vec<usize>,
+    failed: bool,
+}
+
+impl<'a, K: Clone, T> Iterator for Tokens<'a, K, T> {
+    type Item = Result<Token<K>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.position >= self.input.len() {
+            return None;
+        }
+        let start = self.position;
+        let step = self.lexer.step(self.input, start, *self.modes.last().unwrap(), true);
+        let (result, end) = self.lexer.emit(step, start, 0, &mut self.modes)?;
+        self.position = end;
+        self.failed |= result.is_err() && end == start;
+        Some(result)
+    }
+}
+
+/// What the lexer found at some position.
+enum Step {
+    /// A token from the rule with this index, ending here.
+    Token(usize, usize),
+    /// No token, so this many items were skipped.
+    Error(usize),
+    /// The input so far isn't enough to decide.
+    NeedMore,
+}
+
+impl<K: Clone, T> Lexer<K, T> {
+    /// Turn a step from `start` into its result and where the next one
+    /// begins, with positions counted from `offset`, and follow the
+    /// rule's transition on the stack of `modes`.
+    fn emit(&self, step: Step, start: usize, offset: usize, modes: &mut Vec<usize>) -> Option<(Result<Token<K>, LexError>, usize)> {
+        match step {
+            Step::Token(index, end) => {
+                let rule = &self.rules[index];
+                match rule.then {
+                    Some(Transition::Push(mode)) => modes.push(mode),
+                    Some(Transition::Pop) if modes.len() > 1 => {
+                        modes.pop();
+                    }
+                    Some(Transition::Pop) | None => {}
+                }
+                let token = Token { kind: rule.kind.clone(), span: offset + start..offset + end };
+                Some((Ok(token), end))
+            }
+            Step::Error(skipped) => Some((Err(LexError { position: offset + start, skipped }), start + skipped)),
+            Step::NeedMore => None,
+        }
+    }
+}
+
+/// Tokens from input arriving in chunks, from `Lexer::stream`. Token
+/// spans count items from the start of the first chunk.
+pub struct Stream<'a, K: 'a, T: 'a> {
+    lexer: &'a mut Lexer<K, T>,
+    /// Items which might still be part of a token.
+    buffer: Vec<T>,
+    /// How many items came before the buffer.
+    offset: usize,
+    modes: Vec<usize>,
+    failed: bool,
+}
+
+impl<'a, K: Clone, T: Clone> Stream<'a, K, T> {
+    /// Add `chunk` to the input, and return the tokens and errors which
+    /// can't be affected by what comes after it. The rest are held back
+    /// until more input arrives.
+    pub fn push(&mut self, chunk: &[T]) -> Vec<Result<Token<K>, LexError>> {
+        self.buffer.extend_from_slice(chunk);
+        self.drain(false)
+    }
+
+    /// End the input, returning everything that was held back.
+    pub fn finish(mut self) -> Vec<Result<Token<K>, LexError>> {
+        self.drain(true)
+    }
+
+    fn drain(&mut self, complete: bool) -> Vec<Result<Token<K>, LexError>> {
+        let mut found = Vec::new();
+        let mut start = 0;
+        while !self.failed && start < self.buffer.len() {
+            let step = self.lexer.step(&self.buffer, start, *self.modes.last().unwrap(), complete);
+            let (result, end) = match self.lexer.emit(step, start, self.offset, &mut self.modes) {
+                Some(emitted) => emitted,
+                None => break,
+            };
+            self.failed = result.is_err() && end == start;
+            found.push(result);
+            start = end;
+        }
+        self.buffer.drain(..start);
+        self.offset += start;
+        found
+    }
+}
+
+/// A leaf matching one token of kind `kind`, labeled with the kind's
+/// `Debug` text.
+pub fn kind<K, M>(kind: K) -> AnyRegex<Token<K>, M, impl Grammar<Token<K>, M>> where
+    K: PartialEq + Clone + fmt::Debug,
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<Token<K>, M>,
+{
+    let text = format!("{:?}", kind);
+    is(move |token: &Token<K>| token.kind == kind).label(text)
+}
+
+/// A leaf matching one token for which `f` returns true.
+pub fn kind_where<K, M, F>(f: F) -> AnyRegex<Token<K>, M, impl Grammar<Token<K>, M>> where
+    M: Zero + ops::Mul<Output=M>,
+    F: Fn(&Token<K>) -> bool + Clone,
+    bool: IntoWithInput<Token<K>, M>,
+{
+    is(f)
+}
+
+/// Find the non-overlapping matches of `re` in `tokens`, like
+/// `search::find_spans`, and give each one's span in the input the
+/// tokens came from, from the start of its first token to the end of
+/// its last.
+pub fn find_tokens<K, R>(re: &mut AnyRegex<Token<K>, Leftmost, R>, tokens: &[Token<K>]) -> Vec<Range<usize>> where
+    R: Regex<Token<K>, Leftmost>,
+{
+    find_spans(re, tokens).into_iter()
+        .map(|(start, end)| tokens[start].span.start..tokens[end - 1].span.end)
+        .collect()
+}
+
+/// Declare an enum of token kinds, each with the grammar for its rule,
+/// and a `lexer()` constructor trying the rules in the order given:
+///
+/// ```
+/// # #[macro_use] extern crate weighted_regexp;
+/// # use weighted_regexp::*;
+/// lexer! {
+///     pub enum Token: char {
+///         If = literals(Some("if".chars())),
+///         Ident = is(|c: &char| c.is_alphabetic()) + many(is(|c: &char| c.is_alphanumeric())),
+///         Space = is(|c: &char| c.is_whitespace()),
+///     }
+/// }
+///
+/// # fn main() {
+/// let input: Vec<char> = "if x".chars().collect();
+/// let mut lexer = Token::lexer();
+/// let kinds: Vec<Token> = lexer.tokens(&input).map(|t| t.unwrap().kind).collect();
+/// assert_eq!(kinds, vec![Token::If, Token::Space, Token::Ident]);
+/// # }
+/// ```
+///
+/// The enum derives `Copy`, `Clone`, `Debug`, `PartialEq`, `Eq`, and
+/// `Hash`, and any attributes written before it are kept.
+#[macro_export]
+macro_rules! lexer {
+    ($(#[$attr:meta])* $vis:vis enum $name:ident : $t:ty { $($kind:ident = $re:expr),+ $(,)* }) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        $vis enum $name { $($kind),+ }
+
+        impl $name {
+            /// A lexer for these tokens, where earlier rules win ties.
+            $vis fn lexer() -> $crate::lexer::Lexer<$name, $t> {
+                $crate::lexer::Lexer::new() $(.rule($name::$kind, $re))+
+            }
+        }
+    };
+}
+
+impl<'a, K, T: TextItem> Tokens<'a, K, T> {
+    /// Locate each token and error in the text. An error covers the
+    /// items skipped to recover from it, if any.
+    pub fn located(self) -> LocatedTokens<'a, K, T> {
+        LocatedTokens { tokens: self, at: Position::start() }
+    }
+}
+
+/// Tokens with their positions in the text, from `Tokens::located`.
+pub struct LocatedTokens<'a, K: 'a, T: 'a> {
+    tokens: Tokens<'a, K, T>,
+    /// The position of the next token, and of `tokens.position`.
+    at: Position,
+}
+
+impl<'a, K: Clone, T: TextItem> Iterator for LocatedTokens<'a, K, T> {
+    type Item = Result<Located<Token<K>>, Located<LexError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.at;
+        Some(match self.tokens.next()? {
+            Ok(token) => {
+                let end = self.tokens.input[token.span.clone()].iter().fold(start, Position::after);
+                self.at = end;
+                Ok(Located { value: token, start, end })
+            }
+            Err(error) => {
+                let skipped = &self.tokens.input[error.position..error.position + error.skipped];
+                let end = skipped.iter().fold(start, Position::after);
+                self.at = end;
+                Err(Located { value: error, start, end })
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    enum Kind { If, Ident, Number, Space }
+
+    fn example() -> Lexer<Kind, char> {
+        let lower = || is(|c: &char| c.is_ascii_lowercase());
+        let digit = || is(|c: &char| c.is_ascii_digit());
+        let space = || is(|&c: &char| c == ' ');
+        Lexer::new()
+            .rule(Kind::If, is(|&c| c == 'i') + is(|&c| c == 'f'))
+            .rule(Kind::Ident, lower() + many(lower()))
+            .rule(Kind::Number, many(digit()))
+            .rule(Kind::Space, space() + many(space()))
+    }
+
+    fn kinds(lexer: &mut Lexer<Kind, char>, input: &str) -> Vec<Result<(Kind, Range<usize>), LexError>> {
+        let input: Vec<char> = input.chars().collect();
+        lexer.tokens(&input).map(|t| t.map(|t| (t.kind, t.span))).collect()
+    }
+
+    #[test]
+    fn longest_match() {
+        let mut lexer = example();
+        assert_eq!(kinds(&mut lexer, "if iffy  42"), vec![
+            Ok((Kind::If, 0..2)),
+            Ok((Kind::Space, 2..3)),
+            Ok((Kind::Ident, 3..7)),
+            Ok((Kind::Space, 7..9)),
+            Ok((Kind::Number, 9..11)),
+        ]);
+    }
+
+    lexer! {
+        enum Arith: u8 {
+            Number = many(is(|b: &u8| b.is_ascii_digit())),
+            Plus = is(|&b| b == b'+'),
+            Times = is(|&b| b == b'*'),
+        }
+    }
+
+    #[test]
+    fn declared() {
+        let mut lexer = Arith::lexer();
+        let kinds: Vec<Arith> = lexer.tokens(b"12+3*45").map(|t| t.unwrap().kind).collect();
+        assert_eq!(kinds, vec![Arith::Number, Arith::Plus, Arith::Number, Arith::Times, Arith::Number]);
+    }
+
+    #[test]
+    fn positions() {
+        let mut lexer = Arith::lexer()
+            .rule(Arith::Plus, is(|&b| b == b'\n'));
+        let found: Vec<_> = lexer.tokens(b"1+\n23\n*x").located().map(|t| match t {
+            Ok(t) => (t.start.to_string(), t.end.offset),
+            Err(e) => (format!("error at {}", e.start), e.start.offset),
+        }).collect();
+        assert_eq!(found, vec![
+            ("1:1".to_string(), 1),
+            ("1:2".to_string(), 2),
+            ("1:3".to_string(), 3),
+            ("2:1".to_string(), 5),
+            ("2:3".to_string(), 6),
+            ("3:1".to_string(), 7),
+            ("error at 3:2".to_string(), 7),
+        ]);
+
+        let mut words = Lexer::new().rule(Kind::Ident, many(is(|c: &char| c.is_alphabetic())));
+        let input: Vec<char> = "héllo".chars().collect();
+        let end = words.tokens(&input).located().next().unwrap().unwrap().end;
+        assert_eq!(end, Position { offset: 6, line: 1, column: 6 });
+    }
+
+    #[test]
+    fn recovery() {
+        let mut lexer = example().recover(Recovery::SkipItem);
+        assert_eq!(kinds(&mut lexer, "a+-b"), vec![
+            Ok((Kind::Ident, 0..1)),
+            Err(LexError { position: 1, skipped: 1 }),
+            Err(LexError { position: 2, skipped: 1 }),
+            Ok((Kind::Ident, 3..4)),
+        ]);
+        let mut lexer = example().recover(Recovery::SkipToToken);
+        assert_eq!(kinds(&mut lexer, "a+-b"), vec![
+            Ok((Kind::Ident, 0..1)),
+            Err(LexError { position: 1, skipped: 2 }),
+            Ok((Kind::Ident, 3..4)),
+        ]);
+        assert_eq!(kinds(&mut lexer, "a?!"), vec![
+            Ok((Kind::Ident, 0..1)),
+            Err(LexError { position: 1, skipped: 2 }),
+        ]);
+
+        let mut lexer = Arith::lexer().recover(Recovery::SkipToToken);
+        let errors: Vec<_> = lexer.tokens(b"1\n?\n?+").located().filter_map(Result::err).collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].start.to_string(), errors[0].end.to_string()), ("1:2".to_string(), "3:2".to_string()));
+    }
+
+    #[test]
+    fn chunks() {
+        let input = "if iffy 42 x+y";
+        let whole = kinds(&mut example().recover(Recovery::SkipToToken), input);
+        // Every way of splitting the input gives the same tokens.
+        for size in 1..input.len() {
+            let mut lexer = example().recover(Recovery::SkipToToken);
+            let mut stream = lexer.stream();
+            let chars: Vec<char> = input.chars().collect();
+            let mut found = Vec::new();
+            for chunk in chars.chunks(size) {
+                found.extend(stream.push(chunk));
+            }
+            found.extend(stream.finish());
+            let found: Vec<_> = found.into_iter().map(|t| t.map(|t| (t.kind, t.span))).collect();
+            assert_eq!(found, whole, "chunks of {}", size);
+        }
+
+        // Tokens come out as soon as they're decided.
+        let mut lexer = example();
+        let mut stream = lexer.stream();
+        assert!(stream.push(&['i']).is_empty());
+        assert!(stream.push(&['f']).is_empty());
+        assert_eq!(stream.push(&[' ']), vec![Ok(Token { kind: Kind::If, span: 0..2 })]);
+        assert_eq!(stream.finish(), vec![Ok(Token { kind: Kind::Space, span: 2..3 })]);
+    }
+
+    #[test]
+    fn modes() {
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        enum Kind { Word, Space, Quote, Text, Escape }
+        const STRING: usize = 1;
+        let quote = || is(|&c| c == '"');
+        let plain = || is(|&c: &char| c != '"' && c != '\\');
+        let mut lexer = Lexer::new()
+            .rule(Kind::Word, is(|c: &char| c.is_alphabetic()) + many(is(|c: &char| c.is_alphabetic())))
+            .rule(Kind::Space, is(|&c| c == ' '))
+            .rule_then(Kind::Quote, quote(), Transition::Push(STRING))
+            .mode(STRING)
+            .rule(Kind::Text, plain() + many(plain()))
+            .rule(Kind::Escape, is(|&c| c == '\\') + is(|_| true))
+            .rule_then(Kind::Quote, quote(), Transition::Pop);
+        let input: Vec<char> = r#"say "hi \" there" ok"#.chars().collect();
+        let kinds: Vec<Kind> = lexer.tokens(&input).map(|t| t.unwrap().kind).collect();
+        assert_eq!(kinds, vec![
+            Kind::Word, Kind::Space,
+            Kind::Quote, Kind::Text, Kind::Escape, Kind::Text, Kind::Quote,
+            Kind::Space, Kind::Word,
+        ]);
+    }
+
+    #[test]
+    fn token_grammars() {
+        let input: Vec<char> = "if x  if yy 42".chars().collect();
+        let tokens: Vec<Token<Kind>> = example().tokens(&input).map(Result::unwrap).collect();
+
+        // An `if` and the identifier after it.
+        let mut re = kind(Kind::If) + kind(Kind::Space) + kind_where(|t: &Token<Kind>| t.kind == Kind::Ident && t.span.len() > 1);
+        assert_eq!(find_tokens(&mut re, &tokens), vec![6..11]);
+        let mut re: AnyRegex<_, Match, _> = kind(Kind::If) + kind(Kind::Space) + kind(Kind::Ident);
+        let kinds: Vec<Kind> = tokens[..3].iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, vec![Kind::If, Kind::Space, Kind::Ident]);
+        assert!(has_match(&mut re, tokens[..3].iter().cloned()));
+        assert!(!has_match(&mut re, tokens[1..4].iter().cloned()));
+    }
+
+    #[test]
+    fn no_match() {
+        let mut lexer = example();
+        assert_eq!(kinds(&mut lexer, "ab+c"), vec![
+            Ok((Kind::Ident, 0..2)),
+            Err(LexError { position: 2, skipped: 0 }),
+        ]);
+        // The lexer is ready for more input afterward.
+        assert_eq!(kinds(&mut lexer, "c"), vec![Ok((Kind::Ident, 0..1))]);
+        assert!(kinds(&mut lexer, "").is_empty());
+    }
+}
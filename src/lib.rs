@@ -1,18 +1,127 @@
+#![cfg_attr(feature = "nightly", feature(pattern))]
+#![cfg_attr(not(feature = "std"), no_std)]
+
 #[cfg(test)]
 #[macro_use]
 extern crate quickcheck;
 #[cfg(test)]
 extern crate itertools;
 
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+
+extern crate memchr;
 extern crate num_traits;
+#[cfg(feature = "rand")]
+extern crate rand;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "unicode")]
+extern crate regex_syntax;
+#[cfg(feature = "memmap")]
+extern crate libc;
 
-pub mod core;
+#[cfg(feature = "count-allocations")]
+pub mod alloc_counter;
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod bitparallel;
+#[cfg(feature = "std")]
+pub mod budget;
+#[cfg(feature = "std")]
+pub mod byteclass;
+pub mod engine;
+#[cfg(feature = "std")]
+pub mod debug;
+#[cfg(feature = "std")]
+pub mod decode;
+#[cfg(feature = "std")]
+pub mod derivative;
+#[cfg(feature = "std")]
+pub mod dfa;
+#[cfg(feature = "regex")]
+pub mod differential;
+#[cfg(feature = "std")]
+pub mod dot;
+#[cfg(feature = "std")]
+pub mod dynamic;
+#[cfg(feature = "std")]
+pub mod explain;
+#[cfg(feature = "std")]
+pub mod flat;
+#[cfg(feature = "std")]
+pub mod footprint;
 pub mod grammars;
+#[cfg(feature = "std")]
+pub mod incremental;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod lexer;
+#[cfg(feature = "std")]
+pub mod language;
+#[cfg(feature = "std")]
+pub mod matcher;
+#[cfg(feature = "std")]
+pub mod nfa;
+#[cfg(feature = "openfst")]
+pub mod openfst;
+#[cfg(feature = "std")]
+pub mod parse;
+#[cfg(feature = "nightly")]
+pub mod pattern;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "std")]
+pub mod prefilter;
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod residual;
+#[cfg(feature = "std")]
+pub mod search;
+#[cfg(feature = "std")]
+pub mod set;
+#[cfg(feature = "std")]
+pub mod simplify;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod structural;
+#[cfg(feature = "std")]
+pub mod syntax;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "unicode")]
+pub mod unicode;
+#[cfg(feature = "std")]
+pub mod visit;
 pub mod weights;
+#[cfg(feature = "std")]
+pub mod window;
+#[cfg(feature = "std")]
+pub mod writer;
+
+/// The parts of `std` the engine uses, from `core` and `alloc`.
+#[cfg(not(feature = "std"))]
+mod std {
+    pub use core::{cmp, fmt, iter, marker, mem, ops};
+    pub use alloc::{borrow, boxed, rc, string, vec};
+    #[cfg(test)]
+    pub use core::{cell, str};
+}
+
+/// The engine's old name, kept where it doesn't collide with the
+/// standard library's `core` crate.
+#[cfg(feature = "std")]
+pub use engine as core;
 
 #[doc(inline)]
-pub use core::AnyRegex;
+pub use engine::AnyRegex;
 #[doc(inline)]
-pub use grammars::{empty, is, many, delay};
+pub use grammars::{empty, is, many, delay, literals};
 #[doc(inline)]
-pub use weights::recognize::{has_match, Match};
+pub use weights::recognize::{has_match, has_match_str, has_match_bytes, Match};
@@ -0,0 +1,131 @@
+//! Matching input that arrives in pieces.
+//!
+//! A `Matcher` owns a grammar and the weight of the input it has seen so
+//! far, so items can be pushed as they arrive, for instance as network
+//! packets. At any point, `status` tells whether the input so far
+//! matches, can't match no matter what follows, or might still match
+//! with more input. That distinguishes an invalid frame from an
+//! incomplete one.
+
+use analysis::is_empty_language;
+use core::{AnyRegex, Describe, Regex};
+use num_traits::{Zero, zero, One, one};
+
+/// How the input so far relates to a grammar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Partial<M> {
+    /// The input so far matches, with this weight. Longer input might
+    /// match too.
+    Match(M),
+    /// The input so far doesn't match, and no continuation of it can.
+    NoMatch,
+    /// The input so far doesn't match, but some continuation might.
+    NeedMoreInput,
+}
+
+impl<M> Partial<M> {
+    /// Convert the weight of a match, if any, with `f`.
+    pub fn map<S, F: FnOnce(M) -> S>(self, f: F) -> Partial<S> {
+        match self {
+            Partial::Match(weight) => Partial::Match(f(weight)),
+            Partial::NoMatch => Partial::NoMatch,
+            Partial::NeedMoreInput => Partial::NeedMoreInput,
+        }
+    }
+}
+
+/// Matches input pushed a piece at a time; see the module docs.
+pub struct Matcher<T, M, R> {
+    re: AnyRegex<T, M, R>,
+    /// The weight of the input so far, or `None` before any input.
+    last: Option<M>,
+}
+
+impl<T, M, R> Matcher<T, M, R> where
+    M: Zero + One + Clone,
+    R: Regex<T, M> + Describe<T, M>,
+{
+    pub fn new(re: AnyRegex<T, M, R>) -> Self {
+        Matcher { re, last: None }
+    }
+
+    /// Continue the input with `items`.
+    pub fn push<I: IntoIterator<Item=T>>(&mut self, items: I) {
+        for c in items {
+            let mark = if self.last.is_none() { one() } else { zero() };
+            self.last = Some(self.re.shift(&c, mark));
+        }
+    }
+
+    /// Whether the input so far matches, or might with more input.
+    ///
+    /// Grammars can't always tell that no continuation will match, so
+    /// `NeedMoreInput` may be reported for input that can never match,
+    /// but `NoMatch` is only reported when that's certain.
+    pub fn status(&mut self) -> Partial<M> {
+        let matched = match self.last {
+            Some(ref weight) => weight.clone(),
+            None => if self.re.empty() { one() } else { zero() },
+        };
+        if !matched.is_zero() {
+            Partial::Match(matched)
+        } else if is_empty_language(&self.re.residual(self.last.is_none())) {
+            Partial::NoMatch
+        } else {
+            Partial::NeedMoreInput
+        }
+    }
+
+    /// Forget the input so far, to start matching a new input.
+    pub fn reset(&mut self) {
+        self.re.reset();
+        self.last = None;
+    }
+
+    /// Give back the grammar, reset.
+    pub fn into_inner(mut self) -> AnyRegex<T, M, R> {
+        self.re.reset();
+        self.re
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn frames() {
+        // A frame is a length digit, that many letters, and a newline;
+        // here only lengths 1 and 2 are allowed.
+        let letter = || is(|b: &u8| b.is_ascii_lowercase());
+        let newline = || is(|&b: &u8| b == b'\n');
+        let re = (is(|&b: &u8| b == b'1') + letter() + newline())
+            | (is(|&b: &u8| b == b'2') + letter() + letter() + newline());
+        let mut frame: Matcher<u8, Match, _> = Matcher::new(re);
+
+        assert_eq!(frame.status(), Partial::NeedMoreInput);
+        frame.push(b"2a".iter().cloned());
+        assert_eq!(frame.status(), Partial::NeedMoreInput);
+        frame.push(b"b".iter().cloned());
+        assert_eq!(frame.status(), Partial::NeedMoreInput);
+        frame.push(b"\n".iter().cloned());
+        assert_eq!(frame.status(), Partial::Match(one()));
+        assert_eq!(frame.status().map(|m: Match| !m.is_zero()), Partial::Match(true));
+
+        frame.reset();
+        frame.push(b"1ab".iter().cloned());
+        assert_eq!(frame.status(), Partial::NoMatch);
+        frame.reset();
+        frame.push(b"3".iter().cloned());
+        assert_eq!(frame.status(), Partial::NoMatch);
+    }
+
+    #[test]
+    fn empty_input() {
+        let mut anything: Matcher<u8, Match, _> = Matcher::new(many(is(|_: &u8| true)));
+        assert_eq!(anything.status(), Partial::Match(one()));
+        let mut nothing: Matcher<u8, Match, _> = Matcher::new(is(|_: &u8| true) & empty());
+        assert_eq!(nothing.status(), Partial::NoMatch);
+    }
+}
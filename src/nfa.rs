@@ -0,0 +1,319 @@
+//! Explicit nondeterministic finite automata for regular grammars.
+//!
+//! The combinators in `grammars` already behave like a Glushkov
+//! automaton, but the states are spread across the grammar's structure.
+//! `Nfa` lays the same automaton out explicitly: numbered states, the
+//! test each state applies to the item that enters it, the states that
+//! can follow it, and whether it's final. That's useful for analyzing a
+//! grammar, or for running it with a different execution strategy.
+//!
+//! Only grammars built from regular operators can be converted. Inside
+//! a part of the grammar which always matches exactly one item,
+//! intersections are fine too, because they just combine tests.
+
+use core::{AnyRegex, Describe, Leaf, Node, Regex};
+use num_traits::{Zero, zero};
+use std::fmt;
+use std::mem::replace;
+use std::ops;
+use syntax::Inexpressible;
+
+/// The weight of one input item, computed by combining the `Leaf`s of
+/// a grammar that matches exactly one item.
+pub enum Test<'a, T: 'a, M: 'a> {
+    Is(&'a dyn Leaf<T, M>),
+    Or(Box<Test<'a, T, M>>, Box<Test<'a, T, M>>),
+    And(Box<Test<'a, T, M>>, Box<Test<'a, T, M>>),
+}
+
+impl<'a, T, M> Leaf<T, M> for Test<'a, T, M> where
+    M: Zero + ops::Mul<Output=M>,
+{
+    fn weigh(&self, c : &T) -> M {
+        match *self {
+            Test::Is(leaf) => leaf.weigh(c),
+            Test::Or(ref left, ref right) => left.weigh(c) + right.weigh(c),
+            Test::And(ref left, ref right) => left.weigh(c) * right.weigh(c),
+        }
+    }
+    fn fmt_leaf(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Test::Is(leaf) => leaf.fmt_leaf(f),
+            Test::Or(ref left, ref right) => {
+                left.fmt_leaf(f)?;
+                f.write_str("|")?;
+                right.fmt_leaf(f)
+            }
+            Test::And(ref left, ref right) => {
+                left.fmt_leaf(f)?;
+                f.write_str("&")?;
+                right.fmt_leaf(f)
+            }
+        }
+    }
+}
+
+/// One state of an `Nfa`. Every state except the start state
+/// corresponds to one leaf of the original grammar.
+pub struct State<'a, T: 'a, M: 'a> {
+    test: Option<Test<'a, T, M>>,
+    label: Option<&'a str>,
+    next: Vec<usize>,
+    accepting: bool,
+}
+
+impl<'a, T, M> State<'a, T, M> {
+    /// The test applied to an item on any transition into this state,
+    /// or `None` for the start state, which has no incoming
+    /// transitions.
+    pub fn test(&self) -> Option<&Test<'a, T, M>> { self.test.as_ref() }
+
+    /// The nearest `label` on the leaf this state came from, if any.
+    pub fn label(&self) -> Option<&'a str> { self.label }
+
+    /// States reachable from this one by consuming one item. A state
+    /// may be listed more than once if the grammar is ambiguous, and
+    /// each occurrence contributes to the weight separately.
+    pub fn next(&self) -> &[usize] { &self.next }
+
+    /// Whether a match may end in this state. The final weight is
+    /// `one()` if so and `zero()` otherwise.
+    pub fn is_final(&self) -> bool { self.accepting }
+}
+
+/// A Glushkov automaton borrowed from a grammar. It can be inspected
+/// through `states`, or used as a grammar in its own right, since it
+/// implements `Regex` with the same weights as the original.
+pub struct Nfa<'a, T: 'a, M: 'a> {
+    states: Vec<State<'a, T, M>>,
+    marks: Vec<M>,
+    incoming: Vec<M>,
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero,
+    R: Describe<T, M>,
+{
+    /// Extract the automaton equivalent to this grammar, if it only
+    /// uses regular operators.
+    pub fn to_nfa(&self) -> Result<Nfa<'_, T, M>, Inexpressible> {
+        Nfa::new(self)
+    }
+}
+
+impl<'a, T, M> Nfa<'a, T, M> where
+    M: Zero,
+{
+    /// Like `AnyRegex::to_nfa`, but for any grammar node.
+    pub fn new(re: &'a dyn Describe<T, M>) -> Result<Self, Inexpressible> {
+        let mut states = vec![State { test: None, label: None, next: Vec::new(), accepting: false }];
+        let top = visit(&mut states, re)?;
+        states[0].next = top.first;
+        states[0].accepting = top.nullable;
+        for p in top.last {
+            states[p].accepting = true;
+        }
+        let marks = states.iter().map(|_| zero()).collect();
+        let incoming = states.iter().map(|_| zero()).collect();
+        Ok(Nfa { states, marks, incoming })
+    }
+}
+
+impl<'a, T, M> Nfa<'a, T, M> {
+    /// All states, indexed by state number. State 0 is the start state.
+    pub fn states(&self) -> &[State<'a, T, M>] { &self.states }
+
+    /// The mark held by each state, indexed by state number, while
+    /// this automaton is being used as a grammar.
+    pub(crate) fn marks(&self) -> &[M] { &self.marks }
+}
+
+struct Positions {
+    nullable: bool,
+    first: Vec<usize>,
+    last: Vec<usize>,
+}
+
+fn visit<'a, T, M>(states: &mut Vec<State<'a, T, M>>, re: &'a dyn Describe<T, M>) -> Result<Positions, Inexpressible> {
+    if let Some(test) = single_item(re) {
+        let label = match re.describe() {
+            Node::Label(text, _) => Some(text),
+            _ => None,
+        };
+        // Alternatives get separate states when they don't share a
+        // label, so that each state can keep its own.
+        if label.is_some() || !is_or(re) {
+            let p = states.len();
+            states.push(State { test: Some(test), label, next: Vec::new(), accepting: false });
+            return Ok(Positions { nullable: false, first: vec![p], last: vec![p] });
+        }
+    }
+
+    Ok(match re.describe() {
+        Node::Empty => Positions { nullable: true, first: Vec::new(), last: Vec::new() },
+        Node::Is(_) => unreachable!(),
+        Node::Label(_, inner) => visit(states, inner)?,
+        Node::Not(_) => return Err(Inexpressible::Not),
+        Node::And(_, _) => return Err(Inexpressible::And),
+        Node::Delay => return Err(Inexpressible::Recursion),
+        Node::Or(left, right) => {
+            let mut left = visit(states, left)?;
+            let right = visit(states, right)?;
+            left.first.extend(right.first);
+            left.last.extend(right.last);
+            Positions { nullable: left.nullable || right.nullable, first: left.first, last: left.last }
+        }
+        Node::Sequence(left, right, _) => {
+            let mut left = visit(states, left)?;
+            let mut right = visit(states, right)?;
+            for &p in &left.last {
+                states[p].next.extend(&right.first);
+            }
+            let nullable = left.nullable && right.nullable;
+            if left.nullable {
+                left.first.extend(&right.first);
+            }
+            if right.nullable {
+                right.last.extend(left.last);
+            }
+            Positions { nullable, first: left.first, last: right.last }
+        }
+        Node::Many(re, _) => {
+            let inner = visit(states, re)?;
+            for &p in &inner.last {
+                states[p].next.extend(&inner.first);
+            }
+            Positions { nullable: true, first: inner.first, last: inner.last }
+        }
+    })
+}
+
+fn is_or<T, M>(re: &dyn Describe<T, M>) -> bool {
+    matches!(re.describe(), Node::Or(_, _))
+}
+
+/// If every string in this grammar's language is exactly one item
+/// long, build the equivalent test.
+fn single_item<'a, T, M>(re: &'a dyn Describe<T, M>) -> Option<Test<'a, T, M>> {
+    match re.describe() {
+        Node::Is(leaf) => Some(Test::Is(leaf)),
+        Node::Label(_, inner) => single_item(inner),
+        Node::Or(left, right) => both(left, right, Test::Or),
+        Node::And(left, right) => both(left, right, Test::And),
+        _ => None,
+    }
+}
+
+fn both<'a, T, M, F>(left: &'a dyn Describe<T, M>, right: &'a dyn Describe<T, M>, f: F) -> Option<Test<'a, T, M>> where
+    F: FnOnce(Box<Test<'a, T, M>>, Box<Test<'a, T, M>>) -> Test<'a, T, M>,
+{
+    Some(f(Box::new(single_item(left)?), Box::new(single_item(right)?)))
+}
+
+impl<'a, T, M> Regex<T, M> for Nfa<'a, T, M> where
+    M: Zero + ops::Mul<Output=M> + Clone,
+{
+    fn empty(&mut self) -> bool { self.states[0].accepting }
+    fn active(&self) -> bool { self.marks.iter().any(|m| !m.is_zero()) }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        if !mark.is_zero() {
+            for &p in &self.states[0].next {
+                let old = replace(&mut self.incoming[p], zero());
+                self.incoming[p] = old + mark.clone();
+            }
+        }
+        for (q, state) in self.states.iter().enumerate().skip(1) {
+            let mark = replace(&mut self.marks[q], zero());
+            if mark.is_zero() {
+                continue;
+            }
+            for &p in &state.next {
+                let old = replace(&mut self.incoming[p], zero());
+                self.incoming[p] = old + mark.clone();
+            }
+        }
+
+        let mut result = zero();
+        for (p, state) in self.states.iter().enumerate().skip(1) {
+            let incoming = replace(&mut self.incoming[p], zero());
+            if incoming.is_zero() {
+                continue;
+            }
+            let mark = incoming * state.test.as_ref().unwrap().weigh(c);
+            if state.accepting {
+                result = result + mark.clone();
+            }
+            self.marks[p] = mark;
+        }
+        result
+    }
+    fn reset(&mut self) {
+        for mark in &mut self.marks {
+            *mark = zero();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn structure() {
+        let a = is(|&c| c == 'a').label("a");
+        let b = is(|&c| c == 'b');
+        let re: AnyRegex<char, Match, _> = many(a) + b;
+        let nfa = re.to_nfa().ok().unwrap();
+        let states = nfa.states();
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[0].next(), &[1, 2]);
+        assert_eq!(states[1].next(), &[1, 2]);
+        assert_eq!(states[2].next(), &[] as &[usize]);
+        assert_eq!(states.iter().map(State::is_final).collect::<Vec<_>>(), vec![false, false, true]);
+        assert_eq!(states[1].label(), Some("a"));
+        assert_eq!(states[2].label(), None);
+    }
+
+    #[test]
+    fn single_item_intersection() {
+        let re: AnyRegex<u8, Match, _> = is(|&c| c > 10) & is(|&c| c % 2 == 0);
+        let nfa = re.to_nfa().ok().unwrap();
+        assert_eq!(nfa.states().len(), 2);
+        let test = nfa.states()[1].test().unwrap();
+        assert!(test.weigh(&11).is_zero());
+        assert!(!test.weigh(&12).is_zero());
+        assert!(test.weigh(&8).is_zero());
+    }
+
+    #[test]
+    fn inexpressible() {
+        let re: AnyRegex<u8, Match, _> = many(is(|&c| c > 10)) & many(is(|&c| c % 2 == 0));
+        assert_eq!(re.to_nfa().err(), Some(Inexpressible::And));
+        let re: AnyRegex<u8, Match, _> = is(|&c| c > 10) + !is(|&c| c % 2 == 0);
+        assert_eq!(re.to_nfa().err(), Some(Inexpressible::Not));
+    }
+
+    fn same_as_grammar<R>(re: &mut AnyRegex<char, Match, R>, to_match: &str) -> bool where
+        R: Regex<char, Match> + Describe<char, Match>,
+    {
+        let expected = has_match(re, to_match.chars());
+        let mut nfa = AnyRegex::new(re.to_nfa().ok().unwrap());
+        expected == has_match(&mut nfa, to_match.chars())
+    }
+
+    quickcheck! {
+        fn nested_repetition(to_match : String) -> bool {
+            let a = is(|&c| c == 'A');
+            let b = is(|&c| c == 'B');
+            same_as_grammar(&mut many(many(a) + (b | empty())), &to_match)
+        }
+
+        fn uppercase_words(to_match : String) -> bool {
+            let upper = is(|&c| char::is_uppercase(c));
+            let lower = is(|&c| char::is_lowercase(c));
+            let space = is(|&c| c == ' ');
+            same_as_grammar(&mut (many(upper + many(lower) + space) + empty()), &to_match)
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! Exchanging grammars with [OpenFST] in its text format.
+//!
+//! A grammar which only uses regular operators is equivalent to a
+//! finite automaton. `to_openfst` writes out the `Nfa` for such a
+//! grammar as an unweighted acceptor, using each leaf's `label` as its
+//! input symbol. The result can be compiled with something like
+//! `fstcompile --acceptor --keep_isymbols`, after generating a symbol
+//! table from the labels.
+//!
+//! Leaves compute their weights from the item they consume, so writing
+//! the weights out needs a fixed alphabet: `to_weighted_openfst` takes
+//! a symbol name for each item, and writes one weighted arc for every
+//! item a transition accepts. Going the other way, `Fst` reads a
+//! weighted acceptor in the text format and runs it as a grammar.
+//!
+//! Weights are converted through `FstWeight`, which is implemented for
+//! `Match`, corresponding to OpenFST's tropical semiring, and `Count`,
+//! corresponding to its log semiring.
+//!
+//! [OpenFST]: http://www.openfst.org/twiki/bin/view/FST/FstQuickTour
+
+use core::{AnyRegex, Describe, Leaf, Regex};
+use nfa::Nfa;
+use num_traits::{Zero, zero, One, one};
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::mem::replace;
+use std::ops;
+use syntax::Inexpressible;
+use weights::count::Count;
+use weights::recognize::Match;
+
+/// Weights which have a counterpart among OpenFST's semirings, written
+/// the way its text format writes that semiring's weights.
+pub trait FstWeight: Sized {
+    fn to_fst(&self) -> String;
+    /// Read a weight, or return `None` if `text` isn't one.
+    fn from_fst(text: &str) -> Option<Self>;
+}
+
+/// In the tropical semiring, `one()` is the cost `0` and `zero()` is
+/// `Infinity`. Any finite cost is read as a match.
+impl FstWeight for Match {
+    fn to_fst(&self) -> String {
+        if self.is_zero() { "Infinity".to_string() } else { "0".to_string() }
+    }
+    fn from_fst(text: &str) -> Option<Self> {
+        let cost: f64 = text.parse().ok()?;
+        Some(if cost.is_finite() { one() } else { zero() })
+    }
+}
+
+/// In the log semiring, a count `n` is the cost `-ln(n)`, so adding
+/// and multiplying counts is the same as OpenFST adding and multiplying
+/// costs. Costs are rounded to the nearest count when read, and very
+/// large counts lose precision.
+impl FstWeight for Count {
+    fn to_fst(&self) -> String {
+        if self.is_zero() { "Infinity".to_string() } else { (0.0 - (self.0 as f64).ln()).to_string() }
+    }
+    fn from_fst(text: &str) -> Option<Self> {
+        let cost: f64 = text.parse().ok()?;
+        if cost.is_nan() {
+            return None;
+        }
+        Some(Count((-cost).exp().round() as u64))
+    }
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero,
+    R: Describe<T, M>,
+{
+    /// Render this grammar as an OpenFST text-format acceptor, if it
+    /// only uses regular operators and every leaf has a label.
+    pub fn to_openfst(&self) -> Result<String, Inexpressible> {
+        to_openfst(self)
+    }
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero + ops::Mul<Output=M> + FstWeight,
+    R: Describe<T, M>,
+{
+    /// Render this grammar as a weighted OpenFST text-format acceptor
+    /// over `alphabet`, a list of symbols and the items they stand
+    /// for, if it only uses regular operators.
+    pub fn to_weighted_openfst(&self, alphabet: &[(&str, T)]) -> Result<String, Inexpressible> {
+        to_weighted_openfst(self, alphabet)
+    }
+}
+
+/// Like `AnyRegex::to_openfst`, but for any grammar node.
+///
+/// State 0 is the start state and each other state corresponds to one
+/// leaf of the grammar. Labels are used as OpenFST symbols, so they
+/// must not contain whitespace.
+pub fn to_openfst<T, M>(re: &dyn Describe<T, M>) -> Result<String, Inexpressible> where
+    M: Zero,
+{
+    let nfa = Nfa::new(re)?;
+    let states = nfa.states();
+    let mut labels = Vec::with_capacity(states.len());
+    labels.push("");
+    for state in &states[1..] {
+        labels.push(state.label().ok_or(Inexpressible::Predicate)?);
+    }
+
+    let mut out = String::new();
+    for (q, state) in states.iter().enumerate() {
+        for &p in state.next() {
+            writeln!(out, "{}\t{}\t{}", q, p, labels[p]).unwrap();
+        }
+    }
+    for (q, state) in states.iter().enumerate() {
+        if state.is_final() {
+            writeln!(out, "{}", q).unwrap();
+        }
+    }
+    Ok(out)
+}
+
+/// Like `AnyRegex::to_weighted_openfst`, but for any grammar node.
+///
+/// States are numbered as in `to_openfst`. Each transition becomes one
+/// arc per item in the alphabet that it gives a nonzero weight, and
+/// final states have the weight `one()`, which is left implicit.
+pub fn to_weighted_openfst<T, M>(re: &dyn Describe<T, M>, alphabet: &[(&str, T)]) -> Result<String, Inexpressible> where
+    M: Zero + ops::Mul<Output=M> + FstWeight,
+{
+    let nfa = Nfa::new(re)?;
+    let states = nfa.states();
+    let mut out = String::new();
+    for (q, state) in states.iter().enumerate() {
+        for &p in state.next() {
+            let test = states[p].test().unwrap();
+            for &(symbol, ref c) in alphabet {
+                let weight = test.weigh(c);
+                if !weight.is_zero() {
+                    writeln!(out, "{}\t{}\t{}\t{}", q, p, symbol, weight.to_fst()).unwrap();
+                }
+            }
+        }
+    }
+    for (q, state) in states.iter().enumerate() {
+        if state.is_final() {
+            writeln!(out, "{}", q).unwrap();
+        }
+    }
+    Ok(out)
+}
+
+/// Why OpenFST text couldn't be read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FstProblem {
+    /// A line which is neither an arc nor a final state, or has a
+    /// state number which isn't one.
+    Malformed,
+    /// An arc whose symbol isn't in the alphabet. Epsilon arcs aren't
+    /// supported, so this includes them.
+    UnknownSymbol,
+    /// A weight which `FstWeight::from_fst` doesn't accept.
+    BadWeight,
+}
+
+/// OpenFST text that couldn't be read, and the line it was on, counting
+/// from 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FstError {
+    pub line: usize,
+    pub problem: FstProblem,
+}
+
+impl fmt::Display for FstError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self.problem {
+            FstProblem::Malformed => "malformed line",
+            FstProblem::UnknownSymbol => "symbol not in alphabet",
+            FstProblem::BadWeight => "invalid weight",
+        })?;
+        write!(f, " on line {}", self.line)
+    }
+}
+
+impl Error for FstError {}
+
+/// A weighted acceptor read from OpenFST text, which can be used as a
+/// grammar with `AnyRegex::new`.
+///
+/// As with every grammar, whether the empty input matches is all that
+/// `empty` reports, so a final weight on the start state only counts as
+/// `one()` or `zero()` there. Everywhere else, final weights apply in
+/// full.
+pub struct Fst<T, M> {
+    start: usize,
+    /// Outgoing arcs from each state: the state they lead to, the item
+    /// they accept, and their weight.
+    arcs: Vec<Vec<(usize, T, M)>>,
+    finals: Vec<M>,
+    marks: Vec<M>,
+    incoming: Vec<M>,
+}
+
+impl<T, M> Fst<T, M> where
+    T: Clone,
+    M: Zero + FstWeight,
+{
+    /// Read an acceptor in the OpenFST text format, with symbols from
+    /// `alphabet`, a list of symbols and the items they stand for. The
+    /// source state of the first line is the start state, and arcs and
+    /// final states without a weight have the weight `one()`. Only
+    /// acceptors are supported, so an arc is a source, a destination,
+    /// one symbol, and an optional weight.
+    pub fn from_openfst(text: &str, alphabet: &[(&str, T)]) -> Result<Self, FstError> where
+        M: One,
+    {
+        let mut fst = Fst { start: 0, arcs: Vec::new(), finals: Vec::new(), marks: Vec::new(), incoming: Vec::new() };
+        let mut first = true;
+        for (index, line) in text.lines().enumerate() {
+            let error = |problem| FstError { line: index + 1, problem };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+            let state = |i: usize| fields[i].parse::<usize>().map_err(|_| error(FstProblem::Malformed));
+            let weight = |i: usize| match fields.get(i) {
+                Some(text) => M::from_fst(text).ok_or_else(|| error(FstProblem::BadWeight)),
+                None => Ok(one()),
+            };
+            match fields.len() {
+                1 | 2 => {
+                    let q = state(0)?;
+                    let weight = weight(1)?;
+                    fst.grow(q);
+                    fst.finals[q] = weight;
+                }
+                3 | 4 => {
+                    let (q, p) = (state(0)?, state(1)?);
+                    let c = alphabet.iter().find(|&&(symbol, _)| symbol == fields[2])
+                        .ok_or_else(|| error(FstProblem::UnknownSymbol))?.1.clone();
+                    let weight = weight(3)?;
+                    fst.grow(q.max(p));
+                    fst.arcs[q].push((p, c, weight));
+                }
+                _ => return Err(error(FstProblem::Malformed)),
+            }
+            if first {
+                fst.start = state(0)?;
+                first = false;
+            }
+        }
+        Ok(fst)
+    }
+
+    fn grow(&mut self, state: usize) {
+        while self.arcs.len() <= state {
+            self.arcs.push(Vec::new());
+            self.finals.push(zero());
+            self.marks.push(zero());
+            self.incoming.push(zero());
+        }
+    }
+}
+
+impl<T, M> Regex<T, M> for Fst<T, M> where
+    T: PartialEq,
+    M: Zero + ops::Mul<Output=M> + Clone,
+{
+    fn empty(&mut self) -> bool {
+        self.finals.get(self.start).is_some_and(|weight| !weight.is_zero())
+    }
+    fn active(&self) -> bool { self.marks.iter().any(|m| !m.is_zero()) }
+    fn shift(&mut self, c : &T, mark : M) -> M {
+        if !mark.is_zero() && self.start < self.marks.len() {
+            let old = replace(&mut self.marks[self.start], zero());
+            self.marks[self.start] = old + mark;
+        }
+        for (q, arcs) in self.arcs.iter().enumerate() {
+            let mark = replace(&mut self.marks[q], zero());
+            if mark.is_zero() {
+                continue;
+            }
+            for &(p, ref item, ref weight) in arcs {
+                if item == c {
+                    let old = replace(&mut self.incoming[p], zero());
+                    self.incoming[p] = old + mark.clone() * weight.clone();
+                }
+            }
+        }
+        let mut result = zero();
+        for (p, incoming) in self.incoming.iter_mut().enumerate() {
+            let mark = replace(incoming, zero());
+            if mark.is_zero() {
+                continue;
+            }
+            result = result + mark.clone() * self.finals[p].clone();
+            self.marks[p] = mark;
+        }
+        result
+    }
+    fn reset(&mut self) {
+        for mark in &mut self.marks {
+            *mark = zero();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn sequence_and_star() {
+        let a = is(|&c| c == 'a').label("a");
+        let b = is(|&c| c == 'b').label("b");
+        let re: AnyRegex<char, Match, _> = many(a) + b;
+        assert_eq!(re.to_openfst().unwrap(), "0\t1\ta\n0\t2\tb\n1\t1\ta\n1\t2\tb\n2\n");
+    }
+
+    #[test]
+    fn nullable() {
+        let a = is(|&c| c == 'a').label("a");
+        let re: AnyRegex<char, Match, _> = a | empty();
+        assert_eq!(re.to_openfst().unwrap(), "0\t1\ta\n0\n1\n");
+    }
+
+    #[test]
+    fn labeled_class() {
+        let vowel = (is(|&c| c == 'a') | is(|&c| c == 'e')).label("vowel");
+        let re: AnyRegex<char, Match, _> = vowel;
+        assert_eq!(re.to_openfst().unwrap(), "0\t1\tvowel\n1\n");
+    }
+
+    fn digits() -> Vec<(&'static str, char)> {
+        vec![("zero", '0'), ("one", '1'), ("two", '2')]
+    }
+
+    #[test]
+    fn weighted_export() {
+        // Two ways to match a `1`, one to match a `2`, and none for `0`.
+        let weigh = |&c: &char| Count(match c { '1' => 2, '2' => 1, _ => 0 });
+        let re: AnyRegex<char, Count, _> = is(weigh) + many(is(|&c: &char| c == '0'));
+        assert_eq!(re.to_weighted_openfst(&digits()).unwrap(), "\
+0\t1\tone\t-0.6931471805599453
+0\t1\ttwo\t0
+1\t2\tzero\t0
+2\t2\tzero\t0
+1
+2
+");
+    }
+
+    #[test]
+    fn round_trip() {
+        let digit = || is(|&c: &char| c.is_ascii_digit());
+        let re: AnyRegex<char, Count, _> = many(digit()) + (is(|&c| c == '1') | digit());
+        let text = re.to_weighted_openfst(&digits()).unwrap();
+        let mut fst: AnyRegex<char, Count, _> = AnyRegex::new(Fst::from_openfst(&text, &digits()).unwrap());
+        let mut re = re;
+        for input in &["", "1", "2", "11", "021", "0"] {
+            assert_eq!(fst.over(input.chars()), re.over(input.chars()), "{:?}", input);
+        }
+        assert_eq!(fst.over("1".chars()), Count(2));
+    }
+
+    #[test]
+    fn weighted_import() {
+        // Two paths for "ab", with costs for counts 2 and 3, and a final
+        // weight for a count of 2.
+        let text = "0 1 a -0.6931471805599453\n0 2 a\n1 3 b\n2 3 b -1.0986122886681098\n3 -0.6931471805599453\n";
+        let alphabet = [("a", 'a'), ("b", 'b')];
+        let mut fst: AnyRegex<char, Count, _> = AnyRegex::new(Fst::from_openfst(text, &alphabet).unwrap());
+        assert_eq!(fst.over("ab".chars()), Count(10));
+        assert_eq!(fst.over("a".chars()), Count(0));
+
+        let mut fst: AnyRegex<char, Match, _> = AnyRegex::new(Fst::from_openfst("0 1 a Infinity\n0 1 b 3.5\n1\n", &alphabet).unwrap());
+        assert!(!has_match(&mut fst, "a".chars()));
+        assert!(has_match(&mut fst, "b".chars()));
+    }
+
+    #[test]
+    fn import_errors() {
+        let alphabet = [("a", 'a')];
+        let error = |text| Fst::<char, Count>::from_openfst(text, &alphabet).err().unwrap();
+        assert_eq!(error("0 1 a\n0 1 <eps>\n"), FstError { line: 2, problem: FstProblem::UnknownSymbol });
+        assert_eq!(error("0 1 a x\n"), FstError { line: 1, problem: FstProblem::BadWeight });
+        assert_eq!(error("0 x a\n"), FstError { line: 1, problem: FstProblem::Malformed });
+        assert_eq!(error("0 1 a 0 0\n").to_string(), "malformed line on line 1");
+    }
+
+    #[test]
+    fn unlabeled() {
+        let re: AnyRegex<char, Match, _> = is(|&c| c == 'a').label("a") + is(|&c| c == 'b');
+        assert_eq!(re.to_openfst(), Err(Inexpressible::Predicate));
+    }
+}
@@ -0,0 +1,469 @@
+//! Parsing patterns written in the extended syntax into byte grammars.
+//!
+//! This accepts the syntax that `Display` produces for grammars, plus
+//! the usual conveniences of other engines:
+//!
+//! - `a|b` for alternation, binding loosest, then `a&b` for
+//!   intersection, then concatenation;
+//! - a prefix `!` for complement, which applies to the following atom
+//!   along with any repetition operators after it;
+//! - `*`, `+`, and `?` for repetition;
+//! - `(...)` and `(?:...)` for grouping, which capture nothing;
+//! - `.` for any byte except newline;
+//! - `[...]` and `[^...]` for sets of ASCII characters and ranges;
+//! - `\d`, `\w`, and `\s` for ASCII digits, word characters, and
+//!   whitespace, `\n`, `\r`, and `\t`, `\xNN` for any byte in hex,
+//!   and a backslash before any punctuation to match it literally.
+//!
+//! Any other character matches its UTF-8 encoding. Grammars are built
+//! with `Dynamic`, so their shape can depend on the pattern, and every
+//! leaf is labeled so the grammar displays much like the pattern. An
+//! alternation of plain text, like `GET|PUT|POST`, becomes a single
+//! `literals` trie.
+
+use core::{AnyRegex, IntoWithInput};
+use dynamic::Dynamic;
+use grammars::literals;
+use num_traits::{Zero, One};
+use std::error::Error;
+use std::fmt;
+use std::ops;
+use std::vec::Vec;
+
+type Grammar<M> = AnyRegex<u8, M, Dynamic<u8, M>>;
+
+/// Why a pattern couldn't be parsed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// A character which can't appear here, such as an unmatched `)`
+    /// or a repetition operator with nothing to repeat.
+    Unexpected,
+    /// The pattern ended inside a group, a set, or an escape.
+    UnexpectedEnd,
+    /// A backslash before a letter or digit with no special meaning.
+    UnknownEscape,
+    /// A set range whose end comes before its start.
+    BadRange,
+    /// A non-ASCII character inside a set, which can't be matched one
+    /// byte at a time.
+    NonAsciiSet,
+}
+
+/// A pattern that couldn't be parsed, and the byte offset in the
+/// pattern where the problem was found.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+    pub problem: Problem,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self.problem {
+            Problem::Unexpected => "unexpected character",
+            Problem::UnexpectedEnd => "unexpected end of pattern",
+            Problem::UnknownEscape => "unknown escape",
+            Problem::BadRange => "set range is out of order",
+            Problem::NonAsciiSet => "non-ASCII character in set",
+        })?;
+        write!(f, " at offset {}", self.position)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Build a grammar over bytes from a pattern in the extended syntax.
+pub fn parse<M>(pattern: &str) -> Result<Grammar<M>, ParseError> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+    bool: IntoWithInput<u8, M>,
+{
+    let mut parser = Parser { pattern, position: 0 };
+    let re = parser.alternation()?;
+    match parser.peek() {
+        None => Ok(re),
+        Some(_) => Err(parser.error(Problem::Unexpected)),
+    }
+}
+
+struct Parser<'a> {
+    pattern: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> { self.pattern[self.position..].chars().next() }
+
+    fn next(&mut self) -> Result<char, ParseError> {
+        let c = self.peek().ok_or_else(|| self.error(Problem::UnexpectedEnd))?;
+        self.position += c.len_utf8();
+        Ok(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += c.len_utf8();
+        }
+        found
+    }
+
+    fn error(&self, problem: Problem) -> ParseError {
+        ParseError { position: self.position, problem }
+    }
+
+    fn alternation<M>(&mut self) -> Result<Grammar<M>, ParseError> where
+        M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+        bool: IntoWithInput<u8, M>,
+    {
+        let start = self.position;
+        let mut branch = self.position;
+        let mut re = self.intersection()?;
+        let mut words = plain(&self.pattern[branch..self.position]).map(|word| vec![word]);
+        while self.eat('|') {
+            branch = self.position;
+            re = Dynamic::or(re, self.intersection()?);
+            words = words.and_then(|mut words| {
+                words.push(plain(&self.pattern[branch..self.position])?);
+                Some(words)
+            });
+        }
+        // Alternatives which are all plain text share one trie instead
+        // of each following its own chain of leaves.
+        match words {
+            Some(ref words) if words.len() > 1 => {
+                let trie = literals(words.iter().map(|word| word.iter().cloned()));
+                Ok(Dynamic::label(Dynamic::other(trie), self.pattern[start..self.position].to_string()))
+            }
+            _ => Ok(re),
+        }
+    }
+
+    fn intersection<M>(&mut self) -> Result<Grammar<M>, ParseError> where
+        M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+        bool: IntoWithInput<u8, M>,
+    {
+        let mut re = self.concatenation()?;
+        while self.eat('&') {
+            re = Dynamic::and(re, self.concatenation()?);
+        }
+        Ok(re)
+    }
+
+    fn concatenation<M>(&mut self) -> Result<Grammar<M>, ParseError> where
+        M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+        bool: IntoWithInput<u8, M>,
+    {
+        let mut re = None;
+        while let Some(c) = self.peek() {
+            if c == '|' || c == '&' || c == ')' {
+                break;
+            }
+            let next = self.complement()?;
+            re = Some(match re {
+                None => next,
+                Some(re) => Dynamic::sequence(re, next),
+            });
+        }
+        Ok(re.unwrap_or_else(Dynamic::empty))
+    }
+
+    fn complement<M>(&mut self) -> Result<Grammar<M>, ParseError> where
+        M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+        bool: IntoWithInput<u8, M>,
+    {
+        if self.eat('!') {
+            Ok(Dynamic::not(self.complement()?))
+        } else {
+            self.repetition()
+        }
+    }
+
+    fn repetition<M>(&mut self) -> Result<Grammar<M>, ParseError> where
+        M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+        bool: IntoWithInput<u8, M>,
+    {
+        let mut re = self.atom()?;
+        loop {
+            re = if self.eat('*') {
+                Dynamic::many(re)
+            } else if self.eat('+') {
+                let again = re.clone_reset();
+                Dynamic::sequence(re, Dynamic::many(again))
+            } else if self.eat('?') {
+                Dynamic::or(re, Dynamic::empty())
+            } else {
+                return Ok(re);
+            };
+        }
+    }
+
+    fn atom<M>(&mut self) -> Result<Grammar<M>, ParseError> where
+        M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+        bool: IntoWithInput<u8, M>,
+    {
+        let start = self.position;
+        Ok(match self.next()? {
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err(self.error(Problem::Unexpected));
+                }
+                let re = self.alternation()?;
+                if !self.eat(')') {
+                    return Err(self.error(Problem::UnexpectedEnd));
+                }
+                re
+            }
+            '[' => {
+                let set = self.set()?;
+                Dynamic::label(Dynamic::is(move |&b: &u8| set[b as usize]), self.pattern[start..self.position].to_string())
+            }
+            '.' => Dynamic::label(Dynamic::is(|&b: &u8| b != b'\n'), "."),
+            '\\' => match self.escape()? {
+                Escape::Set(f) => Dynamic::label(Dynamic::is(move |&b: &u8| f(b)), self.pattern[start..self.position].to_string()),
+                Escape::Char(c) => literal(c, self.pattern[start..self.position].to_string()),
+                Escape::Byte(b) => Dynamic::label(Dynamic::is(move |&x: &u8| x == b), self.pattern[start..self.position].to_string()),
+            },
+            ')' | '|' | '&' | '*' | '+' | '?' => {
+                self.position = start;
+                return Err(self.error(Problem::Unexpected));
+            }
+            c => literal(c, c.to_string()),
+        })
+    }
+
+    /// The bytes matched by the set starting after its `[`.
+    fn set(&mut self) -> Result<[bool; 256], ParseError> {
+        let negated = self.eat('^');
+        let mut set = [false; 256];
+        let mut first = true;
+        loop {
+            let start = self.position;
+            let low = match self.next()? {
+                ']' if !first => break,
+                '\\' => match self.escape()? {
+                    Escape::Set(f) => {
+                        for b in 0..=255u8 {
+                            set[b as usize] |= f(b);
+                        }
+                        first = false;
+                        continue;
+                    }
+                    Escape::Char(c) => ascii(c, start)?,
+                    Escape::Byte(b) => b,
+                },
+                c => ascii(c, start)?,
+            };
+            first = false;
+            let high = if self.peek() == Some('-') && !self.pattern[self.position + 1..].starts_with(']') {
+                self.position += 1;
+                match self.next()? {
+                    '\\' => match self.escape()? {
+                        Escape::Char(c) => ascii(c, start)?,
+                        Escape::Byte(b) => b,
+                        Escape::Set(_) => return Err(ParseError { position: start, problem: Problem::BadRange }),
+                    },
+                    c => ascii(c, start)?,
+                }
+            } else {
+                low
+            };
+            if high < low {
+                return Err(ParseError { position: start, problem: Problem::BadRange });
+            }
+            for b in low..=high {
+                set[b as usize] = true;
+            }
+        }
+        if negated {
+            for found in set.iter_mut() {
+                *found = !*found;
+            }
+        }
+        Ok(set)
+    }
+
+    /// The meaning of the escape sequence after a backslash.
+    fn escape(&mut self) -> Result<Escape, ParseError> {
+        let start = self.position;
+        Ok(match self.next()? {
+            'd' => Escape::Set(|b| b.is_ascii_digit()),
+            'w' => Escape::Set(|b| b.is_ascii_alphanumeric() || b == b'_'),
+            's' => Escape::Set(|b| b.is_ascii_whitespace()),
+            'n' => Escape::Char('\n'),
+            'r' => Escape::Char('\r'),
+            't' => Escape::Char('\t'),
+            'x' => {
+                let digits = self.pattern[self.position..].get(..2).filter(|d| d.bytes().all(|b| b.is_ascii_hexdigit()));
+                match digits {
+                    Some(digits) => {
+                        self.position += 2;
+                        Escape::Byte(u8::from_str_radix(digits, 16).unwrap())
+                    }
+                    None if self.pattern.len() < self.position + 2 => {
+                        return Err(ParseError { position: self.pattern.len(), problem: Problem::UnexpectedEnd });
+                    }
+                    None => return Err(ParseError { position: start, problem: Problem::UnknownEscape }),
+                }
+            }
+            c if c.is_ascii_punctuation() || c == ' ' => Escape::Char(c),
+            _ => return Err(ParseError { position: start, problem: Problem::UnknownEscape }),
+        })
+    }
+}
+
+enum Escape {
+    Set(fn(u8) -> bool),
+    Char(char),
+    Byte(u8),
+}
+
+/// `c` as a byte, if it's ASCII, for a set starting at `start`.
+fn ascii(c: char, start: usize) -> Result<u8, ParseError> {
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(ParseError { position: start, problem: Problem::NonAsciiSet })
+    }
+}
+
+/// The bytes `text` matches, if it's nothing but characters which
+/// match themselves and escaped punctuation.
+fn plain(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c) if c.is_ascii_punctuation() => bytes.push(c as u8),
+                _ => return None,
+            },
+            '|' | '&' | '!' | '(' | ')' | '[' | '.' | '*' | '+' | '?' => return None,
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Some(bytes)
+}
+
+/// A grammar matching the UTF-8 encoding of `c`.
+fn literal<M>(c: char, text: String) -> Grammar<M> where
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+    bool: IntoWithInput<u8, M>,
+{
+    let mut buf = [0; 4];
+    let mut bytes = c.encode_utf8(&mut buf).bytes().map(|b| Dynamic::is(move |&x: &u8| x == b));
+    let first = bytes.next().unwrap();
+    Dynamic::label(bytes.fold(first, Dynamic::sequence), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    fn matches(pattern: &str, input: &str) -> bool {
+        let mut re = parse::<Match>(pattern).unwrap();
+        has_match(&mut re, input.bytes())
+    }
+
+    #[test]
+    fn operators() {
+        assert!(matches("ab|c", "ab"));
+        assert!(matches("ab|c", "c"));
+        assert!(!matches("ab|c", "ac"));
+        assert!(matches("a(b|c)+d?", "abcb"));
+        assert!(matches("a(?:b|c)+d?", "abcbd"));
+        assert!(!matches("a(b|c)+d?", "ad"));
+        assert!(matches("[a-c]*&!(.*aa.*)", "abab"));
+        assert!(!matches("[a-c]*&!(.*aa.*)", "baab"));
+        assert!(matches("!a*", "b"));
+        assert!(!matches("!a*", "aa"));
+        assert!(matches("", ""));
+        assert!(matches("a|", ""));
+    }
+
+    #[test]
+    fn complements_after_other_items() {
+        assert!(matches("x!(a)", "x"));
+        assert!(matches("x!(a)", "xaa"));
+        assert!(!matches("x!(a)", "xa"));
+        assert!(!matches("x!(a)", "yb"));
+        assert!(matches("b!(a*)c", "bbc"));
+        assert!(!matches("b!(a*)c", "bac"));
+        // Both sides of an intersection have to start at the same place.
+        assert!(!matches("x*(xab&.b)", "xxab"));
+        assert!(matches("x*(x.b&.ab)", "xxab"));
+    }
+
+    #[test]
+    fn sets_and_escapes() {
+        assert!(matches(r"[^\d]\w+\s\.", "xy_1 ."));
+        assert!(!matches(r"[^\d]\w+\s\.", "1y_1 ."));
+        assert!(matches("[]a-]+", "]-a"));
+        assert!(matches(r"\(\*\)", "(*)"));
+        assert!(matches("é+", "éé"));
+        assert!(!matches("é", "e"));
+        assert!(matches(r"\x41\x2a[\x30-\x39]", "A*5"));
+        let mut re = parse::<Match>(r"[\x80-\xFF]\xc3").unwrap();
+        assert!(has_match(&mut re, vec![0x80, 0xC3]));
+    }
+
+    #[test]
+    fn parses_byte_grammars() {
+        use grammars::bytes::{byte, byte_range, byte_set};
+        let re = byte::<Match>(b'a') + byte_set(vec![b'0', b'1', b'2', b' ']) + byte_range(0x80..=0xFF);
+        let mut parsed = parse::<Match>(&re.to_string()).unwrap();
+        assert!(has_match(&mut parsed, b"a \xF0".iter().cloned()));
+        assert!(has_match(&mut parsed, b"a1\x80".iter().cloned()));
+        assert!(!has_match(&mut parsed, b"a3\x80".iter().cloned()));
+        assert!(!has_match(&mut parsed, b"a 0".iter().cloned()));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |pattern| parse::<Match>(pattern).err().map(|e| (e.position, e.problem));
+        assert_eq!(error("a)"), Some((1, Problem::Unexpected)));
+        assert_eq!(error("*a"), Some((0, Problem::Unexpected)));
+        assert_eq!(error("(a"), Some((2, Problem::UnexpectedEnd)));
+        assert_eq!(error("[ab"), Some((3, Problem::UnexpectedEnd)));
+        assert_eq!(error(r"\q"), Some((1, Problem::UnknownEscape)));
+        assert_eq!(error(r"\xg0"), Some((1, Problem::UnknownEscape)));
+        assert_eq!(error(r"\x4"), Some((3, Problem::UnexpectedEnd)));
+        assert_eq!(error("[z-a]"), Some((1, Problem::BadRange)));
+        assert_eq!(error("[é]"), Some((1, Problem::NonAsciiSet)));
+        assert_eq!(parse::<Match>("a)").err().unwrap().to_string(), "unexpected character at offset 1");
+    }
+
+    #[test]
+    fn displays_like_pattern() {
+        assert_eq!(parse::<Match>(r"(a|[b-d])*&!\d").unwrap().to_string(), r"(?:a|[b-d])*&!\d");
+        assert_eq!(parse::<Match>(r"x(GET|P\.T|é)y").unwrap().to_string(), r"x(?:GET|P\.T|é)y");
+    }
+
+    #[test]
+    fn plain_alternations() {
+        use core::{Describe, Node};
+        use weights::count::Count;
+
+        // Only the trie follows its own match attempts here.
+        fn has_trie(re: &dyn Describe<u8, Match>) -> bool {
+            re.attempts().is_some() || match re.describe() {
+                Node::Label(_, inner) | Node::Many(inner, _) => has_trie(inner),
+                Node::Or(a, b) | Node::Sequence(a, b, _) => has_trie(a) || has_trie(b),
+                _ => false,
+            }
+        }
+        let trie = |pattern| has_trie(&parse::<Match>(pattern).unwrap());
+        assert!(trie("GET|PUT|POST"));
+        assert!(trie("x(GET|PUT)*y"));
+        assert!(!trie("GET|P.T"));
+        assert!(!trie("GET"));
+
+        assert!(matches(r"x(GET|P\.T|é|)y", "xP.Ty"));
+        assert!(matches(r"x(GET|P\.T|é|)y", "xéy"));
+        assert!(matches(r"x(GET|P\.T|é|)y", "xy"));
+        assert!(!matches(r"x(GET|P\.T|é|)y", "xPUTy"));
+        // A repeated alternative still counts once per occurrence.
+        let mut re = parse::<Count>("a|b|a").unwrap();
+        assert_eq!(re.over(b"a".iter().cloned()), Count(2));
+    }
+}
@@ -0,0 +1,91 @@
+//! Searching strings with the standard library's `str` methods.
+//!
+//! A mutable reference to a grammar over `char`s is a `Pattern`, so it
+//! can be passed to `str::find`, `str::split`, `str::contains`, and the
+//! rest. Matches are found left to right, taking the longest match at
+//! each position, and a match ends where the next search begins. Empty
+//! matches are never reported, so a grammar which only matches the
+//! empty string finds nothing.
+//!
+//! Each position is tried separately, so searching takes time
+//! proportional to the length of the haystack times the length of the
+//! longest partial match.
+
+use core::{AnyRegex, Regex};
+use num_traits::{Zero, zero, one};
+use std::str::pattern::{Pattern, SearchStep, Searcher};
+use weights::recognize::Match;
+
+/// Searches a string for matches of a grammar; see `Pattern`.
+pub struct GrammarSearcher<'a, 'r, R: 'r> {
+    haystack: &'a str,
+    re: &'r mut AnyRegex<char, Match, R>,
+    position: usize,
+}
+
+impl<'r, R: Regex<char, Match>> Pattern for &'r mut AnyRegex<char, Match, R> {
+    type Searcher<'a> = GrammarSearcher<'a, 'r, R>;
+
+    fn into_searcher(self, haystack: &str) -> GrammarSearcher<'_, 'r, R> {
+        GrammarSearcher { haystack, re: self, position: 0 }
+    }
+}
+
+impl<'a, 'r, R: Regex<char, Match>> GrammarSearcher<'a, 'r, R> {
+    /// Where the longest non-empty match starting at the current
+    /// position ends, if there is one.
+    fn longest(&mut self) -> Option<usize> {
+        let mut end = None;
+        let mut mark = one();
+        for (i, c) in self.haystack[self.position..].char_indices() {
+            if !self.re.shift(&c, mark).is_zero() {
+                end = Some(self.position + i + c.len_utf8());
+            }
+            if !self.re.active() {
+                break;
+            }
+            mark = zero();
+        }
+        self.re.reset();
+        end
+    }
+}
+
+unsafe impl<'a, 'r, R: Regex<char, Match>> Searcher<'a> for GrammarSearcher<'a, 'r, R> {
+    fn haystack(&self) -> &'a str { self.haystack }
+
+    fn next(&mut self) -> SearchStep {
+        let start = self.position;
+        match self.haystack[start..].chars().next() {
+            None => SearchStep::Done,
+            Some(c) => match self.longest() {
+                Some(end) => {
+                    self.position = end;
+                    SearchStep::Match(start, end)
+                }
+                None => {
+                    self.position = start + c.len_utf8();
+                    SearchStep::Reject(start, self.position)
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+
+    #[test]
+    fn str_methods() {
+        let mut digits = many(is(|c: &char| c.is_ascii_digit())) + is(|c: &char| c.is_ascii_digit());
+        assert_eq!("ab12c345".find(&mut digits), Some(2));
+        assert!("x9".contains(&mut digits));
+        assert!(!"none".contains(&mut digits));
+        assert_eq!("ab12c345é6".split(&mut digits).collect::<Vec<_>>(), vec!["ab", "c", "é", ""]);
+        assert_eq!("1a22".matches(&mut digits).collect::<Vec<_>>(), vec!["1", "22"]);
+
+        // Empty matches are skipped.
+        assert_eq!("abc".find(&mut many(is(|&c| c == 'x'))), None);
+    }
+}
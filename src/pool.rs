@@ -0,0 +1,121 @@
+//! Reusing matchers across many short-lived matches.
+//!
+//! Each match needs a grammar with its own marks. Building one with
+//! `clone_reset` allocates a new copy of the whole grammar, which adds
+//! up in services that match a fresh input on every request. A
+//! `MatcherPool` keeps matchers around after they're used, resets
+//! them, and hands them out again.
+
+use core::{AnyRegex, CloneRegex};
+use num_traits::Zero;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// A thread-safe pool of copies of one grammar.
+pub struct MatcherPool<T, M, R> {
+    prototype: AnyRegex<T, M, R>,
+    idle: Mutex<Vec<AnyRegex<T, M, R>>>,
+}
+
+/// A matcher borrowed from a `MatcherPool`. It dereferences to the
+/// grammar, and goes back to the pool when dropped.
+pub struct Pooled<'a, T: 'a, M: 'a, R: 'a> where
+    M: Zero,
+    R: CloneRegex<T, M>,
+{
+    pool: &'a MatcherPool<T, M, R>,
+    re: Option<AnyRegex<T, M, R>>,
+}
+
+impl<T, M, R> MatcherPool<T, M, R> where
+    M: Zero,
+    R: CloneRegex<T, M>,
+{
+    /// Create an empty pool. New matchers are made with `clone_reset`
+    /// from `re` as needed.
+    pub fn new(re: AnyRegex<T, M, R>) -> Self {
+        MatcherPool { prototype: re, idle: Mutex::new(Vec::new()) }
+    }
+
+    /// Take a reset matcher from the pool, or make a new one if none
+    /// are idle.
+    pub fn get(&self) -> Pooled<'_, T, M, R> {
+        let re = self.idle().pop();
+        let re = re.unwrap_or_else(|| self.prototype.clone_reset());
+        Pooled { pool: self, re: Some(re) }
+    }
+
+    /// How many matchers are waiting to be reused.
+    pub fn idle_count(&self) -> usize { self.idle().len() }
+
+    /// The idle list. Everything in it has been reset, and a panic
+    /// while it's locked can't leave it half-updated, so it's still
+    /// good to use if another thread panicked holding the lock.
+    fn idle(&self) -> MutexGuard<'_, Vec<AnyRegex<T, M, R>>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<'a, T, M, R> Deref for Pooled<'a, T, M, R> where
+    M: Zero,
+    R: CloneRegex<T, M>,
+{
+    type Target = AnyRegex<T, M, R>;
+    fn deref(&self) -> &AnyRegex<T, M, R> { self.re.as_ref().unwrap() }
+}
+
+impl<'a, T, M, R> DerefMut for Pooled<'a, T, M, R> where
+    M: Zero,
+    R: CloneRegex<T, M>,
+{
+    fn deref_mut(&mut self) -> &mut AnyRegex<T, M, R> { self.re.as_mut().unwrap() }
+}
+
+impl<'a, T, M, R> Drop for Pooled<'a, T, M, R> where
+    M: Zero,
+    R: CloneRegex<T, M>,
+{
+    fn drop(&mut self) {
+        if let Some(mut re) = self.re.take() {
+            re.reset();
+            self.pool.idle().push(re);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn reuses_matchers() {
+        let pool = MatcherPool::new(many(is(|&c: &char| c.is_alphabetic())));
+        assert_eq!(pool.idle_count(), 0);
+        {
+            let mut first = pool.get();
+            let mut second = pool.get();
+            assert!(has_match(&mut first, "abc".chars()));
+            // Leave this one in the middle of a match.
+            second.shift(&'x', num_traits::one());
+        }
+        assert_eq!(pool.idle_count(), 2);
+        let mut again = pool.get();
+        assert_eq!(pool.idle_count(), 1);
+        assert!(!again.active());
+        assert!(!has_match(&mut again, "a1".chars()));
+    }
+
+    #[test]
+    fn survives_poisoning() {
+        let pool = MatcherPool::new(many(is(|&c: &char| c.is_alphabetic())));
+        let poisoner = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            let _guard = pool.idle.lock().unwrap();
+            panic!("poison the pool");
+        }));
+        assert!(poisoner.is_err());
+        drop(pool.get());
+        assert_eq!(pool.idle_count(), 1);
+        assert!(has_match(&mut pool.get(), "abc".chars()));
+    }
+}
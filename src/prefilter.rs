@@ -0,0 +1,202 @@
+//! Skipping ahead to where a match could start.
+//!
+//! Searching for a grammar anywhere in a haystack means starting a new
+//! match attempt at every position, so the shift engine has to look at
+//! every byte even when nothing is in progress. Most grammars can only
+//! start with a handful of bytes, though, and often with a fixed
+//! literal. `Prefilter` extracts that information from the grammar's
+//! `Nfa` and uses `memchr` to jump straight to the next position where
+//! a match might begin. When there are too many possible first bytes
+//! for `memchr`, it falls back to scanning for a `ByteClass`.
+//!
+//! `Finder` keeps a grammar together with its prefilter, so searching
+//! many haystacks only analyzes the grammar once.
+
+use byteclass::ByteClass;
+use core::{AnyRegex, Describe, Regex};
+use memchr::{memchr, memchr2, memchr3, memmem};
+use nfa::Nfa;
+use num_traits::{Zero, one};
+use std::ops::BitOr;
+use weights::recognize::Match;
+
+/// What every non-empty match of a byte grammar has to start with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Prefilter {
+    first: ByteClass,
+    prefix: Vec<u8>,
+}
+
+impl Prefilter {
+    /// Analyze `nfa`, returning `None` if skipping wouldn't help: that
+    /// is, if the grammar matches the empty string or can start with
+    /// any byte at all.
+    pub fn new(nfa: &Nfa<u8, Match>) -> Option<Self> {
+        let states = nfa.states();
+        if states[0].is_final() {
+            return None;
+        }
+
+        let first = bytes_entering(nfa, states[0].next());
+        if first.len() == 256 {
+            return None;
+        }
+
+        // Follow the chain of states that each admit exactly one byte
+        // and have exactly one successor, for as long as it lasts.
+        let mut prefix = Vec::new();
+        let mut next = states[0].next();
+        while let [p] = *next {
+            match bytes_entering(nfa, &[p]).bytes()[..] {
+                [b] => prefix.push(b),
+                _ => break,
+            }
+            if states[p].is_final() {
+                break;
+            }
+            next = states[p].next();
+        }
+
+        Some(Prefilter { first, prefix })
+    }
+
+    /// Bytes which may begin a match.
+    pub fn first_bytes(&self) -> &ByteClass { &self.first }
+
+    /// A literal which every match begins with. It may be empty.
+    pub fn prefix(&self) -> &[u8] { &self.prefix }
+
+    /// Find the first position in `haystack` where a match might start.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        if self.prefix.len() > 1 {
+            return memmem::find(haystack, &self.prefix);
+        }
+        if self.first.len() > 3 {
+            return self.first.find(haystack);
+        }
+        match self.first.bytes()[..] {
+            [] => None,
+            [a] => memchr(a, haystack),
+            [a, b] => memchr2(a, b, haystack),
+            [a, b, c] => memchr3(a, b, c, haystack),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn bytes_entering(nfa: &Nfa<u8, Match>, targets: &[usize]) -> ByteClass {
+    targets.iter()
+        .map(|&p| ByteClass::new(nfa.states()[p].test().unwrap()))
+        .fold(ByteClass::default(), BitOr::bitor)
+}
+
+/// A byte grammar for unanchored search, along with its `Prefilter`,
+/// which is only worked out once.
+pub struct Finder<R> {
+    re: AnyRegex<u8, Match, R>,
+    prefilter: Option<Prefilter>,
+}
+
+impl<R> AnyRegex<u8, Match, R> where
+    R: Regex<u8, Match> + Describe<u8, Match>,
+{
+    /// Prepare this grammar for searching with `Finder::find_end`.
+    pub fn finder(self) -> Finder<R> { Finder::new(self) }
+}
+
+impl<R> Finder<R> where
+    R: Regex<u8, Match> + Describe<u8, Match>,
+{
+    /// If `re` can be converted to an `Nfa`, build a `Prefilter` for it
+    /// to skip stretches of input where no match is in progress.
+    pub fn new(re: AnyRegex<u8, Match, R>) -> Self {
+        let prefilter = re.to_nfa().ok().and_then(|nfa| Prefilter::new(&nfa));
+        Finder { re, prefilter }
+    }
+
+    pub fn prefilter(&self) -> Option<&Prefilter> { self.prefilter.as_ref() }
+
+    /// Find the earliest position in `haystack` where some match of the
+    /// grammar ends, returning the offset just past it. Matches may
+    /// start anywhere.
+    pub fn find_end(&mut self, haystack: &[u8]) -> Option<usize> {
+        let re = &mut self.re;
+        if re.empty() {
+            return Some(0);
+        }
+
+        let mut at = 0;
+        while at < haystack.len() {
+            if !re.active() {
+                if let Some(ref prefilter) = self.prefilter {
+                    match prefilter.find(&haystack[at..]) {
+                        Some(skip) => at += skip,
+                        None => break,
+                    }
+                }
+            }
+            if !re.shift(&haystack[at], one()).is_zero() {
+                re.reset();
+                return Some(at + 1);
+            }
+            at += 1;
+        }
+        re.reset();
+        None
+    }
+
+    /// Give back the grammar.
+    pub fn into_inner(self) -> AnyRegex<u8, Match, R> { self.re }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    fn byte(b: u8) -> AnyRegex<u8, Match, impl Regex<u8, Match> + Describe<u8, Match>> {
+        is(move |&c: &u8| c == b)
+    }
+
+    #[test]
+    fn literal_prefix() {
+        let re = byte(b'f') + byte(b'o') + (byte(b'o') | byte(b'x'));
+        let prefilter = Prefilter::new(&re.to_nfa().ok().unwrap()).unwrap();
+        assert_eq!(prefilter.first_bytes().bytes(), b"f");
+        assert_eq!(prefilter.prefix(), b"fo");
+        assert_eq!(prefilter.find(b"a fox"), Some(2));
+        assert_eq!(prefilter.find(b"a cat"), None);
+    }
+
+    #[test]
+    fn unhelpful() {
+        let re = many(byte(b'a')) + byte(b'b');
+        assert!(Prefilter::new(&re.to_nfa().ok().unwrap()).is_some());
+        let re = many(byte(b'a'));
+        assert_eq!(Prefilter::new(&re.to_nfa().ok().unwrap()), None);
+        let re: AnyRegex<u8, Match, _> = is(|_: &u8| true) + byte(b'a');
+        assert_eq!(Prefilter::new(&re.to_nfa().ok().unwrap()), None);
+    }
+
+    #[test]
+    fn byte_class() {
+        let re: AnyRegex<u8, Match, _> = is(|&c: &u8| c.is_ascii_digit()) + byte(b'%');
+        let prefilter = Prefilter::new(&re.to_nfa().ok().unwrap()).unwrap();
+        assert_eq!(prefilter.first_bytes().len(), 10);
+        assert_eq!(prefilter.find(b"only 9%"), Some(5));
+    }
+
+    quickcheck! {
+        fn same_as_unfiltered(haystack : Vec<u8>) -> bool {
+            let haystack: Vec<u8> = haystack.iter().map(|b| b"xab"[*b as usize % 3]).collect();
+            let mut re = byte(b'a') + many(byte(b'x')) + byte(b'b');
+            let expected = (1..=haystack.len()).find(|&end| {
+                (0..end).any(|start| has_match(&mut re, haystack[start..end].iter().cloned()))
+            });
+            let mut finder = re.finder();
+            assert!(finder.prefilter().is_some());
+            // The same finder is good for more than one search.
+            expected == finder.find_end(&haystack) && expected == finder.find_end(&haystack)
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Everything most grammars need, for importing all at once:
+//!
+//! ```
+//! use weighted_regexp::prelude::*;
+//!
+//! let mut re: CharRegex<_> = is(|c: &char| c.is_alphabetic()) >> many(is(|c: &char| c.is_alphanumeric()));
+//! assert!(has_match_str(&mut re, "x1"));
+//!
+//! # #[cfg(feature = "std")] {
+//! let mut word = len_between(Dynamic::repeat(parse("[a-z]").unwrap(), 1, None), 2, 3);
+//! assert!(has_match_bytes(&mut word, b"abc"));
+//! assert!(!has_match_bytes(&mut word, b"abcd"));
+//! # }
+//! ```
+//!
+//! `Dynamic::exactly` and `Dynamic::repeat`, and the `repeat` method on
+//! `Dynamic` grammars, come along with `Dynamic`.
+
+pub use engine::{AnyRegex, CloneRegex, Describe, Grammar, IntoWithInput, Regex};
+pub use grammars::{empty, is, many, delay, literals, len_between, max_len, limit_occurrences};
+pub use weights::count::Count;
+pub use weights::leftmost::Leftmost;
+pub use weights::recognize::{has_match, has_match_str, has_match_bytes, Match};
+pub use weights::rightmost::Rightmost;
+pub use weights::values::{Values, Transducer, action, emit, replace, compose, stage};
+#[cfg(feature = "std")]
+pub use dynamic::Dynamic;
+#[cfg(feature = "std")]
+pub use parse::{parse, ParseError};
+#[cfg(feature = "std")]
+pub use set::PatternSet;
+
+/// A grammar recognizing strings.
+pub type CharRegex<R> = AnyRegex<char, Match, R>;
+
+/// A grammar recognizing byte strings.
+pub type ByteRegex<R> = AnyRegex<u8, Match, R>;
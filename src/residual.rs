@@ -0,0 +1,213 @@
+//! What's left to match after part of the input.
+//!
+//! After shifting a prefix of the input through a grammar, its marks
+//! encode which continuations would complete a match. `residual` turns
+//! those marks back into a grammar for the continuations, which is the
+//! Brzozowski derivative of the original grammar with respect to the
+//! prefix. The result borrows the original's leaves and unchanged
+//! subtrees, and implements `Describe`, so it can be displayed,
+//! analyzed, or matched with `derivative::Derivative`.
+//!
+//! Whether the prefix itself matched was decided by the last shift and
+//! isn't part of the grammar's state, so the residual only covers
+//! continuations of at least one item.
+//!
+//! Complements and intersections follow each match attempt with a
+//! separate copy of their children, so their residual is the union of
+//! the residuals of each copy.
+//!
+//! A `delay`ed grammar's state can't be inspected, so a residual which
+//! depends on one shows it as `<delay>` and can't be matched.
+
+use analysis::nullable;
+use core::{AnyRegex, Describe, Leaf, Node};
+use num_traits::{Zero, zero, One, one};
+use std::fmt;
+use syntax::fmt_grammar;
+
+/// A grammar for the continuations of a partial match, from
+/// `AnyRegex::residual`.
+pub enum Residual<'a, T: 'a, M: 'a> {
+    Empty,
+    /// Any single item.
+    Any,
+    /// Part of the original grammar, unchanged.
+    Original(&'a dyn Describe<T, M>),
+    /// A `delay`ed grammar whose state can't be seen.
+    Opaque,
+    Not(Box<Self>),
+    Or(Box<Self>, Box<Self>),
+    And(Box<Self>, Box<Self>),
+    Sequence(Box<Self>, Box<Self>, M),
+    Many(Box<Self>, M),
+}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    M: Zero,
+    R: Describe<T, M>,
+{
+    /// The grammar for the nonempty continuations which would complete
+    /// a match from this grammar's current state. Pass `at_start` if
+    /// the next shift would start a new match, as in `next_allowed`.
+    pub fn residual(&self, at_start: bool) -> Residual<'_, T, M> {
+        residual(self, at_start).unwrap_or_else(Residual::void)
+    }
+}
+
+impl<'a, T, M: Zero> Residual<'a, T, M> {
+    /// Nothing at all, as the intersection of the empty input with one
+    /// item.
+    fn void() -> Self { Residual::And(Box::new(Residual::Empty), Box::new(Residual::Any)) }
+
+    /// One or more items.
+    fn nonempty() -> Self {
+        Residual::Sequence(Box::new(Residual::Any), Box::new(Residual::Many(Box::new(Residual::Any), zero())), zero())
+    }
+}
+
+/// The nonempty continuations from `re`, if there are any, given
+/// whether the next shift brings it a new mark. The result never
+/// matches the empty input, which keeps sequences of residuals right.
+fn residual<'a, T, M: Zero>(re: &'a dyn Describe<T, M>, incoming: bool) -> Option<Residual<'a, T, M>> {
+    // A node which follows each match attempt separately continues each
+    // of them, and starts a new one if there's a mark.
+    let state = match re.attempts() {
+        Some(state) => state,
+        None => return one_attempt(re, incoming),
+    };
+    if !state.settled.is_zero() {
+        return Some(Residual::nonempty());
+    }
+    let mut attempts: Vec<_> = state.attempts.into_iter().map(|(_, attempt)| one_attempt(attempt, false)).collect();
+    if incoming {
+        attempts.push(one_attempt(re, true));
+    }
+    attempts.into_iter().flatten().reduce(|result, next| Residual::Or(Box::new(result), Box::new(next)))
+}
+
+/// Like `residual`, but as if `re` were only following one match
+/// attempt, which has already started unless there's a mark.
+fn one_attempt<'a, T, M: Zero>(re: &'a dyn Describe<T, M>, incoming: bool) -> Option<Residual<'a, T, M>> {
+    match re.describe() {
+        Node::Empty => None,
+        Node::Is(_) => if incoming { Some(Residual::Original(re)) } else { None },
+        Node::Label(_, inner) => match inner.describe() {
+            Node::Is(_) => if incoming { Some(Residual::Original(re)) } else { None },
+            _ => residual(inner, incoming),
+        },
+        Node::Delay => if incoming || re.activity() != Some(false) { Some(Residual::Opaque) } else { None },
+        Node::Not(inner) => {
+            let complement = match residual(inner, incoming) {
+                Some(inner) => Residual::And(Box::new(Residual::Not(Box::new(inner))), Box::new(Residual::nonempty())),
+                None => Residual::nonempty(),
+            };
+            Some(complement)
+        }
+        Node::Or(left, right) => match (residual(left, incoming), residual(right, incoming)) {
+            (None, other) | (other, None) => other,
+            (Some(left), Some(right)) => Some(Residual::Or(Box::new(left), Box::new(right))),
+        },
+        Node::And(left, right) => {
+            let left = residual(left, incoming)?;
+            let right = residual(right, incoming)?;
+            Some(Residual::And(Box::new(left), Box::new(right)))
+        }
+        Node::Sequence(left, right, from_left) => {
+            // Finish the left side and then match all of the right, or
+            // finish whatever the right side has already started.
+            let through_left = residual(left, incoming)
+                .map(|left| Residual::Sequence(Box::new(left), Box::new(Residual::Original(right)), zero()));
+            let right_incoming = (incoming && nullable(left)) || !from_left.is_zero();
+            match (through_left, residual(right, right_incoming)) {
+                (None, other) | (other, None) => other,
+                (Some(left), Some(right)) => Some(Residual::Or(Box::new(left), Box::new(right))),
+            }
+        }
+        Node::Many(inner, marked) => {
+            let inner = residual(inner, incoming || !marked.is_zero())?;
+            Some(Residual::Sequence(Box::new(inner), Box::new(Residual::Original(re)), zero()))
+        }
+    }
+}
+
+impl<'a, T, M: One> Leaf<T, M> for Residual<'a, T, M> {
+    // Only `Any` describes itself as a leaf.
+    fn weigh(&self, _c : &T) -> M { one() }
+    fn fmt_leaf(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(".") }
+}
+
+impl<'a, T, M: One> Describe<T, M> for Residual<'a, T, M> {
+    fn describe(&self) -> Node<'_, T, M> {
+        match *self {
+            Residual::Empty => Node::Empty,
+            Residual::Any => Node::Is(self),
+            Residual::Original(re) => re.describe(),
+            Residual::Opaque => Node::Delay,
+            Residual::Not(ref inner) => Node::Not(&**inner),
+            Residual::Or(ref left, ref right) => Node::Or(&**left, &**right),
+            Residual::And(ref left, ref right) => Node::And(&**left, &**right),
+            Residual::Sequence(ref left, ref right, ref mark) => Node::Sequence(&**left, &**right, mark),
+            Residual::Many(ref inner, ref mark) => Node::Many(&**inner, mark),
+        }
+    }
+}
+
+impl<'a, T, M: One> fmt::Display for Residual<'a, T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use derivative::Derivative;
+
+    fn letter(c: char) -> AnyRegex<char, Match, impl ::core::Regex<char, Match> + Describe<char, Match>> {
+        is(move |&x| x == c).label(c.to_string())
+    }
+
+    #[test]
+    fn after_prefix() {
+        let mut re = (letter('a') + letter('b')) | (letter('a') + many(letter('c')) + letter('d'));
+        assert_eq!(re.residual(true).to_string(), "ab|ac*d");
+        re.shift(&'a', one());
+        assert_eq!(re.residual(false).to_string(), "b|cc*d|d");
+        re.shift(&'c', zero());
+        assert_eq!(re.residual(false).to_string(), "cc*d|d");
+        re.shift(&'x', zero());
+        assert!(::analysis::is_empty_language(&re.residual(false)));
+    }
+
+    #[test]
+    fn bounded() {
+        // A length bound continues each attempt from the count it has.
+        let x = is(|&c: &char| c != 'd').label("x");
+        let mut re = letter('a') + grammars::len_between(many(x), 1, 3) + letter('d');
+        re.shift(&'a', one());
+        re.shift(&'b', zero());
+        assert_eq!(re.residual(false).to_string(), "(?:xx*&.(?:|.))d|d");
+        re.shift(&'c', zero());
+        assert_eq!(re.residual(false).to_string(), "(?:xx*&.)d|d");
+        re.shift(&'c', zero());
+        assert_eq!(re.residual(false).to_string(), "d");
+    }
+
+    quickcheck! {
+        fn derivative(prefix : Vec<bool>, suffix : Vec<bool>) -> bool {
+            let t = || is(|&b: &bool| b).label("t");
+            let f = || is(|&b: &bool| !b).label("f");
+            let mut re = many(t() + f()) + (!many(f()) & many(f() | (t() + t())));
+            let expected = has_match(&mut re, prefix.iter().chain(&suffix).cloned());
+
+            let mut first = true;
+            for &c in &prefix {
+                re.shift(&c, if first { one() } else { zero() });
+                first = false;
+            }
+            let residual = re.residual(first);
+            let matcher = Derivative::new(&residual).ok().unwrap();
+            suffix.is_empty() || expected == matcher.is_match(suffix.iter().cloned())
+        }
+
+    }
+}
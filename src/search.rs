@@ -0,0 +1,284 @@
+//! Finding every match of a byte grammar in a larger haystack.
+//!
+//! Matches may start anywhere, so the search starts a new match attempt
+//! at every position, and uses the `Leftmost` semiring to remember
+//! where each attempt began. Once some attempt reaches the end of a
+//! match, the search stops starting new attempts but keeps going while
+//! the ones in progress can still match, and reports the span with the
+//! leftmost start and, among those, the latest end. Then it starts over
+//! just past that, so reported matches never overlap. Empty matches are
+//! never reported.
+
+use core::{AnyRegex, Regex};
+use num_traits::{Zero, zero, one};
+use std::fs;
+use std::io;
+use std::ops;
+use std::path::Path;
+#[cfg(all(feature = "memmap", unix))]
+use std::{ptr, slice};
+use weights::count::Count;
+use weights::leftmost::Leftmost;
+
+/// The offsets of each non-overlapping match of `re` in `haystack`, as
+/// `(start, end)` pairs with `end` exclusive. Each match starts as
+/// early as possible, and among those, ends as late as possible.
+pub fn find_spans<T, R>(re: &mut AnyRegex<T, Leftmost, R>, haystack: &[T]) -> Vec<(usize, usize)> where
+    R: Regex<T, Leftmost>,
+{
+    let mut spans = Vec::new();
+    let mut at = 0;
+    while at < haystack.len() {
+        let mark = re.shift(&haystack[at], Leftmost::at(at));
+        at += 1;
+        if mark.is_zero() {
+            continue;
+        }
+        // Without a known start, a match could have begun right after
+        // the previous one.
+        let after = spans.last().map_or(0, |&(_, end)| end);
+        let (mut start, mut end) = (mark.start().unwrap_or(after), at);
+        while re.active() && at < haystack.len() {
+            let mark = re.shift(&haystack[at], zero());
+            at += 1;
+            if mark.is_zero() {
+                continue;
+            }
+            let from = mark.start().unwrap_or(after);
+            if from <= start {
+                start = from;
+                end = at;
+            }
+        }
+        re.reset();
+        spans.push((start, end));
+        at = end;
+    }
+    re.reset();
+    spans
+}
+
+/// Which matches `count_occurrences` counts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Occurrences {
+    /// Matches which don't overlap, chosen as in `find_spans`. Without
+    /// starts in the weights, finding each match's start means trying
+    /// the positions before it one at a time, so this can take time
+    /// quadratic in the length of `haystack`.
+    NonOverlapping,
+    /// Every span which matches, once per way it matches.
+    Overlapping,
+}
+
+/// How many matches of `re` appear anywhere in `haystack`, in a single
+/// pass, without collecting their spans.
+pub fn count_occurrences<T, R>(re: &mut AnyRegex<T, Count, R>, haystack: &[T], which: Occurrences) -> u64 where
+    R: Regex<T, Count>,
+{
+    let mut total = 0u64;
+    let mut at = 0;
+    let mut after = 0;
+    while at < haystack.len() {
+        let Count(ends_here) = re.shift(&haystack[at], one());
+        at += 1;
+        if ends_here == 0 {
+            continue;
+        }
+        match which {
+            Occurrences::NonOverlapping => {
+                // Some match starts between the last one and here, so
+                // the first start with a match is the leftmost.
+                re.reset();
+                let (start, len) = (after..at).find_map(|start| longest(re, &haystack[start..]).map(|len| (start, len))).unwrap();
+                total += 1;
+                at = start + len;
+                after = at;
+            }
+            Occurrences::Overlapping => total = total.saturating_add(ends_here),
+        }
+    }
+    re.reset();
+    total
+}
+
+/// The length of the longest nonempty match at the start of
+/// `haystack`, if there is one.
+fn longest<T, R>(re: &mut AnyRegex<T, Count, R>, haystack: &[T]) -> Option<usize> where
+    R: Regex<T, Count>,
+{
+    let mut found = None;
+    for (i, c) in haystack.iter().enumerate() {
+        if !re.shift(c, if i == 0 { one() } else { zero() }).is_zero() {
+            found = Some(i + 1);
+        }
+        if !re.active() {
+            break;
+        }
+    }
+    re.reset();
+    found
+}
+
+/// The contents of a file, mapped into memory with the `memmap`
+/// feature on Unix, or read into a buffer otherwise. Either way it
+/// dereferences to the file's bytes.
+///
+/// A mapped file which another process changes or truncates while
+/// it's being searched may give inconsistent results, as with any tool
+/// which maps the files it searches.
+pub struct Haystack {
+    #[cfg(all(feature = "memmap", unix))]
+    mapped: Mapping,
+    #[cfg(not(all(feature = "memmap", unix)))]
+    bytes: Vec<u8>,
+}
+
+impl Haystack {
+    /// Open the file at `path` for searching.
+    #[cfg(all(feature = "memmap", unix))]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Haystack> {
+        Ok(Haystack { mapped: Mapping::new(&fs::File::open(path)?)? })
+    }
+
+    /// Open the file at `path` for searching.
+    #[cfg(not(all(feature = "memmap", unix)))]
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Haystack> {
+        Ok(Haystack { bytes: fs::read(path)? })
+    }
+}
+
+impl ops::Deref for Haystack {
+    type Target = [u8];
+
+    #[cfg(all(feature = "memmap", unix))]
+    fn deref(&self) -> &[u8] { self.mapped.bytes() }
+
+    #[cfg(not(all(feature = "memmap", unix)))]
+    fn deref(&self) -> &[u8] { &self.bytes }
+}
+
+/// A read-only, private mapping of a whole file.
+#[cfg(all(feature = "memmap", unix))]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(all(feature = "memmap", unix))]
+impl Mapping {
+    fn new(file: &fs::File) -> io::Result<Mapping> {
+        use std::convert::TryFrom;
+        use std::os::unix::io::AsRawFd;
+        let len = file.metadata()?.len();
+        let len = usize::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+        // Mapping nothing is an error, so an empty file has no mapping.
+        if len == 0 {
+            return Ok(Mapping { ptr: ptr::null_mut(), len });
+        }
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // The mapping is readable for `len` bytes until it's dropped.
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(all(feature = "memmap", unix))]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+}
+
+/// Open the file at `path`, as with `Haystack::open`, and find the
+/// non-overlapping matches of `re` in it, like `find_spans`.
+pub fn search_file<P, R>(path: P, re: &mut AnyRegex<u8, Leftmost, R>) -> io::Result<Vec<(usize, usize)>> where
+    P: AsRef<Path>,
+    R: Regex<u8, Leftmost>,
+{
+    let haystack = Haystack::open(path)?;
+    Ok(find_spans(re, &haystack))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use std::env;
+    use std::io::Write;
+
+    fn word() -> AnyRegex<u8, Leftmost, impl Regex<u8, Leftmost>> {
+        let letter = || is(|&c: &u8| c.is_ascii_lowercase());
+        letter() + many(letter()) + is(|&c: &u8| c == b'!')
+    }
+
+    #[test]
+    fn spans() {
+        let mut re = word();
+        assert_eq!(find_spans(&mut re, b"hi! 123 there!! x!"), vec![(0, 3), (8, 14), (16, 18)]);
+        assert_eq!(find_spans(&mut re, b"none here"), vec![]);
+    }
+
+    #[test]
+    fn longest_spans() {
+        let a = || is(|&c: &u8| c == b'a');
+        let b = || is(|&c: &u8| c == b'b');
+        let mut re = a() + many(a()) + (b() | empty());
+        assert_eq!(find_spans(&mut re, b"xaab"), vec![(1, 4)]);
+        assert_eq!(find_spans(&mut re, b"aa aba"), vec![(0, 2), (3, 5), (5, 6)]);
+        // A match which started earlier wins over one which ended first.
+        let mut re = (a() + b() + b()) | b();
+        assert_eq!(find_spans(&mut re, b"abb"), vec![(0, 3)]);
+        assert_eq!(find_spans(&mut re, b"abx b"), vec![(1, 2), (4, 5)]);
+    }
+
+    #[test]
+    fn filtered() {
+        // Only matches starting at position 2 or later survive.
+        let re = is(|&b: &u8| b == b'a') + is(|&b: &u8| b == b'b');
+        let mut re = re.filter_weight(|m: &Leftmost| m.start().is_some_and(|start| start >= 2));
+        assert_eq!(find_spans(&mut re, b"ab ab"), vec![(3, 5)]);
+    }
+
+    #[test]
+    fn counted() {
+        let a = || is(|&b: &u8| b == b'a');
+        let mut aa = a() + a();
+        assert_eq!(count_occurrences(&mut aa, b"aaaa", Occurrences::NonOverlapping), 2);
+        assert_eq!(count_occurrences(&mut aa, b"aaaa", Occurrences::Overlapping), 3);
+        let mut run = a() + many(a());
+        assert_eq!(count_occurrences(&mut run, b"aaa", Occurrences::NonOverlapping), 1);
+        assert_eq!(count_occurrences(&mut run, b"aabaa", Occurrences::NonOverlapping), 2);
+        assert_eq!(count_occurrences(&mut run, b"aaa", Occurrences::Overlapping), 6);
+        assert_eq!(count_occurrences(&mut run, b"bbb", Occurrences::Overlapping), 0);
+    }
+
+    #[test]
+    fn file() {
+        let path = env::temp_dir().join(format!("weighted-regexp-search-{}", std::process::id()));
+        fs::File::create(&path).unwrap().write_all(b"abc! de!").unwrap();
+        let spans = search_file(&path, &mut word()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(spans, vec![(0, 4), (5, 8)]);
+    }
+
+    #[test]
+    fn empty_file() {
+        let path = env::temp_dir().join(format!("weighted-regexp-empty-{}", std::process::id()));
+        fs::File::create(&path).unwrap();
+        let haystack = Haystack::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&haystack[..], b"");
+        assert_eq!(find_spans(&mut word(), &haystack), vec![]);
+    }
+}
@@ -0,0 +1,184 @@
+//! Matching many patterns against the same input at once.
+//!
+//! A `PatternSet` holds any number of grammars, each with its own
+//! weights, and a function converting each one's weight to a common
+//! result type, such as a score. One pass over the input reports every
+//! pattern that matched, by the ID `add` gave it, with its result.
+//! That answers "which rules fired, and how strongly" without running
+//! each pattern separately.
+//!
+//! Like `over`, each pattern must match the whole input; wrap patterns
+//! in `many` of anything to look for them anywhere.
+//!
+//! Patterns can be added and removed at any time without disturbing the
+//! others, even while an input is being pushed a piece at a time. IDs
+//! are never reused, so results can't be mistaken for those of a
+//! removed pattern. A pattern added partway through an input has missed
+//! its start, so it only takes part from the next input on.
+
+use core::{AnyRegex, Regex};
+use num_traits::{Zero, zero, One, one};
+use std::collections::BTreeMap;
+use std::mem::replace;
+
+/// One pattern, with its weight type hidden.
+trait Entry<T, S> {
+    fn shift(&mut self, c : &T, first : bool);
+    /// The result for the input so far, if it matched, ready for the
+    /// next input.
+    fn finish(&mut self, empty_input : bool) -> Option<S>;
+}
+
+struct Typed<T, M, R, F> {
+    re: AnyRegex<T, M, R>,
+    last: M,
+    convert: F,
+}
+
+impl<T, M, R, F, S> Entry<T, S> for Typed<T, M, R, F> where
+    M: Zero + One,
+    R: Regex<T, M>,
+    F: Fn(M) -> S,
+{
+    fn shift(&mut self, c : &T, first : bool) {
+        self.last = self.re.shift(c, if first { one() } else { zero() });
+    }
+
+    fn finish(&mut self, empty_input : bool) -> Option<S> {
+        let weight = if empty_input {
+            if self.re.empty() { one() } else { zero() }
+        } else {
+            replace(&mut self.last, zero())
+        };
+        self.re.reset();
+        if weight.is_zero() { None } else { Some((self.convert)(weight)) }
+    }
+}
+
+struct Slot<T, S> {
+    entry: Box<dyn Entry<T, S>>,
+    /// Whether this pattern was added partway through the current
+    /// input, and is waiting for the next one.
+    waiting: bool,
+}
+
+/// A set of patterns matched together; see the module docs.
+pub struct PatternSet<T, S> {
+    /// Each pattern by ID, or `None` once it's removed.
+    patterns: Vec<Option<Slot<T, S>>>,
+    /// Whether any of the current input has been pushed.
+    started: bool,
+}
+
+impl<T, S> Default for PatternSet<T, S> {
+    fn default() -> Self { PatternSet { patterns: Vec::new(), started: false } }
+}
+
+impl<T, S> PatternSet<T, S> {
+    pub fn new() -> Self { Self::default() }
+
+    /// Add a pattern, reporting its weight through `convert`, and
+    /// return the ID its results will be reported under.
+    pub fn add<M, R, F>(&mut self, re: AnyRegex<T, M, R>, convert: F) -> usize where
+        T: 'static,
+        M: Zero + One + 'static,
+        R: Regex<T, M> + 'static,
+        F: Fn(M) -> S + 'static,
+    {
+        let entry = Box::new(Typed { re, last: zero(), convert });
+        self.patterns.push(Some(Slot { entry, waiting: self.started }));
+        self.patterns.len() - 1
+    }
+
+    /// Remove the pattern with this ID, returning whether there was one.
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.patterns.get_mut(id).and_then(Option::take).is_some()
+    }
+
+    /// How many patterns are in the set.
+    pub fn len(&self) -> usize { self.patterns.iter().filter(|slot| slot.is_some()).count() }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Continue the current input with `items`.
+    pub fn push<I>(&mut self, items: I) where
+        I: IntoIterator<Item=T>,
+    {
+        for c in items {
+            for slot in self.patterns.iter_mut().flatten() {
+                if !slot.waiting {
+                    slot.entry.shift(&c, !self.started);
+                }
+            }
+            self.started = true;
+        }
+    }
+
+    /// End the current input, and return the result of each pattern
+    /// that matched it, by ID.
+    pub fn finish(&mut self) -> BTreeMap<usize, S> {
+        let empty_input = !self.started;
+        self.started = false;
+        self.patterns.iter_mut().enumerate().filter_map(|(id, slot)| {
+            let slot = slot.as_mut()?;
+            if slot.waiting {
+                slot.waiting = false;
+                return None;
+            }
+            slot.entry.finish(empty_input).map(|result| (id, result))
+        }).collect()
+    }
+
+    /// Match every pattern against all of `over` in one pass, and
+    /// return the result of each pattern that matched, by ID.
+    pub fn matches<I>(&mut self, over: I) -> BTreeMap<usize, S> where
+        I: IntoIterator<Item=T>,
+    {
+        self.push(over);
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use weights::count::Count;
+
+    #[test]
+    fn scores() {
+        let mut rules: PatternSet<char, u64> = PatternSet::new();
+        let anything = || many(is(|_: &char| true));
+        let digit = || is(|c: &char| c.is_ascii_digit());
+        // There's one way to match for each digit in the input.
+        let digits = rules.add(anything() + digit() + anything(), |Count(n)| n);
+        let shouting = rules.add(many(is(|c: &char| !c.is_lowercase())), |_: Match| 10);
+        let empty = rules.add(empty(), |_: Match| 1);
+
+        let fired = rules.matches("A1 B2".chars());
+        assert_eq!(fired.into_iter().collect::<Vec<_>>(), vec![(digits, 2), (shouting, 10)]);
+        let fired = rules.matches("".chars());
+        assert_eq!(fired.into_iter().collect::<Vec<_>>(), vec![(shouting, 10), (empty, 1)]);
+        assert!(rules.matches("ab".chars()).is_empty());
+    }
+
+    #[test]
+    fn changing_rules() {
+        let mut rules: PatternSet<char, &str> = PatternSet::new();
+        let a = rules.add(many(is(|&c: &char| c == 'a')), |_: Match| "a");
+        let any = rules.add(many(is(|_: &char| true)), |_: Match| "any");
+        assert_eq!(rules.matches("aa".chars()).keys().cloned().collect::<Vec<_>>(), vec![a, any]);
+
+        assert!(rules.remove(a));
+        assert!(!rules.remove(a));
+        assert_eq!(rules.len(), 1);
+
+        // Added partway through, so it sits out this input.
+        rules.push("a".chars());
+        let b = rules.add(many(is(|_: &char| true)), |_: Match| "b");
+        rules.push("a".chars());
+        assert_eq!(rules.finish().keys().cloned().collect::<Vec<_>>(), vec![any]);
+        assert_eq!(rules.matches("aa".chars()).keys().cloned().collect::<Vec<_>>(), vec![any, b]);
+        assert!(b > a);
+    }
+}
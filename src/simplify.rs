@@ -0,0 +1,183 @@
+//! Algebraic simplification of `Dynamic` grammars.
+//!
+//! Grammars assembled from user input, like parsed patterns, tend to
+//! contain redundant structure: sequences with an empty side, stars of
+//! stars, alternatives that repeat themselves. Every node costs time on
+//! every shift, so it pays to rewrite these away once before matching.
+//!
+//! `simplify` only applies rewrites which preserve weights in every
+//! semiring. Several more identities, like `e | e → e`, only hold when
+//! the number of ways a grammar matches doesn't matter, so they're
+//! applied by `simplify_idempotent`, which requires weights that are
+//! `Idempotent`.
+//!
+//! Labels are kept, with their contents simplified, and `delay`ed and
+//! user-defined grammars are left alone.
+
+use analysis::is_empty_language;
+use core::{AnyRegex, Describe, Node};
+use dynamic::Dynamic;
+use grammars::{Label, Many, Not, Or, And, Sequence};
+use num_traits::{Zero, One};
+use std::ops;
+use structural::structurally_eq;
+use weights::Idempotent;
+
+type Dyn<T, M> = AnyRegex<T, M, Dynamic<T, M>>;
+
+/// Rewrite `re` into an equivalent grammar with the same weights,
+/// using these identities:
+///
+/// - `ε e → e` and `e ε → e`
+/// - `e | ∅ → e` and `∅ | e → e`, where `∅` is any grammar which
+///   `is_empty_language` shows can't match
+/// - `(ε)* → ε`
+pub fn simplify<T, M>(re: Dyn<T, M>) -> Dyn<T, M> where
+    T: 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+{
+    rewrite(re, false)
+}
+
+/// Like `simplify`, but also using identities which only hold for
+/// idempotent weights:
+///
+/// - `e | e → e` and `e & e → e`
+/// - `(e*)* → e*`
+/// - `e* | ε → e*` and `ε | e* → e*`
+pub fn simplify_idempotent<T, M>(re: Dyn<T, M>) -> Dyn<T, M> where
+    T: 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + Idempotent + 'static,
+{
+    rewrite(re, true)
+}
+
+fn rewrite<T, M>(re: Dyn<T, M>, idempotent: bool) -> Dyn<T, M> where
+    T: 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+{
+    match re.into_inner() {
+        Dynamic::Label(re) => {
+            let Label { re, text } = re.into_inner();
+            Dynamic::label(rewrite(re, idempotent), text)
+        }
+        Dynamic::Not(re) => {
+            let Not { re, .. } = re.into_inner();
+            Dynamic::not(rewrite(re, idempotent))
+        }
+        Dynamic::Or(re) => {
+            let Or { left, right } = re.into_inner();
+            let (left, right) = (rewrite(left, idempotent), rewrite(right, idempotent));
+            if is_empty_language(&right) {
+                return left;
+            }
+            if is_empty_language(&left) {
+                return right;
+            }
+            if idempotent {
+                if structurally_eq(&left, &right) {
+                    return left;
+                }
+                match (left.describe(), right.describe()) {
+                    (Node::Many(_, _), Node::Empty) => return left,
+                    (Node::Empty, Node::Many(_, _)) => return right,
+                    _ => {}
+                }
+            }
+            Dynamic::or(left, right)
+        }
+        Dynamic::And(re) => {
+            let And { left, right, .. } = re.into_inner();
+            let (left, right) = (rewrite(left, idempotent), rewrite(right, idempotent));
+            if idempotent && structurally_eq(&left, &right) {
+                return left;
+            }
+            Dynamic::and(left, right)
+        }
+        Dynamic::Sequence(re) => {
+            let Sequence { left, right, .. } = re.into_inner();
+            let (left, right) = (rewrite(left, idempotent), rewrite(right, idempotent));
+            match (left.describe(), right.describe()) {
+                (Node::Empty, _) => right,
+                (_, Node::Empty) => left,
+                _ => Dynamic::sequence(left, right),
+            }
+        }
+        Dynamic::Many(re) => {
+            let Many { re, .. } = re.into_inner();
+            let re = rewrite(re, idempotent);
+            match re.describe() {
+                Node::Empty => re,
+                Node::Many(_, _) if idempotent => re,
+                _ => Dynamic::many(re),
+            }
+        }
+        other => AnyRegex::new(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use core::Leaf;
+    use std::rc::Rc;
+    use syntax::Inexpressible;
+
+    type D = Dynamic<char, Match>;
+
+    fn a() -> Dyn<char, Match> { D::label(D::is(|&c| c == 'a'), "a") }
+
+    fn redundant() -> Dyn<char, Match> {
+        // Copies of the same leaf, as a pattern parser might produce.
+        let b: Rc<dyn Leaf<char, Match>> = Rc::new(is(|&c| c == 'b'));
+        let b = || D::label(D::leaf(b.clone()), "b");
+        D::sequence(
+            D::sequence(D::empty(), D::many(D::many(a()))),
+            D::or(D::and(b(), b()), D::sequence(D::or(b(), b()), D::many(D::empty()))),
+        )
+    }
+
+    #[test]
+    fn weight_preserving() {
+        let re = simplify(redundant());
+        assert_eq!(re.to_string(), "(?:a*)*(?:b&b|b|b)");
+    }
+
+    #[test]
+    fn idempotent() {
+        let re = simplify_idempotent(redundant());
+        assert_eq!(re.to_string(), "a*b");
+        let re = simplify_idempotent(D::or(D::empty(), D::many(a())));
+        assert_eq!(re.to_string(), "a*");
+    }
+
+    #[test]
+    fn never_matching_alternative() {
+        let never = || D::and(D::empty(), a());
+        let re = simplify(D::or(a(), never()));
+        assert_eq!(re.to_string(), "a");
+        let re = simplify(D::or(never(), D::many(a())));
+        assert_eq!(re.to_string(), "a*");
+    }
+
+    #[test]
+    fn recursion_is_kept() {
+        fn parens() -> Dyn<char, Match> {
+            D::many(D::sequence(D::sequence(D::is(|&c| c == '('), D::delay(parens)), D::is(|&c| c == ')')))
+        }
+        let mut re = simplify_idempotent(D::sequence(D::empty(), parens()));
+        assert_eq!(re.to_regex_string(), Err(Inexpressible::Predicate));
+        assert_eq!(re.to_string(), "(?:<is><delay><is>)*");
+        assert!(has_match(&mut re, "(()())".chars()));
+    }
+
+    quickcheck! {
+        fn same_language(to_match : String) -> bool {
+            let mut original = redundant();
+            let expected = has_match(&mut original, to_match.chars());
+            expected == has_match(&mut simplify(redundant()), to_match.chars())
+                && expected == has_match(&mut simplify_idempotent(redundant()), to_match.chars())
+        }
+    }
+}
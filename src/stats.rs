@@ -0,0 +1,135 @@
+//! Counting the work matching does.
+//!
+//! Every `AnyRegex` node skips shifts while it has no marks in progress
+//! and the incoming mark is zero, which is what keeps matching cheap
+//! for grammars where only a few alternatives are live at a time. To
+//! see how well that works for a particular grammar, or how much a
+//! rewrite helps, wrap matching in `measure`:
+//!
+//! ```
+//! use weighted_regexp::{has_match_str, is, many, AnyRegex, Match};
+//! use weighted_regexp::stats::measure;
+//!
+//! let mut re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a')) + is(|&c| c == 'b');
+//! let (matched, stats) = measure(|| has_match_str(&mut re, "aab"));
+//! assert!(matched);
+//! println!("{} shifts, {} skipped", stats.shifts, stats.skipped);
+//! ```
+//!
+//! Counts are kept per thread, for all grammars on that thread. This
+//! module is only available with the `stats` feature, and without it
+//! nodes don't count anything.
+
+use std::cell::Cell;
+use std::cmp::max;
+
+thread_local! {
+    static SHIFTS: Cell<u64> = const { Cell::new(0) };
+    static SKIPPED: Cell<u64> = const { Cell::new(0) };
+    static ACTIVE: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+/// What matching did during a call to `measure`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// How many times a node's `shift` did work.
+    pub shifts: u64,
+    /// How many times a node skipped a shift because it was inactive
+    /// and the incoming mark was zero.
+    pub skipped: u64,
+    /// The most nodes which had marks in progress at once, beyond how
+    /// many had them when `measure` was called. Nodes are counted from
+    /// when they become active until they're reset, so a grammar
+    /// dropped in the middle of a match stays counted, but only in the
+    /// baseline of later calls.
+    pub peak_active: usize,
+}
+
+/// Run `f`, counting the shifts done by every grammar on this thread.
+pub fn measure<F, R>(f: F) -> (R, Stats) where
+    F: FnOnce() -> R,
+{
+    let shifts = SHIFTS.with(Cell::get);
+    let skipped = SKIPPED.with(Cell::get);
+    let active = ACTIVE.with(Cell::get);
+    let outer_peak = PEAK.with(|peak| peak.replace(active));
+    let result = f();
+    let peak = PEAK.with(Cell::get);
+    let stats = Stats {
+        shifts: SHIFTS.with(Cell::get) - shifts,
+        skipped: SKIPPED.with(Cell::get) - skipped,
+        peak_active: peak.saturating_sub(active),
+    };
+    // Nested calls shouldn't hide their peak from the outer one.
+    PEAK.with(|peak_so_far| peak_so_far.set(max(outer_peak, peak)));
+    (result, stats)
+}
+
+pub(crate) fn shifted() {
+    SHIFTS.with(|n| n.set(n.get() + 1));
+}
+
+pub(crate) fn skipped() {
+    SKIPPED.with(|n| n.set(n.get() + 1));
+}
+
+/// Record that a node which was `before` active is now `after` active.
+pub(crate) fn activity(before: bool, after: bool) {
+    if before == after {
+        return;
+    }
+    let active = ACTIVE.with(|n| {
+        let active = if after { n.get() + 1 } else { n.get().saturating_sub(1) };
+        n.set(active);
+        active
+    });
+    PEAK.with(|peak| peak.set(max(peak.get(), active)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn counts() {
+        let a = || is(|&c| c == 'a');
+        let mut re: AnyRegex<char, Match, _> = many(a() + a()) | (a() + many(a()) + is(|&c| c == 'b'));
+        let (matched, stats) = measure(|| has_match(&mut re, "aab".chars()));
+        assert!(matched);
+        assert!(stats.shifts > 0 && stats.skipped > 0, "{:?}", stats);
+        assert!(stats.peak_active > 0);
+
+        // Once nothing is active, every later item is skipped.
+        let mut re: AnyRegex<char, Match, _> = a() + a() + a() + a();
+        let (_, stats) = measure(|| has_match(&mut re, "bbbbbbbb".chars()));
+        let (_, longer) = measure(|| has_match(&mut re, "bbbbbbbbbbbbbbbb".chars()));
+        assert_eq!(stats.shifts, longer.shifts);
+        assert!(longer.skipped > stats.skipped);
+        assert_eq!(measure(|| ()).1, Stats::default());
+    }
+
+    #[test]
+    fn reset_grammars_are_inactive() {
+        let active = || ACTIVE.with(Cell::get);
+        let before = active();
+        let mut re: AnyRegex<char, Match, _> = is(|&c| c == 'a') + is(|&c| c == 'b');
+        re.shift(&'a', num_traits::one());
+        assert!(active() > before);
+        re.reset();
+        assert_eq!(active(), before);
+    }
+
+    #[test]
+    fn dropped_grammars_only_raise_the_baseline() {
+        let mut re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a')) + is(|&c| c == 'b');
+        let (_, fresh) = measure(|| has_match(&mut re, "aab".chars()));
+        {
+            let mut dropped: AnyRegex<char, Match, _> = is(|&c| c == 'a') + is(|&c| c == 'b');
+            dropped.shift(&'a', num_traits::one());
+        }
+        let (_, later) = measure(|| has_match(&mut re, "aab".chars()));
+        assert_eq!(fresh.peak_active, later.peak_active);
+    }
+}
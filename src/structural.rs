@@ -0,0 +1,182 @@
+//! Comparing grammars by how they were built.
+//!
+//! Grammars don't implement `PartialEq`, because their leaves are
+//! arbitrary functions, which can't be compared. But most of a grammar
+//! is its combinator structure, which `Describe` exposes, so two
+//! grammars can be compared node by node as long as there's some rule
+//! for leaves.
+//!
+//! The rule here is conservative: two leaves are the same only if they
+//! are literally the same leaf, or if both describe themselves through
+//! `Leaf::fmt_leaf` with the same text other than the default `<is>`.
+//! Labels must have the same text and the same structure underneath.
+//! Marks are ignored, so a grammar in the middle of a match compares
+//! equal to a fresh copy. `delay`ed grammars can't be inspected, so
+//! they never compare equal, even to themselves.
+//!
+//! `structural_hash` is consistent with `structurally_eq`, so it can be
+//! used to key caches of things derived from a grammar, like compiled
+//! automata. It uses FNV-1a over a fixed encoding of the structure, so
+//! the same grammar hashes the same way across runs and builds.
+
+use core::{Describe, Leaf, Node};
+use std::fmt;
+use std::hash::Hasher;
+
+/// Whether `a` and `b` were built from the same combinators, in the
+/// same arrangement, over the same leaves.
+pub fn structurally_eq<T, M>(a: &dyn Describe<T, M>, b: &dyn Describe<T, M>) -> bool {
+    match (a.describe(), b.describe()) {
+        (Node::Empty, Node::Empty) => true,
+        (Node::Is(a), Node::Is(b)) => same_leaf(a, b),
+        (Node::Label(a_text, a), Node::Label(b_text, b)) =>
+            a_text == b_text && structurally_eq(a, b),
+        (Node::Not(a), Node::Not(b)) => structurally_eq(a, b),
+        (Node::Or(a_left, a_right), Node::Or(b_left, b_right)) |
+        (Node::And(a_left, a_right), Node::And(b_left, b_right)) |
+        (Node::Sequence(a_left, a_right, _), Node::Sequence(b_left, b_right, _)) =>
+            structurally_eq(a_left, b_left) && structurally_eq(a_right, b_right),
+        (Node::Many(a, _), Node::Many(b, _)) => structurally_eq(a, b),
+        _ => false,
+    }
+}
+
+/// A hash of `re`'s structure which is equal for any two grammars that
+/// are `structurally_eq`, and stable across runs and builds.
+pub fn structural_hash<T, M>(re: &dyn Describe<T, M>) -> u64 {
+    let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+    hash_structure(re, &mut hasher);
+    hasher.finish()
+}
+
+/// Feed `re`'s structure into any `Hasher`, for combining it with other
+/// data in a `Hash` implementation. Whether the result is stable
+/// depends on the `Hasher`.
+pub fn hash_structure<T, M, H: Hasher>(re: &dyn Describe<T, M>, state: &mut H) {
+    match re.describe() {
+        Node::Empty => state.write_u8(0),
+        Node::Is(leaf) => {
+            state.write_u8(1);
+            // Leaves without a description all hash alike, which is
+            // consistent with equality because they only equal
+            // themselves.
+            write_text(state, &leaf_text(leaf).unwrap_or_default());
+        }
+        Node::Label(text, inner) => {
+            state.write_u8(2);
+            write_text(state, text);
+            hash_structure(inner, state);
+        }
+        Node::Not(inner) => {
+            state.write_u8(3);
+            hash_structure(inner, state);
+        }
+        Node::Or(left, right) => {
+            state.write_u8(4);
+            hash_structure(left, state);
+            hash_structure(right, state);
+        }
+        Node::And(left, right) => {
+            state.write_u8(5);
+            hash_structure(left, state);
+            hash_structure(right, state);
+        }
+        Node::Sequence(left, right, _) => {
+            state.write_u8(6);
+            hash_structure(left, state);
+            hash_structure(right, state);
+        }
+        Node::Many(inner, _) => {
+            state.write_u8(7);
+            hash_structure(inner, state);
+        }
+        Node::Delay => state.write_u8(8),
+    }
+}
+
+/// Write `text` so that it can't run together with what follows.
+fn write_text<H: Hasher>(state: &mut H, text: &str) {
+    state.write(text.as_bytes());
+    // No UTF-8 encoding contains this byte.
+    state.write_u8(0xff);
+}
+
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 { self.0 }
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn same_leaf<T, M>(a: &dyn Leaf<T, M>, b: &dyn Leaf<T, M>) -> bool {
+    if a as *const dyn Leaf<T, M> as *const () == b as *const dyn Leaf<T, M> as *const () {
+        return true;
+    }
+    match (leaf_text(a), leaf_text(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// How a leaf describes itself, unless it only gives the default
+/// placeholder, which says nothing about which leaf it is.
+pub(crate) fn leaf_text<T, M>(leaf: &dyn Leaf<T, M>) -> Option<String> {
+    struct Show<'a, T: 'a, M: 'a>(&'a dyn Leaf<T, M>);
+    impl<'a, T, M> fmt::Display for Show<'a, T, M> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.0.fmt_leaf(f) }
+    }
+    let text = Show(leaf).to_string();
+    if text == "<is>" { None } else { Some(text) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use num_traits::one;
+
+    #[test]
+    fn equality() {
+        let a = is(|&c| c == 'a').label("a");
+        let b = is(|&c| c == 'b').label("b");
+        let mut re: AnyRegex<char, Match, _> = many(a) + b;
+        let copy = re.clone_reset();
+
+        // A copy has its own leaves, which can't be compared.
+        assert!(!structurally_eq(&re, &copy));
+        assert!(structurally_eq(&re, &re));
+
+        // Marks don't matter.
+        let before = format!("{}", re);
+        re.shift(&'a', one());
+        assert!(structurally_eq(&re, &re));
+        assert_eq!(before, re.to_string());
+
+        let re: AnyRegex<char, Match, _> = empty() | empty();
+        let other: AnyRegex<char, Match, _> = empty() + empty();
+        assert!(structurally_eq(&re, &re.clone_reset()));
+        assert!(!structurally_eq(&re, &other));
+
+        let recursive: AnyRegex<char, Match, _> = delay(|| empty().boxed());
+        assert!(!structurally_eq(&recursive, &recursive));
+    }
+
+    #[test]
+    fn hashing() {
+        let build = || -> AnyRegex<char, Match, _> {
+            many(is(|&c| c == 'a').label("a")) + (empty() | empty())
+        };
+        assert_eq!(structural_hash(&build()), structural_hash(&build()));
+        let other: AnyRegex<char, Match, _> = many(is(|&c| c == 'a').label("b")) + (empty() | empty());
+        assert!(structural_hash(&build()) != structural_hash(&other));
+        let other: AnyRegex<char, Match, _> = many(is(|&c| c == 'a').label("a")) + (empty() & empty());
+        assert!(structural_hash(&build()) != structural_hash(&other));
+        // Pin the encoding, so changes to it are deliberate.
+        let re: AnyRegex<char, Match, _> = empty();
+        assert_eq!(structural_hash(&re), 0xaf63_bd4c_8601_b7df);
+    }
+}
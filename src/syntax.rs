@@ -0,0 +1,335 @@
+//! Converting grammars back to conventional regular expression syntax.
+//!
+//! Only a subset of the grammars this library supports can be written
+//! as a traditional regular expression. Intersection, complement, and
+//! recursion have no standard syntax, and a grammar built with `is`
+//! can't be rendered at all unless it has been given a `label`.
+//!
+//! For diagnostics, every grammar node also implements `Display`, which
+//! never fails. It extends the syntax with `&` for intersection, a
+//! prefix `!` for complement, and `<delay>` for recursion, and shows
+//! unlabeled leaves using `Leaf::fmt_leaf`.
+
+use core::{AnyRegex, Describe, Leaf, Node};
+use dynamic::Dynamic;
+use grammars::{Empty, Is, Label, Many, Not, Or, And, Sequence, Thunk};
+use std::error::Error;
+use std::fmt;
+
+/// The reason a grammar could not be converted to regular expression
+/// syntax.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Inexpressible {
+    /// The grammar contains an `is` leaf without a `label`.
+    Predicate,
+    /// The grammar contains an intersection (`&`).
+    And,
+    /// The grammar contains a complement (`!`).
+    Not,
+    /// The grammar contains a `delay`ed, possibly recursive, grammar.
+    Recursion,
+}
+
+impl fmt::Display for Inexpressible {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Inexpressible::Predicate => "unlabeled predicate",
+            Inexpressible::And => "intersection",
+            Inexpressible::Not => "complement",
+            Inexpressible::Recursion => "recursive grammar",
+        })?;
+        f.write_str(" has no regular expression syntax")
+    }
+}
+
+impl Error for Inexpressible {}
+
+impl<T, M, R> AnyRegex<T, M, R> where
+    R: Describe<T, M>,
+{
+    /// Render this grammar using standard regular expression syntax, if
+    /// it only uses features which such syntax can express.
+    ///
+    /// Labels are copied into the output verbatim, wrapped in a
+    /// non-capturing group if they don't look like a single atom.
+    pub fn to_regex_string(&self) -> Result<String, Inexpressible> {
+        to_regex_string(self)
+    }
+}
+
+/// Like `AnyRegex::to_regex_string`, but for any grammar node.
+pub fn to_regex_string<T, M>(re: &dyn Describe<T, M>) -> Result<String, Inexpressible> {
+    render(re, true).map(|(text, _)| text)
+}
+
+/// Write any grammar node in the extended syntax used by `Display`.
+pub fn fmt_grammar<T, M>(re: &dyn Describe<T, M>, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&to_extended_string(re))
+}
+
+/// Render any grammar node in the extended syntax used by `Display`.
+pub(crate) fn to_extended_string<T, M>(re: &dyn Describe<T, M>) -> String {
+    // Rendering only fails in strict mode.
+    render(re, false).unwrap().0
+}
+
+// Binding strength of rendered syntax, from loosest to tightest.
+const ALTERNATION: u8 = 0;
+const INTERSECTION: u8 = 1;
+const CONCATENATION: u8 = 2;
+const ATOM: u8 = 3;
+
+struct ShowLeaf<'a, T: 'a, M: 'a>(&'a dyn Leaf<T, M>);
+
+impl<'a, T, M> fmt::Display for ShowLeaf<'a, T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.0.fmt_leaf(f) }
+}
+
+/// Render `re`, failing on anything standard syntax can't express if
+/// `strict` is set, or extending the syntax to cover it otherwise.
+fn render<T, M>(re: &dyn Describe<T, M>, strict: bool) -> Result<(String, u8), Inexpressible> {
+    Ok(match re.describe() {
+        Node::Empty => (String::new(), CONCATENATION),
+        Node::Is(_) if strict => return Err(Inexpressible::Predicate),
+        Node::Is(leaf) => {
+            let text = ShowLeaf(leaf).to_string();
+            // Placeholders like `<is>` are atoms in the extended syntax.
+            let placeholder = text.starts_with('<') && text.find('>') == Some(text.len() - 1);
+            let precedence = if placeholder { ATOM } else { precedence(&text) };
+            (text, precedence)
+        }
+        Node::Label(text, _) => (text.to_string(), precedence(text)),
+        Node::Not(_) if strict => return Err(Inexpressible::Not),
+        Node::Not(inner) => ("!".to_string() + &render_at(inner, ATOM, strict)?, CONCATENATION),
+        Node::And(_, _) if strict => return Err(Inexpressible::And),
+        Node::And(left, right) => {
+            let left = render_at(left, INTERSECTION, strict)?;
+            let right = render_at(right, INTERSECTION, strict)?;
+            (left + "&" + &right, INTERSECTION)
+        }
+        Node::Delay if strict => return Err(Inexpressible::Recursion),
+        Node::Delay => ("<delay>".to_string(), ATOM),
+        Node::Or(left, right) => {
+            let left = render_at(left, ALTERNATION, strict)?;
+            let right = render_at(right, ALTERNATION, strict)?;
+            (left + "|" + &right, ALTERNATION)
+        }
+        Node::Sequence(left, right, _) => {
+            let left = render_at(left, CONCATENATION, strict)?;
+            let right = render_at(right, CONCATENATION, strict)?;
+            (left + &right, CONCATENATION)
+        }
+        Node::Many(re, _) => {
+            // Some engines reject stacked repetition operators like
+            // `a**`, so treat the result as binding no tighter than
+            // concatenation.
+            (render_at(re, ATOM, strict)? + "*", CONCATENATION)
+        }
+    })
+}
+
+fn render_at<T, M>(re: &dyn Describe<T, M>, needed: u8, strict: bool) -> Result<String, Inexpressible> {
+    let (text, actual) = render(re, strict)?;
+    Ok(if actual < needed { format!("(?:{})", text) } else { text })
+}
+
+impl<T, M, R> fmt::Display for AnyRegex<T, M, R> where
+    R: Describe<T, M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+impl fmt::Display for Empty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar::<(), ()>(self, f) }
+}
+
+// An arbitrary function can't describe itself, so this matches the
+// default `Leaf::fmt_leaf`.
+impl<U, N, F> fmt::Display for Is<U, N, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("<is>") }
+}
+
+impl<T, M, R> fmt::Display for Label<T, M, R> where
+    R: Describe<T, M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+impl<T, M, R> fmt::Display for Not<T, M, R> where
+    R: Describe<T, M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+impl<T, M, L, R> fmt::Display for Or<T, M, L, R> where
+    L: Describe<T, M>,
+    R: Describe<T, M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+impl<T, M, L, R> fmt::Display for And<T, M, L, R> where
+    L: Describe<T, M>,
+    R: Describe<T, M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+impl<T, M, L, R> fmt::Display for Sequence<T, M, L, R> where
+    L: Describe<T, M>,
+    R: Describe<T, M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+impl<T, M, R> fmt::Display for Many<T, M, R> where
+    R: Describe<T, M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+impl<T, M, F> fmt::Display for Thunk<T, M, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+impl<T, M> fmt::Display for Dynamic<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt_grammar(self, f) }
+}
+
+/// Guess how tightly a user-provided label binds. This is conservative:
+/// anything that isn't clearly a single atom gets grouped when it's
+/// used as an operand.
+fn precedence(text: &str) -> u8 {
+    let mut units = 0;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '|' {
+            return ALTERNATION;
+        }
+        let len = match c {
+            '\\' => escape_len(rest),
+            '[' | '(' => bracket_len(rest),
+            // Anything else, including a repetition operator, counts
+            // as a unit of its own, so `a*` is a concatenation for our
+            // purposes, as in `render` for `Many`.
+            _ => c.len_utf8(),
+        };
+        units += 1;
+        rest = &rest[len..];
+    }
+    if units == 1 { ATOM } else { CONCATENATION }
+}
+
+/// Length in bytes of the escape sequence at the start of `text`,
+/// including braced forms like `\p{Greek}`.
+fn escape_len(text: &str) -> usize {
+    let mut chars = text.char_indices().skip(1);
+    match chars.next() {
+        None => 1,
+        Some((i, c)) => {
+            let after = i + c.len_utf8();
+            if text[after..].starts_with('{') {
+                text[after..].find('}').map_or(text.len(), |end| after + end + 1)
+            } else {
+                after
+            }
+        }
+    }
+}
+
+/// Length in bytes of the bracketed class or group at the start of
+/// `text`. Unbalanced brackets consume the rest of the string.
+fn bracket_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '\\' => escape_len(rest),
+            '[' => {
+                depth += 1;
+                // A `]` at the very start of a class is a literal.
+                1 + if rest[1..].starts_with(']') {
+                    1
+                } else if rest[1..].starts_with("^]") {
+                    2
+                } else {
+                    0
+                }
+            }
+            '(' => { depth += 1; 1 }
+            ']' | ')' => { depth -= 1; 1 }
+            _ => c.len_utf8(),
+        };
+        rest = &rest[len..];
+        if depth == 0 {
+            return text.len() - rest.len();
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use ::core::Grammar;
+
+    fn char_class(text: &'static str) -> AnyRegex<char, Match, impl Grammar<char, Match>> {
+        is(move |&c: &char| text.contains(c)).label(text)
+    }
+
+    #[test]
+    fn simple() {
+        let re = many(char_class("a") | char_class("b")) + char_class("c");
+        assert_eq!(re.to_regex_string(), Ok("(?:a|b)*c".to_string()));
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(precedence("[a-z]"), ATOM);
+        assert_eq!(precedence("[]a]"), ATOM);
+        assert_eq!(precedence("\\p{Greek}"), ATOM);
+        assert_eq!(precedence("(a|b)"), ATOM);
+        assert_eq!(precedence("[a-z]+"), CONCATENATION);
+        assert_eq!(precedence("ab"), CONCATENATION);
+        assert_eq!(precedence(""), CONCATENATION);
+        assert_eq!(precedence("a|b"), ALTERNATION);
+
+        let re = many(char_class("ab")) + many(empty::<char, Match>());
+        assert_eq!(re.to_regex_string(), Ok("(?:ab)*(?:)*".to_string()));
+    }
+
+    #[test]
+    fn nested_repetition() {
+        let re = many(many(char_class("x")));
+        assert_eq!(re.to_regex_string(), Ok("(?:x*)*".to_string()));
+    }
+
+    #[test]
+    fn inexpressible() {
+        let re = char_class("a") + is(|&c| c == 'b');
+        assert_eq!(re.to_regex_string(), Err(Inexpressible::Predicate));
+        let re = char_class("a") & char_class("b");
+        assert_eq!(re.to_regex_string(), Err(Inexpressible::And));
+        let re = !char_class("a");
+        assert_eq!(re.to_regex_string(), Err(Inexpressible::Not));
+        let re = char_class("a") | delay(|| empty().boxed());
+        assert_eq!(re.to_regex_string(), Err(Inexpressible::Recursion));
+    }
+
+    #[test]
+    fn display() {
+        let re = !char_class("a") & (many(is(|&c| c == 'b')) | delay(|| empty().boxed()));
+        assert_eq!(re.to_string(), "!a&(?:<is>*|<delay>)");
+        let re = many(char_class("a") & char_class("b")) + !(char_class("c") + char_class("d"));
+        assert_eq!(re.to_string(), "(?:a&b)*!(?:cd)");
+    }
+
+    quickcheck! {
+        fn label_is_transparent(to_match : String) -> bool {
+            let mut re = many(is(|&c| c == 'a'));
+            has_match(&mut re.clone_reset().label("a*"), to_match.chars()) ==
+                has_match(&mut re, to_match.chars())
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Helpers for testing grammars against each other.
+//!
+//! Property tests with random inputs rarely hit the interesting cases
+//! for a grammar over a small alphabet, while trying every input up to
+//! a modest length hits all of them. `assert_language_eq` does that for
+//! a pair of grammars which should give every input the same weight,
+//! and `counterexample` returns the input they disagree on instead of
+//! panicking.
+//!
+//! With the `rand` feature, `random_grammar` builds grammars of random
+//! shape, for checking that combinators and rewrites interact the way
+//! they should. `language::sample_member` then generates inputs they
+//! match, and `!re` gives inputs they don't. `grammar_from_bytes`
+//! builds the same kinds of grammars from a fuzzer's input instead.
+//!
+//! For tests of one grammar on particular inputs, `assert_matches!` and
+//! `assert_no_match!` explain failures with the grammar and, for inputs
+//! that should have matched, where matching went wrong.
+
+use core::{AnyRegex, Describe, IntoWithInput, Regex};
+use dynamic::Dynamic;
+use explain::explain;
+use num_traits::{Zero, One};
+#[cfg(feature = "rand")]
+use rand::Rng;
+use std::fmt::Debug;
+use std::ops;
+
+/// Every sequence of items from `alphabet`, shortest first, and in
+/// lexicographic order by position in `alphabet` within each length.
+pub(crate) struct Strings<'a, T: 'a> {
+    alphabet: &'a [T],
+    next: Option<Vec<usize>>,
+    max_len: Option<usize>,
+}
+
+impl<'a, T> Strings<'a, T> {
+    /// Sequences up to `max_len` items long, or without end if there's
+    /// no limit.
+    pub(crate) fn new(alphabet: &'a [T], max_len: Option<usize>) -> Self {
+        Strings { alphabet, next: Some(Vec::new()), max_len }
+    }
+}
+
+impl<'a, T: Clone> Iterator for Strings<'a, T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        let indexes = self.next.take()?;
+        let item = indexes.iter().map(|&i| self.alphabet[i].clone()).collect();
+
+        // Count up in base `alphabet.len()`, adding a digit on overflow.
+        let mut next = indexes;
+        let mut carry = true;
+        for i in next.iter_mut().rev() {
+            *i += 1;
+            if *i < self.alphabet.len() {
+                carry = false;
+                break;
+            }
+            *i = 0;
+        }
+        if carry {
+            next.push(0);
+        }
+        let too_long = self.max_len.is_some_and(|max| next.len() > max);
+        if !self.alphabet.is_empty() && !too_long {
+            self.next = Some(next);
+        }
+        Some(item)
+    }
+}
+
+/// An input two grammars give different weights.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Counterexample<T, M> {
+    pub input: Vec<T>,
+    pub left: M,
+    pub right: M,
+}
+
+/// Find the shortest input of up to `max_len` items from `alphabet`
+/// which `a` and `b` give different weights. Among inputs of the same
+/// length, the one using the earliest items in `alphabet` is chosen.
+pub fn counterexample<T, M, A, B>(a: &mut AnyRegex<T, M, A>, b: &mut AnyRegex<T, M, B>, alphabet: &[T], max_len: usize) -> Option<Counterexample<T, M>> where
+    T: Clone,
+    M: Zero + One + PartialEq,
+    A: Regex<T, M>,
+    B: Regex<T, M>,
+{
+    Strings::new(alphabet, Some(max_len)).find_map(|input| {
+        let left = a.over(input.iter().cloned());
+        let right = b.over(input.iter().cloned());
+        if left == right { None } else { Some(Counterexample { input, left, right }) }
+    })
+}
+
+/// Panic unless `a` and `b` give the same weight to every input of up
+/// to `max_len` items from `alphabet`. The panic message shows the
+/// shortest input they disagree on.
+pub fn assert_language_eq<T, M, A, B>(a: &mut AnyRegex<T, M, A>, b: &mut AnyRegex<T, M, B>, alphabet: &[T], max_len: usize) where
+    T: Clone + Debug,
+    M: Zero + One + PartialEq + Debug,
+    A: Regex<T, M>,
+    B: Regex<T, M>,
+{
+    if let Some(found) = counterexample(a, b, alphabet, max_len) {
+        panic!("grammars differ on {:?}: left gives {:?}, right gives {:?}", found.input, found.left, found.right);
+    }
+}
+
+/// Assert that a grammar matches each of the given inputs, like
+/// `assert_matches!(re, "ab".chars(), "abab".chars())`. On failure, the
+/// message shows the grammar, the input, and the `explain`ation.
+#[macro_export]
+macro_rules! assert_matches {
+    ($re:expr, $($input:expr),+ $(,)*) => {{
+        let re = &mut $re;
+        $( $crate::testing::assert_match(re, $input, true); )+
+    }};
+}
+
+/// Assert that a grammar matches none of the given inputs, like
+/// `assert_no_match!(re, "ba".chars())`.
+#[macro_export]
+macro_rules! assert_no_match {
+    ($re:expr, $($input:expr),+ $(,)*) => {{
+        let re = &mut $re;
+        $( $crate::testing::assert_match(re, $input, false); )+
+    }};
+}
+
+/// Panic unless `re` matches `input` exactly when `expected` is set.
+/// This is what `assert_matches!` and `assert_no_match!` call.
+pub fn assert_match<T, M, R, I>(re: &mut AnyRegex<T, M, R>, input: I, expected: bool) where
+    M: Zero + One,
+    R: Regex<T, M> + Describe<T, M>,
+    I: IntoIterator<Item=T> + Clone + Debug,
+{
+    match explain(re, input.clone()) {
+        Err(failure) => if expected {
+            panic!("grammar {} didn't match {:?}: {}", re, input, failure);
+        },
+        Ok(_) => if !expected {
+            panic!("grammar {} matched {:?}", re, input);
+        },
+    }
+}
+
+/// A grammar of random shape, at most `depth` operators deep, using
+/// every kind of node except `delay`. Each leaf accepts one item from
+/// `alphabet` and is labeled with that item's `Debug` text.
+#[cfg(feature = "rand")]
+pub fn random_grammar<T, M, G>(alphabet: &[T], depth: usize, rng: &mut G) -> AnyRegex<T, M, Dynamic<T, M>> where
+    T: PartialEq + Clone + Debug + 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+    bool: IntoWithInput<T, M>,
+    G: Rng + ?Sized,
+{
+    build(alphabet, depth, &mut |n| rng.gen_range(0, n))
+}
+
+/// Decode `bytes` into a grammar shaped like those from
+/// `random_grammar`, for structure-aware fuzzing: every byte string
+/// decodes to some grammar, and similar byte strings decode to similar
+/// grammars. Once the bytes run out, the rest of the grammar is leaves.
+pub fn grammar_from_bytes<T, M>(bytes: &[u8], alphabet: &[T], depth: usize) -> AnyRegex<T, M, Dynamic<T, M>> where
+    T: PartialEq + Clone + Debug + 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+    bool: IntoWithInput<T, M>,
+{
+    let mut bytes = bytes.iter();
+    build(alphabet, depth, &mut |n| bytes.next().map_or(0, |&b| b as usize % n))
+}
+
+/// Build a grammar, making each choice among `n` options by calling
+/// `choose(n)`.
+fn build<T, M>(alphabet: &[T], depth: usize, choose: &mut dyn FnMut(usize) -> usize) -> AnyRegex<T, M, Dynamic<T, M>> where
+    T: PartialEq + Clone + Debug + 'static,
+    M: Zero + One + ops::Mul<Output=M> + Clone + PartialEq + 'static,
+    bool: IntoWithInput<T, M>,
+{
+    // Stop early sometimes, so grammars come in every size up to the
+    // limit rather than all being full trees.
+    let kind = if depth == 0 || choose(4) == 0 { choose(2) } else { 2 + choose(6) };
+    match kind {
+        0 if !alphabet.is_empty() => {
+            let c = alphabet[choose(alphabet.len())].clone();
+            let text = format!("{:?}", c);
+            Dynamic::label(Dynamic::is(move |x: &T| *x == c), text)
+        }
+        0 | 1 => Dynamic::empty(),
+        2 => Dynamic::not(build(alphabet, depth - 1, choose)),
+        3 => Dynamic::many(build(alphabet, depth - 1, choose)),
+        4 | 5 => {
+            let left = build(alphabet, depth - 1, choose);
+            Dynamic::sequence(left, build(alphabet, depth - 1, choose))
+        }
+        6 => {
+            let left = build(alphabet, depth - 1, choose);
+            Dynamic::or(left, build(alphabet, depth - 1, choose))
+        }
+        _ => {
+            let left = build(alphabet, depth - 1, choose);
+            Dynamic::and(left, build(alphabet, depth - 1, choose))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn strings_in_order() {
+        let all: Vec<String> = Strings::new(&['a', 'b'], Some(2)).map(|s| s.into_iter().collect()).collect();
+        assert_eq!(all, vec!["", "a", "b", "aa", "ab", "ba", "bb"]);
+        assert_eq!(Strings::new(&[] as &[char], None).count(), 1);
+    }
+
+    #[test]
+    fn equal_languages() {
+        let a = || is(|&c| c == 'a');
+        let mut left: AnyRegex<char, Match, _> = many(a()) + many(a());
+        let mut right = many(a());
+        assert_language_eq(&mut left, &mut right, &['a', 'b'], 6);
+    }
+
+    #[test]
+    fn shortest_counterexample() {
+        let a = || is(|&c| c == 'a');
+        let b = || is(|&c| c == 'b');
+        let mut left: AnyRegex<char, Match, _> = many(a() + b());
+        let mut right = many((a() + b()) | (b() + a()));
+        let found = counterexample(&mut left, &mut right, &['a', 'b'], 4).unwrap();
+        assert_eq!(found.input, vec!['b', 'a']);
+        assert!(found.left.is_zero() && !found.right.is_zero());
+        assert_eq!(counterexample(&mut left, &mut right, &['a'], 4), None);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn simplifying_random_grammars() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+        use simplify::simplify_idempotent;
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let mut original: AnyRegex<char, Match, _> = random_grammar(&['a', 'b'], 4, &mut rng);
+            let mut simplified = simplify_idempotent(original.clone_reset());
+            assert_language_eq(&mut original, &mut simplified, &['a', 'b'], 4);
+        }
+    }
+
+    #[test]
+    fn decoding_bytes() {
+        let decode = |bytes: &[u8]| grammar_from_bytes::<char, Match>(bytes, &['a', 'b'], 3).to_string();
+        assert_eq!(decode(&[]), "'a'");
+        // Continue, then a sequence of two leaves.
+        assert_eq!(decode(&[1, 2, 0, 0, 0, 0, 0, 1]), "'a''b'");
+        assert_eq!(decode(&[1, 0, 1, 1]), "!(?:(?:'a')*)");
+    }
+
+    #[test]
+    fn assertions() {
+        let a = || is(|&c| c == 'a').label("a");
+        let b = || is(|&c| c == 'b').label("b");
+        let mut re: AnyRegex<char, Match, _> = many(a() + b());
+        assert_matches!(re, "".chars(), "ab".chars(), "abab".chars());
+        assert_no_match!(re, vec!['b', 'a'], "aba".chars(),);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"grammar (?:ab)* didn't match ['a', 'a']: unexpected item at position 1, expected one of: b"#)]
+    fn failed_match() {
+        let mut re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a').label("a") + is(|&c| c == 'b').label("b"));
+        assert_matches!(re, vec!['a', 'b'], vec!['a', 'a']);
+    }
+
+    #[test]
+    #[should_panic(expected = "grammar a* matched []")]
+    fn unexpected_match() {
+        let mut re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a').label("a"));
+        assert_no_match!(re, Vec::new());
+    }
+
+    #[test]
+    #[should_panic(expected = r#"grammars differ on ['b', 'a']: left gives Match(false), right gives Match(true)"#)]
+    fn different_languages() {
+        let mut left: AnyRegex<char, Match, _> = many(is(|&c| c == 'a'));
+        let mut right = many(is(|&c| c == 'a') | (is(|&c| c == 'b') + is(|&c| c == 'a')));
+        assert_language_eq(&mut left, &mut right, &['a', 'b'], 6);
+    }
+}
@@ -0,0 +1,106 @@
+//! Unicode property classes for grammars over `char`s.
+//!
+//! These leaves match what `\p{...}` matches in other engines, using
+//! the Unicode tables from the `regex-syntax` crate. Each leaf is
+//! labeled with its `\p{...}` syntax, so grammars using them can still
+//! be rendered with `to_regex_string`.
+
+use core::{AnyRegex, Grammar, IntoWithInput};
+use grammars::is;
+use num_traits::Zero;
+use regex_syntax;
+use regex_syntax::hir::{Class, HirKind};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::ops;
+use std::rc::Rc;
+
+/// A class name that Unicode doesn't define.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownClass(pub String);
+
+impl fmt::Display for UnknownClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown Unicode class: {}", self.0)
+    }
+}
+
+impl Error for UnknownClass {}
+
+/// A leaf matching one character in the class written `\p{name}`, such
+/// as `Greek`, `Lu`, `sc=Latin`, or `Alphabetic`.
+pub fn class<M>(name: &str) -> Result<AnyRegex<char, M, impl Grammar<char, M>>, UnknownClass> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<char, M>,
+{
+    let text = format!("\\p{{{}}}", name);
+    let unknown = || UnknownClass(name.to_string());
+    // Other syntax could combine several classes into one.
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || "_-= ".contains(c)) {
+        return Err(unknown());
+    }
+    let hir = regex_syntax::parse(&text).map_err(|_| unknown())?;
+    let ranges: Rc<Vec<(char, char)>> = match *hir.kind() {
+        HirKind::Class(Class::Unicode(ref class)) => Rc::new(class.ranges().iter().map(|r| (r.start(), r.end())).collect()),
+        _ => return Err(unknown()),
+    };
+    Ok(is(move |&c: &char| ranges.binary_search_by(|&(low, high)| {
+        if high < c {
+            Ordering::Less
+        } else if low > c {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }).is_ok()).label(text))
+}
+
+/// A leaf matching one character in the general category `name`, such
+/// as `Letter`, `L`, or `Uppercase_Letter`.
+pub fn category<M>(name: &str) -> Result<AnyRegex<char, M, impl Grammar<char, M>>, UnknownClass> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<char, M>,
+{
+    class(&format!("gc={}", name))
+}
+
+/// A leaf matching one character in the script `name`, such as `Greek`.
+pub fn script<M>(name: &str) -> Result<AnyRegex<char, M, impl Grammar<char, M>>, UnknownClass> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<char, M>,
+{
+    class(&format!("sc={}", name))
+}
+
+/// A leaf matching one character with the binary property `name`, such
+/// as `Alphabetic` or `White_Space`.
+pub fn property<M>(name: &str) -> Result<AnyRegex<char, M, impl Grammar<char, M>>, UnknownClass> where
+    M: Zero + ops::Mul<Output=M>,
+    bool: IntoWithInput<char, M>,
+{
+    class(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn classes() {
+        let mut re = category::<Match>("Lu").unwrap() + many(script("Greek").unwrap());
+        assert!(has_match(&mut re, "Δαβ".chars()));
+        assert!(has_match(&mut re, "A".chars()));
+        assert!(!has_match(&mut re, "δα".chars()));
+        assert!(!has_match(&mut re, "Aa".chars()));
+
+        let mut space = property::<Match>("White_Space").unwrap();
+        assert!(has_match(&mut space, "\u{3000}".chars()));
+        assert!(!has_match(&mut space, "x".chars()));
+
+        assert_eq!(script::<Match>("Klingon").err(), Some(UnknownClass("sc=Klingon".to_string())));
+        assert!(class::<Match>("L}|\\p{N").is_err());
+        assert_eq!(re.to_regex_string(), Ok("\\p{gc=Lu}\\p{sc=Greek}*".to_string()));
+    }
+}
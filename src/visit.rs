@@ -0,0 +1,168 @@
+//! Walking and folding over grammars.
+//!
+//! `Describe` exposes one level of a grammar at a time, which leaves
+//! every analysis to write its own recursion and its own `match` over
+//! `Node`. The traits here factor that out. Implement `Visit` to be
+//! called back for each node in a depth-first walk, overriding only the
+//! node kinds you care about, or `Fold` to compute one result per node
+//! from the results for its children.
+
+use core::{Describe, Leaf, Node};
+
+/// Callbacks for a depth-first walk over a grammar, started by `walk`.
+///
+/// Each method is called when the walk reaches that kind of node. The
+/// default implementations just continue into the node's children, so
+/// an override which still wants to see the children should call
+/// `walk` on them itself.
+pub trait Visit<'a, T: 'a, M: 'a> {
+    fn visit_empty(&mut self) {}
+    fn visit_is(&mut self, _leaf: &'a dyn Leaf<T, M>) {}
+    fn visit_label(&mut self, _text: &'a str, inner: &'a dyn Describe<T, M>) {
+        walk(self, inner)
+    }
+    fn visit_not(&mut self, inner: &'a dyn Describe<T, M>) {
+        walk(self, inner)
+    }
+    fn visit_or(&mut self, left: &'a dyn Describe<T, M>, right: &'a dyn Describe<T, M>) {
+        walk(self, left);
+        walk(self, right);
+    }
+    fn visit_and(&mut self, left: &'a dyn Describe<T, M>, right: &'a dyn Describe<T, M>) {
+        walk(self, left);
+        walk(self, right);
+    }
+    fn visit_sequence(&mut self, left: &'a dyn Describe<T, M>, right: &'a dyn Describe<T, M>, _mark: &'a M) {
+        walk(self, left);
+        walk(self, right);
+    }
+    fn visit_many(&mut self, inner: &'a dyn Describe<T, M>, _mark: &'a M) {
+        walk(self, inner)
+    }
+    fn visit_delay(&mut self) {}
+}
+
+/// Call the `Visit` method for the kind of node `re` is.
+pub fn walk<'a, T, M, V>(visitor: &mut V, re: &'a dyn Describe<T, M>) where
+    V: Visit<'a, T, M> + ?Sized,
+{
+    match re.describe() {
+        Node::Empty => visitor.visit_empty(),
+        Node::Is(leaf) => visitor.visit_is(leaf),
+        Node::Label(text, inner) => visitor.visit_label(text, inner),
+        Node::Not(inner) => visitor.visit_not(inner),
+        Node::Or(left, right) => visitor.visit_or(left, right),
+        Node::And(left, right) => visitor.visit_and(left, right),
+        Node::Sequence(left, right, mark) => visitor.visit_sequence(left, right, mark),
+        Node::Many(inner, mark) => visitor.visit_many(inner, mark),
+        Node::Delay => visitor.visit_delay(),
+    }
+}
+
+/// A bottom-up computation over a grammar, run by `fold`. Each method
+/// gets the results already computed for the node's children.
+pub trait Fold<'a, T: 'a, M: 'a> {
+    type Output;
+
+    fn fold_empty(&mut self) -> Self::Output;
+    fn fold_is(&mut self, leaf: &'a dyn Leaf<T, M>) -> Self::Output;
+    /// Labels don't change the language, so by default they're
+    /// transparent.
+    fn fold_label(&mut self, _text: &'a str, inner: Self::Output) -> Self::Output { inner }
+    fn fold_not(&mut self, inner: Self::Output) -> Self::Output;
+    fn fold_or(&mut self, left: Self::Output, right: Self::Output) -> Self::Output;
+    fn fold_and(&mut self, left: Self::Output, right: Self::Output) -> Self::Output;
+    fn fold_sequence(&mut self, left: Self::Output, right: Self::Output, mark: &'a M) -> Self::Output;
+    fn fold_many(&mut self, inner: Self::Output, mark: &'a M) -> Self::Output;
+    fn fold_delay(&mut self) -> Self::Output;
+}
+
+/// Compute `folder`'s result for `re`, after computing it for each of
+/// `re`'s children from left to right.
+pub fn fold<'a, T, M, F>(folder: &mut F, re: &'a dyn Describe<T, M>) -> F::Output where
+    F: Fold<'a, T, M> + ?Sized,
+{
+    match re.describe() {
+        Node::Empty => folder.fold_empty(),
+        Node::Is(leaf) => folder.fold_is(leaf),
+        Node::Label(text, inner) => {
+            let inner = fold(folder, inner);
+            folder.fold_label(text, inner)
+        }
+        Node::Not(inner) => {
+            let inner = fold(folder, inner);
+            folder.fold_not(inner)
+        }
+        Node::Or(left, right) => {
+            let (left, right) = (fold(folder, left), fold(folder, right));
+            folder.fold_or(left, right)
+        }
+        Node::And(left, right) => {
+            let (left, right) = (fold(folder, left), fold(folder, right));
+            folder.fold_and(left, right)
+        }
+        Node::Sequence(left, right, mark) => {
+            let (left, right) = (fold(folder, left), fold(folder, right));
+            folder.fold_sequence(left, right, mark)
+        }
+        Node::Many(inner, mark) => {
+            let inner = fold(folder, inner);
+            folder.fold_many(inner, mark)
+        }
+        Node::Delay => folder.fold_delay(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use std::cmp::min;
+
+    struct Labels<'a>(Vec<&'a str>);
+
+    impl<'a, T: 'a, M: 'a> Visit<'a, T, M> for Labels<'a> {
+        fn visit_label(&mut self, text: &'a str, _inner: &'a dyn Describe<T, M>) {
+            self.0.push(text);
+        }
+    }
+
+    /// The length of the shortest input that could match, assuming
+    /// every leaf accepts something.
+    struct ShortestLength;
+
+    impl<'a, T: 'a, M: 'a> Fold<'a, T, M> for ShortestLength {
+        type Output = Option<usize>;
+        fn fold_empty(&mut self) -> Option<usize> { Some(0) }
+        fn fold_is(&mut self, _leaf: &'a dyn Leaf<T, M>) -> Option<usize> { Some(1) }
+        fn fold_not(&mut self, _inner: Option<usize>) -> Option<usize> { None }
+        fn fold_or(&mut self, left: Option<usize>, right: Option<usize>) -> Option<usize> {
+            match (left, right) {
+                (Some(l), Some(r)) => Some(min(l, r)),
+                (l, r) => l.or(r),
+            }
+        }
+        fn fold_and(&mut self, _left: Option<usize>, _right: Option<usize>) -> Option<usize> { None }
+        fn fold_sequence(&mut self, left: Option<usize>, right: Option<usize>, _mark: &'a M) -> Option<usize> {
+            Some(left? + right?)
+        }
+        fn fold_many(&mut self, _inner: Option<usize>, _mark: &'a M) -> Option<usize> { Some(0) }
+        fn fold_delay(&mut self) -> Option<usize> { None }
+    }
+
+    #[test]
+    fn visit_labels() {
+        let re: AnyRegex<char, Match, _> = many(is(|&c| c == 'a').label("a"))
+            + (is(|&c| c == 'b').label("b").label("outer") | empty());
+        let mut labels = Labels(Vec::new());
+        walk(&mut labels, &re);
+        assert_eq!(labels.0, vec!["a", "outer"]);
+    }
+
+    #[test]
+    fn fold_length() {
+        let a = || is(|&c| c == 'a');
+        let re: AnyRegex<char, Match, _> = a() + many(a()) + ((a() + a()) | a().label("x"));
+        assert_eq!(fold(&mut ShortestLength, &re), Some(2));
+    }
+}
@@ -0,0 +1,43 @@
+//! Count the ways a grammar matches. Alternatives add their counts and
+//! sequences multiply them, so the weight of a match is how many
+//! distinct derivations produce it. Started at every position, the
+//! weight of each match end is how many spans end there, counted once
+//! per derivation.
+//!
+//! Counts saturate at `u64::MAX` instead of overflowing.
+
+use num_traits::{Zero, One};
+use std::ops::{Add, Mul};
+use ::engine::IntoWithInput;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Count(pub u64);
+
+impl Add for Count {
+    type Output = Count;
+    fn add(self, rhs : Count) -> Count { Count(self.0.saturating_add(rhs.0)) }
+}
+
+impl Zero for Count {
+    fn zero() -> Count { Count(0) }
+    fn is_zero(&self) -> bool { self.0 == 0 }
+}
+
+impl Mul for Count {
+    type Output = Count;
+    fn mul(self, rhs : Count) -> Count { Count(self.0.saturating_mul(rhs.0)) }
+}
+
+impl One for Count {
+    fn one() -> Count { Count(1) }
+}
+
+impl<T> IntoWithInput<T, Count> for Count {
+    fn into_with_input(self, _input: &T) -> Count { self }
+}
+
+impl<T> IntoWithInput<T, Count> for bool {
+    fn into_with_input(self, _input: &T) -> Count {
+        if self { Count::one() } else { Count::zero() }
+    }
+}
@@ -0,0 +1,75 @@
+//! Find where a match started. Each mark carries the earliest input
+//! position at which a match leading to it could have begun, so when a
+//! match is found, its weight says where the leftmost such match
+//! starts.
+//!
+//! Marks for this semiring are created with `Leftmost::at` when the
+//! search begins a match attempt at a given position. The semiring's
+//! `one()` isn't anchored to any position, so grammars which can
+//! produce a mark without consuming one, like complements, report
+//! matches with no known start.
+
+use num_traits::{Zero, One};
+use std::cmp::min;
+use std::ops::{Add, Mul};
+use ::engine::IntoWithInput;
+use super::Idempotent;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Leftmost(Option<usize>);
+
+impl Leftmost {
+    /// A mark for a match attempt starting at `position`.
+    pub fn at(position: usize) -> Leftmost { Leftmost(Some(position)) }
+
+    /// Where the earliest match began, if there was a match and it was
+    /// anchored to a position.
+    pub fn start(&self) -> Option<usize> {
+        match self.0 {
+            Some(usize::MAX) => None,
+            start => start,
+        }
+    }
+}
+
+impl Add for Leftmost {
+    type Output = Leftmost;
+    fn add(self, rhs : Leftmost) -> Leftmost {
+        match (self.0, rhs.0) {
+            (Some(a), Some(b)) => Leftmost(Some(min(a, b))),
+            (None, b) => Leftmost(b),
+            (a, None) => Leftmost(a),
+        }
+    }
+}
+
+impl Zero for Leftmost {
+    fn zero() -> Leftmost { Leftmost(None) }
+    fn is_zero(&self) -> bool { self.0.is_none() }
+}
+
+impl Mul for Leftmost {
+    type Output = Leftmost;
+    fn mul(self, rhs : Leftmost) -> Leftmost {
+        match (self.0, rhs.0) {
+            (Some(a), Some(b)) => Leftmost(Some(min(a, b))),
+            _ => Leftmost(None),
+        }
+    }
+}
+
+impl One for Leftmost {
+    fn one() -> Leftmost { Leftmost(Some(usize::MAX)) }
+}
+
+impl Idempotent for Leftmost {}
+
+impl<T> IntoWithInput<T, Leftmost> for Leftmost {
+    fn into_with_input(self, _input: &T) -> Leftmost { self }
+}
+
+impl<T> IntoWithInput<T, Leftmost> for bool {
+    fn into_with_input(self, _input: &T) -> Leftmost {
+        if self { Leftmost::one() } else { Leftmost::zero() }
+    }
+}
@@ -1,4 +1,13 @@
 //! Implementations of widely-useful semirings for tracking state during
 //! parsing.
 
+pub mod count;
+pub mod leftmost;
 pub mod recognize;
+pub mod rightmost;
+pub mod values;
+
+/// Semirings where `x + x == x` and `x * x == x`. With these weights,
+/// how many ways a grammar can match doesn't affect the result, so
+/// more rewrites preserve a grammar's weights.
+pub trait Idempotent {}
@@ -4,9 +4,10 @@
 
 use num_traits::{Zero, zero, One, one};
 use std::ops::{Add, Mul};
-use ::core::{Regex, AnyRegex, IntoWithInput};
+use ::engine::{Regex, AnyRegex, IntoWithInput};
+use super::Idempotent;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Match(bool);
 
 impl Add for Match {
@@ -28,6 +29,8 @@ impl One for Match {
     fn one() -> Match { Match(true) }
 }
 
+impl Idempotent for Match {}
+
 impl<T> IntoWithInput<T, Match> for Match {
     fn into_with_input(self, _input: &T) -> Match { self }
 }
@@ -44,11 +47,27 @@ pub fn has_match<T, R, I>(re : &mut AnyRegex<T, Match, R>, over : I) -> bool
     re.over(over).0
 }
 
+/// Like `has_match`, but for a grammar over `char`s and a whole string.
+pub fn has_match_str<R>(re : &mut AnyRegex<char, Match, R>, input : &str) -> bool
+    where R: Regex<char, Match>
+{
+    has_match(re, input.chars())
+}
+
+/// Like `has_match`, but for a grammar over bytes and a whole slice.
+pub fn has_match_bytes<R>(re : &mut AnyRegex<u8, Match, R>, input : &[u8]) -> bool
+    where R: Regex<u8, Match>
+{
+    has_match(re, input.iter().cloned())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use ::*;
     use itertools::{Itertools, repeat_n};
+    use std::string::String;
+    use std::vec::Vec;
 
     quickcheck! {
         fn epsilon_bool(to_match : Vec<bool>) -> bool {
@@ -163,6 +182,13 @@ mod tests {
                 has_match(&mut re, to_match.chars())
         }
 
+        fn sequence_shr_same_as_add(to_match : String) -> bool {
+            let mut added = is(|&c| char::is_uppercase(c)) + many(is(|&c| char::is_lowercase(c)));
+            let mut shifted = is(|&c| char::is_uppercase(c)) >> many(is(|&c| char::is_lowercase(c)));
+            has_match(&mut added, to_match.chars()) ==
+                has_match(&mut shifted, to_match.chars())
+        }
+
         fn repeat_epsilon(to_match : String) -> bool {
             to_match.is_empty() ==
                 has_match(&mut many(empty()), to_match.chars())
@@ -231,4 +257,168 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn bounded_length() {
+        use grammars::{len_between, max_len};
+        let lower = || many(is(|c: &char| c.is_lowercase()));
+        let mut short = max_len(lower(), 3);
+        let mut medium = len_between(lower(), 2, 3);
+        for &(s, is_short, is_medium) in &[("", true, false), ("a", true, false), ("abc", true, true), ("abcd", false, false), ("aB", false, false)] {
+            assert_eq!(has_match_str(&mut short, s), is_short, "{}", s);
+            assert_eq!(has_match_str(&mut medium, s), is_medium, "{}", s);
+        }
+        // Attempts that started at different times are counted apart.
+        let mut anywhere = many(is(|_: &char| true)) + max_len(lower(), 2) + is(|&c: &char| c == '!');
+        assert!(has_match_str(&mut anywhere, "abcdef!"));
+        assert!(!has_match_str(&mut anywhere, "abcdef?"));
+        let c = |x: char| is(move |&c: &char| c == x);
+        let any = || many(is(|_: &char| true));
+        assert!(!has_match_str(&mut (any() + max_len(c('a') + c('b') + c('b'), 1)), "xabb"));
+        assert!(has_match_str(&mut (any() + len_between(c('a') + c('b') + c('b'), 3, 3)), "xabb"));
+    }
+
+    // The bound describes itself, so it can be displayed and flattened.
+    #[cfg(feature = "std")]
+    #[test]
+    fn bounded_length_described() {
+        use grammars::len_between;
+        let lower = || many(is(|c: &char| c.is_lowercase()));
+        let re = len_between(lower(), 1, 2);
+        assert_eq!(re.to_string(), "<is>*&.{1,2}");
+        let mut flat = AnyRegex::new(re.to_flat().ok().unwrap());
+        assert!(has_match_str(&mut flat, "ab"));
+        assert!(!has_match_str(&mut flat, "abc"));
+    }
+
+    #[test]
+    fn occurrence_limit() {
+        use grammars::limit_occurrences;
+        let c = |x: char| is(move |&c: &char| c == x);
+        let anything = || many(is(|_: &char| true));
+        let mut once = limit_occurrences(anything(), c('a') + c('b'), 1);
+        for &(s, expected) in &[("", true), ("xabx", true), ("aba", true), ("abab", false), ("abxxab", false)] {
+            assert_eq!(has_match_str(&mut once, s), expected, "{}", s);
+        }
+        // Overlapping occurrences only count once.
+        let mut once = limit_occurrences(anything(), c('a') + c('a'), 1);
+        assert!(has_match_str(&mut once, "aaa"));
+        assert!(!has_match_str(&mut once, "aaaa"));
+        let mut never = limit_occurrences(anything(), c('a') + c('a'), 0);
+        assert!(has_match_str(&mut never, "aba"));
+        assert!(!has_match_str(&mut never, "baab"));
+
+        // Attempts that started at different times are counted apart.
+        let mut anywhere = anything() + limit_occurrences(anything(), c('a'), 1) + c('!');
+        assert!(has_match_str(&mut anywhere, "aaaa!"));
+        let mut anchored = c('^') + limit_occurrences(anything(), c('a'), 1) + c('!');
+        assert!(has_match_str(&mut anchored, "^xa!"));
+        assert!(!has_match_str(&mut anchored, "^aa!"));
+    }
+
+    // The limit describes itself, so it can be displayed and flattened.
+    #[cfg(feature = "std")]
+    #[test]
+    fn occurrence_limit_described() {
+        use grammars::limit_occurrences;
+        let c = |x: char| is(move |&c: &char| c == x);
+        let anything = || many(is(|_: &char| true));
+        let re = limit_occurrences(anything(), c('a') + c('b'), 1);
+        assert_eq!(re.to_string(), "<is>*&!(?:.*<is><is>.*<is><is>.*)");
+        let mut flat = AnyRegex::new(re.to_flat().ok().unwrap());
+        assert!(has_match_str(&mut flat, "abxa"));
+        assert!(!has_match_str(&mut flat, "abab"));
+    }
+
+    #[test]
+    fn parameterized() {
+        use grammars::with_params;
+
+        #[derive(Clone)]
+        struct Config { allowed: &'static str }
+
+        let re = with_params(Config { allowed: "xyz" }, |p: &Config| {
+            let allowed = p.allowed;
+            many(is(move |&c: &char| allowed.contains(c)))
+        });
+        assert_eq!(re.params().allowed, "xyz");
+        let mut copy = re.clone_reset();
+        assert!(has_match_str(&mut copy, "zyx"));
+        assert!(!has_match_str(&mut copy, "abc"));
+    }
+
+    #[test]
+    fn context() {
+        use grammars::is_with_ctx;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let allow_upper = Rc::new(Cell::new(false));
+        let mut re = many(is_with_ctx(allow_upper.clone(), |allow: &Cell<bool>, c: &char| {
+            c.is_lowercase() || (allow.get() && c.is_uppercase())
+        }));
+        assert!(!has_match_str(&mut re, "aBc"));
+        allow_upper.set(true);
+        assert!(has_match_str(&mut re, "aBc"));
+        assert!(has_match_str(&mut re.clone_reset(), "aBc"));
+    }
+
+    #[test]
+    fn collected_literals() {
+        let mut methods: AnyRegex<char, Match, _> = ["GET", "PUT", "POST"].iter().collect();
+        assert!(has_match_str(&mut methods, "POST"));
+        assert!(!has_match_str(&mut methods, "PATCH"));
+        let mut magic: AnyRegex<u8, Match, _> = vec![b"\x7fELF".to_vec(), b"MZ".to_vec()].into_iter().collect();
+        assert!(has_match_bytes(&mut magic, b"MZ"));
+        assert!(!has_match_bytes(&mut magic, b"ELF"));
+    }
+
+    #[test]
+    fn keywords() {
+        let words = ["ab", "abc", "b", "ca", "ab"];
+        let mut anchored = literals(words.iter().map(|w| w.chars()));
+        let mut anywhere = many(is(|_| true)) + literals(words.iter().map(|w| w.chars())) + many(is(|_| true));
+        for len in 0..=6 {
+            for to_match in repeat_n("abc".chars(), len).multi_cartesian_product() {
+                let s: String = to_match.iter().collect();
+                assert_eq!(words.contains(&&s[..]), has_match(&mut anchored, s.chars()), "{}", s);
+                assert_eq!(words.iter().any(|w| s.contains(w)), has_match(&mut anywhere, s.chars()), "{}", s);
+            }
+        }
+    }
+
+    // The trie describes itself, so it can be displayed, flattened, and
+    // continued from the middle of a match.
+    #[cfg(feature = "std")]
+    #[test]
+    fn keywords_described() {
+        use testing::assert_language_eq;
+        use weights::count::Count;
+        let words = ["ab", "abc", "b", "ca", "ab"];
+        let trie = || many(is(|_: &char| true)) + literals(words.iter().map(|w| w.chars()));
+        let source = trie();
+        let mut flat = AnyRegex::new(source.to_flat().ok().unwrap());
+        assert_language_eq::<_, Count, _, _>(&mut trie(), &mut flat, &['a', 'b', 'c'], 6);
+
+        let mut re: AnyRegex<char, Match, _> = literals(words.iter().map(|w| w.chars()));
+        assert_eq!(re.to_string(), "<is>(?:<is>(?:|<is>)|<is>)|<is>|<is><is>");
+        re.shift(&'a', one());
+        let residual = re.residual(false);
+        let rest = ::derivative::Derivative::new(&residual).ok().unwrap();
+        assert!(rest.is_match("b".chars()));
+        assert!(rest.is_match("bc".chars()));
+        assert!(!rest.is_match("c".chars()));
+    }
+
+    #[test]
+    fn whole_inputs() {
+        let mut word = is(|c: &char| c.is_alphabetic()) + many(is(|c: &char| c.is_alphabetic()));
+        assert!(has_match_str(&mut word, "héllo"));
+        assert!(!has_match_str(&mut word, "hi there"));
+
+        let mut digits = many(is(|b: &u8| b.is_ascii_digit()));
+        assert!(has_match_bytes(&mut digits, b"0123"));
+        assert!(has_match_bytes(&mut digits, b""));
+        assert!(!has_match_bytes(&mut digits, b"12a"));
+    }
 }
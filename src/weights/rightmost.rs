@@ -0,0 +1,74 @@
+//! Find where the latest match started. Each mark carries the latest
+//! input position at which a match leading to it could have begun, so
+//! when a match is found, its weight says where the shortest match
+//! ending there starts.
+//!
+//! This is the mirror image of `Leftmost`, and marks are likewise
+//! created with `Rightmost::at`. Its `one()` isn't anchored to any
+//! position either, and since it counts as later than every position,
+//! a match with no known start hides any known ones.
+
+use num_traits::{Zero, One};
+use std::cmp::{max, min};
+use std::ops::{Add, Mul};
+use ::engine::IntoWithInput;
+use super::Idempotent;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rightmost(Option<usize>);
+
+impl Rightmost {
+    /// A mark for a match attempt starting at `position`.
+    pub fn at(position: usize) -> Rightmost { Rightmost(Some(position)) }
+
+    /// Where the latest match began, if there was a match and it was
+    /// anchored to a position.
+    pub fn start(&self) -> Option<usize> {
+        match self.0 {
+            Some(usize::MAX) => None,
+            start => start,
+        }
+    }
+}
+
+impl Add for Rightmost {
+    type Output = Rightmost;
+    fn add(self, rhs : Rightmost) -> Rightmost {
+        match (self.0, rhs.0) {
+            (Some(a), Some(b)) => Rightmost(Some(max(a, b))),
+            (None, b) => Rightmost(b),
+            (a, None) => Rightmost(a),
+        }
+    }
+}
+
+impl Zero for Rightmost {
+    fn zero() -> Rightmost { Rightmost(None) }
+    fn is_zero(&self) -> bool { self.0.is_none() }
+}
+
+impl Mul for Rightmost {
+    type Output = Rightmost;
+    fn mul(self, rhs : Rightmost) -> Rightmost {
+        match (self.0, rhs.0) {
+            (Some(a), Some(b)) => Rightmost(Some(min(a, b))),
+            _ => Rightmost(None),
+        }
+    }
+}
+
+impl One for Rightmost {
+    fn one() -> Rightmost { Rightmost(Some(usize::MAX)) }
+}
+
+impl Idempotent for Rightmost {}
+
+impl<T> IntoWithInput<T, Rightmost> for Rightmost {
+    fn into_with_input(self, _input: &T) -> Rightmost { self }
+}
+
+impl<T> IntoWithInput<T, Rightmost> for bool {
+    fn into_with_input(self, _input: &T) -> Rightmost {
+        if self { Rightmost::one() } else { Rightmost::zero() }
+    }
+}
@@ -0,0 +1,580 @@
+//! Compute values from a match, like a parser's semantic actions.
+//!
+//! Leaves emit values with `Values::emit`, and `action` collects the
+//! values emitted inside a sub-grammar and replaces them with whatever
+//! its function makes of them. Each mark carries a stack of the values
+//! collected so far, one frame per `action` the mark is inside, so
+//! marks from different starting points never mix their values.
+//!
+//! `emit` and `replace` add fixed values after a sub-grammar or in
+//! place of its own, so a grammar can act as a transducer, and
+//! `transduce` returns the output for an input.
+//!
+//! Where a grammar is ambiguous, values from one of the parses are
+//! kept and the rest are dropped. Intersections keep the values from
+//! both sides, the left side's first. An action isn't run where its
+//! grammar matches the empty input, because the empty part of a match
+//! never shifts anything.
+
+use engine::{AnyRegex, Attempts, CloneRegex, Describe, IntoWithInput, Node, Regex};
+use num_traits::{Zero, zero, One};
+use std::marker::PhantomData;
+use std::ops::{Add, Mul};
+use std::vec::Vec;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Values<V>(Option<Parse<V>>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Parse<V> {
+    /// A mark's values, innermost action last.
+    Stack(Vec<Vec<V>>),
+    /// Values emitted by a leaf, to be added to a mark's innermost
+    /// frame.
+    Emit(Vec<V>),
+}
+
+impl<V> Values<V> {
+    /// A leaf weight which adds `value` to the match.
+    pub fn emit(value: V) -> Self { Values(Some(Parse::Emit(vec![value]))) }
+
+    /// The values collected outside of any action, if this is a match.
+    pub fn into_vec(self) -> Option<Vec<V>> {
+        match self.0? {
+            Parse::Stack(mut stack) => stack.pop(),
+            Parse::Emit(values) => Some(values),
+        }
+    }
+
+    /// A leaf weight which adds all of `values` to the match, in order.
+    pub fn emit_all(values: Vec<V>) -> Self { Values(Some(Parse::Emit(values))) }
+
+    fn push_frame(self) -> Self {
+        Values(self.0.map(|parse| match parse {
+            Parse::Stack(mut stack) => {
+                stack.push(Vec::new());
+                Parse::Stack(stack)
+            }
+            Parse::Emit(values) => Parse::Stack(vec![values, Vec::new()]),
+        }))
+    }
+
+    /// Replace the innermost frame's values with what `f` makes of
+    /// them, added to the frame around it.
+    /// The values emitted outside of any action so far. These only ever
+    /// grow at the end, so they start whatever output the mark leads to.
+    fn output(&self) -> Option<&[V]> {
+        match *self.0.as_ref()? {
+            Parse::Stack(ref stack) => stack.first().map(|frame| &frame[..]),
+            Parse::Emit(ref values) => Some(values),
+        }
+    }
+
+    fn pop_frame<F: Fn(Vec<V>) -> Vec<V>>(self, f: &F) -> Self {
+        Values(self.0.map(|parse| match parse {
+            Parse::Stack(mut stack) => {
+                let top = stack.pop().unwrap_or_default();
+                let values = f(top);
+                match stack.last_mut() {
+                    Some(frame) => frame.extend(values),
+                    None => stack.push(values),
+                }
+                Parse::Stack(stack)
+            }
+            Parse::Emit(values) => Parse::Emit(f(values)),
+        }))
+    }
+}
+
+impl<V> Add for Values<V> {
+    type Output = Values<V>;
+    fn add(self, rhs : Values<V>) -> Values<V> {
+        if self.0.is_some() { self } else { rhs }
+    }
+}
+
+impl<V> Zero for Values<V> {
+    fn zero() -> Values<V> { Values(None) }
+    fn is_zero(&self) -> bool { self.0.is_none() }
+}
+
+impl<V> Mul for Values<V> {
+    type Output = Values<V>;
+    fn mul(self, rhs : Values<V>) -> Values<V> {
+        Values(match (self.0, rhs.0) {
+            (Some(Parse::Stack(mut stack)), Some(Parse::Emit(values))) |
+            (Some(Parse::Emit(values)), Some(Parse::Stack(mut stack))) => {
+                stack.last_mut().unwrap().extend(values);
+                Some(Parse::Stack(stack))
+            }
+            (Some(Parse::Emit(mut left)), Some(Parse::Emit(right))) => {
+                left.extend(right);
+                Some(Parse::Emit(left))
+            }
+            // The right side's values come after the left's, the way
+            // an attempt's values follow the ones from before it
+            // started.
+            (Some(Parse::Stack(mut left)), Some(Parse::Stack(right))) => {
+                let mut right = right.into_iter();
+                left.last_mut().unwrap().extend(right.next().unwrap_or_default());
+                left.extend(right);
+                Some(Parse::Stack(left))
+            }
+            _ => None,
+        })
+    }
+}
+
+impl<V> One for Values<V> {
+    fn one() -> Values<V> { Values(Some(Parse::Stack(vec![Vec::new()]))) }
+}
+
+impl<T, V> IntoWithInput<T, Values<V>> for Values<V> {
+    fn into_with_input(self, _input: &T) -> Values<V> { self }
+}
+
+impl<T, V> IntoWithInput<T, Values<V>> for bool {
+    fn into_with_input(self, _input: &T) -> Values<V> {
+        if self { Values(Some(Parse::Emit(Vec::new()))) } else { zero() }
+    }
+}
+
+pub struct Action<T, V, R, F> {
+    re: AnyRegex<T, Values<V>, R>,
+    f: F,
+}
+
+/// Match `re`, and replace the values emitted inside it with the
+/// result of `f` on them, in order.
+pub fn action<T, V, R, F>(re: AnyRegex<T, Values<V>, R>, f: F) -> AnyRegex<T, Values<V>, Action<T, V, R, F>> where
+    R: Regex<T, Values<V>>,
+    F: Fn(Vec<V>) -> V,
+{
+    AnyRegex::new(Action { re, f })
+}
+
+impl<T, V, R, F> Regex<T, Values<V>> for Action<T, V, R, F> where
+    R: Regex<T, Values<V>>,
+    F: Fn(Vec<V>) -> V,
+{
+    fn empty(&mut self) -> bool { self.re.empty() }
+    fn active(&self) -> bool { self.re.active() }
+    fn shift(&mut self, c : &T, mark : Values<V>) -> Values<V> {
+        let f = &self.f;
+        self.re.shift(c, mark.push_frame()).pop_frame(&|values| vec![f(values)])
+    }
+    fn reset(&mut self) { self.re.reset() }
+}
+
+impl<T, V, R, F> CloneRegex<T, Values<V>> for Action<T, V, R, F> where
+    R: CloneRegex<T, Values<V>>,
+    F: Fn(Vec<V>) -> V + Clone,
+{
+    fn clone_reset(&self) -> AnyRegex<T, Values<V>, Self> {
+        action(self.re.clone_reset(), self.f.clone())
+    }
+}
+
+impl<T, V, R, F> Describe<T, Values<V>> for Action<T, V, R, F> where
+    R: Describe<T, Values<V>>,
+{
+    fn describe(&self) -> Node<'_, T, Values<V>> { self.re.describe() }
+    fn attempts(&self) -> Option<Attempts<'_, T, Values<V>>> { self.re.attempts() }
+}
+
+pub struct Emit<T, V, R> {
+    re: AnyRegex<T, Values<V>, R>,
+    values: Vec<V>,
+    /// Whether to drop the values emitted inside `re`.
+    replace: bool,
+}
+
+/// Match `re`, and emit `values` after whatever it emits.
+pub fn emit<T, V, R>(re: AnyRegex<T, Values<V>, R>, values: Vec<V>) -> AnyRegex<T, Values<V>, Emit<T, V, R>> where
+    R: Regex<T, Values<V>>,
+    V: Clone,
+{
+    AnyRegex::new(Emit { re, values, replace: false })
+}
+
+/// Match `re`, and emit `values` in place of whatever it emits. Along
+/// with leaves that emit the items they match, this makes a grammar a
+/// transducer, which can normalize or redact its input in one pass.
+pub fn replace<T, V, R>(re: AnyRegex<T, Values<V>, R>, values: Vec<V>) -> AnyRegex<T, Values<V>, Emit<T, V, R>> where
+    R: Regex<T, Values<V>>,
+    V: Clone,
+{
+    AnyRegex::new(Emit { re, values, replace: true })
+}
+
+impl<T, V, R> Regex<T, Values<V>> for Emit<T, V, R> where
+    R: Regex<T, Values<V>>,
+    V: Clone,
+{
+    fn empty(&mut self) -> bool { self.re.empty() }
+    fn active(&self) -> bool { self.re.active() }
+    fn shift(&mut self, c : &T, mark : Values<V>) -> Values<V> {
+        let values = &self.values;
+        if self.replace {
+            return self.re.shift(c, mark.push_frame()).pop_frame(&|_| values.clone());
+        }
+        let mark = self.re.shift(c, mark);
+        if mark.is_zero() { mark } else { mark * Values::emit_all(values.clone()) }
+    }
+    fn reset(&mut self) { self.re.reset() }
+}
+
+impl<T, V, R> CloneRegex<T, Values<V>> for Emit<T, V, R> where
+    R: CloneRegex<T, Values<V>>,
+    V: Clone,
+{
+    fn clone_reset(&self) -> AnyRegex<T, Values<V>, Self> {
+        AnyRegex::new(Emit { re: self.re.clone_reset(), values: self.values.clone(), replace: self.replace })
+    }
+}
+
+impl<T, V, R> Describe<T, Values<V>> for Emit<T, V, R> where
+    R: Describe<T, Values<V>>,
+{
+    fn describe(&self) -> Node<'_, T, Values<V>> { self.re.describe() }
+    fn attempts(&self) -> Option<Attempts<'_, T, Values<V>>> { self.re.attempts() }
+}
+
+impl<T, V, R> AnyRegex<T, Values<V>, R> where
+    R: Regex<T, Values<V>>,
+{
+    /// Match `over`, and if it matches, build a result from the values
+    /// collected outside of any action, such as the fields of a struct.
+    /// `convert` can reject values it can't use by returning `None`.
+    pub fn extract<I, S, F>(&mut self, over: I, convert: F) -> Option<S> where
+        I: IntoIterator<Item=T>,
+        F: FnOnce(Vec<V>) -> Option<S>,
+    {
+        self.over(over).into_vec().and_then(convert)
+    }
+
+    /// Match `over`, and if it matches, return the values emitted along
+    /// the way, which for a transducer is its output.
+    pub fn transduce<I>(&mut self, over: I) -> Option<Vec<V>> where
+        I: IntoIterator<Item=T>,
+    {
+        self.over(over).into_vec()
+    }
+}
+
+/// Grammars, or pipelines of them, which turn an input into an output
+/// sequence one item at a time.
+pub trait Transducer<T, V> {
+    /// Read `c`, and pass any output it settles to `out`. Returns
+    /// `false` once the input can't match any more, or `out` returns
+    /// `false` to reject an item.
+    fn push(&mut self, c: T, out: &mut dyn FnMut(V) -> bool) -> bool;
+
+    /// End the input, passing any output still to come to `out`, and
+    /// get ready for the next input. Returns whether the input matched
+    /// and all of its output was accepted.
+    fn finish(&mut self, out: &mut dyn FnMut(V) -> bool) -> bool;
+
+    /// The output for `over`, or `None` if it doesn't match.
+    fn transduce_iter<I: IntoIterator<Item=T>>(&mut self, over: I) -> Option<Vec<V>> where
+        Self: Sized,
+    {
+        let mut output = Vec::new();
+        let mut matching = true;
+        for c in over {
+            if !self.push(c, &mut |v| { output.push(v); true }) {
+                matching = false;
+                break;
+            }
+        }
+        let finished = self.finish(&mut |v| { output.push(v); true });
+        if matching && finished { Some(output) } else { None }
+    }
+}
+
+/// A grammar as one stage of a pipeline; see `stage`.
+pub struct Stage<T, V, R> {
+    re: AnyRegex<T, Values<V>, R>,
+    read: bool,
+    /// The output for the items read so far, if they match.
+    matched: Option<Vec<V>>,
+    sent: usize,
+}
+
+/// Use `re` as a transducer. Output is passed on as soon as every way
+/// the input could go on agrees on it, so the next stage can start on
+/// it before this one has read all of its input.
+///
+/// Where a longer input might still change earlier output, as with
+/// `replace(a, x) | replace(a + b, y)` after an `a`, that output waits
+/// until the input rules one of them out. Output emitted inside a
+/// `delay`ed grammar waits until that grammar has no marks left.
+pub fn stage<T, V, R>(re: AnyRegex<T, Values<V>, R>) -> Stage<T, V, R> where
+    R: Describe<T, Values<V>>,
+{
+    Stage { re, read: false, matched: None, sent: 0 }
+}
+
+/// Adds the output so far of each mark in `re` to `outputs`. Returns
+/// `false` if some marks can't be seen, so nothing is known for sure.
+fn outputs<'a, T, V>(re: &'a dyn Describe<T, Values<V>>, outputs: &mut Vec<&'a [V]>) -> bool {
+    if re.activity() == Some(false) {
+        return true;
+    }
+    // An attempt's own marks start from scratch, so its weight is what
+    // carries the output from before it.
+    if let Some(attempts) = re.attempts() {
+        outputs.extend(attempts.settled.output());
+        outputs.extend(attempts.attempts.iter().filter_map(|&(weight, _)| weight.output()));
+        return true;
+    }
+    match re.describe() {
+        Node::Empty | Node::Is(_) => true,
+        Node::Delay => false,
+        Node::Label(_, inner) | Node::Not(inner) => self::outputs(inner, outputs),
+        Node::Or(left, right) | Node::And(left, right) => {
+            self::outputs(left, outputs) && self::outputs(right, outputs)
+        }
+        Node::Sequence(left, right, mark) => {
+            outputs.extend(mark.output());
+            self::outputs(left, outputs) && self::outputs(right, outputs)
+        }
+        Node::Many(inner, mark) => {
+            outputs.extend(mark.output());
+            self::outputs(inner, outputs)
+        }
+    }
+}
+
+impl<T, V, R> Stage<T, V, R> where
+    R: Describe<T, Values<V>>,
+    V: Clone + PartialEq,
+{
+    /// Pass on the output which every live mark, and the current match,
+    /// start with.
+    fn forward(&mut self, out: &mut dyn FnMut(V) -> bool) -> bool {
+        let mut live = Vec::new();
+        if !outputs(&self.re, &mut live) {
+            return true;
+        }
+        live.extend(self.matched.as_ref().map(|values| &values[..]));
+        let (first, rest) = match live.split_first() {
+            Some(split) => split,
+            None => return true,
+        };
+        let decided = rest.iter().fold(first.len(), |len, other| {
+            first[..len].iter().zip(other.iter()).take_while(|&(a, b)| a == b).count()
+        });
+        let sent = self.sent;
+        self.sent = self.sent.max(decided);
+        first.get(sent..decided).is_none_or(|values| values.iter().all(|v| out(v.clone())))
+    }
+}
+
+impl<T, V, R> Transducer<T, V> for Stage<T, V, R> where
+    R: Regex<T, Values<V>> + Describe<T, Values<V>>,
+    V: Clone + PartialEq,
+{
+    fn push(&mut self, c: T, out: &mut dyn FnMut(V) -> bool) -> bool {
+        let mark = if self.read { zero() } else { Values::one() };
+        self.read = true;
+        self.matched = self.re.shift(&c, mark).into_vec();
+        (self.matched.is_some() || self.re.active()) && self.forward(out)
+    }
+
+    fn finish(&mut self, out: &mut dyn FnMut(V) -> bool) -> bool {
+        if !self.read && self.re.empty() {
+            self.matched = Some(Vec::new());
+        }
+        let sent = self.sent;
+        let matched = match self.matched.take() {
+            Some(values) => values[sent..].iter().all(|v| out(v.clone())),
+            None => false,
+        };
+        self.re.reset();
+        self.read = false;
+        self.sent = 0;
+        matched
+    }
+}
+
+/// Two transducers in a pipeline; see `compose`.
+pub struct Compose<A, B, U> {
+    first: A,
+    second: B,
+    middle: PhantomData<U>,
+}
+
+/// Feed the output of `first` to `second` as its input. Pipelines nest,
+/// as in `compose(compose(tokenize, normalize), classify)`.
+///
+/// Each item `first` passes on is shifted into `second` right away, so
+/// no stage's whole output is held between them.
+pub fn compose<T, U, V, A, B>(first: A, second: B) -> Compose<A, B, U> where
+    A: Transducer<T, U>,
+    B: Transducer<U, V>,
+{
+    Compose { first, second, middle: PhantomData }
+}
+
+impl<T, U, V, A, B> Transducer<T, V> for Compose<A, B, U> where
+    A: Transducer<T, U>,
+    B: Transducer<U, V>,
+{
+    fn push(&mut self, c: T, out: &mut dyn FnMut(V) -> bool) -> bool {
+        let second = &mut self.second;
+        self.first.push(c, &mut |u| second.push(u, &mut *out))
+    }
+
+    fn finish(&mut self, out: &mut dyn FnMut(V) -> bool) -> bool {
+        let first = {
+            let second = &mut self.second;
+            self.first.finish(&mut |u| second.push(u, &mut *out))
+        };
+        // Finish both stages either way, so both are ready for the
+        // next input.
+        self.second.finish(out) && first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+    use std::string::String;
+
+    fn digit() -> AnyRegex<char, Values<u32>, impl Regex<char, Values<u32>>> {
+        is(|c: &char| match c.to_digit(10) {
+            Some(d) => Values::emit(d),
+            None => zero(),
+        })
+    }
+
+    #[test]
+    fn numbers() {
+        let number = || action(digit() + many(digit()), |digits| digits.into_iter().fold(0, |n, d| n * 10 + d));
+        let comma = || is(|&c| c == ',');
+        let mut re = number() + many(comma() + number());
+        assert_eq!(re.over("12,345,6".chars()).into_vec(), Some(vec![12, 345, 6]));
+        assert_eq!(re.over("12,".chars()).into_vec(), None);
+
+        // Nested actions see only their own values.
+        let pair = || action(number() + comma() + number(), |v| v[0] * v[1]);
+        let mut re = pair() + is(|&c| c == ';') + pair();
+        assert_eq!(re.over("2,3;40,5".chars()).into_vec(), Some(vec![6, 200]));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Date { year: u32, month: u32, day: u32 }
+
+    #[test]
+    fn extract() {
+        let number = || action(digit() + many(digit()), |digits| digits.into_iter().fold(0, |n, d| n * 10 + d));
+        let dash = || is(|&c| c == '-');
+        let mut re = number() + dash() + number() + dash() + number();
+        let date = |input: &str, re: &mut AnyRegex<_, _, _>| re.extract(input.chars(), |fields: Vec<u32>| match fields[..] {
+            [year, month @ 1..=12, day @ 1..=31] => Some(Date { year, month, day }),
+            _ => None,
+        });
+        assert_eq!(date("2024-10-16", &mut re), Some(Date { year: 2024, month: 10, day: 16 }));
+        assert_eq!(date("2024-13-16", &mut re), None);
+        assert_eq!(date("2024-10", &mut re), None);
+    }
+
+    #[test]
+    fn transducer() {
+        // Spell "colour" the American way, redact digits, and copy
+        // spaces. As with any weights here, the grammar must be
+        // unambiguous for its output to be predictable.
+        let space = || is(|&c: &char| if c == ' ' { Values::emit(c) } else { zero() });
+        let digit = || is(|c: &char| c.is_ascii_digit());
+        let colour = || literals(std::iter::once("colour".chars()));
+        let mut re = many(replace(colour(), "color".chars().collect()) | replace(digit(), vec!['#']) | space());
+        assert_eq!(re.transduce("colour 12 colour".chars()).map(|out| out.into_iter().collect::<String>()), Some(String::from("color ## color")));
+        assert_eq!(re.transduce("color".chars()), None);
+
+        let copy = || is(|&c: &char| Values::emit(c));
+        let mut re = emit(copy() + copy(), vec!['!']) + copy();
+        assert_eq!(re.transduce("abc".chars()), Some(vec!['a', 'b', '!', 'c']));
+        assert_eq!(re.transduce("ab".chars()), None);
+    }
+
+    #[test]
+    fn intersected() {
+        // Values emitted inside an intersection follow the ones from
+        // before it, from both sides in turn.
+        let copy = || is(|&c: &char| Values::emit(c));
+        let any = || many(is(|_: &char| true));
+        let x = || emit(is(|&c: &char| c == 'x'), vec!['<']);
+        let mut re = x() + (many(copy()) & any());
+        assert_eq!(re.transduce("xab".chars()), Some(vec!['<', 'a', 'b']));
+        let mut re = x() + (any() & emit(any(), vec!['>']));
+        assert_eq!(re.transduce("xab".chars()), Some(vec!['<', '>']));
+        let ab = || many(is(|&c: &char| c != 'x'));
+        let count = |values: Vec<char>| (b'0' + values.len() as u8) as char;
+        let mut re = many(x() + action(many(copy()) & ab(), count));
+        assert_eq!(re.transduce("xabxc".chars()), Some(vec!['<', '2', '<', '1']));
+    }
+
+    #[test]
+    fn pipeline() {
+        let upper = many(is(|c: &char| Values::emit(c.to_ascii_uppercase())));
+        let ab = || is(|&c: &char| c == 'A') + is(|&c: &char| c == 'B');
+        let shorten = many(replace(ab(), vec!['x']) | is(|&c: &char| if c == 'C' { Values::emit(c) } else { zero() }));
+        let count = many(is(|&c: &char| Values::emit(c == 'x')));
+        let mut pipeline = compose(compose(stage(upper), stage(shorten)), stage(count));
+        assert_eq!(pipeline.transduce_iter("abcab".chars()), Some(vec![true, false, true]));
+        assert_eq!(pipeline.transduce_iter("abd".chars()), None);
+        assert_eq!(pipeline.transduce_iter("".chars()), Some(vec![]));
+        assert_eq!(pipeline.transduce_iter("cab".chars()), Some(vec![false, true]));
+    }
+
+    #[test]
+    fn pipeline_streams() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // The second stage sees each letter of the first word as soon
+        // as the first stage has read it, but the second word's length
+        // only once the space after it shows that the word is over.
+        let log = Rc::new(RefCell::new(String::new()));
+        let letter = || is(|&c: &char| if c.is_ascii_lowercase() { Values::emit(c) } else { zero() });
+        let space = || is(|&c: &char| c == ' ');
+        let length = |word: Vec<char>| (b'0' + word.len() as u8) as char;
+        let counted = action(letter() + many(letter()), length);
+        let words = many(letter() + many(letter()) + space() + counted + space());
+        let seen = log.clone();
+        let echo = many(is(move |&c: &char| { seen.borrow_mut().push(c); Values::emit(c) }));
+        let mut pipeline = compose(stage(words), stage(echo));
+        let read = log.clone();
+        let input = "ab cde ".chars().inspect(move |&c| read.borrow_mut().push(c.to_ascii_uppercase()));
+        assert_eq!(pipeline.transduce_iter(input), Some("ab3".chars().collect()));
+        assert_eq!(*log.borrow(), "AaBb CDE 3");
+
+        // Output which a longer match would take back waits until the
+        // input decides between them, as it would in series.
+        let a = || is(|&c: &char| c == 'a');
+        let short = || replace(a(), vec!['x']);
+        let long = || replace(a() + is(|&c: &char| c == 'b'), vec!['y']);
+        let copy = || many(is(|&c: &char| Values::emit(c)));
+        let mut pipeline = compose(stage(short() | long()), stage(copy()));
+        assert_eq!(pipeline.transduce_iter("a".chars()), Some(vec!['x']));
+        assert_eq!(pipeline.transduce_iter("ab".chars()), Some(vec!['y']));
+        assert_eq!(pipeline.transduce_iter("ac".chars()), None);
+
+        // The same goes for output inside an intersection.
+        let any = many(is(|_: &char| true));
+        let mut pipeline = compose(stage(copy() + ((short() | long()) & any)), stage(copy()));
+        assert_eq!(pipeline.transduce_iter("ca".chars()), Some(vec!['c', 'x']));
+        assert_eq!(pipeline.transduce_iter("cab".chars()), Some(vec!['c', 'y']));
+    }
+
+    #[test]
+    fn repeated() {
+        // Each repetition starts a fresh frame.
+        let number = || action(digit() + many(digit()), |digits| digits.len() as u32);
+        let comma = || is(|&c| c == ',');
+        let mut re = many(number() + comma()) + number();
+        assert_eq!(re.over("12,1234,5".chars()).into_vec(), Some(vec![2, 4, 1]));
+    }
+}
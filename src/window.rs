@@ -0,0 +1,102 @@
+//! Watching an endless stream for matches within its most recent items.
+//!
+//! A `Window` answers whether some match of a grammar lies entirely
+//! within the last `size` items pushed, as for monitoring traffic for
+//! a signature that has to occur close together. Matches may start
+//! anywhere. Each attempt is marked with its starting position using
+//! the `Rightmost` semiring, so whenever a match ends, its weight gives
+//! the latest start of any match ending there.
+//!
+//! Starts only ever move forward, so the window only needs to remember
+//! the latest start of any match so far. Once that falls out of the
+//! window, so has every other match. Together with the grammar's own
+//! marks, one per node, the state stays the same size however long the
+//! stream runs.
+
+use core::{AnyRegex, Regex};
+use num_traits::Zero;
+use weights::rightmost::Rightmost;
+
+/// Tracks whether a grammar matches within the last few items of a
+/// stream; see the module docs.
+pub struct Window<T, R> {
+    re: AnyRegex<T, Rightmost, R>,
+    size: usize,
+    /// How many items have been pushed.
+    position: usize,
+    /// The latest start of any match found so far.
+    latest: Option<usize>,
+    /// Whether the grammar matches the empty sequence, which fits in
+    /// any window.
+    nullable: bool,
+}
+
+impl<T, R> Window<T, R> where
+    R: Regex<T, Rightmost>,
+{
+    /// Look for matches of `re` no longer than `size` items.
+    pub fn new(mut re: AnyRegex<T, Rightmost, R>, size: usize) -> Self {
+        let nullable = re.empty();
+        Window { re, size, position: 0, latest: None, nullable }
+    }
+
+    /// Add `item` to the stream, and report whether a match now fits
+    /// in the window.
+    pub fn push(&mut self, item: T) -> bool {
+        let weight = self.re.shift(&item, Rightmost::at(self.position));
+        self.position += 1;
+        if !weight.is_zero() {
+            // A match without a known start can't be placed in the
+            // window, so it doesn't count.
+            if let Some(start) = weight.start() {
+                self.latest = Some(self.latest.map_or(start, |latest| latest.max(start)));
+            }
+        }
+        self.matched()
+    }
+
+    /// Whether some match lies entirely within the last `size` items.
+    pub fn matched(&self) -> bool {
+        self.nullable || match self.latest {
+            Some(start) => self.position - start <= self.size,
+            None => false,
+        }
+    }
+
+    /// How many items have been pushed so far.
+    pub fn position(&self) -> usize { self.position }
+
+    /// Forget the stream so far.
+    pub fn reset(&mut self) {
+        self.re.reset();
+        self.position = 0;
+        self.latest = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn sliding() {
+        let re = is(|&b: &u8| b == b'a') + is(|&b: &u8| b == b'b');
+        let mut window = Window::new(re, 3);
+        let seen: Vec<bool> = b"abxxab".iter().map(|&b| window.push(b)).collect();
+        assert_eq!(seen, vec![false, true, true, false, false, true]);
+    }
+
+    #[test]
+    fn latest_start_wins() {
+        // An `a`, anything, then a `b`; the second `a` starts a shorter
+        // match ending at the same `b`.
+        let re = is(|&b: &u8| b == b'a') + many(is(|_: &u8| true)) + is(|&b: &u8| b == b'b');
+        let mut window = Window::new(re, 3);
+        let seen: Vec<bool> = b"axxxaxbxx".iter().map(|&b| window.push(b)).collect();
+        assert_eq!(seen, vec![false, false, false, false, false, false, true, false, false]);
+        window.reset();
+        assert!(!window.matched());
+        assert_eq!(window.position(), 0);
+    }
+}
@@ -0,0 +1,117 @@
+//! Matching bytes as they're written somewhere else.
+//!
+//! `MatchingWriter` wraps any `Write` and passes everything written to
+//! it through, while also shifting each byte through a grammar. Matches
+//! may start at any byte, as in `search`, and each time one ends, the
+//! writer calls a function with the offset just past its end and its
+//! weight. It also keeps the total weight of every match, so data that
+//! is already flowing through IO plumbing can be scanned without
+//! buffering it separately.
+
+use core::{AnyRegex, Regex};
+use num_traits::{Zero, zero, One, one};
+use std::io::{self, Write};
+
+/// A writer that matches a grammar against everything written through
+/// it; see the module docs.
+pub struct MatchingWriter<W, M, R, F> {
+    inner: W,
+    re: AnyRegex<u8, M, R>,
+    on_match: F,
+    position: usize,
+    total: M,
+}
+
+impl<W, M, R, F> MatchingWriter<W, M, R, F> where
+    W: Write,
+    M: Zero + One + Clone,
+    R: Regex<u8, M>,
+    F: FnMut(usize, M),
+{
+    /// Forward writes to `inner`, calling `on_match` with the end
+    /// offset and weight of each match of `re`.
+    pub fn new(inner: W, re: AnyRegex<u8, M, R>, on_match: F) -> Self {
+        MatchingWriter { inner, re, on_match, position: 0, total: zero() }
+    }
+
+    /// The sum of the weights of every match so far.
+    pub fn total(&self) -> &M { &self.total }
+
+    /// How many bytes have been written through so far.
+    pub fn position(&self) -> usize { self.position }
+
+    pub fn get_ref(&self) -> &W { &self.inner }
+
+    pub fn get_mut(&mut self) -> &mut W { &mut self.inner }
+
+    /// Give back the inner writer, without flushing it.
+    pub fn into_inner(self) -> W { self.inner }
+}
+
+impl<W, M, R, F> Write for MatchingWriter<W, M, R, F> where
+    W: Write,
+    M: Zero + One + Clone,
+    R: Regex<u8, M>,
+    F: FnMut(usize, M),
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only match what the inner writer accepted, so a retried write
+        // isn't matched twice.
+        let written = self.inner.write(buf)?;
+        for b in &buf[..written] {
+            self.position += 1;
+            let weight = self.re.shift(b, one());
+            if !weight.is_zero() {
+                self.total = self.total.clone() + weight.clone();
+                (self.on_match)(self.position, weight);
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::*;
+
+    #[test]
+    fn tee() {
+        let re = is(|&b: &u8| b == b'a') + many(is(|&b: &u8| b == b'b'));
+        let mut ends = Vec::new();
+        let total = {
+            let mut writer = MatchingWriter::new(Vec::new(), re, |end, _: Match| ends.push(end));
+            writer.write_all(b"xab").unwrap();
+            writer.write_all(b"bya").unwrap();
+            writer.flush().unwrap();
+            assert_eq!(writer.position(), 6);
+            let total = *writer.total();
+            assert_eq!(writer.into_inner(), b"xabbya");
+            total
+        };
+        assert!(!total.is_zero());
+        assert_eq!(ends, vec![2, 3, 4, 6]);
+    }
+
+    #[test]
+    fn partial_writes() {
+        // A writer which only takes two bytes at a time.
+        struct Slow(Vec<u8>);
+        impl Write for Slow {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let n = buf.len().min(2);
+                self.0.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+            fn flush(&mut self) -> io::Result<()> { Ok(()) }
+        }
+
+        let mut count = 0;
+        let mut writer = MatchingWriter::new(Slow(Vec::new()), is(|&b: &u8| b == b'a'), |_, _: Match| count += 1);
+        writer.write_all(b"banana").unwrap();
+        assert_eq!(writer.into_inner().0, b"banana");
+        assert_eq!(count, 3);
+    }
+}
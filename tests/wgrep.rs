@@ -0,0 +1,40 @@
+#![cfg(feature = "cli")]
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+fn wgrep(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_wgrep")).args(args).output().unwrap();
+    (output.status.code(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn searches_files() {
+    let path = env::temp_dir().join(format!("wgrep-test-{}", std::process::id()));
+    fs::write(&path, "one 23 four\n567 eight\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    assert_eq!(wgrep(&[r"\d\d", path]), (Some(0), "4-6:23\n12-14:56\n".to_string()));
+    // Three letters, none of which is an "o".
+    assert_eq!(wgrep(&["[a-z][a-z][a-z]&!(.*o.*)", path]), (Some(0), "16-19:eig\n".to_string()));
+    assert_eq!(wgrep(&["x", path, path]), (Some(1), String::new()));
+    assert_eq!(wgrep(&["(x", path]).0, Some(2));
+    // Each match is as long as it can be.
+    assert_eq!(wgrep(&["e+i?g", path]), (Some(0), "16-19:eig\n".to_string()));
+    assert_eq!(wgrep(&[r"\d+", path]), (Some(0), "4-6:23\n12-15:567\n".to_string()));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn complements_anywhere() {
+    let path = env::temp_dir().join(format!("wgrep-complement-{}", std::process::id()));
+    fs::write(&path, "aaabbb xb xa yb\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    // Runs of "b", none of which contain an "a", even after one was seen.
+    assert_eq!(wgrep(&["b+&!(.*a.*)", path]), (Some(0), "3-6:bbb\n8-9:b\n14-15:b\n".to_string()));
+    // An "x" followed by anything that doesn't start with "b", and a space.
+    assert_eq!(wgrep(&["x!(b.*) ", path]), (Some(0), "10-13:xa \n".to_string()));
+    fs::remove_file(path).unwrap();
+}